@@ -0,0 +1,343 @@
+use crate::command::{CommandBuilder, CommandError};
+use crate::flea_scope::{IdleFleaScope, ScopeReading};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ActionError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Command exited with {0}")]
+    CommandFailed(ExitStatus),
+
+    #[error("Unsupported webhook URL {0}, only plain http:// is supported")]
+    InvalidUrl(String),
+
+    #[error("Webhook answered with {0:?}")]
+    WebhookFailed(String),
+}
+
+/// Something to do after a capture, e.g. power-cycle the DUT or notify someone
+pub trait CaptureAction: Send {
+    fn run(&mut self, scope: &mut IdleFleaScope, reading: &ScopeReading)
+        -> Result<(), ActionError>;
+}
+
+impl<F> CaptureAction for F
+where
+    F: FnMut(&mut IdleFleaScope, &ScopeReading) -> Result<(), ActionError> + Send,
+{
+    fn run(
+        &mut self,
+        scope: &mut IdleFleaScope,
+        reading: &ScopeReading,
+    ) -> Result<(), ActionError> {
+        self(scope, reading)
+    }
+}
+
+/// Run a program on the host. The hostname of the scope is passed in `FLEASCOPE_HOSTNAME`.
+#[derive(Debug, Clone)]
+pub struct ShellCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ShellCommand {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+}
+
+impl CaptureAction for ShellCommand {
+    fn run(&mut self, scope: &mut IdleFleaScope, _: &ScopeReading) -> Result<(), ActionError> {
+        let status = std::process::Command::new(&self.program)
+            .args(&self.args)
+            .env("FLEASCOPE_HOSTNAME", scope.hostname().trim())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(ActionError::CommandFailed(status))
+        }
+    }
+}
+
+/// POST a small JSON summary of the capture to a plain `http://` endpoint
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Split `http://host[:port]/path` into `(host:port, path)`
+    fn split_url(&self) -> Result<(String, String), ActionError> {
+        let invalid_url = || ActionError::InvalidUrl(self.url.clone());
+        let rest = self.url.strip_prefix("http://").ok_or_else(invalid_url)?;
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+        if authority.is_empty() {
+            return Err(invalid_url());
+        }
+
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok((address, path.to_string()))
+    }
+}
+
+/// Connect to the first reachable address `address` resolves to, giving each one
+/// `timeout` to answer
+fn connect(address: &str, timeout: Duration) -> Result<TcpStream, std::io::Error> {
+    let mut last_error = None;
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{address} did not resolve to any address"),
+        )
+    }))
+}
+
+/// `text` as a quoted JSON string
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            control if control.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(control));
+            }
+            _ => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl CaptureAction for Webhook {
+    fn run(
+        &mut self,
+        scope: &mut IdleFleaScope,
+        reading: &ScopeReading,
+    ) -> Result<(), ActionError> {
+        let (address, path) = self.split_url()?;
+        let body = format!(
+            r#"{{"hostname":{},"effective_msps":{},"bytes":{}}}"#,
+            json_string(scope.hostname().trim()),
+            reading.effective_msps,
+            reading.data.len()
+        );
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: {address}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+
+        let mut stream = connect(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status_line = response.lines().next().unwrap_or_default();
+        if status_line
+            .split_whitespace()
+            .nth(1)
+            .is_some_and(|code| code.starts_with('2'))
+        {
+            Ok(())
+        } else {
+            Err(ActionError::WebhookFailed(status_line.to_string()))
+        }
+    }
+}
+
+/// Send a command to the scope's shell, e.g. to toggle a pin driving a relay.
+///
+/// The command is validated when the action is created, so a line break or CTRL-C can't
+/// desynchronize the protocol when it runs later.
+#[derive(Debug, Clone)]
+pub struct DeviceCommand {
+    command: String,
+}
+
+impl DeviceCommand {
+    /// Parse `line` with `CommandBuilder::from_line`
+    pub fn new(line: &str) -> Result<Self, CommandError> {
+        Ok(CommandBuilder::from_line(line)?.into())
+    }
+}
+
+impl From<CommandBuilder> for DeviceCommand {
+    fn from(builder: CommandBuilder) -> Self {
+        Self {
+            command: builder.build(),
+        }
+    }
+}
+
+impl CaptureAction for DeviceCommand {
+    fn run(&mut self, scope: &mut IdleFleaScope, _: &ScopeReading) -> Result<(), ActionError> {
+        scope.exec_raw(&self.command);
+        Ok(())
+    }
+}
+
+type Condition = dyn Fn(&ScopeReading) -> bool + Send;
+
+struct Hook {
+    condition: Box<Condition>,
+    action: Box<dyn CaptureAction>,
+}
+
+/// Registry of actions to run after captures.
+///
+/// ```rust,no_run
+/// use fleascope_rs::actions::{ActionHooks, DeviceCommand, ShellCommand};
+///
+/// let mut hooks = ActionHooks::new();
+/// hooks.on_every_capture(ShellCommand::new("./save_evidence.sh"));
+/// hooks.when(|reading| reading.data.is_empty(), DeviceCommand::new("reset")?);
+/// # Ok::<(), fleascope_rs::command::CommandError>(())
+/// ```
+#[derive(Default)]
+pub struct ActionHooks {
+    hooks: Vec<Hook>,
+}
+
+impl ActionHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_every_capture(&mut self, action: impl CaptureAction + 'static) {
+        self.when(|_| true, action);
+    }
+
+    /// Run `action` for every capture matching `condition`, e.g. a glitch detector
+    pub fn when(
+        &mut self,
+        condition: impl Fn(&ScopeReading) -> bool + Send + 'static,
+        action: impl CaptureAction + 'static,
+    ) {
+        self.hooks.push(Hook {
+            condition: Box::new(condition),
+            action: Box::new(action),
+        });
+    }
+
+    /// Run all matching actions in registration order.
+    /// A failing action doesn't stop the remaining ones; all errors are returned.
+    pub fn fire(&mut self, scope: &mut IdleFleaScope, reading: &ScopeReading) -> Vec<ActionError> {
+        profiling::scope!("ActionHooks::fire");
+
+        self.hooks
+            .iter_mut()
+            .filter(|hook| (hook.condition)(reading))
+            .filter_map(|hook| hook.action.run(scope, reading).err())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_url() {
+        let (address, path) = Webhook::new("http://lab-server:8080/hooks/glitch")
+            .split_url()
+            .unwrap();
+        assert_eq!(address, "lab-server:8080");
+        assert_eq!(path, "/hooks/glitch");
+
+        let (address, path) = Webhook::new("http://lab-server").split_url().unwrap();
+        assert_eq!(address, "lab-server:80");
+        assert_eq!(path, "/");
+
+        assert!(Webhook::new("https://lab-server/").split_url().is_err());
+        assert!(Webhook::new("http:///path").split_url().is_err());
+    }
+
+    #[test]
+    fn test_json_string() {
+        for text in [
+            "bench-3",
+            "quote \" and \\ slash",
+            "line\nbreak\t\u{1}",
+            "ünïcode",
+        ] {
+            let json = json_string(text);
+            assert_eq!(serde_json::from_str::<String>(&json).unwrap(), text);
+        }
+        assert_eq!(json_string("a\u{1}"), r#""a\u0001""#);
+    }
+
+    #[test]
+    fn test_device_command() {
+        assert!(matches!(
+            DeviceCommand::new("hostname a\nreset"),
+            Err(CommandError::ControlCharacter { .. })
+        ));
+        assert!(matches!(DeviceCommand::new(""), Err(CommandError::Empty)));
+
+        let device = crate::simulator::SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let reading = ScopeReading {
+            effective_msps: 1.0,
+            data: Vec::new(),
+            metadata: None,
+            parsed: std::sync::OnceLock::new(),
+        };
+        let mut hooks = ActionHooks::new();
+        hooks.on_every_capture(DeviceCommand::new("hostname bench-3").unwrap());
+        let print = CommandBuilder::new("print").quoted_arg("a b").unwrap();
+        hooks.on_every_capture(DeviceCommand::from(print));
+        assert!(hooks.fire(&mut scope, &reading).is_empty());
+        assert_eq!(
+            device.commands()[device.commands().len() - 2..],
+            ["hostname bench-3", "print \"a b\""]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::flea_scope::{ScopeReading, TIME_COLUMN_NAME};
+use crate::trigger_config::DIGITAL_CHANNELS;
+use polars::prelude::*;
+
+/// Index of the capture within the merged frame of `align_captures`
+pub const DEVICE_COLUMN_NAME: &str = "device";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AlignmentError {
+    #[error("No captures to align")]
+    NoReadings,
+
+    #[error("Reference bit {0} out of range (max {max})", max = DIGITAL_CHANNELS - 1)]
+    BitOutOfRange(usize),
+
+    #[error("The marker never changes in capture {device}, it can't be aligned")]
+    NoMarkerEdge { device: usize },
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+}
+
+/// Result of `align_captures`
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    /// Seconds to add to each capture's `time` column to move it onto the time axis of the
+    /// first capture, which has an offset of 0
+    pub offsets: Vec<f64>,
+    /// All captures with corrected `time` and a `device` column holding their index
+    pub merged: DataFrame,
+}
+
+/// Align captures of several devices sharing a digital marker wired to `reference_bit`.
+///
+/// The marker of every capture is cross-correlated with the one of the first capture, so it
+/// needs at least one edge in each. The offset is found to the nearest sample of the first
+/// capture and may be up to half a capture long.
+pub fn align_captures(
+    readings: &[ScopeReading],
+    reference_bit: usize,
+) -> Result<Alignment, AlignmentError> {
+    profiling::scope!("align_captures");
+
+    if reference_bit >= DIGITAL_CHANNELS {
+        return Err(AlignmentError::BitOutOfRange(reference_bit));
+    }
+    let frames = readings
+        .iter()
+        .map(|reading| {
+            let mut df = reading.parse_csv()?.collect()?;
+            ScopeReading::extract_bits(&mut df)?;
+            Ok(df)
+        })
+        .collect::<Result<Vec<_>, PolarsError>>()?;
+
+    let markers = frames
+        .iter()
+        .zip(readings)
+        .enumerate()
+        .map(|(device, (df, reading))| {
+            let bits: Vec<bool> = df
+                .column(&format!("bit_{reference_bit}"))?
+                .bool()?
+                .into_iter()
+                .map(|bit| bit == Some(true))
+                .collect();
+            if bits.windows(2).all(|pair| pair[0] == pair[1]) {
+                return Err(AlignmentError::NoMarkerEdge { device });
+            }
+            let marker: Vec<f64> = bits
+                .into_iter()
+                .map(|bit| if bit { 1.0 } else { -1.0 })
+                .collect();
+            Ok((marker, 1.0 / (reading.effective_msps * 1_000_000.0)))
+        })
+        .collect::<Result<Vec<_>, AlignmentError>>()?;
+
+    let (reference, reference_period) = markers.first().ok_or(AlignmentError::NoReadings)?;
+    let offsets: Vec<f64> = markers
+        .iter()
+        .map(|(marker, period)| {
+            let lag = best_lag(reference, marker, reference_period / period);
+            #[allow(clippy::cast_precision_loss)]
+            let lag = lag as f64;
+            -lag * reference_period
+        })
+        .collect();
+
+    let aligned = frames
+        .into_iter()
+        .zip(&offsets)
+        .zip(0u32..)
+        .map(|((df, offset), device)| {
+            df.lazy().with_columns([
+                (col(TIME_COLUMN_NAME) + lit(*offset)).alias(TIME_COLUMN_NAME),
+                lit(device).alias(DEVICE_COLUMN_NAME),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let merged = concat(aligned, UnionArgs::default())?.collect()?;
+
+    Ok(Alignment { offsets, merged })
+}
+
+/// Lag in samples of `reference` maximizing the correlation of zero-mean signals, e.g. ±1
+/// encoded markers.
+/// `rate_ratio` converts sample indices of `reference` into indices of `other`.
+pub(crate) fn best_lag(reference: &[f64], other: &[f64], rate_ratio: f64) -> isize {
+    let max_lag = isize::try_from(reference.len() / 2).unwrap_or(isize::MAX);
+    best_shift(max_lag, |lag| {
+        let mut sum = 0.0;
+        let mut overlap = 0u32;
+        for (index, a) in (0isize..).zip(reference) {
+            #[allow(clippy::cast_precision_loss)]
+            let position = ((index + lag) as f64 * rate_ratio).round();
+            if position < 0.0 {
+                continue;
+            }
+            #[allow(clippy::cast_sign_loss)]
+            let Some(b) = other.get(position as usize) else {
+                break;
+            };
+            sum += a * b;
+            overlap += 1;
+        }
+        Some(sum / f64::from(overlap.max(1)))
+    })
+    .unwrap_or(0)
+}
+
+/// Lag within `max_lag` either way with the highest `score`, skipping lags scored `None`.
+/// Small lags are tried first, so they win among equally good ones.
+pub(crate) fn best_shift(
+    max_lag: isize,
+    mut score: impl FnMut(isize) -> Option<f64>,
+) -> Option<isize> {
+    let mut best: Option<(f64, isize)> = None;
+    for lag in (0..=max_lag).flat_map(|lag| [lag, -lag]) {
+        let Some(score) = score(lag) else {
+            continue;
+        };
+        if best.is_none_or(|(best, _)| score > best) {
+            best = Some((score, lag));
+        }
+    }
+    best.map(|(_, lag)| lag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    fn marker_reading(edge: usize) -> ScopeReading {
+        let data = (0..2000)
+            .flat_map(|i| {
+                let line: &[u8] = if i >= edge {
+                    b"2048,0x001\r\n"
+                } else {
+                    b"2048,0x000\r\n"
+                };
+                line.iter().copied()
+            })
+            .collect();
+        ScopeReading {
+            effective_msps: 1.0,
+            data,
+            metadata: None,
+            parsed: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn test_align_captures() {
+        let readings = [
+            marker_reading(500),
+            marker_reading(700),
+            marker_reading(450),
+        ];
+        let alignment = align_captures(&readings, 0).unwrap();
+
+        let expected = [0.0, -200e-6, 50e-6];
+        for (offset, expected) in alignment.offsets.iter().zip(expected) {
+            assert!((offset - expected).abs() < 1e-12, "{offset} != {expected}");
+        }
+        assert_eq!(alignment.merged.height(), 6000);
+        assert!(alignment.merged.column(DEVICE_COLUMN_NAME).is_ok());
+    }
+
+    #[test]
+    fn test_align_captures_errors() {
+        assert!(matches!(
+            align_captures(&[], 0),
+            Err(AlignmentError::NoReadings)
+        ));
+        assert!(matches!(
+            align_captures(&[marker_reading(500)], 10),
+            Err(AlignmentError::BitOutOfRange(10))
+        ));
+        assert!(matches!(
+            align_captures(&[marker_reading(500), marker_reading(5000)], 0),
+            Err(AlignmentError::NoMarkerEdge { device: 1 })
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::digital::is_within;
+use crate::flea_scope::CALIBRATED_COLUMN_NAME;
+use crate::math_channel::time_and_values;
+use polars::prelude::*;
+use std::time::Duration;
+
+/// Time a spike of `find_spikes` crosses the threshold
+pub const START_COLUMN_NAME: &str = "start";
+pub const WIDTH_COLUMN_NAME: &str = "width";
+/// Voltage furthest beyond the threshold during a spike
+pub const PEAK_COLUMN_NAME: &str = "peak";
+/// Whether a spike goes up from below the threshold
+pub const RISING_COLUMN_NAME: &str = "rising";
+
+/// Short excursions of the calibrated voltage across `threshold` volts that return
+/// within `max_width`, e.g. spikes on a supply or runts on a clock line.
+///
+/// Spikes go up from a signal below the threshold or down from one above it. One row per
+/// spike with its `start` time, `width`, `peak` voltage and whether it is `rising`.
+/// Excursions in progress at the start or end of the capture are left out. Two spikes
+/// closer together than `max_width` make the gap between them a spike as well. Samples
+/// without a voltage are skipped.
+///
+/// ```rust
+/// use fleascope_rs::analog;
+/// use polars::prelude::*;
+/// use std::time::Duration;
+///
+/// let df = df!(
+///     "time" => [0.0, 1e-6, 2e-6, 3e-6, 4e-6],
+///     "bnc_calibrated" => [3.3, 3.3, 2.1, 3.3, 3.3],
+/// )?;
+/// let spikes = analog::find_spikes(&df, 3.0, Duration::from_micros(1))?;
+/// assert_eq!(spikes.column("peak")?.f64()?.get(0), Some(2.1));
+/// # Ok::<(), PolarsError>(())
+/// ```
+pub fn find_spikes(
+    df: &DataFrame,
+    threshold: f64,
+    max_width: Duration,
+) -> Result<DataFrame, PolarsError> {
+    profiling::scope!("analog::find_spikes");
+
+    let (time, volts) = time_and_values(df, CALIBRATED_COLUMN_NAME)?;
+
+    // Indices at which the signal changes sides of the threshold
+    let crossings: Vec<usize> = (1..volts.len())
+        .filter(|&index| (volts[index] > threshold) != (volts[index - 1] > threshold))
+        .collect();
+
+    let (mut start, mut width, mut peak, mut rising) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for pair in crossings.windows(2) {
+        let (first, end) = (pair[0], pair[1]);
+        if !is_within(time[end] - time[first], max_width) {
+            continue;
+        }
+        let up = volts[first] > threshold;
+        let excursion = volts[first..end].iter().copied();
+        start.push(time[first]);
+        width.push(time[end] - time[first]);
+        peak.push(if up {
+            excursion.fold(f64::NEG_INFINITY, f64::max)
+        } else {
+            excursion.fold(f64::INFINITY, f64::min)
+        });
+        rising.push(up);
+    }
+
+    DataFrame::new(vec![
+        Column::new(START_COLUMN_NAME.into(), start),
+        Column::new(WIDTH_COLUMN_NAME.into(), width),
+        Column::new(PEAK_COLUMN_NAME.into(), peak),
+        Column::new(RISING_COLUMN_NAME.into(), rising),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::TIME_COLUMN_NAME;
+
+    #[test]
+    fn test_find_spikes() {
+        // A 2 µs spike up, a step that isn't a spike, then a 1 µs dip down
+        let df = df!(
+            TIME_COLUMN_NAME => (0..14).map(|i| f64::from(i) / 1e6).collect::<Vec<_>>(),
+            CALIBRATED_COLUMN_NAME => [
+                0.0, 1.2, 1.5, 0.0, 0.0, 0.0, 2.0, 2.0, 2.0, 2.0, 0.2, 2.0, 2.0, 2.0
+            ],
+        )
+        .unwrap();
+
+        let spikes = find_spikes(&df, 1.0, Duration::from_micros(2)).unwrap();
+        let floats = |column: &str| -> Vec<f64> {
+            spikes
+                .column(column)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(floats(START_COLUMN_NAME), [1e-6, 10e-6]);
+        let width = floats(WIDTH_COLUMN_NAME);
+        assert!((width[0] - 2e-6).abs() < 1e-15 && (width[1] - 1e-6).abs() < 1e-15);
+        assert_eq!(floats(PEAK_COLUMN_NAME), [1.5, 0.2]);
+        let rising: Vec<bool> = spikes
+            .column(RISING_COLUMN_NAME)
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(rising, [true, false]);
+
+        let narrow = find_spikes(&df, 1.0, Duration::from_micros(1)).unwrap();
+        assert_eq!(narrow.height(), 1);
+    }
+}
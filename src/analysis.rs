@@ -0,0 +1,249 @@
+use crate::alignment::best_lag;
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+use crate::math_channel::{interpolate, time_and_values};
+use polars::prelude::*;
+
+pub const X_COLUMN_NAME: &str = "x";
+pub const Y_COLUMN_NAME: &str = "y";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnalysisError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("The captures don't overlap in time")]
+    NoOverlap,
+
+    #[error("Column {0:?} is constant in a capture, there is nothing to align")]
+    Constant(String),
+}
+
+/// Pair the calibrated voltages of two captures for XY display, e.g. a Lissajous figure
+/// from two devices or two sequential captures on a shared trigger.
+///
+/// Both are resampled onto the time base of the one with the higher sample rate, linearly
+/// interpolating the other. Only the part both cover is kept. The result has `time`, `x`
+/// (from `df_a`) and `y` (from `df_b`) columns.
+///
+/// ```rust
+/// use fleascope_rs::analysis;
+/// use polars::prelude::*;
+///
+/// let a = df!("time" => [0.0, 1.0, 2.0], "bnc_calibrated" => [0.0, 1.0, 2.0])?;
+/// let b = df!("time" => [0.0, 2.0], "bnc_calibrated" => [4.0, 0.0])?;
+/// let xy = analysis::xy(&a, &b)?;
+/// let y: Vec<_> = xy.column("y")?.f64()?.into_no_null_iter().collect();
+/// assert_eq!(y, [4.0, 2.0, 0.0]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn xy(df_a: &DataFrame, df_b: &DataFrame) -> Result<DataFrame, AnalysisError> {
+    profiling::scope!("analysis::xy");
+
+    let a = time_and_values(df_a, CALIBRATED_COLUMN_NAME)?;
+    let b = time_and_values(df_b, CALIBRATED_COLUMN_NAME)?;
+
+    let (Some(start), Some(end)) = (
+        a.0.first().zip(b.0.first()).map(|(a, b)| a.max(*b)),
+        a.0.last().zip(b.0.last()).map(|(a, b)| a.min(*b)),
+    ) else {
+        return Err(AnalysisError::NoOverlap);
+    };
+    let in_overlap = |time: &[f64]| time.iter().filter(|t| (start..=end).contains(*t)).count();
+    // The capture with more samples in the overlap has the higher sample rate
+    let base = if in_overlap(&a.0) >= in_overlap(&b.0) {
+        &a.0
+    } else {
+        &b.0
+    };
+
+    let (mut time, mut x, mut y) = (Vec::new(), Vec::new(), Vec::new());
+    for &t in base.iter().filter(|t| (start..=end).contains(*t)) {
+        if let (Some(a), Some(b)) = (interpolate(&a.0, &a.1, t), interpolate(&b.0, &b.1, t)) {
+            time.push(t);
+            x.push(a);
+            y.push(b);
+        }
+    }
+    if time.is_empty() {
+        return Err(AnalysisError::NoOverlap);
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new(TIME_COLUMN_NAME.into(), time),
+        Column::new(X_COLUMN_NAME.into(), x),
+        Column::new(Y_COLUMN_NAME.into(), y),
+    ])?)
+}
+
+/// Time shift between two captures of the same signal, found by cross-correlating
+/// `column`, e.g. to average auto-triggered captures or to compare a stimulus with its
+/// response.
+///
+/// Returns the seconds to add to `df_b`'s `time` to line it up with `df_a`, and both
+/// captures cut to the time they share after shifting `df_b`. The shift is found to the
+/// nearest sample of `df_a` and may be up to half of it long. The sample rates may differ.
+///
+/// ```rust
+/// use fleascope_rs::analysis;
+/// use polars::prelude::*;
+///
+/// let time: Vec<f64> = (0..100).map(f64::from).collect();
+/// let pulse = |start: f64| -> Vec<f64> {
+///     time.iter().map(|t| if (start..start + 10.0).contains(t) { 1.0 } else { 0.0 }).collect()
+/// };
+/// let a = df!("time" => &time, "bnc_calibrated" => pulse(40.0))?;
+/// let b = df!("time" => &time, "bnc_calibrated" => pulse(55.0))?;
+///
+/// let (offset, [a, b]) = analysis::align(&a, &b, "bnc_calibrated")?;
+/// assert_eq!(offset, -15.0);
+/// assert_eq!(a.height(), b.height());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn align(
+    df_a: &DataFrame,
+    df_b: &DataFrame,
+    column: &str,
+) -> Result<(f64, [DataFrame; 2]), AnalysisError> {
+    profiling::scope!("analysis::align");
+
+    let signal = |df: &DataFrame| -> Result<(Vec<f64>, f64), AnalysisError> {
+        let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+        let (Some(first), Some(last)) = (time.first(), time.last()) else {
+            return Err(AnalysisError::NoOverlap);
+        };
+        let values = df.column(column)?.cast(&DataType::Float64)?;
+        let values = values.f64()?;
+        // Zero mean and unit variance, so level and gain differences don't matter
+        #[allow(clippy::cast_precision_loss)]
+        let count = (values.len() - values.null_count()) as f64;
+        let mean = values.into_iter().flatten().sum::<f64>() / count;
+        let std = (values
+            .into_iter()
+            .flatten()
+            .map(|v| (v - mean).powi(2))
+            .sum::<f64>()
+            / count)
+            .sqrt();
+        if std.is_nan() || std <= 0.0 {
+            return Err(AnalysisError::Constant(column.to_string()));
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let period = (last - first) / (time.len().max(2) - 1) as f64;
+        // Gaps are kept as zero, so they don't shift the samples or sway the correlation
+        let normalized = values
+            .into_iter()
+            .map(|v| v.map_or(0.0, |v| (v - mean) / std))
+            .collect();
+        Ok((normalized, period))
+    };
+    let (a, a_period) = signal(df_a)?;
+    let (b, b_period) = signal(df_b)?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let lag = best_lag(&a, &b, a_period / b_period) as f64;
+    let offset = -lag * a_period;
+
+    let shifted = df_b
+        .clone()
+        .lazy()
+        .with_column((col(TIME_COLUMN_NAME) + lit(offset)).alias(TIME_COLUMN_NAME))
+        .collect()?;
+    let span = |df: &DataFrame| -> Result<(f64, f64), AnalysisError> {
+        let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+        time.first()
+            .zip(time.last())
+            .ok_or(AnalysisError::NoOverlap)
+    };
+    let ((a_start, a_end), (b_start, b_end)) = (span(df_a)?, span(&shifted)?);
+    // A little slack, so rounding in the shifted times doesn't cut off the edge samples
+    let slack = a_period.min(b_period) * 1e-6;
+    let (start, end) = (a_start.max(b_start) - slack, a_end.min(b_end) + slack);
+    let overlap = |df: DataFrame| {
+        let time = col(TIME_COLUMN_NAME);
+        df.lazy()
+            .filter(time.clone().gt_eq(lit(start)).and(time.lt_eq(lit(end))))
+            .collect()
+    };
+    Ok((offset, [overlap(df_a.clone())?, overlap(shifted)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(time: &[f64], volts: &[f64]) -> DataFrame {
+        df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts).unwrap()
+    }
+
+    fn floats(df: &DataFrame, column: &str) -> Vec<f64> {
+        df.column(column)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_xy_resamples_onto_faster_capture() {
+        // b samples twice as fast and starts later
+        let a = capture(&[0.0, 2.0, 4.0, 6.0], &[0.0, 2.0, 4.0, 6.0]);
+        let b = capture(&[1.0, 2.0, 3.0, 4.0, 5.0], &[10.0, 20.0, 30.0, 40.0, 50.0]);
+
+        let xy = xy(&a, &b).unwrap();
+        assert_eq!(floats(&xy, TIME_COLUMN_NAME), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(floats(&xy, X_COLUMN_NAME), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(floats(&xy, Y_COLUMN_NAME), [10.0, 20.0, 30.0, 40.0, 50.0]);
+
+        // The order only swaps the axes
+        let yx = super::xy(&b, &a).unwrap();
+        assert_eq!(floats(&yx, X_COLUMN_NAME), floats(&xy, Y_COLUMN_NAME));
+    }
+
+    #[test]
+    fn test_xy_without_overlap() {
+        let a = capture(&[0.0, 1.0], &[0.0, 1.0]);
+        let b = capture(&[2.0, 3.0], &[0.0, 1.0]);
+        assert!(matches!(xy(&a, &b), Err(AnalysisError::NoOverlap)));
+        assert!(matches!(
+            xy(&a, &b.head(Some(0))),
+            Err(AnalysisError::NoOverlap)
+        ));
+    }
+
+    #[test]
+    fn test_align() {
+        // b samples twice as fast, and its pulse comes 20 µs later
+        let pulse = |rate: f64, start: f64, len: u32| {
+            let time: Vec<f64> = (0..len).map(|i| f64::from(i) / rate).collect();
+            let volts: Vec<f64> = time
+                .iter()
+                .map(|t| {
+                    if (start..start + 10e-6).contains(t) {
+                        3.3
+                    } else {
+                        0.5
+                    }
+                })
+                .collect();
+            capture(&time, &volts)
+        };
+        // Starting between samples keeps rounding from moving the edges
+        let a = pulse(1e6, 40.25e-6, 100);
+        let b = pulse(2e6, 60.25e-6, 200);
+
+        let (offset, [a, b]) = align(&a, &b, CALIBRATED_COLUMN_NAME).unwrap();
+        assert!((offset + 20e-6).abs() < 1e-12, "{offset}");
+        // b now starts 20 µs before a, a loses the 20 µs at its end
+        assert_eq!(a.height(), 80);
+        assert_eq!(b.height(), 160);
+        let b_time = floats(&b, TIME_COLUMN_NAME);
+        assert!(b_time[0].abs() < 1e-12 && (b_time[159] - 79.5e-6).abs() < 1e-12);
+
+        let flat = capture(&[0.0, 1.0], &[1.0, 1.0]);
+        assert!(matches!(
+            align(&flat, &flat, CALIBRATED_COLUMN_NAME),
+            Err(AnalysisError::Constant(_))
+        ));
+    }
+}
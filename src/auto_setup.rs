@@ -0,0 +1,142 @@
+use crate::flea_scope::{
+    CaptureConfig, CaptureConfigError, FleaProbe, IdleFleaScope, InvalidCaptureConfig,
+    CALIBRATED_COLUMN_NAME,
+};
+use crate::measurements::rising_crossing_frequency;
+use crate::trigger_config::{AnalogTrigger, DigitalTrigger, TriggerConfig};
+use crate::units::Volts;
+use polars::prelude::*;
+use std::time::Duration;
+
+/// Time frame of the coarse capture, long enough to see a couple of periods of 50 Hz
+const COARSE_TIME_FRAME: Duration = Duration::from_millis(100);
+/// Number of signal periods the recommended time frame should show
+const PERIODS_IN_VIEW: f64 = 4.0;
+const MIN_TIME_FRAME: Duration = Duration::from_micros(111);
+const MAX_TIME_FRAME: Duration = Duration::from_millis(3490);
+/// Signals with a smaller peak-to-peak amplitude are treated as DC
+const MIN_AMPLITUDE_VOLTS: f64 = 0.05;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutoSetupError {
+    #[error("Invalid capture configuration: {0}")]
+    CaptureConfig(#[from] CaptureConfigError),
+
+    #[error("{0}")]
+    InvalidConfig(#[from] InvalidCaptureConfig),
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+}
+
+/// Properties of a signal estimated from a capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalEstimate {
+    pub min_volts: f64,
+    pub max_volts: f64,
+    /// `None` for DC or signals with less than two periods in the capture
+    pub frequency_hz: Option<f64>,
+}
+
+impl SignalEstimate {
+    /// Estimate amplitude and frequency from evenly spaced samples
+    pub fn from_samples(volts: &[f64], sample_rate: f64) -> Option<Self> {
+        let min_volts = volts.iter().copied().reduce(f64::min)?;
+        let max_volts = volts.iter().copied().reduce(f64::max)?;
+
+        let frequency_hz = if max_volts - min_volts >= MIN_AMPLITUDE_VOLTS {
+            rising_crossing_frequency(volts, sample_rate).0
+        } else {
+            None
+        };
+
+        Some(Self {
+            min_volts,
+            max_volts,
+            frequency_hz,
+        })
+    }
+
+    /// Time frame showing a few periods of the signal
+    pub fn recommended_time_frame(&self) -> Duration {
+        self.frequency_hz.map_or(COARSE_TIME_FRAME, |frequency| {
+            Duration::try_from_secs_f64(PERIODS_IN_VIEW / frequency)
+                .unwrap_or(MAX_TIME_FRAME)
+                .clamp(MIN_TIME_FRAME, MAX_TIME_FRAME)
+        })
+    }
+
+    /// Level halfway between the signal's extremes
+    pub fn trigger_level(&self) -> Result<Volts, CaptureConfigError> {
+        Volts::new(f64::midpoint(self.min_volts, self.max_volts))
+    }
+}
+
+impl IdleFleaScope {
+    /// Like the "Auto" button of a bench scope: take a coarse capture, estimate the
+    /// signal's frequency and amplitude and recommend a time frame showing a few periods,
+    /// triggered on a rising edge at 50 %.
+    ///
+    /// DC signals get an untriggered configuration.
+    pub fn auto_setup(&mut self, probe: &FleaProbe) -> Result<CaptureConfig, AutoSetupError> {
+        profiling::scope!("IdleFleaScope::auto_setup");
+
+        let untriggered = || {
+            DigitalTrigger::start_capturing_when()
+                .is_matching()
+                .into_trigger_fields()
+        };
+        let reading = self.read_sync(COARSE_TIME_FRAME, untriggered(), None)?;
+        let df = probe.apply_calibration(reading.parse_csv()?).collect()?;
+        let volts: Vec<f64> = df
+            .column(CALIBRATED_COLUMN_NAME)?
+            .f64()?
+            .into_no_null_iter()
+            .collect();
+
+        let builder = CaptureConfig::builder();
+        let Some(estimate) = SignalEstimate::from_samples(&volts, reading.effective_msps * 1e6)
+        else {
+            return Ok(builder.time_frame(COARSE_TIME_FRAME).build()?);
+        };
+        log::debug!("Auto setup estimate: {estimate:?}");
+
+        let builder = builder.time_frame(estimate.recommended_time_frame());
+        let config = if estimate.frequency_hz.is_some() {
+            let trigger = AnalogTrigger::start_capturing_when(estimate.trigger_level()?)
+                .rising_edge()
+                .into_trigger(probe)?;
+            builder.trigger(trigger).build()?
+        } else {
+            builder.build()?
+        };
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_square_wave() {
+        // 1 kHz square wave between 0 V and 3.3 V, sampled at 100 kHz
+        let volts: Vec<f64> = (0..1000)
+            .map(|i| if (i / 50) % 2 == 0 { 0.0 } else { 3.3 })
+            .collect();
+        let estimate = SignalEstimate::from_samples(&volts, 100_000.0).unwrap();
+
+        assert!((estimate.frequency_hz.unwrap() - 1000.0).abs() < 1e-9);
+        assert!((estimate.trigger_level().unwrap().get() - 1.65).abs() < 1e-9);
+        assert_eq!(estimate.recommended_time_frame(), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_estimate_dc() {
+        let volts = vec![1.0, 1.01, 0.99, 1.0];
+        let estimate = SignalEstimate::from_samples(&volts, 100_000.0).unwrap();
+        assert_eq!(estimate.frequency_hz, None);
+        assert_eq!(estimate.recommended_time_frame(), COARSE_TIME_FRAME);
+        assert!(SignalEstimate::from_samples(&[], 1.0).is_none());
+    }
+}
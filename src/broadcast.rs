@@ -0,0 +1,275 @@
+#[cfg(feature = "dataframe")]
+use crate::capture_frame::CaptureFrame;
+#[cfg(feature = "dataframe")]
+use crate::sink::{CaptureSink, SinkError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+#[cfg(feature = "dataframe")]
+use std::thread::{self, JoinHandle};
+
+/// What to do when a consumer falls behind and its queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the consumer, slowing down the acquisition. For consumers that must not
+    /// lose data, e.g. a disk writer.
+    Block,
+    /// Discard the new item. The consumer sees a contiguous prefix of the stream.
+    DropNewest,
+    /// Discard the oldest queued item. For consumers only interested in recent data,
+    /// e.g. a UI.
+    DropOldest,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    dropped: u64,
+    publisher_gone: bool,
+    subscriber_gone: bool,
+}
+
+struct Queue<T> {
+    state: Mutex<State<T>>,
+    changed: Condvar,
+    capacity: usize,
+    policy: Backpressure,
+}
+
+impl<T> Queue<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // A panicking consumer must not take the acquisition down with it
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Returns `false` if the subscriber is gone
+    fn push(&self, item: T) -> bool {
+        let mut state = self.lock();
+        if self.policy == Backpressure::Block {
+            while state.items.len() >= self.capacity && !state.subscriber_gone {
+                state = self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+            }
+        }
+        if state.subscriber_gone {
+            return false;
+        }
+
+        if state.items.len() >= self.capacity {
+            state.dropped += 1;
+            match self.policy {
+                Backpressure::DropNewest => return true,
+                Backpressure::DropOldest => {
+                    state.items.pop_front();
+                }
+                Backpressure::Block => unreachable!("Waited for space above"),
+            }
+        }
+        state.items.push_back(item);
+        drop(state);
+        self.changed.notify_all();
+        true
+    }
+}
+
+/// Receiving end of a `Broadcast`
+pub struct Subscription<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Subscription<T> {
+    /// Wait for the next item. `None` once the broadcast is dropped and the queue is drained.
+    pub fn recv(&self) -> Option<T> {
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                drop(state);
+                self.queue.changed.notify_all();
+                return Some(item);
+            }
+            if state.publisher_gone {
+                return None;
+            }
+            state = self
+                .queue
+                .changed
+                .wait(state)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        let item = self.queue.lock().items.pop_front();
+        self.queue.changed.notify_all();
+        item
+    }
+
+    /// Number of items discarded because this subscription fell behind
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.queue.lock().subscriber_gone = true;
+        self.queue.changed.notify_all();
+    }
+}
+
+/// Fans a single acquisition stream out to several consumers (e.g. UI, disk and network),
+/// each with its own queue and backpressure policy.
+///
+/// ```rust
+/// use fleascope_rs::broadcast::{Backpressure, Broadcast};
+///
+/// let mut broadcast = Broadcast::new();
+/// let ui = broadcast.subscribe(1, Backpressure::DropOldest);
+/// let disk = broadcast.subscribe(16, Backpressure::Block);
+///
+/// broadcast.publish(1);
+/// broadcast.publish(2);
+/// drop(broadcast);
+///
+/// assert_eq!(ui.recv(), Some(2));
+/// assert_eq!(disk.recv(), Some(1));
+/// assert_eq!(disk.recv(), Some(2));
+/// assert_eq!(disk.recv(), None);
+/// ```
+pub struct Broadcast<T> {
+    queues: Vec<Arc<Queue<T>>>,
+}
+
+impl<T> Default for Broadcast<T> {
+    fn default() -> Self {
+        Self { queues: Vec::new() }
+    }
+}
+
+impl<T: Clone> Broadcast<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a consumer with a queue of up to `capacity` items
+    pub fn subscribe(&mut self, capacity: usize, policy: Backpressure) -> Subscription<T> {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                dropped: 0,
+                publisher_gone: false,
+                subscriber_gone: false,
+            }),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+        });
+        self.queues.push(Arc::clone(&queue));
+        Subscription { queue }
+    }
+
+    /// Hand `item` to every consumer. Blocks while a `Backpressure::Block` consumer is full.
+    pub fn publish(&mut self, item: T) {
+        profiling::scope!("Broadcast::publish");
+
+        self.queues.retain(|queue| queue.push(item.clone()));
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.queues.len()
+    }
+}
+
+impl<T> Drop for Broadcast<T> {
+    fn drop(&mut self) {
+        for queue in &self.queues {
+            queue.lock().publisher_gone = true;
+            queue.changed.notify_all();
+        }
+    }
+}
+
+#[cfg(feature = "dataframe")]
+impl Broadcast<CaptureFrame> {
+    /// Drain a new subscription into `sink` on a background thread.
+    /// The thread ends when the broadcast is dropped or the sink fails.
+    pub fn spawn_sink(
+        &mut self,
+        mut sink: impl CaptureSink + 'static,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> JoinHandle<Result<(), SinkError>> {
+        let subscription = self.subscribe(capacity, policy);
+        thread::spawn(move || {
+            while let Some(frame) = subscription.recv() {
+                sink.consume(&frame)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "dataframe")]
+impl CaptureSink for Broadcast<CaptureFrame> {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        self.publish(frame.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_drop_policies() {
+        let mut broadcast = Broadcast::new();
+        let newest = broadcast.subscribe(2, Backpressure::DropNewest);
+        let oldest = broadcast.subscribe(2, Backpressure::DropOldest);
+
+        for i in 0..5 {
+            broadcast.publish(i);
+        }
+
+        assert_eq!(newest.dropped(), 3);
+        assert_eq!(oldest.dropped(), 3);
+        assert_eq!((newest.try_recv(), newest.try_recv()), (Some(0), Some(1)));
+        assert_eq!((oldest.try_recv(), oldest.try_recv()), (Some(3), Some(4)));
+        assert_eq!(oldest.try_recv(), None);
+    }
+
+    #[test]
+    fn test_block_waits_for_consumer() {
+        let mut broadcast = Broadcast::new();
+        let subscription = broadcast.subscribe(1, Backpressure::Block);
+
+        let consumer = thread::spawn(move || {
+            let mut received = Vec::new();
+            while let Some(i) = subscription.recv() {
+                received.push(i);
+            }
+            received
+        });
+        for i in 0..100 {
+            broadcast.publish(i);
+        }
+        drop(broadcast);
+
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dropped_subscription_is_removed() {
+        let mut broadcast = Broadcast::new();
+        let subscription = broadcast.subscribe(1, Backpressure::Block);
+        broadcast.publish(1);
+        drop(subscription);
+
+        // Must not block on the full queue of the departed consumer
+        broadcast.publish(2);
+        assert_eq!(broadcast.subscriber_count(), 0);
+    }
+}
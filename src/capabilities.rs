@@ -0,0 +1,195 @@
+use std::fmt;
+
+/// Firmware version as reported by the `ver` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Find the first `major.minor[.patch]` in a `ver` response, ignoring any banner text
+    pub fn parse(response: &str) -> Option<Self> {
+        response
+            .split(|c: char| !c.is_ascii_digit() && c != '.')
+            .find_map(|word| {
+                let mut parts = word.split('.').map(str::parse::<u32>);
+                let major = parts.next()?.ok()?;
+                let minor = parts.next()?.ok()?;
+                let patch = parts.next().map_or(Some(0), Result::ok)?;
+                Some(Self::new(major, minor, patch))
+            })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Oldest firmware the command set of this library is written for. `IdleFleaScope::connect`
+/// rejects older firmware, `IdleFleaScope::connect_allow_unsupported` only warns.
+pub const MIN_SUPPORTED_FIRMWARE: FirmwareVersion = FirmwareVersion::new(1, 0, 0);
+
+/// Device features that depend on the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Raw sample streaming via the `stream` command
+    Streaming,
+    /// Captures transferred as binary instead of CSV
+    BinaryTransfer,
+    /// User-defined waveforms for the signal generator
+    ArbitraryWaveform,
+    /// Samples from before the trigger. Without it, `CaptureStream` emulates pre-trigger
+    /// captures, see `CaptureConfigBuilder::trigger_position`
+    PreTrigger,
+    /// Amplitude, offset and duty cycle for the signal generator
+    WaveformShaping,
+}
+
+impl Feature {
+    pub const ALL: [Self; 5] = [
+        Self::Streaming,
+        Self::BinaryTransfer,
+        Self::ArbitraryWaveform,
+        Self::PreTrigger,
+        Self::WaveformShaping,
+    ];
+
+    /// Oldest firmware supporting the feature. `None` if no released firmware does.
+    ///
+    /// This is the capability map: add an entry here when a firmware release adds a feature.
+    pub const fn available_since(self) -> Option<FirmwareVersion> {
+        match self {
+            Self::Streaming => Some(FirmwareVersion::new(0, 0, 0)),
+            Self::BinaryTransfer
+            | Self::ArbitraryWaveform
+            | Self::PreTrigger
+            | Self::WaveformShaping => None,
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Streaming => "streaming",
+            Self::BinaryTransfer => "binary transfer",
+            Self::ArbitraryWaveform => "arbitrary waveforms",
+            Self::PreTrigger => "pre-trigger capture",
+            Self::WaveformShaping => "waveform amplitude, offset and duty cycle",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Firmware {version} does not support {feature}")]
+pub struct UnsupportedByFirmware {
+    pub feature: Feature,
+    /// The raw `ver` response, as the version may not have been parseable
+    pub version: String,
+}
+
+/// Features supported by a connected device, see `IdleFleaScope::capabilities`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    version: Option<FirmwareVersion>,
+    raw_version: String,
+}
+
+impl Capabilities {
+    /// Capabilities for a `ver` response. Unrecognized versions only get the features every
+    /// firmware has.
+    pub fn from_version_response(response: &str) -> Self {
+        Self {
+            version: FirmwareVersion::parse(response),
+            raw_version: response.trim().to_string(),
+        }
+    }
+
+    pub fn version(&self) -> Option<FirmwareVersion> {
+        self.version
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        let version = self.version.unwrap_or(FirmwareVersion::new(0, 0, 0));
+        feature
+            .available_since()
+            .is_some_and(|since| version >= since)
+    }
+
+    /// Whether the firmware is at least `MIN_SUPPORTED_FIRMWARE`. Unrecognized versions
+    /// aren't.
+    pub fn is_firmware_supported(&self) -> bool {
+        self.version
+            .is_some_and(|version| version >= MIN_SUPPORTED_FIRMWARE)
+    }
+
+    /// Fail with `UnsupportedByFirmware` instead of sending a command the device won't
+    /// understand
+    pub fn require(&self, feature: Feature) -> Result<(), UnsupportedByFirmware> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(UnsupportedByFirmware {
+                feature,
+                version: self.raw_version.clone(),
+            })
+        }
+    }
+}
+
+/// Identity of a connected device, see `IdleFleaScope::device_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Parsed firmware version, `None` if the `ver` response wasn't recognized
+    pub firmware: Option<FirmwareVersion>,
+    /// The `ver` response without surrounding whitespace
+    pub raw_version: String,
+    pub hostname: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            FirmwareVersion::parse("FleaScope v1.4\r\n"),
+            Some(FirmwareVersion::new(1, 4, 0))
+        );
+        assert_eq!(
+            FirmwareVersion::parse("StickOS 2.10.3 (c) 2024"),
+            Some(FirmwareVersion::new(2, 10, 3))
+        );
+        assert_eq!(FirmwareVersion::parse("unknown"), None);
+    }
+
+    #[test]
+    fn test_require() {
+        let capabilities = Capabilities::from_version_response("garbage");
+        assert!(capabilities.require(Feature::Streaming).is_ok());
+        let error = capabilities.require(Feature::PreTrigger).unwrap_err();
+        assert_eq!(error.feature, Feature::PreTrigger);
+        assert_eq!(error.version, "garbage");
+    }
+
+    #[test]
+    fn test_is_firmware_supported() {
+        assert!(Capabilities::from_version_response("FleaScope v1.4\r\n").is_firmware_supported());
+        assert!(!Capabilities::from_version_response("FleaScope v0.9").is_firmware_supported());
+        assert!(!Capabilities::from_version_response("garbage").is_firmware_supported());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::channel_labels::ChannelLabels;
+use crate::flea_scope::{FleaProbe, ScopeReading, BITMAP_COLUMN_NAME};
+use crate::measurements::VoltageMeasurement;
+use polars::prelude::*;
+
+/// Name of the column holding digital channel `bit`
+pub fn bit_column_name(bit: usize) -> String {
+    format!("bit_{bit}")
+}
+
+/// Lazy expression extracting a single digital channel from the bitmap column
+pub fn bit_expr(bit: usize) -> Expr {
+    col(BITMAP_COLUMN_NAME)
+        .and(lit(1u32 << bit))
+        .neq(lit(0u32))
+        // Default to false for null values
+        .fill_null(lit(false))
+        .alias(bit_column_name(bit))
+}
+
+/// A capture on its way to a `DataFrame`.
+///
+/// Each combinator only extends the polars query plan; nothing is materialized until
+/// `collect()` is called, so chaining calibration and bit extraction parses the
+/// reading exactly once.
+#[derive(Clone)]
+#[must_use]
+pub struct CaptureFrame {
+    lazy: LazyFrame,
+}
+
+impl CaptureFrame {
+    pub fn new(lazy: LazyFrame) -> Self {
+        Self { lazy }
+    }
+
+    /// Add the calibrated voltage column (and the probe's unit conversion, if any)
+    pub fn calibrated(self, probe: &FleaProbe) -> Self {
+        Self::new(probe.apply_calibration(self.lazy))
+    }
+
+    /// Add a boolean `bit_<n>` column for every selected digital channel
+    pub fn bits(self, selection: &[usize]) -> Self {
+        let columns: Vec<Expr> = selection.iter().map(|&bit| bit_expr(bit)).collect();
+        Self::new(self.lazy.with_columns(columns))
+    }
+
+    /// Add a boolean column for every labeled digital channel, named after its label
+    pub fn labeled_bits(self, labels: &ChannelLabels) -> Self {
+        let columns: Vec<Expr> = labels
+            .iter()
+            .map(|(bit, label)| bit_expr(bit).alias(label))
+            .collect();
+        Self::new(self.lazy.with_columns(columns))
+    }
+
+    /// Reduce the capture to one row with a column per measurement of the calibrated
+    /// voltage, see `calibrated`
+    pub fn measurements(self, set: &[VoltageMeasurement]) -> Self {
+        let columns: Vec<Expr> = set.iter().map(|measurement| measurement.expr()).collect();
+        Self::new(self.lazy.select(columns))
+    }
+
+    /// Apply an arbitrary transformation to the underlying plan
+    pub fn map<F>(self, f: F) -> Self
+    where
+        F: FnOnce(LazyFrame) -> LazyFrame,
+    {
+        Self::new(f(self.lazy))
+    }
+
+    pub fn into_lazy(self) -> LazyFrame {
+        self.lazy
+    }
+
+    /// Execute the accumulated plan
+    pub fn collect(self) -> Result<DataFrame, PolarsError> {
+        profiling::scope!("CaptureFrame::collect");
+
+        self.lazy.collect()
+    }
+
+    /// The first `max_rows` rows as CSV text, small enough to paste into a chat or wiki
+    pub fn to_csv_snippet(self, max_rows: u32) -> Result<String, PolarsError> {
+        profiling::scope!("CaptureFrame::to_csv_snippet");
+
+        let mut df = self.lazy.limit(max_rows).collect()?;
+        let mut csv = Vec::new();
+        CsvWriter::new(&mut csv).finish(&mut df)?;
+        Ok(String::from_utf8_lossy(&csv).into_owned())
+    }
+
+    /// Copy `to_csv_snippet` to the system clipboard, to paste a capture into a chat or wiki.
+    ///
+    /// On Linux, the clipboard is served by the process that set it, so the contents are
+    /// only available while the returned handle is alive. Keep it until the snippet was
+    /// pasted, e.g. for the rest of the program.
+    #[cfg(feature = "clipboard")]
+    #[must_use = "on Linux, the copied snippet is lost when the clipboard handle is dropped"]
+    pub fn copy_csv_snippet(self, max_rows: u32) -> Result<arboard::Clipboard, ClipboardError> {
+        profiling::scope!("CaptureFrame::copy_csv_snippet");
+
+        let snippet = self.to_csv_snippet(max_rows)?;
+        let mut clipboard = arboard::Clipboard::new()?;
+        clipboard.set_text(snippet)?;
+        Ok(clipboard)
+    }
+}
+
+#[cfg(feature = "clipboard")]
+#[derive(Debug, thiserror::Error)]
+pub enum ClipboardError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not access the clipboard: {0}")]
+    Clipboard(#[from] arboard::Error),
+}
+
+impl From<LazyFrame> for CaptureFrame {
+    fn from(lazy: LazyFrame) -> Self {
+        Self::new(lazy)
+    }
+}
+
+impl ScopeReading {
+    /// Start a lazy processing pipeline on this reading
+    pub fn frame(&self) -> Result<CaptureFrame, PolarsError> {
+        Ok(CaptureFrame::new(self.parse_csv()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::{ProbeType, CALIBRATED_COLUMN_NAME};
+    use std::sync::OnceLock;
+
+    fn reading() -> ScopeReading {
+        ScopeReading {
+            effective_msps: 1.0,
+            metadata: None,
+            parsed: OnceLock::new(),
+            data: b"2048,0x000\n2148,0x005\n2248,0x3ff\n".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_bits() {
+        let df = reading()
+            .frame()
+            .unwrap()
+            .bits(&[0, 1, 9])
+            .collect()
+            .unwrap();
+
+        let bit = |n| -> Vec<bool> {
+            df.column(&bit_column_name(n))
+                .unwrap()
+                .bool()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(bit(0), vec![false, true, true]);
+        assert_eq!(bit(1), vec![false, false, true]);
+        assert_eq!(bit(9), vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_labeled_bits() {
+        let labels = ChannelLabels::new()
+            .label(0, "CS")
+            .unwrap()
+            .label(2, "MISO")
+            .unwrap();
+        let df = reading()
+            .frame()
+            .unwrap()
+            .labeled_bits(&labels)
+            .collect()
+            .unwrap();
+
+        let miso: Vec<bool> = df
+            .column("MISO")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(miso, vec![false, true, true]);
+        assert!(df.column("CS").is_ok());
+        assert!(df.column(&bit_column_name(1)).is_err());
+    }
+
+    #[test]
+    fn test_calibrated() {
+        let mut probe = FleaProbe::new(ProbeType::X1);
+        probe.set_calibration(2048.0, 1000.0);
+
+        let df = reading()
+            .frame()
+            .unwrap()
+            .calibrated(&probe)
+            .bits(&[2])
+            .collect()
+            .unwrap();
+
+        let volts: Vec<f64> = df
+            .column(CALIBRATED_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(volts, vec![0.0, 0.33, 0.66]);
+        assert!(df.column(&bit_column_name(2)).is_ok());
+    }
+
+    #[test]
+    fn test_measurements() {
+        let mut probe = FleaProbe::new(ProbeType::X1);
+        probe.set_calibration(2048.0, 1000.0);
+
+        let df = reading()
+            .frame()
+            .unwrap()
+            .calibrated(&probe)
+            .measurements(&VoltageMeasurement::ALL)
+            .collect()
+            .unwrap();
+        assert_eq!(df.height(), 1);
+        let value = |measurement: VoltageMeasurement| {
+            df.column(measurement.column_name())
+                .unwrap()
+                .f64()
+                .unwrap()
+                .get(0)
+                .unwrap()
+        };
+        assert!((value(VoltageMeasurement::Min) - 0.0).abs() < 1e-9);
+        assert!((value(VoltageMeasurement::Max) - 0.66).abs() < 1e-9);
+        assert!((value(VoltageMeasurement::Vpp) - 0.66).abs() < 1e-9);
+        assert!((value(VoltageMeasurement::Vavg) - 0.33).abs() < 1e-9);
+        let vrms = 0.33f64.hypot(0.66) / 3f64.sqrt();
+        assert!((value(VoltageMeasurement::Vrms) - vrms).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_csv_snippet() {
+        let csv = reading()
+            .frame()
+            .unwrap()
+            .bits(&[0])
+            .to_csv_snippet(2)
+            .unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().next().unwrap().ends_with("bit_0"));
+    }
+}
@@ -0,0 +1,587 @@
+use crate::flea_scope::{
+    CaptureConfig, FleaProbe, IdleFleaScope, ReadingFleaScope, ScopeReading, TIME_COLUMN_NAME,
+};
+use crate::nth_event::NthEvent;
+use crate::serial_terminal::{FleaTerminalError, ReadInterrupted};
+use crate::soft_trigger::{emulated_conditions_hold, pre_trigger_window};
+use polars::prelude::*;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Captures that may be waiting for the worker at the same time. Bounds memory when the
+/// consumer is slower than the device.
+const MAX_PENDING: usize = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureStreamError {
+    #[error("Failure while parsing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Connection lost while capturing")]
+    ConnectionLost,
+
+    #[error("Device rebooted while capturing")]
+    DeviceRebooted,
+
+    #[error("Serial terminal error: {0}")]
+    Terminal(#[from] FleaTerminalError),
+}
+
+impl From<ReadInterrupted> for CaptureStreamError {
+    fn from(e: ReadInterrupted) -> Self {
+        match e {
+            ReadInterrupted::ConnectionLost => Self::ConnectionLost,
+            ReadInterrupted::DeviceRebooted(_) => Self::DeviceRebooted,
+        }
+    }
+}
+
+// Only ever one of these per stream, boxing the scope would buy nothing
+#[allow(clippy::large_enum_variant)]
+enum State {
+    Idle(IdleFleaScope),
+    Reading(ReadingFleaScope),
+    Failed,
+}
+
+/// Back-to-back captures with parsing off the acquisition path.
+///
+/// The next capture is armed as soon as the previous transfer completes, and readings are
+/// parsed on a worker thread while the device captures, so neither waits for the other.
+///
+/// Captures triggered by noise are dropped if the trigger has a hysteresis, see
+/// `AnalogTriggerBuilder::hysteresis`, as are captures in which the digital qualifier
+/// didn't match at the trigger, see `AnalogTriggerBuilder::qualified_by`, and captures in
+/// which a digital pattern wasn't held, see `BitTriggerBuilder::held_for`. Pre-trigger
+/// captures are cropped around the trigger, see `CaptureConfigBuilder::trigger_position`.
+///
+/// ```rust,no_run
+/// use fleascope_rs::capture_stream::CaptureStream;
+/// use fleascope_rs::{CaptureConfig, IdleFleaScope};
+/// use std::time::Duration;
+///
+/// let (scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = CaptureConfig::builder().time_frame(Duration::from_millis(1)).build()?;
+/// let mut stream = CaptureStream::new(scope, &config, Some(&x1));
+/// for df in stream.by_ref().take(100) {
+///     println!("{} samples", df?.height());
+/// }
+/// let scope = stream.stop()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct CaptureStream {
+    state: State,
+    config: CaptureConfig,
+    readings: Option<Sender<ScopeReading>>,
+    /// `None` for captures the worker skipped
+    frames: Receiver<Option<Result<DataFrame, PolarsError>>>,
+    worker: Option<JoinHandle<()>>,
+    /// Captures handed to the worker whose frames weren't returned yet
+    pending: usize,
+    error: Option<CaptureStreamError>,
+    /// End of the holdoff after the last capture, see `CaptureConfigBuilder::holdoff`
+    holdoff_until: Option<Instant>,
+}
+
+impl CaptureStream {
+    /// Start streaming. Frames are calibrated with `probe` if given, raw otherwise.
+    pub fn new(scope: IdleFleaScope, config: &CaptureConfig, probe: Option<&FleaProbe>) -> Self {
+        Self::spawn(scope, config, probe, None)
+    }
+
+    /// Like `new`, but only yields the captures in which `counter` sees its Nth event.
+    /// `config` should be untriggered, see `NthEvent`.
+    pub fn nth_event(
+        scope: IdleFleaScope,
+        config: &CaptureConfig,
+        probe: Option<&FleaProbe>,
+        counter: NthEvent,
+    ) -> Self {
+        Self::spawn(scope, config, probe, Some(counter))
+    }
+
+    fn spawn(
+        scope: IdleFleaScope,
+        config: &CaptureConfig,
+        probe: Option<&FleaProbe>,
+        mut counter: Option<NthEvent>,
+    ) -> Self {
+        let (readings, reading_rx) = mpsc::channel::<ScopeReading>();
+        let (frame_tx, frames) = mpsc::channel();
+        let probe = probe.cloned();
+        let (emulated, pre_trigger) = match config.emulated_trigger() {
+            Some(trigger) if !config.pre_trigger().is_zero() => (
+                None,
+                Some((trigger.clone(), config.pre_trigger(), config.time_frame())),
+            ),
+            trigger => (trigger.cloned(), None),
+        };
+        let worker = thread::spawn(move || {
+            profiling::register_thread!("CaptureStream worker");
+            for reading in reading_rx {
+                // Captures that don't parse can't be checked, their error is passed on
+                let window = pre_trigger.as_ref().and_then(|(trigger, pre, frame)| {
+                    let samples = reading.samples().ok()?;
+                    Some(
+                        pre_trigger_window(trigger, samples, *pre, *frame)
+                            .map(|window| (samples[window.start].time, window)),
+                    )
+                });
+                let rejected = window.as_ref().map_or_else(
+                    || {
+                        emulated.as_ref().is_some_and(|trigger| {
+                            reading
+                                .samples()
+                                .is_ok_and(|samples| !emulated_conditions_hold(trigger, samples))
+                        })
+                    },
+                    Option::is_none,
+                );
+                let skip = rejected
+                    || counter.as_mut().is_some_and(|counter| {
+                        reading
+                            .samples()
+                            .is_ok_and(|samples| counter.feed(samples).is_empty())
+                    });
+                let frame = (!skip).then(|| {
+                    let df = reading.parse_csv().map(|df| match &window {
+                        Some(Some((start_time, window))) => df
+                            .slice(
+                                i64::try_from(window.start).unwrap_or(i64::MAX),
+                                IdxSize::try_from(window.len()).unwrap_or(IdxSize::MAX),
+                            )
+                            .with_column(col(TIME_COLUMN_NAME) - lit(*start_time)),
+                        _ => df,
+                    });
+                    df.and_then(|df| match &probe {
+                        Some(probe) => probe.apply_calibration(df).collect(),
+                        None => df.collect(),
+                    })
+                });
+                if frame_tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut stream = Self {
+            state: State::Idle(scope),
+            config: config.clone(),
+            readings: Some(readings),
+            frames,
+            worker: Some(worker),
+            pending: 0,
+            error: None,
+            holdoff_until: None,
+        };
+        stream.arm();
+        stream
+    }
+
+    /// Start the next capture if the scope is idle and the worker isn't too far behind
+    fn arm(&mut self) {
+        if self.pending >= MAX_PENDING
+            || self
+                .holdoff_until
+                .is_some_and(|until| Instant::now() < until)
+        {
+            return;
+        }
+        self.state = match std::mem::replace(&mut self.state, State::Failed) {
+            State::Idle(scope) => match scope.read_async_unfiltered(&self.config) {
+                Ok(reading) => State::Reading(reading),
+                Err((scope, e)) => {
+                    self.error = Some(e.into());
+                    State::Idle(scope)
+                }
+            },
+            state => state,
+        };
+    }
+
+    /// Poll the capture in flight once, handing it to the worker and rearming when done
+    fn poll_capture(&mut self) {
+        self.state = match std::mem::replace(&mut self.state, State::Failed) {
+            State::Reading(reading) => match reading.try_get_result() {
+                Ok(Ok((scope, data))) => {
+                    self.state = State::Idle(scope);
+                    let holdoff = self.config.holdoff();
+                    self.holdoff_until = (!holdoff.is_zero()).then(|| Instant::now() + holdoff);
+                    if let Some(readings) = &self.readings {
+                        if readings.send(data).is_ok() {
+                            self.pending += 1;
+                        }
+                    }
+                    self.arm();
+                    return;
+                }
+                Ok(Err(reading)) => State::Reading(reading),
+                Err(e) => {
+                    self.error = Some(e.into());
+                    State::Failed
+                }
+            },
+            state => state,
+        };
+    }
+
+    /// Cancel the capture in flight and hand the scope back
+    pub fn stop(mut self) -> Result<IdleFleaScope, CaptureStreamError> {
+        profiling::scope!("CaptureStream::stop");
+
+        self.shutdown_worker();
+        match std::mem::replace(&mut self.state, State::Failed) {
+            State::Idle(scope) => Ok(scope),
+            State::Reading(reading) => reading.cancel().map_err(|(_, e)| e.into()),
+            State::Failed => Err(self
+                .error
+                .take()
+                .unwrap_or(CaptureStreamError::ConnectionLost)),
+        }
+    }
+
+    fn shutdown_worker(&mut self) {
+        // Closing the channel ends the worker once it has parsed what it has
+        self.readings = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Iterator for CaptureStream {
+    type Item = Result<DataFrame, CaptureStreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        profiling::scope!("CaptureStream::next");
+
+        loop {
+            match self.frames.try_recv() {
+                Ok(frame) => {
+                    self.pending -= 1;
+                    self.arm();
+                    if let Some(frame) = frame {
+                        return Some(frame.map_err(Into::into));
+                    }
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if let Some(e) = self.error.take() {
+                return Some(Err(e));
+            }
+            match self.state {
+                State::Reading(_) => self.poll_capture(),
+                // Nothing in flight, only the worker can make progress
+                State::Idle(_) | State::Failed if self.pending > 0 => {
+                    let frame = self.frames.recv().ok()?;
+                    self.pending -= 1;
+                    self.arm();
+                    if let Some(frame) = frame {
+                        return Some(frame.map_err(Into::into));
+                    }
+                }
+                // Only the holdoff keeps the next capture from being armed
+                State::Idle(_) if self.holdoff_until.is_some() => {
+                    if let Some(until) = self.holdoff_until.take() {
+                        thread::sleep(until.saturating_duration_since(Instant::now()));
+                    }
+                    self.arm();
+                }
+                State::Idle(_) | State::Failed => return None,
+            }
+        }
+    }
+}
+
+impl Drop for CaptureStream {
+    fn drop(&mut self) {
+        self.shutdown_worker();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimulatedDevice;
+    use std::time::Duration;
+
+    #[test]
+    fn test_capture_stream() {
+        let device = SimulatedDevice::new().calibrated(2048, 1000);
+        let (scope, x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let mut stream = CaptureStream::new(scope, &config, Some(&x1));
+        for frame in stream.by_ref().take(5) {
+            let frame = frame.unwrap();
+            assert_eq!(frame.height(), 2000);
+            assert!(frame
+                .column(crate::flea_scope::CALIBRATED_COLUMN_NAME)
+                .is_ok());
+        }
+        let mut scope = stream.stop().unwrap();
+
+        // The next capture was already armed, so more than 5 were taken
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert!(captures > 5);
+        assert_eq!(
+            scope
+                .read(&config)
+                .parse_csv()
+                .unwrap()
+                .collect()
+                .unwrap()
+                .height(),
+            2000
+        );
+    }
+
+    #[test]
+    fn test_capture_stream_holdoff() {
+        let device = SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .holdoff(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert_eq!(config.holdoff(), Duration::from_millis(50));
+
+        let started = Instant::now();
+        let mut stream = CaptureStream::new(scope, &config, None);
+        assert_eq!(stream.by_ref().take(3).flatten().count(), 3);
+        // Two holdoffs between three captures at the least
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        stream.stop().unwrap();
+    }
+
+    #[test]
+    fn test_capture_stream_nth_event() {
+        use crate::trigger_config::{BitState, DigitalTrigger};
+        use std::num::NonZeroUsize;
+
+        // Bit 0 rises once per capture
+        let device = SimulatedDevice::new().digital(|t| u16::from(t > 500e-6));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let pattern = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .starts_matching()
+            .unwrap();
+
+        let counter = NthEvent::new(&pattern, NonZeroUsize::new(3).unwrap());
+        let mut stream = CaptureStream::nth_event(scope, &config, None, counter);
+        assert_eq!(stream.by_ref().take(2).flatten().count(), 2);
+        stream.stop().unwrap();
+
+        // Every third capture was returned
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert!(captures >= 6);
+    }
+
+    #[test]
+    fn test_capture_stream_pre_trigger() {
+        use crate::flea_scope::BITMAP_COLUMN_NAME;
+        use crate::trigger_config::{BitState, DigitalTrigger};
+
+        // Bit 0 rises 1.5 ms into every capture
+        let device = SimulatedDevice::new().digital(|t| u16::from(t >= 1.5e-3));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(2))
+            .trigger(
+                DigitalTrigger::start_capturing_when()
+                    .bit0(BitState::High)
+                    .starts_matching()
+                    .unwrap(),
+            )
+            .trigger_position(-0.001)
+            .build()
+            .unwrap();
+
+        let mut stream = CaptureStream::new(scope, &config, None);
+        let frame = stream.next().unwrap().unwrap();
+        stream.stop().unwrap();
+
+        let time = frame.column(TIME_COLUMN_NAME).unwrap().f64().unwrap();
+        let bitmap = frame.column(BITMAP_COLUMN_NAME).unwrap().u32().unwrap();
+        let rise = bitmap.into_no_null_iter().position(|bits| bits & 1 == 1);
+        // The trigger is 1 ms into the 2 ms frame
+        assert_eq!(time.get(0), Some(0.0));
+        assert!((time.get(rise.unwrap()).unwrap() - 1e-3).abs() < 1e-5);
+        assert!((time.get(frame.height() - 1).unwrap() - 2e-3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_capture_stream_hysteresis() {
+        use crate::flea_scope::RAW_COLUMN_NAME;
+        use crate::trigger_config::{AnalogTrigger, AnalogTriggerBehavior};
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        // Every other capture falls back below the level right after triggering
+        let captures = AtomicU32::new(0);
+        let device = SimulatedDevice::new().signal(move |t| {
+            if t <= 0.0 {
+                captures.fetch_add(1, Ordering::Relaxed);
+            }
+            match (t < 100e-6, captures.load(Ordering::Relaxed) % 2) {
+                (true, _) => 2000.0,
+                (false, 0) => 1990.0,
+                (false, _) => 2400.0,
+            }
+        });
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let trigger = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).with_hysteresis(20);
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .trigger(trigger)
+            .build()
+            .unwrap();
+
+        let mut stream = CaptureStream::new(scope, &config, None);
+        for frame in stream.by_ref().take(3) {
+            let max = frame
+                .unwrap()
+                .column(RAW_COLUMN_NAME)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .max();
+            assert_eq!(max, Some(2400.0));
+        }
+        stream.stop().unwrap();
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert!(captures >= 5);
+    }
+
+    #[test]
+    fn test_capture_stream_qualifier() {
+        use crate::flea_scope::BITMAP_COLUMN_NAME;
+        use crate::trigger_config::{AnalogTrigger, AnalogTriggerBehavior, BitState};
+        use crate::DigitalTrigger;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        // Bit 3 is only high during every other capture
+        let captures = AtomicU32::new(0);
+        let device = SimulatedDevice::new().digital(move |t| {
+            if t <= 0.0 {
+                captures.fetch_add(1, Ordering::Relaxed);
+            }
+            match captures.load(Ordering::Relaxed) % 2 {
+                0 => 0b1000,
+                _ => 0,
+            }
+        });
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let trigger = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).qualified_by(
+            &DigitalTrigger::start_capturing_when()
+                .bit3(BitState::High)
+                .is_matching(),
+        );
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .trigger(trigger)
+            .build()
+            .unwrap();
+
+        let mut stream = CaptureStream::new(scope, &config, None);
+        for frame in stream.by_ref().take(3) {
+            let frame = frame.unwrap();
+            let first = frame
+                .column(BITMAP_COLUMN_NAME)
+                .unwrap()
+                .u32()
+                .unwrap()
+                .get(0);
+            assert_eq!(first, Some(0x008));
+        }
+        stream.stop().unwrap();
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert!(captures >= 5);
+    }
+
+    #[test]
+    fn test_capture_stream_held_for() {
+        use crate::flea_scope::BITMAP_COLUMN_NAME;
+        use crate::trigger_config::BitState;
+        use crate::DigitalTrigger;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        // Every other capture starts with a 50 µs glitch instead of a stable high
+        let captures = AtomicU32::new(0);
+        let device = SimulatedDevice::new().digital(move |t| {
+            if t <= 0.0 {
+                captures.fetch_add(1, Ordering::Relaxed);
+            }
+            match (t < 50e-6, captures.load(Ordering::Relaxed) % 2) {
+                (true, _) | (false, 0) => 0b1,
+                (false, _) => 0,
+            }
+        });
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let trigger = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .held_for(Duration::from_micros(200))
+            .starts_matching()
+            .unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .trigger(trigger)
+            .build()
+            .unwrap();
+
+        let mut stream = CaptureStream::new(scope, &config, None);
+        for frame in stream.by_ref().take(3) {
+            let frame = frame.unwrap();
+            let bitmaps = frame.column(BITMAP_COLUMN_NAME).unwrap().u32().unwrap();
+            assert!(bitmaps.into_iter().all(|bitmap| bitmap == Some(0x001)));
+        }
+        stream.stop().unwrap();
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert!(captures >= 5);
+    }
+
+    #[test]
+    fn test_capture_stream_connection_lost() {
+        let device = SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        device.disconnect();
+        let mut stream = CaptureStream::new(scope, &config, None);
+        assert!(stream.by_ref().any(|frame| frame.is_err()));
+        assert!(stream.next().is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::flea_scope::{
+    BITMAP_COLUMN_NAME, CALIBRATED_COLUMN_NAME, RAW_COLUMN_NAME, TIME_COLUMN_NAME,
+};
+use crate::trigger_config::{BitTriggerBuilder, DIGITAL_CHANNELS};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ChannelLabelError {
+    #[error("No digital channel {0}, channels are 0 to {max}", max = DIGITAL_CHANNELS - 1)]
+    NoSuchChannel(usize),
+
+    #[error("Channel label must not be empty")]
+    Empty,
+
+    #[error("Label {label:?} is already used for channel {channel}")]
+    Duplicate { label: String, channel: usize },
+
+    #[error("Label {0:?} clashes with a column of the captured data")]
+    Reserved(String),
+}
+
+/// Names for the digital channels, e.g. the signals of the bus they are wired to.
+///
+/// Register them with `IdleFleaScope::set_channel_labels` to build triggers by name and
+/// get the same names as `DataFrame` columns from `CaptureFrame::labeled_bits`.
+///
+/// ```rust
+/// use fleascope_rs::channel_labels::ChannelLabels;
+/// use fleascope_rs::BitState;
+///
+/// let labels = ChannelLabels::new().label(5, "SCL")?.label(6, "SDA")?;
+/// let start_condition = labels
+///     .start_capturing_when()
+///     .bit_named("SCL", BitState::High)?
+///     .bit_named("SDA", BitState::Low)?
+///     .starts_matching()?;
+/// assert_eq!(labels.channel("SDA"), Some(6));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[must_use]
+pub struct ChannelLabels {
+    labels: [Option<String>; DIGITAL_CHANNELS],
+}
+
+impl ChannelLabels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name `channel`, replacing its previous label
+    pub fn label(mut self, channel: usize, label: &str) -> Result<Self, ChannelLabelError> {
+        if channel >= DIGITAL_CHANNELS {
+            return Err(ChannelLabelError::NoSuchChannel(channel));
+        }
+        if label.trim().is_empty() {
+            return Err(ChannelLabelError::Empty);
+        }
+        let reserved = [
+            TIME_COLUMN_NAME,
+            RAW_COLUMN_NAME,
+            CALIBRATED_COLUMN_NAME,
+            BITMAP_COLUMN_NAME,
+        ];
+        // Labels must not be confused with another channel's default column name either
+        if reserved.contains(&label) || label.starts_with("bit_") {
+            return Err(ChannelLabelError::Reserved(label.to_string()));
+        }
+        if let Some(other) = self.channel(label).filter(|&other| other != channel) {
+            return Err(ChannelLabelError::Duplicate {
+                label: label.to_string(),
+                channel: other,
+            });
+        }
+        self.labels[channel] = Some(label.to_string());
+        Ok(self)
+    }
+
+    /// Channel named `label`
+    pub fn channel(&self, label: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .position(|existing| existing.as_deref() == Some(label))
+    }
+
+    /// Label of `channel`, if it has one
+    pub fn get(&self, channel: usize) -> Option<&str> {
+        self.labels.get(channel)?.as_deref()
+    }
+
+    /// Labeled channels in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.labels
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, label)| Some((channel, label.as_deref()?)))
+    }
+
+    /// Column name of `channel` in extracted data: its label, or `bit_<n>` without one
+    pub fn column_name(&self, channel: usize) -> String {
+        self.get(channel)
+            .map_or_else(|| format!("bit_{channel}"), str::to_string)
+    }
+
+    /// Start a digital trigger that can refer to channels by label, see
+    /// `BitTriggerBuilder::bit_named`
+    pub fn start_capturing_when(&self) -> BitTriggerBuilder {
+        BitTriggerBuilder::new().with_labels(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trigger_config::{BitState, TriggerConfig};
+
+    #[test]
+    fn test_label_validation() {
+        let labels = ChannelLabels::new().label(5, "SCL").unwrap();
+        assert_eq!(labels.get(5), Some("SCL"));
+        assert_eq!(labels.column_name(5), "SCL");
+        assert_eq!(labels.column_name(4), "bit_4");
+
+        assert_eq!(
+            labels.clone().label(6, "SCL"),
+            Err(ChannelLabelError::Duplicate {
+                label: "SCL".to_string(),
+                channel: 5
+            })
+        );
+        // Relabeling the same channel is fine
+        let labels = labels.label(5, "CLK").unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), [(5, "CLK")]);
+
+        assert_eq!(
+            ChannelLabels::new().label(10, "X"),
+            Err(ChannelLabelError::NoSuchChannel(10))
+        );
+        assert_eq!(
+            ChannelLabels::new().label(0, " "),
+            Err(ChannelLabelError::Empty)
+        );
+        assert_eq!(
+            ChannelLabels::new().label(0, "bit_3"),
+            Err(ChannelLabelError::Reserved("bit_3".to_string()))
+        );
+        assert_eq!(
+            ChannelLabels::new().label(0, BITMAP_COLUMN_NAME),
+            Err(ChannelLabelError::Reserved(BITMAP_COLUMN_NAME.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_trigger_by_label() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        scope.set_channel_labels(
+            ChannelLabels::new()
+                .label(5, "SCL")
+                .unwrap()
+                .label(6, "SDA")
+                .unwrap(),
+        );
+
+        let trigger = scope
+            .channel_labels()
+            .start_capturing_when()
+            .bit_named("SCL", BitState::High)
+            .unwrap()
+            .bit_named("SDA", BitState::Low)
+            .unwrap()
+            .starts_matching()
+            .unwrap();
+        assert_eq!(trigger.into_trigger_fields().into_string(), "+0x20 0x60");
+
+        // The last channel is usable in triggers as well
+        let labels = ChannelLabels::new().label(9, "IRQ").unwrap();
+        let trigger = labels
+            .start_capturing_when()
+            .bit_named("IRQ", BitState::High)
+            .unwrap()
+            .bit0(BitState::Low)
+            .is_matching();
+        assert_eq!(trigger.into_trigger_fields().into_string(), "0x200 0x201");
+    }
+}
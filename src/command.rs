@@ -0,0 +1,161 @@
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CommandError {
+    #[error("Argument {argument:?} contains control character {character:?}")]
+    ControlCharacter { argument: String, character: char },
+
+    #[error("Argument {argument:?} contains whitespace")]
+    Whitespace { argument: String },
+
+    #[error("Argument {argument:?} contains a double quote")]
+    Quote { argument: String },
+
+    #[error("Empty argument")]
+    Empty,
+}
+
+/// Builds a line for the device's shell from untrusted arguments.
+///
+/// A newline or CTRL-C inside an argument would end or abort the command early and
+/// desynchronize the protocol, so every argument is validated before it is appended.
+///
+/// ```rust
+/// use fleascope_rs::command::CommandBuilder;
+///
+/// let command = CommandBuilder::new("hostname").arg("bench-3")?.build();
+/// assert_eq!(command, "hostname bench-3");
+/// assert!(CommandBuilder::new("hostname").arg("evil\nreset").is_err());
+/// # Ok::<(), fleascope_rs::command::CommandError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct CommandBuilder {
+    line: String,
+}
+
+impl CommandBuilder {
+    pub fn new(command: &str) -> Self {
+        Self {
+            line: command.to_string(),
+        }
+    }
+
+    /// Validate a whole command line, e.g. from a configuration file. Words are separated
+    /// by single spaces and checked like `arg`; quoted strings need `quoted_arg`.
+    pub fn from_line(line: &str) -> Result<Self, CommandError> {
+        let mut words = line.split(' ').filter(|word| !word.is_empty());
+        let command = words.next().ok_or(CommandError::Empty)?;
+        // Builds the command word with the argument checks, then drops the leading space
+        let mut builder = Self::new("").arg(command)?;
+        builder.line.remove(0);
+        words.try_fold(builder, Self::arg)
+    }
+
+    /// Append a bare word, e.g. a hostname or a number
+    pub fn arg(self, argument: impl std::fmt::Display) -> Result<Self, CommandError> {
+        let argument = argument.to_string();
+        check_control_characters(&argument)?;
+        if argument.is_empty() {
+            return Err(CommandError::Empty);
+        }
+        if argument.chars().any(char::is_whitespace) {
+            return Err(CommandError::Whitespace { argument });
+        }
+        Ok(self.push(&argument))
+    }
+
+    /// Append a double-quoted string literal, which may contain spaces
+    pub fn quoted_arg(self, argument: &str) -> Result<Self, CommandError> {
+        check_control_characters(argument)?;
+        // The device shell has no escape sequence for quotes inside strings
+        if argument.contains('"') {
+            return Err(CommandError::Quote {
+                argument: argument.to_string(),
+            });
+        }
+        Ok(self.push(&format!("\"{argument}\"")))
+    }
+
+    fn push(mut self, argument: &str) -> Self {
+        let _ = write!(self.line, " {argument}");
+        self
+    }
+
+    pub fn build(self) -> String {
+        self.line
+    }
+}
+
+fn check_control_characters(argument: &str) -> Result<(), CommandError> {
+    argument
+        .chars()
+        .find(|c| c.is_control())
+        .map_or(Ok(()), |character| {
+            Err(CommandError::ControlCharacter {
+                argument: argument.to_string(),
+                character,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let command = CommandBuilder::new("wave")
+            .arg("sine")
+            .unwrap()
+            .arg(1000)
+            .unwrap()
+            .build();
+        assert_eq!(command, "wave sine 1000");
+
+        let command = CommandBuilder::new("print")
+            .quoted_arg("hello world")
+            .unwrap()
+            .build();
+        assert_eq!(command, "print \"hello world\"");
+
+        let command = CommandBuilder::from_line("gpio  3 high").unwrap().build();
+        assert_eq!(command, "gpio 3 high");
+    }
+
+    #[test]
+    fn test_rejects_unsafe_arguments() {
+        assert_eq!(
+            CommandBuilder::new("hostname").arg("a\nreset"),
+            Err(CommandError::ControlCharacter {
+                argument: "a\nreset".to_string(),
+                character: '\n'
+            })
+        );
+        assert!(matches!(
+            CommandBuilder::new("hostname").arg("a\u{3}"),
+            Err(CommandError::ControlCharacter { .. })
+        ));
+        assert!(matches!(
+            CommandBuilder::new("hostname").arg("a b"),
+            Err(CommandError::Whitespace { .. })
+        ));
+        assert_eq!(
+            CommandBuilder::new("hostname").arg(""),
+            Err(CommandError::Empty)
+        );
+        assert!(matches!(
+            CommandBuilder::new("print").quoted_arg("say \"hi\""),
+            Err(CommandError::Quote { .. })
+        ));
+        assert!(matches!(
+            CommandBuilder::new("print").quoted_arg("\r"),
+            Err(CommandError::ControlCharacter { .. })
+        ));
+        assert!(matches!(
+            CommandBuilder::from_line("reset\nhostname evil"),
+            Err(CommandError::ControlCharacter { .. })
+        ));
+        assert_eq!(CommandBuilder::from_line(" "), Err(CommandError::Empty));
+    }
+}
@@ -0,0 +1,3 @@
+//! Protocol decoders for the digital channels
+
+pub mod uart;
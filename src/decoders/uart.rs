@@ -0,0 +1,283 @@
+use crate::capture_frame::bit_expr;
+use crate::digital::START_COLUMN_NAME;
+use crate::flea_scope::TIME_COLUMN_NAME;
+use crate::trigger_config::DIGITAL_CHANNELS;
+use polars::prelude::*;
+
+/// End of the stop bit of a frame
+pub const END_COLUMN_NAME: &str = "end";
+pub const VALUE_COLUMN_NAME: &str = "value";
+/// Whether the stop bit was low
+pub const FRAMING_ERROR_COLUMN_NAME: &str = "framing_error";
+/// Whether the parity bit didn't match, null without parity
+pub const PARITY_ERROR_COLUMN_NAME: &str = "parity_error";
+
+/// Fewer samples per bit can't reliably hit the middle of every bit
+const MIN_SAMPLES_PER_BIT: f64 = 3.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum UartError {
+    #[error("Bit {0} out of range (max {max})", max = DIGITAL_CHANNELS - 1)]
+    BitOutOfRange(usize),
+
+    #[error("UART frames have 5 to 9 data bits, not {0}")]
+    InvalidDataBits(u8),
+
+    #[error("Only {samples_per_bit:.1} samples per bit, capture at a higher sample rate")]
+    Undersampled { samples_per_bit: f64 },
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    /// The data and parity bits hold an even number of ones
+    Even,
+    Odd,
+}
+
+/// Decode the UART frames received on digital channel `rx_bit`: idle high, a low start
+/// bit, `bits` data bits LSB first, an optional parity bit and one stop bit.
+///
+/// One row per frame with its `start` time at the falling edge of the start bit, the
+/// `end` of the stop bit, the data `value`, whether the stop bit was low as
+/// `framing_error` and whether the parity didn't match as `parity_error`. Every bit is
+/// read in its middle, timed from the start edge. Frames cut off by the end of the
+/// capture are left out.
+///
+/// ```rust
+/// use fleascope_rs::decoders::uart::{self, Parity};
+/// use polars::prelude::*;
+///
+/// // 0x55 at 100 kBaud, sampled at 1 MSPS on bit 0: start bit, 1010 1010, stop bit
+/// let levels = [1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 1];
+/// let bitmap: Vec<u32> = levels.iter().flat_map(|&level| [level; 10]).collect();
+/// let time: Vec<f64> = (0..bitmap.len()).map(|i| i as f64 / 1e6).collect();
+/// let df = df!("time" => time, "bitmap" => bitmap)?;
+///
+/// let frames = uart::decode(&df, 0, 100_000, Parity::None, 8)?;
+/// assert_eq!(frames.column("value")?.u32()?.get(0), Some(0x55));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decode(
+    df: &DataFrame,
+    rx_bit: usize,
+    baud: u32,
+    parity: Parity,
+    bits: u8,
+) -> Result<DataFrame, UartError> {
+    profiling::scope!("uart::decode");
+
+    if rx_bit >= DIGITAL_CHANNELS {
+        return Err(UartError::BitOutOfRange(rx_bit));
+    }
+    if !(5..=9).contains(&bits) {
+        return Err(UartError::InvalidDataBits(bits));
+    }
+    let levels = df
+        .clone()
+        .lazy()
+        .select([col(TIME_COLUMN_NAME), bit_expr(rx_bit)])
+        .collect()?;
+    let times: Vec<f64> = levels
+        .column(TIME_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let high: Vec<bool> = levels[1].bool()?.into_no_null_iter().collect();
+
+    let bit_time = 1.0 / f64::from(baud);
+    if let [first, .., last] = times[..] {
+        #[allow(clippy::cast_precision_loss)]
+        let sample_period = (last - first) / (times.len() - 1) as f64;
+        let samples_per_bit = bit_time / sample_period;
+        if samples_per_bit < MIN_SAMPLES_PER_BIT {
+            return Err(UartError::Undersampled { samples_per_bit });
+        }
+    }
+    // Level at `time`, i.e. of the last sample not after it
+    let level_at = |time: f64| {
+        let index = times.partition_point(|&sample| sample <= time);
+        index.checked_sub(1).map(|index| high[index])
+    };
+
+    let frame_bits = 2 + u32::from(bits) + u32::from(parity != Parity::None);
+    let (mut start, mut end, mut value) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut framing_error, mut parity_error) = (Vec::new(), Vec::new());
+    let mut index = 1;
+    while index < times.len() {
+        let falling = high[index - 1] && !high[index];
+        if !falling {
+            index += 1;
+            continue;
+        }
+        let edge = f64::midpoint(times[index - 1], times[index]);
+        let middle_of = |bit: u32| f64::from(bit).mul_add(bit_time, edge + bit_time / 2.0);
+        // A start bit that is over by its middle was a glitch
+        if level_at(middle_of(0)) != Some(false) {
+            index += 1;
+            continue;
+        }
+        let Some(levels) = (1..frame_bits)
+            .map(|bit| {
+                level_at(middle_of(bit)).filter(|_| middle_of(bit) <= times[times.len() - 1])
+            })
+            .collect::<Option<Vec<bool>>>()
+        else {
+            break;
+        };
+
+        let data = &levels[..usize::from(bits)];
+        let ones = u32::try_from(data.iter().filter(|&&level| level).count())
+            .expect("at most 9 data bits");
+        let stop = levels[levels.len() - 1];
+        start.push(edge);
+        end.push(f64::from(frame_bits).mul_add(bit_time, edge));
+        value.push(
+            data.iter()
+                .rev()
+                .fold(0u32, |value, &level| (value << 1) | u32::from(level)),
+        );
+        framing_error.push(!stop);
+        parity_error.push(match parity {
+            Parity::None => None,
+            Parity::Even => Some((ones + u32::from(levels[usize::from(bits)])) % 2 != 0),
+            Parity::Odd => Some((ones + u32::from(levels[usize::from(bits)])) % 2 == 0),
+        });
+
+        // Look for the next start bit from the middle of the stop bit on. After a framing
+        // error the line has to go high again first.
+        let stop_middle = middle_of(frame_bits - 1);
+        index = times
+            .partition_point(|&sample| sample <= stop_middle)
+            .max(index + 1);
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new(START_COLUMN_NAME.into(), start),
+        Column::new(END_COLUMN_NAME.into(), end),
+        Column::new(VALUE_COLUMN_NAME.into(), value),
+        Column::new(FRAMING_ERROR_COLUMN_NAME.into(), framing_error),
+        Column::new(PARITY_ERROR_COLUMN_NAME.into(), parity_error),
+    ])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 1e6;
+    const BAUD: u32 = 115_200;
+    const RX_BIT: usize = 3;
+
+    /// Levels of `frames` on the line, each frame given as its bits after the start bit,
+    /// with idle time before, between and after
+    fn line(frames: &[Vec<bool>]) -> DataFrame {
+        let bit_time = 1.0 / f64::from(BAUD);
+        let mut edges = vec![(0.0, true)];
+        let mut time = 20e-6;
+        for frame in frames {
+            edges.push((time, false));
+            for (bit, &level) in frame.iter().enumerate() {
+                let bit = u32::try_from(bit).unwrap() + 1;
+                edges.push((f64::from(bit).mul_add(bit_time, time), level));
+            }
+            let frame_bits = u32::try_from(frame.len()).unwrap() + 1;
+            edges.push((f64::from(frame_bits).mul_add(bit_time, time), true));
+            time += f64::from(frame_bits).mul_add(bit_time, 15e-6);
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let samples = 1 + (time * SAMPLE_RATE) as u32;
+        let time: Vec<f64> = (0..samples).map(|i| f64::from(i) / SAMPLE_RATE).collect();
+        let bitmap: Vec<u32> = time
+            .iter()
+            .map(|&time| {
+                let index = edges.partition_point(|&(edge, _)| edge <= time) - 1;
+                u32::from(edges[index].1) << RX_BIT
+            })
+            .collect();
+        df!("time" => time, "bitmap" => bitmap).unwrap()
+    }
+
+    /// Bits of a frame after the start bit
+    fn frame(value: u32, bits: u8, parity: Option<bool>, stop: bool) -> Vec<bool> {
+        let mut levels: Vec<bool> = (0..bits).map(|bit| value >> bit & 1 == 1).collect();
+        levels.extend(parity);
+        levels.push(stop);
+        levels
+    }
+
+    #[test]
+    fn test_decode_8n1() {
+        let df = line(&[
+            frame(b'H'.into(), 8, None, true),
+            frame(b'i'.into(), 8, None, true),
+        ]);
+        let frames = decode(&df, RX_BIT, BAUD, Parity::None, 8).unwrap();
+
+        let values: Vec<u32> = frames
+            .column(VALUE_COLUMN_NAME)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(values, [u32::from(b'H'), u32::from(b'i')]);
+        let start = frames.column(START_COLUMN_NAME).unwrap().f64().unwrap();
+        assert!((start.get(0).unwrap() - 20e-6).abs() < 1e-6);
+        let end = frames.column(END_COLUMN_NAME).unwrap().f64().unwrap();
+        assert!((end.get(0).unwrap() - start.get(0).unwrap() - 10.0 / 115_200.0).abs() < 1e-9);
+        assert_eq!(
+            frames
+                .column(PARITY_ERROR_COLUMN_NAME)
+                .unwrap()
+                .null_count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // 0x03 has two ones: even parity is 0, odd parity is 1
+        let df = line(&[
+            frame(0x03, 7, Some(false), true),
+            frame(0x03, 7, Some(true), true),
+            frame(0x41, 7, Some(false), false),
+        ]);
+        let frames = decode(&df, RX_BIT, BAUD, Parity::Even, 7).unwrap();
+        let flags = |name: &str| -> Vec<bool> {
+            frames
+                .column(name)
+                .unwrap()
+                .bool()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(flags(PARITY_ERROR_COLUMN_NAME), [false, true, false]);
+        assert_eq!(flags(FRAMING_ERROR_COLUMN_NAME), [false, false, true]);
+        let values = frames.column(VALUE_COLUMN_NAME).unwrap().u32().unwrap();
+        assert_eq!(values.get(2), Some(0x41));
+
+        assert!(matches!(
+            decode(&df, RX_BIT, 500_000, Parity::Even, 7),
+            Err(UartError::Undersampled { .. })
+        ));
+        assert!(matches!(
+            decode(&df, RX_BIT, BAUD, Parity::Even, 4),
+            Err(UartError::InvalidDataBits(4))
+        ));
+    }
+
+    #[test]
+    fn test_incomplete_frame() {
+        let df = line(&[frame(0x12, 8, None, true)]);
+        // Cut off in the middle of the data bits
+        let df = df.head(Some(50));
+        let frames = decode(&df, RX_BIT, BAUD, Parity::None, 8).unwrap();
+        assert_eq!(frames.height(), 0);
+    }
+}
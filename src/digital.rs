@@ -0,0 +1,248 @@
+use crate::capture_frame::bit_expr;
+use crate::flea_scope::TIME_COLUMN_NAME;
+use crate::trigger_config::DIGITAL_CHANNELS;
+use polars::prelude::*;
+use std::time::Duration;
+
+/// Whether an edge of `edges` goes from low to high
+pub const RISING_COLUMN_NAME: &str = "rising";
+/// Time of the rising edge starting a pulse of `pulses`
+pub const START_COLUMN_NAME: &str = "start";
+pub const WIDTH_COLUMN_NAME: &str = "width";
+pub const PERIOD_COLUMN_NAME: &str = "period";
+pub const DUTY_COLUMN_NAME: &str = "duty";
+/// Level of a glitch of `find_glitches`, true for a high one
+pub const LEVEL_COLUMN_NAME: &str = "level";
+
+#[derive(Debug, thiserror::Error)]
+pub enum DigitalError {
+    #[error("Bit {0} out of range (max {max})", max = DIGITAL_CHANNELS - 1)]
+    BitOutOfRange(usize),
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+}
+
+/// Changes of digital channel `bit` in a capture with a `bitmap` column: the `time` of the
+/// first sample at the new level and whether it is `rising`.
+///
+/// A level already present at the start of the capture is not an edge.
+///
+/// ```rust
+/// use fleascope_rs::digital;
+/// use polars::prelude::*;
+///
+/// let df = df!("time" => [0.0, 1.0, 2.0, 3.0], "bitmap" => [0u32, 4, 4, 0])?;
+/// let edges = digital::edges(&df, 2)?;
+/// let rising: Vec<_> = edges.column("rising")?.bool()?.into_no_null_iter().collect();
+/// assert_eq!(rising, [true, false]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn edges(df: &DataFrame, bit: usize) -> Result<DataFrame, DigitalError> {
+    profiling::scope!("digital::edges");
+
+    if bit >= DIGITAL_CHANNELS {
+        return Err(DigitalError::BitOutOfRange(bit));
+    }
+    let level = col(RISING_COLUMN_NAME);
+    Ok(df
+        .clone()
+        .lazy()
+        .select([
+            col(TIME_COLUMN_NAME),
+            bit_expr(bit).alias(RISING_COLUMN_NAME),
+        ])
+        .filter(level.clone().neq(level.shift(lit(1))))
+        .collect()?)
+}
+
+/// High pulses of digital channel `bit`, one row each.
+///
+/// The columns are the `start` time of the rising edge, the `width` until the falling
+/// edge, the `period` until the next rising edge and the `duty` cycle of that period.
+///
+/// Pulses cut off by the end of the capture are left out, `period` and `duty` are null
+/// when it ends before the next rising edge. Low pulses are the gaps, `period - width`.
+pub fn pulses(df: &DataFrame, bit: usize) -> Result<DataFrame, DigitalError> {
+    profiling::scope!("digital::pulses");
+
+    let edges = edges(df, bit)?;
+    let times: Vec<f64> = edges
+        .column(TIME_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let rising: Vec<bool> = edges
+        .column(RISING_COLUMN_NAME)?
+        .bool()?
+        .into_no_null_iter()
+        .collect();
+
+    let starts: Vec<usize> = (0..times.len())
+        .filter(|&index| rising[index] && index + 1 < times.len())
+        .collect();
+    let start: Vec<f64> = starts.iter().map(|&index| times[index]).collect();
+    // Edges alternate, so a rising edge is followed by a falling one
+    let width: Vec<f64> = starts
+        .iter()
+        .map(|&index| times[index + 1] - times[index])
+        .collect();
+    let period: Vec<Option<f64>> = starts
+        .iter()
+        .map(|&index| Some(times.get(index + 2)? - times[index]))
+        .collect();
+    let duty: Vec<Option<f64>> = width
+        .iter()
+        .zip(&period)
+        .map(|(width, period)| period.map(|period| width / period))
+        .collect();
+
+    Ok(DataFrame::new(vec![
+        Column::new(START_COLUMN_NAME.into(), start),
+        Column::new(WIDTH_COLUMN_NAME.into(), width),
+        Column::new(PERIOD_COLUMN_NAME.into(), period),
+        Column::new(DUTY_COLUMN_NAME.into(), duty),
+    ])?)
+}
+
+/// Pulses of digital channel `bit` no wider than `max_width`, high or low, e.g. runts in
+/// a long rolling capture.
+///
+/// One row per glitch with its `start` time, `width` and `level`, true for a high pulse.
+/// A glitch is only seen if it lasts at least one sample. Two glitches closer together
+/// than `max_width` make the gap between them a glitch as well.
+pub fn find_glitches(
+    df: &DataFrame,
+    bit: usize,
+    max_width: Duration,
+) -> Result<DataFrame, DigitalError> {
+    profiling::scope!("digital::find_glitches");
+
+    let edges = edges(df, bit)?;
+    let times: Vec<f64> = edges
+        .column(TIME_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let rising: Vec<bool> = edges
+        .column(RISING_COLUMN_NAME)?
+        .bool()?
+        .into_no_null_iter()
+        .collect();
+
+    let (mut start, mut width, mut level) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, pair) in times.windows(2).enumerate() {
+        if is_within(pair[1] - pair[0], max_width) {
+            start.push(pair[0]);
+            width.push(pair[1] - pair[0]);
+            level.push(rising[index]);
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new(START_COLUMN_NAME.into(), start),
+        Column::new(WIDTH_COLUMN_NAME.into(), width),
+        Column::new(LEVEL_COLUMN_NAME.into(), level),
+    ])?)
+}
+
+/// Whether `width` seconds is no longer than `max_width`, allowing for the rounding of
+/// the time column
+pub(crate) fn is_within(width: f64, max_width: Duration) -> bool {
+    width <= max_width.as_secs_f64() * (1.0 + 1e-9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::BITMAP_COLUMN_NAME;
+
+    fn capture() -> DataFrame {
+        // Bit 1 starts high, then two pulses 2 wide, 5 and 4 apart, the last one cut off
+        df!(
+            TIME_COLUMN_NAME => (0..12).map(f64::from).collect::<Vec<_>>(),
+            BITMAP_COLUMN_NAME => [2u32, 0, 2, 3, 0, 0, 1, 2, 2, 0, 0, 2],
+        )
+        .unwrap()
+    }
+
+    fn floats(df: &DataFrame, column: &str) -> Vec<Option<f64>> {
+        df.column(column)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_edges() {
+        let edges = edges(&capture(), 1).unwrap();
+        assert_eq!(
+            floats(&edges, TIME_COLUMN_NAME),
+            [
+                Some(1.0),
+                Some(2.0),
+                Some(4.0),
+                Some(7.0),
+                Some(9.0),
+                Some(11.0)
+            ]
+        );
+        let rising: Vec<bool> = edges
+            .column(RISING_COLUMN_NAME)
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(rising, [false, true, false, true, false, true]);
+
+        assert!(matches!(
+            super::edges(&capture(), DIGITAL_CHANNELS),
+            Err(DigitalError::BitOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_pulses() {
+        let pulses = pulses(&capture(), 1).unwrap();
+        assert_eq!(floats(&pulses, START_COLUMN_NAME), [Some(2.0), Some(7.0)]);
+        assert_eq!(floats(&pulses, WIDTH_COLUMN_NAME), [Some(2.0), Some(2.0)]);
+        assert_eq!(floats(&pulses, PERIOD_COLUMN_NAME), [Some(5.0), Some(4.0)]);
+        assert_eq!(floats(&pulses, DUTY_COLUMN_NAME), [Some(0.4), Some(0.5)]);
+
+        // Without the rising edge at the end, the last period is unknown
+        let pulses = super::pulses(&capture().head(Some(11)), 1).unwrap();
+        assert_eq!(floats(&pulses, PERIOD_COLUMN_NAME), [Some(5.0), None]);
+
+        // A channel that never changes has no pulses
+        assert_eq!(super::pulses(&capture(), 5).unwrap().height(), 0);
+    }
+
+    #[test]
+    fn test_find_glitches() {
+        // A 1 µs high runt on bit 0 and a 2 µs low one on bit 3
+        let df = df!(
+            TIME_COLUMN_NAME => (0..10).map(|i| f64::from(i) / 1e6).collect::<Vec<_>>(),
+            BITMAP_COLUMN_NAME => [8u32, 8, 9, 8, 8, 0, 0, 8, 8, 8],
+        )
+        .unwrap();
+
+        let glitches = find_glitches(&df, 0, Duration::from_micros(1)).unwrap();
+        assert_eq!(floats(&glitches, START_COLUMN_NAME), [Some(2e-6)]);
+        let level: Vec<bool> = glitches
+            .column(LEVEL_COLUMN_NAME)
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(level, [true]);
+
+        let glitches = find_glitches(&df, 3, Duration::from_micros(1)).unwrap();
+        assert_eq!(glitches.height(), 0);
+        let glitches = find_glitches(&df, 3, Duration::from_micros(2)).unwrap();
+        assert_eq!(floats(&glitches, START_COLUMN_NAME), [Some(5e-6)]);
+    }
+}
@@ -0,0 +1,553 @@
+use crate::capture_frame::CaptureFrame;
+use crate::flea_scope::{CaptureConfigError, FleaProbe, IdleFleaScope, CALIBRATED_COLUMN_NAME};
+use crate::sink::{CaptureSink, SinkError};
+use crate::trigger_config::{DigitalTrigger, TriggerConfig};
+use polars::prelude::*;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DriftLoggerError {
+    #[error("Invalid capture configuration: {0}")]
+    CaptureConfig(#[from] CaptureConfigError),
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not write log: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Capture contained no samples")]
+    EmptyCapture,
+
+    #[error("Could not forward capture: {0}")]
+    Sink(#[from] SinkError),
+}
+
+/// One decimated log entry, summarizing a whole capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogSample {
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+    pub mean_volts: f64,
+    pub min_volts: f64,
+    pub max_volts: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alert {
+    Below { threshold: f64, sample: LogSample },
+    Above { threshold: f64, sample: LogSample },
+}
+
+type AlertHandler = dyn FnMut(&Alert) + Send;
+type RollHandler = dyn FnMut(&VecDeque<LogSample>) + Send;
+
+/// Column names of the log, in both formats
+const COLUMNS: [&str; 4] = ["timestamp", "mean_V", "min_V", "max_V"];
+
+/// File format of the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One line per entry, appended in place
+    Csv,
+    /// Compact and typed. Appending rewrites the file, so it is only ever complete.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl LogFormat {
+    /// Parquet for `.parquet` files if the `parquet` feature is enabled, CSV otherwise
+    pub fn for_path(path: &Path) -> Self {
+        #[cfg(feature = "parquet")]
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "parquet")
+        {
+            return Self::Parquet;
+        }
+        let _ = path;
+        Self::Csv
+    }
+}
+
+/// Periodic low-rate voltage logger for measurements spanning hours or days.
+///
+/// Every `interval` a short capture is taken and reduced to mean/min/max. With
+/// `decimate(n)`, `n` of these are combined into one log entry, so the log stays small no
+/// matter how long it runs. Alerts are checked on every capture.
+///
+/// In roll mode, the latest entries are also kept in memory for a live view, like the
+/// scrolling display of a bench scope; see `roll` and `on_roll`.
+///
+/// ```rust,no_run
+/// use fleascope_rs::drift_logger::DriftLogger;
+/// use fleascope_rs::IdleFleaScope;
+/// use std::sync::atomic::AtomicBool;
+///
+/// let (mut scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let stop = AtomicBool::new(false);
+///
+/// DriftLogger::battery_discharge("discharge.csv")
+///     .alert_below(3.0)
+///     .on_alert(|alert| eprintln!("{alert:?}"))
+///     .run(&mut scope, &x1, &stop)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[must_use]
+pub struct DriftLogger {
+    output: PathBuf,
+    interval: Duration,
+    time_frame: Duration,
+    low_threshold: Option<f64>,
+    high_threshold: Option<f64>,
+    on_alert: Option<Box<AlertHandler>>,
+    sinks: Vec<Box<dyn CaptureSink>>,
+    format: LogFormat,
+    decimation: usize,
+    /// Captures not yet combined into a log entry
+    pending: Vec<LogSample>,
+    roll_entries: usize,
+    recent: VecDeque<LogSample>,
+    on_roll: Option<Box<RollHandler>>,
+}
+
+impl DriftLogger {
+    /// Log to `output`, in the format given by its extension, see `LogFormat::for_path`
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        let output = output.into();
+        Self {
+            format: LogFormat::for_path(&output),
+            output,
+            interval: Duration::from_secs(1),
+            time_frame: Duration::from_millis(20),
+            low_threshold: None,
+            high_threshold: None,
+            on_alert: None,
+            sinks: Vec::new(),
+            decimation: 1,
+            pending: Vec::new(),
+            roll_entries: 0,
+            recent: VecDeque::new(),
+            on_roll: None,
+        }
+    }
+
+    /// Preset for multi-day battery discharge curves: a capture every 10 seconds, averaged
+    /// into one log entry per minute, with the last 24 hours in roll mode. Pass a
+    /// `.parquet` path for a compact log.
+    pub fn battery_discharge(output: impl Into<PathBuf>) -> Self {
+        Self::new(output)
+            .interval(Duration::from_secs(10))
+            .time_frame(Duration::from_millis(100))
+            .decimate(6)
+            .roll(24 * 60)
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Length of each capture that gets averaged into one log entry
+    pub fn time_frame(mut self, time_frame: Duration) -> Self {
+        self.time_frame = time_frame;
+        self
+    }
+
+    pub fn alert_below(mut self, volts: f64) -> Self {
+        self.low_threshold = Some(volts);
+        self
+    }
+
+    pub fn alert_above(mut self, volts: f64) -> Self {
+        self.high_threshold = Some(volts);
+        self
+    }
+
+    pub fn on_alert(mut self, handler: impl FnMut(&Alert) + Send + 'static) -> Self {
+        self.on_alert = Some(Box::new(handler));
+        self
+    }
+
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Combine `captures` consecutive captures into one log entry: the mean of their means,
+    /// the lowest minimum and the highest maximum. 1, the default, logs every capture.
+    pub fn decimate(mut self, captures: usize) -> Self {
+        self.decimation = captures.max(1);
+        self
+    }
+
+    /// Roll mode: keep the last `entries` log entries in memory, see `recent`. 0 turns it off.
+    pub fn roll(mut self, entries: usize) -> Self {
+        self.roll_entries = entries;
+        self
+    }
+
+    /// Called with the roll mode window after every new log entry, e.g. to redraw a plot
+    pub fn on_roll(mut self, handler: impl FnMut(&VecDeque<LogSample>) + Send + 'static) -> Self {
+        self.on_roll = Some(Box::new(handler));
+        self
+    }
+
+    /// The latest log entries kept in roll mode, oldest first
+    pub fn recent(&self) -> &VecDeque<LogSample> {
+        &self.recent
+    }
+
+    /// Additionally forward every full calibrated capture, not just its summary
+    pub fn sink(mut self, sink: impl CaptureSink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Log until `stop` is set
+    pub fn run(
+        mut self,
+        scope: &mut IdleFleaScope,
+        probe: &FleaProbe,
+        stop: &AtomicBool,
+    ) -> Result<(), DriftLoggerError> {
+        while !stop.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            self.log_once(scope, probe)?;
+
+            // Sleep in small steps so a stop request is noticed quickly
+            while started.elapsed() < self.interval && !stop.load(Ordering::Relaxed) {
+                let remaining = self.interval.saturating_sub(started.elapsed());
+                std::thread::sleep(remaining.min(Duration::from_millis(100)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Take one capture, check the alert thresholds and append a log entry once `decimate`
+    /// captures were taken. Returns the summary of this capture.
+    pub fn log_once(
+        &mut self,
+        scope: &mut IdleFleaScope,
+        probe: &FleaProbe,
+    ) -> Result<LogSample, DriftLoggerError> {
+        profiling::scope!("DriftLogger::log_once");
+
+        let trigger = DigitalTrigger::start_capturing_when()
+            .is_matching()
+            .into_trigger_fields();
+        let reading = scope.read_sync(self.time_frame, trigger, None)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let frame = CaptureFrame::new(reading.parse_csv()?).calibrated(probe);
+        for sink in &mut self.sinks {
+            sink.consume(&frame)?;
+        }
+
+        let stats = frame
+            .into_lazy()
+            .select([
+                col(CALIBRATED_COLUMN_NAME).mean().alias("mean"),
+                col(CALIBRATED_COLUMN_NAME).min().alias("min"),
+                col(CALIBRATED_COLUMN_NAME).max().alias("max"),
+            ])
+            .collect()?;
+        let value = |name: &str| -> Result<f64, DriftLoggerError> {
+            stats
+                .column(name)?
+                .f64()?
+                .get(0)
+                .ok_or(DriftLoggerError::EmptyCapture)
+        };
+
+        let sample = LogSample {
+            timestamp,
+            mean_volts: value("mean")?,
+            min_volts: value("min")?,
+            max_volts: value("max")?,
+        };
+        self.check_thresholds(sample);
+        self.record(sample)?;
+
+        Ok(sample)
+    }
+
+    /// Queue the summary of a capture, writing a log entry once enough are queued
+    fn record(&mut self, sample: LogSample) -> Result<(), DriftLoggerError> {
+        self.pending.push(sample);
+        if self.pending.len() < self.decimation {
+            return Ok(());
+        }
+
+        let entry = decimate(&std::mem::take(&mut self.pending));
+        self.append(&entry)?;
+        if self.roll_entries > 0 {
+            if self.recent.len() == self.roll_entries {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(entry);
+            if let Some(handler) = self.on_roll.as_mut() {
+                handler(&self.recent);
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&self, entry: &LogSample) -> Result<(), DriftLoggerError> {
+        match self.format {
+            LogFormat::Csv => Ok(self.append_csv(entry)?),
+            #[cfg(feature = "parquet")]
+            LogFormat::Parquet => self.append_parquet(entry),
+        }
+    }
+
+    fn append_csv(&self, entry: &LogSample) -> Result<(), std::io::Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.output)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", COLUMNS.join(","))?;
+        }
+        writeln!(
+            file,
+            "{:.3},{},{},{}",
+            entry.timestamp, entry.mean_volts, entry.min_volts, entry.max_volts
+        )
+    }
+
+    /// Parquet files can't be appended to in place, so the log is rewritten next to the
+    /// old one and then moved over it. An interrupted write never corrupts the log.
+    #[cfg(feature = "parquet")]
+    fn append_parquet(&self, entry: &LogSample) -> Result<(), DriftLoggerError> {
+        let [timestamp, mean, min, max] = COLUMNS;
+        let row = df!(
+            timestamp => [entry.timestamp],
+            mean => [entry.mean_volts],
+            min => [entry.min_volts],
+            max => [entry.max_volts],
+        )?;
+        let mut log = if self.output.exists() {
+            let mut log = ParquetReader::new(std::fs::File::open(&self.output)?).finish()?;
+            log.vstack_mut(&row)?;
+            log
+        } else {
+            row
+        };
+
+        let partial = self.output.with_extension("parquet.partial");
+        ParquetWriter::new(std::fs::File::create(&partial)?).finish(&mut log)?;
+        std::fs::rename(&partial, &self.output)?;
+        Ok(())
+    }
+
+    fn check_thresholds(&mut self, sample: LogSample) {
+        let Some(handler) = self.on_alert.as_mut() else {
+            return;
+        };
+
+        if let Some(threshold) = self.low_threshold {
+            if sample.mean_volts < threshold {
+                handler(&Alert::Below { threshold, sample });
+            }
+        }
+        if let Some(threshold) = self.high_threshold {
+            if sample.mean_volts > threshold {
+                handler(&Alert::Above { threshold, sample });
+            }
+        }
+    }
+}
+
+/// Combine capture summaries into one log entry, stamped in the middle of them
+fn decimate(samples: &[LogSample]) -> LogSample {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return LogSample {
+            timestamp: 0.0,
+            mean_volts: f64::NAN,
+            min_volts: f64::NAN,
+            max_volts: f64::NAN,
+        };
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let count = samples.len() as f64;
+    LogSample {
+        timestamp: f64::midpoint(first.timestamp, last.timestamp),
+        mean_volts: samples.iter().map(|sample| sample.mean_volts).sum::<f64>() / count,
+        min_volts: samples
+            .iter()
+            .map(|sample| sample.min_volts)
+            .fold(f64::INFINITY, f64::min),
+        max_volts: samples
+            .iter()
+            .map(|sample| sample.max_volts)
+            .fold(f64::NEG_INFINITY, f64::max),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn sample(mean_volts: f64) -> LogSample {
+        LogSample {
+            timestamp: 0.0,
+            mean_volts,
+            min_volts: mean_volts,
+            max_volts: mean_volts,
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&alerts);
+        let mut logger = DriftLogger::battery_discharge("unused.csv")
+            .alert_below(3.0)
+            .alert_above(4.2)
+            .on_alert(move |alert| sink.lock().unwrap().push(*alert));
+
+        logger.check_thresholds(sample(3.7));
+        logger.check_thresholds(sample(2.9));
+        logger.check_thresholds(sample(4.3));
+
+        let alerts = alerts.lock().unwrap().clone();
+        assert_eq!(alerts.len(), 2);
+        assert!(matches!(alerts[0], Alert::Below { .. }));
+        assert!(matches!(alerts[1], Alert::Above { .. }));
+    }
+
+    #[test]
+    fn test_append_writes_header_once() {
+        let path =
+            std::env::temp_dir().join(format!("fleascope_drift_logger_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let logger = DriftLogger::new(&path);
+
+        logger.append(&sample(3.7)).unwrap();
+        logger.append(&sample(3.6)).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "timestamp,mean_V,min_V,max_V",
+                "0.000,3.7,3.7,3.7",
+                "0.000,3.6,3.6,3.6"
+            ]
+        );
+    }
+
+    fn log_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "fleascope_drift_{name}_{}.{extension}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_decimation_and_roll() {
+        let path = log_path("decimated", "csv");
+        let _ = std::fs::remove_file(&path);
+        let windows = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&windows);
+        let mut logger = DriftLogger::new(&path)
+            .decimate(2)
+            .roll(2)
+            .on_roll(move |recent| seen.lock().unwrap().push(recent.len()));
+
+        for (timestamp, volts) in [
+            (0.0, 3.0),
+            (10.0, 4.0),
+            (20.0, 3.5),
+            (30.0, 3.5),
+            (40.0, 1.0),
+        ] {
+            logger
+                .record(LogSample {
+                    timestamp,
+                    ..sample(volts)
+                })
+                .unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "timestamp,mean_V,min_V,max_V",
+                "5.000,3.5,3,4",
+                "25.000,3.5,3.5,3.5"
+            ]
+        );
+        // The fifth capture waits for its partner
+        assert_eq!(logger.pending.len(), 1);
+        assert_eq!(
+            logger
+                .recent()
+                .iter()
+                .map(|entry| entry.timestamp)
+                .collect::<Vec<_>>(),
+            [5.0, 25.0]
+        );
+        assert_eq!(*windows.lock().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_log_once() {
+        let device = crate::simulator::SimulatedDevice::new().calibrated(2048, 1000);
+        let (mut scope, x1, _x10) = device.connect().unwrap();
+        let path = log_path("simulated", "csv");
+        let _ = std::fs::remove_file(&path);
+        let mut logger = DriftLogger::new(&path)
+            .time_frame(Duration::from_millis(1))
+            .decimate(2);
+
+        let sample = logger.log_once(&mut scope, &x1).unwrap();
+        assert!(sample.min_volts <= sample.mean_volts && sample.mean_volts <= sample.max_volts);
+        assert!(!path.exists());
+        logger.log_once(&mut scope, &x1).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_log() {
+        let path = log_path("log", "parquet");
+        let _ = std::fs::remove_file(&path);
+        let mut logger = DriftLogger::new(&path);
+        assert_eq!(logger.format, LogFormat::Parquet);
+
+        logger.record(sample(3.7)).unwrap();
+        logger.record(sample(3.6)).unwrap();
+
+        let log = ParquetReader::new(std::fs::File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(log.get_column_names_str(), COLUMNS);
+        let mean: Vec<f64> = log
+            .column("mean_V")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(mean, [3.7, 3.6]);
+    }
+}
@@ -0,0 +1,405 @@
+//! Signal processing on captured frames
+
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, RAW_COLUMN_NAME, TIME_COLUMN_NAME};
+use polars::prelude::*;
+use std::num::NonZeroUsize;
+
+pub mod filters;
+
+/// How `decimate` reduces each block of samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decimation {
+    /// Two rows per block holding the minimum and maximum in the order they occur, so
+    /// narrow glitches remain visible in a plot
+    MinMax,
+    /// One row per block holding the mean
+    Mean,
+    /// The first row of every block
+    Nth,
+}
+
+/// Reduce `df` to about `factor` times fewer rows for plotting, e.g. a capture or a
+/// concatenation of rolling captures.
+///
+/// Consecutive rows are taken in blocks of `factor`, the last one may be shorter.
+/// `Decimation::MinMax` keeps whole rows: the rows of the minimum and maximum of the
+/// signal, `bnc_calibrated` if present and `bnc_raw` otherwise, or the first float column
+/// other than time. Without one, it keeps the first and last row of each block.
+/// `Decimation::Mean` averages the float columns, e.g. time and voltages, and other
+/// columns, e.g. the bitmap, take the first row of the block.
+///
+/// ```rust
+/// use fleascope_rs::dsp::{decimate, Decimation};
+/// use polars::prelude::*;
+/// use std::num::NonZeroUsize;
+///
+/// let df = df!("time" => [0.0, 1.0, 2.0, 3.0], "bnc_raw" => [5.0, 9.0, 1.0, 5.0])?;
+/// let reduced = decimate(&df, NonZeroUsize::new(4).unwrap(), Decimation::MinMax)?;
+/// let raw: Vec<_> = reduced.column("bnc_raw")?.f64()?.into_no_null_iter().collect();
+/// assert_eq!(raw, [9.0, 1.0]);
+/// # Ok::<(), PolarsError>(())
+/// ```
+pub fn decimate(
+    df: &DataFrame,
+    factor: NonZeroUsize,
+    mode: Decimation,
+) -> Result<DataFrame, PolarsError> {
+    profiling::scope!("decimate");
+
+    let blocks: Vec<(usize, usize)> = (0..df.height())
+        .step_by(factor.get())
+        .map(|start| (start, (start + factor.get()).min(df.height())))
+        .collect();
+    // Rows taken from every column that isn't averaged
+    let rows = match mode {
+        Decimation::MinMax => min_max_rows(df, &blocks)?,
+        Decimation::Mean | Decimation::Nth => indices(blocks.iter().map(|&(start, _)| start)),
+    };
+
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|column| {
+            let series = column.as_materialized_series();
+            let reduced = if mode == Decimation::Mean && series.dtype().is_float() {
+                let values = as_f64(series)?;
+                let means: Vec<Option<f64>> = blocks
+                    .iter()
+                    .map(|&(start, end)| mean(&values[start..end]))
+                    .collect();
+                Series::new(series.name().clone(), means)
+            } else {
+                series.take(&rows)?
+            };
+            Ok(reduced.into())
+        })
+        .collect::<Result<Vec<Column>, PolarsError>>()?;
+    DataFrame::new(columns)
+}
+
+/// How `resample` computes values between the original samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resampling {
+    /// Straight lines between neighbouring samples, cheap but rounds off fast edges
+    Linear,
+    /// Windowed sinc, exact for band-limited signals. Low-pass filters to the new Nyquist
+    /// frequency when reducing the rate.
+    Sinc,
+}
+
+/// Sample periods on each side of the windowed sinc kernel
+const SINC_HALF_WIDTH: f64 = 16.0;
+
+/// Resample a capture to `target_rate` samples per second, e.g. to combine or compare
+/// captures whose effective sample rates differ with their time frame.
+///
+/// The `time` column is replaced by a uniform one from the first sample on, and float
+/// columns, e.g. voltages, are interpolated with `method`. The original samples are
+/// assumed evenly spaced. Other columns, e.g. the bitmap, hold the value of the last
+/// sample at or before each new time.
+///
+/// ```rust
+/// use fleascope_rs::dsp::{resample, Resampling};
+/// use polars::prelude::*;
+///
+/// let df = df!("time" => [0.0, 1.0, 2.0], "bnc_calibrated" => [0.0, 2.0, 0.0])?;
+/// let resampled = resample(&df, 2.0, Resampling::Linear)?;
+/// let volts: Vec<_> = resampled.column("bnc_calibrated")?.f64()?.into_no_null_iter().collect();
+/// assert_eq!(volts, [0.0, 1.0, 2.0, 1.0, 0.0]);
+/// # Ok::<(), PolarsError>(())
+/// ```
+pub fn resample(
+    df: &DataFrame,
+    target_rate: f64,
+    method: Resampling,
+) -> Result<DataFrame, PolarsError> {
+    profiling::scope!("resample");
+
+    if !target_rate.is_finite() || target_rate <= 0.0 {
+        return Err(polars_err!(ComputeError: "invalid sample rate {target_rate}"));
+    }
+    let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+    let (Some(first), Some(last)) = (time.first(), time.last()) else {
+        return Ok(df.clone());
+    };
+    if df.height() < 2 || last <= first {
+        return Ok(df.clone());
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let rate = (df.height() - 1) as f64 / (last - first);
+
+    // Positions of the new samples in units of original samples
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let count = (last - first).mul_add(target_rate, 1e-9).floor() as usize + 1;
+    #[allow(clippy::cast_precision_loss)]
+    let positions: Vec<f64> = (0..count)
+        .map(|index| index as f64 * rate / target_rate)
+        .collect();
+    #[allow(clippy::cast_precision_loss)]
+    let new_time: Vec<f64> = (0..count)
+        .map(|index| first + index as f64 / target_rate)
+        .collect();
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let held = indices(
+        positions
+            .iter()
+            .map(|position| ((position + 1e-9).floor() as usize).min(df.height() - 1)),
+    );
+
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|column| {
+            let series = column.as_materialized_series();
+            let resampled = if series.name() == TIME_COLUMN_NAME {
+                Series::new(series.name().clone(), &new_time)
+            } else if series.dtype().is_float() {
+                let values = as_f64(series)?;
+                let values: Vec<Option<f64>> = positions
+                    .iter()
+                    .map(|&position| match method {
+                        Resampling::Linear => linear(&values, position),
+                        Resampling::Sinc => sinc(&values, position, (target_rate / rate).min(1.0)),
+                    })
+                    .collect();
+                Series::new(series.name().clone(), values)
+            } else {
+                series.take(&held)?
+            };
+            Ok(resampled.into())
+        })
+        .collect::<Result<Vec<Column>, PolarsError>>()?;
+    DataFrame::new(columns)
+}
+
+fn linear(values: &[Option<f64>], position: f64) -> Option<f64> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let below = (position.floor() as usize).min(values.len() - 1);
+    let above = (below + 1).min(values.len() - 1);
+    let fraction = position - position.floor();
+    let (y0, y1) = (values[below]?, values[above]?);
+    Some((y1 - y0).mul_add(fraction, y0))
+}
+
+/// Lanczos windowed sinc at `position`, with the cutoff at `cutoff` times the original
+/// Nyquist frequency. Weights are normalized, so the ends of the capture keep their level.
+fn sinc(values: &[Option<f64>], position: f64, cutoff: f64) -> Option<f64> {
+    let normalized_sinc = |x: f64| {
+        if x.abs() < 1e-12 {
+            1.0
+        } else {
+            (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+        }
+    };
+    let half_width = SINC_HALF_WIDTH / cutoff;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let start = (position - half_width).ceil().max(0.0) as usize;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let end = ((position + half_width).floor().max(0.0) as usize).min(values.len() - 1);
+
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for (index, value) in values.iter().enumerate().take(end + 1).skip(start) {
+        #[allow(clippy::cast_precision_loss)]
+        let distance = position - index as f64;
+        let weight = normalized_sinc(distance * cutoff) * normalized_sinc(distance / half_width);
+        sum += (*value)? * weight;
+        weights += weight;
+    }
+    (weights.abs() > f64::EPSILON).then(|| sum / weights)
+}
+
+/// Rows of the minimum and maximum of the signal in each block, in the order they occur
+fn min_max_rows(df: &DataFrame, blocks: &[(usize, usize)]) -> Result<IdxCa, PolarsError> {
+    let signal = [CALIBRATED_COLUMN_NAME, RAW_COLUMN_NAME]
+        .into_iter()
+        .find_map(|name| df.column(name).ok())
+        .or_else(|| {
+            df.get_columns().iter().find(|column| {
+                column.name().as_str() != TIME_COLUMN_NAME && column.dtype().is_float()
+            })
+        });
+    let Some(signal) = signal else {
+        return Ok(indices(
+            blocks.iter().flat_map(|&(start, end)| [start, end - 1]),
+        ));
+    };
+
+    let values = as_f64(signal.as_materialized_series())?;
+    Ok(indices(blocks.iter().flat_map(|&(start, end)| {
+        let (min, max) = extremes(&values[start..end]);
+        [start + min.min(max), start + min.max(max)]
+    })))
+}
+
+fn indices(indices: impl Iterator<Item = usize>) -> IdxCa {
+    IdxCa::from_vec(
+        "index".into(),
+        indices.map(|index| index as IdxSize).collect(),
+    )
+}
+
+fn as_f64(series: &Series) -> Result<Vec<Option<f64>>, PolarsError> {
+    Ok(series
+        .cast(&DataType::Float64)?
+        .f64()?
+        .into_iter()
+        .collect())
+}
+
+/// Offsets of the first minimum and first maximum, nulls and NaN are skipped
+fn extremes(values: &[Option<f64>]) -> (usize, usize) {
+    let mut min: Option<(usize, f64)> = None;
+    let mut max: Option<(usize, f64)> = None;
+    for (offset, value) in values.iter().enumerate() {
+        let Some(value) = value.filter(|value| !value.is_nan()) else {
+            continue;
+        };
+        if min.is_none_or(|(_, min)| value < min) {
+            min = Some((offset, value));
+        }
+        if max.is_none_or(|(_, max)| value > max) {
+            max = Some((offset, value));
+        }
+    }
+    (
+        min.map_or(0, |(offset, _)| offset),
+        max.map_or(0, |(offset, _)| offset),
+    )
+}
+
+fn mean(values: &[Option<f64>]) -> Option<f64> {
+    let (sum, count) = values
+        .iter()
+        .flatten()
+        .fold((0.0, 0u32), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / f64::from(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::BITMAP_COLUMN_NAME;
+
+    fn frame() -> DataFrame {
+        df!(
+            TIME_COLUMN_NAME => [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            // A one-sample glitch down in the first block, up in the second
+            RAW_COLUMN_NAME => [2000.0, 2000.0, 200.0, 2000.0, 2000.0, 3800.0, 2000.0],
+            BITMAP_COLUMN_NAME => [0u32, 1, 2, 3, 4, 5, 6],
+            "bit_0" => [false, true, false, true, false, true, false],
+        )
+        .unwrap()
+    }
+
+    fn floats(df: &DataFrame, name: &str) -> Vec<f64> {
+        df.column(name)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_decimate_min_max() {
+        let three = NonZeroUsize::new(3).unwrap();
+        let reduced = decimate(&frame(), three, Decimation::MinMax).unwrap();
+
+        assert_eq!(reduced.height(), 6);
+        assert_eq!(
+            floats(&reduced, TIME_COLUMN_NAME),
+            [0.0, 2.0, 3.0, 5.0, 6.0, 6.0]
+        );
+        assert_eq!(
+            floats(&reduced, RAW_COLUMN_NAME),
+            [2000.0, 200.0, 2000.0, 3800.0, 2000.0, 2000.0]
+        );
+        let bitmap: Vec<u32> = reduced
+            .column(BITMAP_COLUMN_NAME)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(bitmap, [0, 2, 3, 5, 6, 6]);
+        let bit: Vec<bool> = reduced
+            .column("bit_0")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        // Every column follows the rows picked from the signal
+        assert_eq!(bit, [false, false, true, true, false, false]);
+    }
+
+    #[test]
+    fn test_decimate_mean_and_nth() {
+        let three = NonZeroUsize::new(3).unwrap();
+        let mean = decimate(&frame(), three, Decimation::Mean).unwrap();
+        assert_eq!(floats(&mean, TIME_COLUMN_NAME), [1.0, 4.0, 6.0]);
+        assert_eq!(floats(&mean, RAW_COLUMN_NAME), [1400.0, 2600.0, 2000.0]);
+
+        let nth = decimate(&frame(), three, Decimation::Nth).unwrap();
+        assert_eq!(floats(&nth, TIME_COLUMN_NAME), [0.0, 3.0, 6.0]);
+
+        let one = NonZeroUsize::new(1).unwrap();
+        assert!(decimate(&frame(), one, Decimation::Nth)
+            .unwrap()
+            .equals(&frame()));
+    }
+
+    #[test]
+    fn test_resample_linear() {
+        // From 1 Hz to 4 Hz, the bitmap holds the previous sample's value
+        let resampled = resample(&frame().head(Some(3)), 4.0, Resampling::Linear).unwrap();
+        assert_eq!(resampled.height(), 9);
+        assert_eq!(
+            floats(&resampled, TIME_COLUMN_NAME),
+            [0.0, 0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0]
+        );
+        assert_eq!(
+            floats(&resampled, RAW_COLUMN_NAME),
+            [2000.0, 2000.0, 2000.0, 2000.0, 2000.0, 1550.0, 1100.0, 650.0, 200.0]
+        );
+        let bitmap: Vec<u32> = resampled
+            .column(BITMAP_COLUMN_NAME)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(bitmap, [0, 0, 0, 0, 1, 1, 1, 1, 2]);
+
+        assert!(resample(&frame(), 0.0, Resampling::Linear).is_err());
+    }
+
+    #[test]
+    fn test_resample_sinc() {
+        // A 50 kHz sine sampled at 1 MHz, upsampled to 4 MHz
+        let sine = |rate: f64, len: u32| {
+            let time: Vec<f64> = (0..len).map(|i| f64::from(i) / rate).collect();
+            let volts: Vec<f64> = time
+                .iter()
+                .map(|t| (std::f64::consts::TAU * 50e3 * t).sin())
+                .collect();
+            df!(TIME_COLUMN_NAME => time, RAW_COLUMN_NAME => volts).unwrap()
+        };
+        let resampled = resample(&sine(1e6, 400), 4e6, Resampling::Sinc).unwrap();
+        let expected = floats(&sine(4e6, 1597), RAW_COLUMN_NAME);
+        let actual = floats(&resampled, RAW_COLUMN_NAME);
+        assert_eq!(actual.len(), expected.len());
+        // Away from the ends, where the kernel is cut off
+        for (actual, expected) in actual.iter().zip(&expected).skip(100).take(1400) {
+            assert!((actual - expected).abs() < 1e-3, "{actual} != {expected}");
+        }
+
+        // Reducing the rate filters out what the new rate can't represent
+        let resampled = resample(&sine(1e6, 400), 80e3, Resampling::Sinc).unwrap();
+        let aliased = floats(&resampled, RAW_COLUMN_NAME);
+        assert!(
+            aliased[4..28].iter().all(|volts| volts.abs() < 0.1),
+            "{aliased:?}"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Noise filters as polars expressions, usually applied to the calibrated voltage column
+//!
+//! The window filters are centered on each sample, so they don't shift edges in time but
+//! look ahead, which is fine on a finished capture. The single-pole filters only look
+//! back and delay the signal like the RC filter they model. They start from the first
+//! sample instead of from zero, so a capture doesn't begin with a settling ramp.
+//!
+//! ```rust
+//! use fleascope_rs::dsp::filters;
+//! use fleascope_rs::flea_scope::CALIBRATED_COLUMN_NAME;
+//! use polars::prelude::*;
+//! use std::num::NonZeroUsize;
+//!
+//! let df = df!(CALIBRATED_COLUMN_NAME => [1.0, 1.0, 4.0, 1.0, 1.0])?;
+//! let window = NonZeroUsize::new(3).unwrap();
+//! let smoothed = df
+//!     .lazy()
+//!     .with_column(filters::median(col(CALIBRATED_COLUMN_NAME), window).alias("smoothed"))
+//!     .collect()?;
+//! let volts: Vec<_> = smoothed.column("smoothed")?.f64()?.into_no_null_iter().collect();
+//! assert_eq!(volts, [1.0, 1.0, 1.0, 1.0, 1.0]);
+//! # Ok::<(), PolarsError>(())
+//! ```
+
+use polars::prelude::*;
+use std::num::NonZeroUsize;
+
+fn centered(window: NonZeroUsize) -> RollingOptionsFixedWindow {
+    RollingOptionsFixedWindow {
+        window_size: window.get(),
+        min_periods: 1,
+        center: true,
+        ..Default::default()
+    }
+}
+
+/// Mean over `window` samples centered on each one, so edges aren't delayed. The
+/// window shrinks at both ends of the capture.
+pub fn moving_average(signal: Expr, window: NonZeroUsize) -> Expr {
+    signal.rolling_mean(centered(window))
+}
+
+/// Median over `window` samples centered on each one. Removes spikes narrower than half
+/// the window while keeping edges sharp.
+pub fn median(signal: Expr, window: NonZeroUsize) -> Expr {
+    signal.rolling_median(centered(window))
+}
+
+/// Smoothing factor of a single-pole filter with the -3 dB point at `cutoff` Hz
+fn alpha(cutoff: f64, sample_rate: f64) -> f64 {
+    1.0 - (-std::f64::consts::TAU * cutoff / sample_rate).exp()
+}
+
+/// Single-pole IIR low-pass with the -3 dB point at `cutoff` Hz, for samples taken at
+/// `sample_rate` Hz. Like the analog RC filter it models, it delays the signal.
+pub fn low_pass(signal: Expr, cutoff: f64, sample_rate: f64) -> Expr {
+    signal.ewm_mean(EWMOptions {
+        alpha: alpha(cutoff, sample_rate),
+        adjust: false,
+        ..Default::default()
+    })
+}
+
+/// Single-pole IIR high-pass, the complement of `low_pass`, e.g. to remove a DC offset
+/// or slow drift
+pub fn high_pass(signal: Expr, cutoff: f64, sample_rate: f64) -> Expr {
+    signal.clone() - low_pass(signal, cutoff, sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::CALIBRATED_COLUMN_NAME;
+
+    fn filtered(volts: &[f64], filter: Expr) -> Vec<f64> {
+        df!(CALIBRATED_COLUMN_NAME => volts)
+            .unwrap()
+            .lazy()
+            .select([filter])
+            .collect()
+            .unwrap()
+            .column(CALIBRATED_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_moving_average_and_median() {
+        let three = NonZeroUsize::new(3).unwrap();
+        let volts = [0.0, 3.0, 0.0, 0.0, 6.0, 6.0];
+        let signal = col(CALIBRATED_COLUMN_NAME);
+
+        assert_eq!(
+            filtered(&volts, moving_average(signal.clone(), three)),
+            [1.5, 1.0, 1.0, 2.0, 4.0, 6.0]
+        );
+        // The spike disappears, the step stays
+        assert_eq!(
+            filtered(&volts, median(signal, three)),
+            [1.5, 0.0, 0.0, 0.0, 6.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn test_single_pole() {
+        let sample_rate = 1_000_000.0;
+        let step = [1.0; 200];
+        let signal = col(CALIBRATED_COLUMN_NAME);
+
+        // A DC level passes the low-pass and is removed by the high-pass
+        let low = filtered(&step, low_pass(signal.clone(), 10_000.0, sample_rate));
+        assert!(low.iter().all(|volts| (volts - 1.0).abs() < 1e-12));
+        let high = filtered(&step, high_pass(signal.clone(), 10_000.0, sample_rate));
+        assert!(high.iter().all(|volts| volts.abs() < 1e-12));
+
+        // After one time constant a step reaches 1 - 1/e
+        let mut rising = [1.0; 200];
+        rising[0] = 0.0;
+        // 1 / (2π 10 kHz) = 15.9µs, 16 samples
+        let samples_per_tau = 16;
+        let low = filtered(&rising, low_pass(signal, 10_000.0, sample_rate));
+        assert!((low[samples_per_tau] - (1.0 - (-1.0f64).exp())).abs() < 0.02);
+        assert!(low.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
@@ -0,0 +1,505 @@
+use crate::capture_frame::CaptureFrame;
+use crate::capture_frame::{bit_column_name, bit_expr};
+use crate::flea_scope::{
+    device_response, CaptureMetadata, FleaProbe, ScopeReading, BITMAP_COLUMN_NAME,
+    CALIBRATED_COLUMN_NAME, RAW_COLUMN_NAME, TIME_COLUMN_NAME,
+};
+use crate::sink::{CaptureSink, SinkError};
+use crate::trigger_config::DIGITAL_CHANNELS;
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+/// Prefix of the header lines written by `write_csv`. Pass it to
+/// `CsvParseOptions::with_comment_prefix` to read the file back with polars.
+pub const COMMENT_PREFIX: &str = "#";
+
+const SAMPLE_RATE: &str = "sample_rate_msps";
+
+const REQUESTED_TIME_FRAME: &str = "requested_time_frame_ns";
+const ACTUAL_TIME_FRAME: &str = "actual_time_frame_ns";
+const DELAY_SAMPLES: &str = "delay_samples";
+const TRIGGER: &str = "trigger";
+const HOSTNAME: &str = "hostname";
+const TRIGGERED_AT: &str = "triggered_at_unix_ns";
+
+/// A metadata entry that is missing (`value` is `None`) or can't be parsed
+#[derive(Debug)]
+pub(crate) struct InvalidEntry {
+    pub key: &'static str,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not read capture: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Header line {0:?} is missing")]
+    MissingHeader(&'static str),
+
+    #[error("Invalid header line {key}: {value:?}")]
+    InvalidHeader { key: &'static str, value: String },
+}
+
+/// Write `df` to `path` as CSV, preceded by comment lines with the sample rate and, if
+/// given, how the capture was taken.
+///
+/// ```rust,no_run
+/// use fleascope_rs::export::write_csv;
+/// use polars::prelude::*;
+///
+/// let df = df!("time" => [0.0, 1e-6], "bnc_calibrated" => [0.1, 0.2])?;
+/// write_csv(&df, "capture.csv", 1.0, None)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn write_csv(
+    df: &DataFrame,
+    path: impl AsRef<Path>,
+    effective_msps: f64,
+    metadata: Option<&CaptureMetadata>,
+) -> Result<(), SinkError> {
+    profiling::scope!("export::write_csv");
+
+    let mut file = File::create(path)?;
+    writeln!(file, "{COMMENT_PREFIX} {SAMPLE_RATE}: {effective_msps}")?;
+    for (key, value) in metadata
+        .map(CaptureMetadata::key_values)
+        .into_iter()
+        .flatten()
+    {
+        writeln!(file, "{COMMENT_PREFIX} {key}: {value}")?;
+    }
+    CsvWriter::new(&mut file).finish(&mut df.clone())?;
+    Ok(())
+}
+
+/// Read a file written by `write_csv`, returning the data, the sample rate and the
+/// metadata, if any. Integer bitmap and bit columns are restored as `u32`.
+///
+/// ```rust,no_run
+/// use fleascope_rs::export::read_csv;
+///
+/// let (df, effective_msps, metadata) = read_csv("capture.csv")?;
+/// println!("{} samples at {effective_msps} MSPS, {metadata:?}", df.height());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn read_csv(
+    path: impl AsRef<Path>,
+) -> Result<(DataFrame, f64, Option<CaptureMetadata>), LoadError> {
+    profiling::scope!("export::read_csv");
+
+    let content = std::fs::read_to_string(path)?;
+    let header: HashMap<&str, &str> = content
+        .lines()
+        .map_while(|line| line.strip_prefix(COMMENT_PREFIX))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+
+    let effective_msps = header
+        .get(SAMPLE_RATE)
+        .ok_or(LoadError::MissingHeader(SAMPLE_RATE))?;
+    let effective_msps = effective_msps
+        .parse()
+        .map_err(|_| LoadError::InvalidHeader {
+            key: SAMPLE_RATE,
+            value: (*effective_msps).to_string(),
+        })?;
+    let metadata = CaptureMetadata::from_key_values(|key| header.get(key).copied())?;
+
+    let mut df = CsvReadOptions::default()
+        .map_parse_options(|options| options.with_comment_prefix(Some(COMMENT_PREFIX)))
+        .into_reader_with_file_handle(std::io::Cursor::new(content.as_bytes()))
+        .finish()?;
+    // CSV has no column types, integers would come back as i64
+    let names = std::iter::once(BITMAP_COLUMN_NAME.to_string())
+        .chain((0..DIGITAL_CHANNELS).map(bit_column_name));
+    for name in names {
+        if let Some(column) = df
+            .column(&name)
+            .ok()
+            .filter(|column| column.dtype().is_integer())
+        {
+            let column = column.cast(&DataType::UInt32)?;
+            df.with_column(column)?;
+        }
+    }
+    Ok((df, effective_msps, metadata))
+}
+
+/// Writes every capture to its own file in a directory with `write_csv`, as
+/// `capture_00000.csv`, `capture_00001.csv` and so on.
+///
+/// The sample rate in the header is derived from the time column. Frames don't carry
+/// `CaptureMetadata`, so it is left out; use `ScopeReading::write_csv` to keep it.
+///
+/// ```rust,no_run
+/// use fleascope_rs::drift_logger::DriftLogger;
+/// use fleascope_rs::export::CsvExportSink;
+///
+/// let logger = DriftLogger::new("drift.csv").sink(CsvExportSink::new("captures"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CsvExportSink {
+    directory: PathBuf,
+    written: usize,
+}
+
+impl CsvExportSink {
+    /// Write into `directory`, which is created on the first capture if needed
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            written: 0,
+        }
+    }
+}
+
+impl CaptureSink for CsvExportSink {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        profiling::scope!("CsvExportSink::consume");
+
+        let df = frame.clone().collect()?;
+        let path = numbered_path(&self.directory, self.written, "csv")?;
+        write_csv(&df, path, sample_rate_msps(&df)?, None)?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+/// Path of the `index`th capture written by a sink into `directory`, creating the directory
+pub(crate) fn numbered_path(
+    directory: &Path,
+    index: usize,
+    extension: &str,
+) -> Result<PathBuf, std::io::Error> {
+    std::fs::create_dir_all(directory)?;
+    Ok(directory.join(format!("capture_{index:05}.{extension}")))
+}
+
+/// Sample rate of a capture, from its time column
+pub(crate) fn sample_rate_msps(df: &DataFrame) -> Result<f64, PolarsError> {
+    let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+    match (time.first(), time.last()) {
+        (Some(first), Some(last)) if last > first => {
+            #[allow(clippy::cast_precision_loss)]
+            let periods = (time.len() - 1) as f64;
+            Ok(periods / (last - first) / 1e6)
+        }
+        _ => Err(polars_err!(
+            ComputeError: "the sample rate needs at least two samples in the time column"
+        )),
+    }
+}
+
+impl From<InvalidEntry> for LoadError {
+    fn from(entry: InvalidEntry) -> Self {
+        match entry.value {
+            None => Self::MissingHeader(entry.key),
+            Some(value) => Self::InvalidHeader {
+                key: entry.key,
+                value,
+            },
+        }
+    }
+}
+
+impl CaptureMetadata {
+    /// Entries stored in the header of exported files, read back by `from_key_values`
+    pub(crate) fn key_values(&self) -> [(&'static str, String); 6] {
+        let triggered_at = self
+            .triggered_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        [
+            (
+                REQUESTED_TIME_FRAME,
+                self.requested_time_frame.as_nanos().to_string(),
+            ),
+            (
+                ACTUAL_TIME_FRAME,
+                self.actual_time_frame.as_nanos().to_string(),
+            ),
+            (DELAY_SAMPLES, self.delay_samples.to_string()),
+            (TRIGGER, self.trigger.clone()),
+            (HOSTNAME, self.hostname.clone()),
+            (TRIGGERED_AT, triggered_at.as_nanos().to_string()),
+        ]
+    }
+
+    /// Restore the metadata written by `key_values`, `None` if the file has none
+    pub(crate) fn from_key_values<'a>(
+        get: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<Option<Self>, InvalidEntry> {
+        if get(TRIGGER).is_none() {
+            return Ok(None);
+        }
+        let string = |key: &'static str| get(key).ok_or(InvalidEntry { key, value: None });
+        let number = |key: &'static str| -> Result<u64, InvalidEntry> {
+            let value = string(key)?;
+            value.parse().map_err(|_| InvalidEntry {
+                key,
+                value: Some(value.to_string()),
+            })
+        };
+
+        let delay_samples = number(DELAY_SAMPLES)?;
+        Ok(Some(Self {
+            requested_time_frame: Duration::from_nanos(number(REQUESTED_TIME_FRAME)?),
+            actual_time_frame: Duration::from_nanos(number(ACTUAL_TIME_FRAME)?),
+            delay_samples: u32::try_from(delay_samples).map_err(|_| InvalidEntry {
+                key: DELAY_SAMPLES,
+                value: Some(delay_samples.to_string()),
+            })?,
+            trigger: string(TRIGGER)?.to_string(),
+            hostname: string(HOSTNAME)?.to_string(),
+            triggered_at: SystemTime::UNIX_EPOCH + Duration::from_nanos(number(TRIGGERED_AT)?),
+        }))
+    }
+}
+
+impl ScopeReading {
+    /// Write the calibrated capture to `path` as CSV with a metadata header, see
+    /// `export::write_csv`. Columns are the time, the raw and the calibrated voltage and
+    /// `bit_0` to `bit_9` as 0 or 1. Load it again with `ScopeReading::from_csv_file`.
+    pub fn write_csv(&self, path: impl AsRef<Path>, probe: &FleaProbe) -> Result<(), SinkError> {
+        profiling::scope!("ScopeReading::write_csv");
+
+        let mut columns = vec![
+            col(TIME_COLUMN_NAME),
+            col(RAW_COLUMN_NAME),
+            col(CALIBRATED_COLUMN_NAME),
+        ];
+        columns.extend((0..DIGITAL_CHANNELS).map(|bit| bit_expr(bit).cast(DataType::UInt32)));
+        let df = probe
+            .apply_calibration(self.parse_csv()?)
+            .select(columns)
+            .collect()?;
+        write_csv(&df, path, self.effective_msps, self.metadata.as_ref())
+    }
+
+    /// Load a capture written by `ScopeReading::write_csv`, to calibrate, decode and measure
+    /// it without a device. The result is the reading that was written, metadata included.
+    ///
+    /// ```rust,no_run
+    /// use fleascope_rs::flea_scope::ScopeReading;
+    /// use fleascope_rs::measurements::Measurements;
+    /// use fleascope_rs::{FleaProbe, ProbeType};
+    ///
+    /// let reading = ScopeReading::from_csv_file("capture.csv")?;
+    /// let probe = FleaProbe::new(ProbeType::X1);
+    /// let df = probe.apply_calibration(reading.parse_csv()?).collect()?;
+    /// println!("{:?}", Measurements::from_dataframe(&df)?);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_csv_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        profiling::scope!("ScopeReading::from_csv_file");
+
+        let (df, effective_msps, metadata) = read_csv(path)?;
+        let raw = df.column(RAW_COLUMN_NAME)?.cast(&DataType::Float64)?;
+        let mut bitmap = vec![0u16; df.height()];
+        for bit in 0..DIGITAL_CHANNELS {
+            // Other exports may leave out channels, those read as low
+            if let Ok(column) = df.column(&bit_column_name(bit)) {
+                let column = column.cast(&DataType::UInt32)?;
+                for (bitmap, value) in bitmap.iter_mut().zip(column.u32()?) {
+                    *bitmap |= u16::from(value.unwrap_or(0) != 0) << bit;
+                }
+            }
+        }
+        let raw = raw
+            .f64()?
+            .into_iter()
+            .map(|raw| {
+                raw.ok_or_else(|| polars_err!(ComputeError: "capture contains empty values"))
+            })
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        Ok(Self {
+            effective_msps,
+            // Rebuild the device's response, so the reading behaves exactly like a fresh one
+            data: device_response(raw.into_iter().zip(bitmap)),
+            metadata,
+            parsed: OnceLock::new(),
+        })
+    }
+
+    /// Load a raw capture as returned by the device, e.g. `ScopeReading::data` saved to a
+    /// file. The sample rate isn't part of it and has to be given.
+    pub fn from_device_dump(
+        path: impl AsRef<Path>,
+        effective_msps: f64,
+    ) -> Result<Self, LoadError> {
+        Ok(Self {
+            effective_msps,
+            data: std::fs::read(path)?,
+            metadata: None,
+            parsed: OnceLock::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::ProbeType;
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fleascope_{name}_{}.csv", std::process::id()))
+    }
+
+    fn reading() -> ScopeReading {
+        ScopeReading {
+            effective_msps: 2.0,
+            metadata: Some(CaptureMetadata {
+                requested_time_frame: Duration::from_micros(1),
+                actual_time_frame: Duration::from_micros(1),
+                delay_samples: 0,
+                trigger: "+0x01 0x01".to_string(),
+                hostname: "bench".to_string(),
+                triggered_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            }),
+            parsed: OnceLock::new(),
+            data: b"2048,0x000\n2148,0x005\n".to_vec(),
+        }
+    }
+
+    fn probe() -> FleaProbe {
+        let mut probe = FleaProbe::new(ProbeType::X1);
+        probe.set_calibration(2048.0, 1000.0);
+        probe
+    }
+
+    #[test]
+    fn test_write_csv_with_header() {
+        let path = path("export");
+        reading().write_csv(&path, &probe()).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let header: Vec<&str> = content.lines().take(7).collect();
+        assert_eq!(
+            header,
+            [
+                "# sample_rate_msps: 2",
+                "# requested_time_frame_ns: 1000",
+                "# actual_time_frame_ns: 1000",
+                "# delay_samples: 0",
+                "# trigger: +0x01 0x01",
+                "# hostname: bench",
+                "# triggered_at_unix_ns: 1700000000000000000",
+            ]
+        );
+
+        let df = CsvReadOptions::default()
+            .map_parse_options(|options| options.with_comment_prefix(Some(COMMENT_PREFIX)))
+            .try_into_reader_with_file_path(Some(path.clone()))
+            .unwrap()
+            .finish()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(df.width(), 3 + DIGITAL_CHANNELS);
+        let bit = |name: &str| -> Vec<i64> {
+            df.column(name)
+                .unwrap()
+                .i64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(bit("bit_0"), [0, 1]);
+        assert_eq!(bit("bit_1"), [0, 0]);
+        assert_eq!(bit("bit_2"), [0, 1]);
+    }
+
+    #[test]
+    fn test_load_round_trip() {
+        let path = path("load");
+        let original = reading();
+        original.write_csv(&path, &probe()).unwrap();
+        let loaded = ScopeReading::from_csv_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.data, original.data);
+        assert_eq!(loaded.metadata, original.metadata);
+        assert_eq!(loaded.samples().unwrap(), original.samples().unwrap());
+
+        // Files written without metadata load as well, e.g. replays
+        let original = ScopeReading {
+            metadata: None,
+            ..reading()
+        };
+        original.write_csv(&path, &probe()).unwrap();
+        let loaded = ScopeReading::from_csv_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.metadata, None);
+        assert_eq!(loaded.data, original.data);
+
+        // Unnamed devices leave the value empty
+        let mut original = reading();
+        original.metadata.as_mut().unwrap().hostname = String::new();
+        original.write_csv(&path, &probe()).unwrap();
+        let loaded = ScopeReading::from_csv_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.metadata, original.metadata);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let path = path("invalid");
+        std::fs::write(&path, "time,bnc_raw\n0.0,2048\n").unwrap();
+        assert!(matches!(
+            ScopeReading::from_csv_file(&path),
+            Err(LoadError::MissingHeader(SAMPLE_RATE))
+        ));
+
+        std::fs::write(&path, "# sample_rate_msps: 2\n# trigger: ~\ntime\n").unwrap();
+        assert!(matches!(
+            ScopeReading::from_csv_file(&path),
+            Err(LoadError::MissingHeader(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_device_dump() {
+        let path = path("dump");
+        std::fs::write(&path, &reading().data).unwrap();
+        let loaded = ScopeReading::from_device_dump(&path, 2.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.samples().unwrap(), reading().samples().unwrap());
+    }
+
+    #[test]
+    fn test_csv_export_sink() {
+        let directory =
+            std::env::temp_dir().join(format!("fleascope_csv_sink_{}", std::process::id()));
+        let frame = CaptureFrame::new(
+            df!(TIME_COLUMN_NAME => [0.0, 0.5e-6], CALIBRATED_COLUMN_NAME => [0.1, 0.2])
+                .unwrap()
+                .lazy(),
+        );
+        let mut sink = CsvExportSink::new(&directory);
+        sink.consume(&frame).unwrap();
+        sink.consume(&frame).unwrap();
+
+        let (first, effective_msps, metadata) =
+            read_csv(directory.join("capture_00000.csv")).unwrap();
+        let (second, _, _) = read_csv(directory.join("capture_00001.csv")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(first.equals(&frame.collect().unwrap()));
+        assert!(second.equals(&first));
+        assert!((effective_msps - 2.0).abs() < 1e-9);
+        assert_eq!(metadata, None);
+    }
+}
@@ -0,0 +1,185 @@
+use super::{numbered_path, InvalidEntry};
+use crate::capture_frame::CaptureFrame;
+use crate::flea_scope::CaptureMetadata;
+use crate::sink::{CaptureSink, SinkError};
+use polars::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not access archive: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid capture metadata {key}: {value:?}")]
+    InvalidMetadata { key: String, value: Option<String> },
+}
+
+/// Prefix of the file-level key/value metadata written by `write`
+const KEY_PREFIX: &str = "fleascope.";
+
+impl From<InvalidEntry> for ArchiveError {
+    fn from(entry: InvalidEntry) -> Self {
+        Self::InvalidMetadata {
+            key: entry.key.to_string(),
+            value: entry.value,
+        }
+    }
+}
+
+/// Write `df` to `path` as Parquet, with `metadata` stored in the file's key/value
+/// metadata so `read` can restore it.
+///
+/// ```rust,no_run
+/// use fleascope_rs::export::parquet;
+/// use polars::prelude::*;
+///
+/// let df = df!("time" => [0.0, 1e-6], "bnc_calibrated" => [0.1, 0.2])?;
+/// parquet::write(&df, "capture.parquet", None)?;
+/// let (archived, metadata) = parquet::read("capture.parquet")?;
+/// assert!(archived.equals(&df) && metadata.is_none());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn write(
+    df: &DataFrame,
+    path: impl AsRef<Path>,
+    metadata: Option<&CaptureMetadata>,
+) -> Result<(), ArchiveError> {
+    profiling::scope!("export::parquet::write");
+
+    let key_value = metadata.map(|metadata| {
+        KeyValueMetadata::from_static(
+            metadata
+                .key_values()
+                .into_iter()
+                .map(|(key, value)| (format!("{KEY_PREFIX}{key}"), value))
+                .collect(),
+        )
+    });
+
+    ParquetWriter::new(File::create(path)?)
+        .with_key_value_metadata(key_value)
+        .finish(&mut df.clone())?;
+    Ok(())
+}
+
+/// Read a file written by `write`. The metadata is `None` if the file was written
+/// without it, e.g. by another tool.
+pub fn read(path: impl AsRef<Path>) -> Result<(DataFrame, Option<CaptureMetadata>), ArchiveError> {
+    profiling::scope!("export::parquet::read");
+
+    let mut reader = ParquetReader::new(File::open(path)?);
+    let key_value = reader
+        .get_metadata()?
+        .key_value_metadata()
+        .clone()
+        .unwrap_or_default();
+    let metadata = CaptureMetadata::from_key_values(|key| {
+        key_value
+            .iter()
+            .find(|pair| pair.key.strip_prefix(KEY_PREFIX) == Some(key))
+            .and_then(|pair| pair.value.as_deref())
+    })?;
+
+    Ok((reader.finish()?, metadata))
+}
+
+/// Writes every capture to its own archive in a directory with `write`, as
+/// `capture_00000.parquet`, `capture_00001.parquet` and so on. Frames don't carry
+/// `CaptureMetadata`, so the archives have none.
+#[derive(Debug, Clone)]
+pub struct ParquetSink {
+    directory: PathBuf,
+    written: usize,
+}
+
+impl ParquetSink {
+    /// Write into `directory`, which is created on the first capture if needed
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            written: 0,
+        }
+    }
+}
+
+impl CaptureSink for ParquetSink {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        profiling::scope!("ParquetSink::consume");
+
+        let df = frame.clone().collect()?;
+        let path = numbered_path(&self.directory, self.written, "parquet")?;
+        write(&df, path, None).map_err(|e| match e {
+            ArchiveError::Data(e) => SinkError::Data(e),
+            ArchiveError::Io(e) => SinkError::Io(e),
+            e @ ArchiveError::InvalidMetadata { .. } => SinkError::Io(std::io::Error::other(e)),
+        })?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurements::Measurements;
+    use std::time::{Duration, SystemTime};
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fleascope_{name}_{}.parquet", std::process::id()))
+    }
+
+    #[test]
+    fn test_parquet_round_trip() {
+        let path = path("archive");
+        let metadata = CaptureMetadata {
+            requested_time_frame: Duration::from_millis(1),
+            actual_time_frame: Duration::from_micros(1_001),
+            delay_samples: 12,
+            trigger: "+0x01 0x01".to_string(),
+            hostname: "bench".to_string(),
+            triggered_at: SystemTime::UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789),
+        };
+        let time: Vec<f64> = (0..100).map(|i| f64::from(i) / 1e6).collect();
+        let volts: Vec<f64> = (0..100).map(|i| f64::from(i % 10) / 10.0).collect();
+        let df = df!("time" => time, "bnc_calibrated" => volts).unwrap();
+
+        write(&df, &path, Some(&metadata)).unwrap();
+        let (archived, restored) = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(archived.equals(&df));
+        assert_eq!(restored, Some(metadata));
+        // Archives feed straight into the analysis tools
+        assert!(Measurements::from_dataframe(&archived).is_ok());
+    }
+
+    #[test]
+    fn test_parquet_without_metadata() {
+        let path = path("plain");
+        let df = df!("time" => [0.0, 1.0]).unwrap();
+        write(&df, &path, None).unwrap();
+        let (_, metadata) = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(metadata, None);
+    }
+
+    #[test]
+    fn test_parquet_sink() {
+        let directory =
+            std::env::temp_dir().join(format!("fleascope_parquet_sink_{}", std::process::id()));
+        let frame = CaptureFrame::new(df!("time" => [0.0, 1.0], "v" => [1.5, 2.5]).unwrap().lazy());
+        let mut sink = ParquetSink::new(&directory);
+        sink.consume(&frame).unwrap();
+        sink.consume(&frame).unwrap();
+
+        let (first, metadata) = read(directory.join("capture_00000.parquet")).unwrap();
+        let (second, _) = read(directory.join("capture_00001.parquet")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(first.equals(&frame.collect().unwrap()));
+        assert!(second.equals(&first));
+        assert_eq!(metadata, None);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::flea_connector::FleaConnectorError;
+use crate::flea_scope::{FleaProbe, IdleFleaScope};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FarmError {
+    #[error("Could not read farm config: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid farm config line {line}: {content:?}")]
+    InvalidLine { line: usize, content: String },
+
+    #[error("Role {role} is assigned more than once")]
+    DuplicateRole { role: String },
+
+    #[error("Could not connect to {device} for role {role}: {source}")]
+    Connect {
+        role: String,
+        device: String,
+        source: FleaConnectorError,
+    },
+
+    #[error("Health check for role {role} failed: expected hostname {expected}, found {found}")]
+    HealthCheckFailed {
+        role: String,
+        expected: String,
+        found: String,
+    },
+}
+
+/// Mapping of logical roles to device names (USB product name / hostname).
+///
+/// The file format is one `role = device` pair per line; blank lines and lines
+/// starting with `#` are ignored:
+///
+/// ```text
+/// # Bench 3
+/// power_rail_scope = FleaScope-PSU
+/// clock_scope = FleaScope-CLK
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FarmConfig {
+    roles: Vec<(String, String)>,
+}
+
+impl FarmConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FarmError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(config: &str) -> Result<Self, FarmError> {
+        let mut farm_config = Self::new();
+
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || FarmError::InvalidLine {
+                line: index + 1,
+                content: line.to_string(),
+            };
+            let (role, device) = line.split_once('=').ok_or_else(invalid_line)?;
+            let (role, device) = (role.trim(), device.trim());
+            if role.is_empty() || device.is_empty() {
+                return Err(invalid_line());
+            }
+
+            farm_config = farm_config.with_role(role, device)?;
+        }
+
+        Ok(farm_config)
+    }
+
+    /// Assign a device to a role
+    pub fn with_role(mut self, role: &str, device: &str) -> Result<Self, FarmError> {
+        if self.roles.iter().any(|(r, _)| r == role) {
+            return Err(FarmError::DuplicateRole {
+                role: role.to_string(),
+            });
+        }
+        self.roles.push((role.to_string(), device.to_string()));
+        Ok(self)
+    }
+
+    pub fn roles(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.roles
+            .iter()
+            .map(|(role, device)| (role.as_str(), device.as_str()))
+    }
+}
+
+/// A connected scope together with its calibrated probes
+pub struct FarmMember {
+    pub scope: IdleFleaScope,
+    pub x1: FleaProbe,
+    pub x10: FleaProbe,
+}
+
+/// A set of connected `FleaScope`s addressed by role
+pub struct Farm {
+    members: HashMap<String, FarmMember>,
+}
+
+impl Farm {
+    /// Connect to every device in the config and check it reports the expected hostname.
+    /// If any of them fails, the ones already connected are disconnected again.
+    pub fn connect(config: &FarmConfig) -> Result<Self, FarmError> {
+        Self::connect_with(config, |device| {
+            IdleFleaScope::connect(Some(device), None, true)
+        })
+    }
+
+    fn connect_with(
+        config: &FarmConfig,
+        mut open: impl FnMut(&str) -> Result<(IdleFleaScope, FleaProbe, FleaProbe), FleaConnectorError>,
+    ) -> Result<Self, FarmError> {
+        let mut farm = Self {
+            members: HashMap::new(),
+        };
+
+        for (role, device) in config.roles() {
+            log::debug!("Connecting to {device} as {role}");
+            let (scope, x1, x10) = match open(device) {
+                Ok(connection) => connection,
+                Err(source) => {
+                    farm.teardown();
+                    return Err(FarmError::Connect {
+                        role: role.to_string(),
+                        device: device.to_string(),
+                        source,
+                    });
+                }
+            };
+
+            let found = scope.hostname().trim().to_string();
+            if found != device {
+                scope.teardown();
+                farm.teardown();
+                return Err(FarmError::HealthCheckFailed {
+                    role: role.to_string(),
+                    expected: device.to_string(),
+                    found,
+                });
+            }
+
+            farm.members
+                .insert(role.to_string(), FarmMember { scope, x1, x10 });
+        }
+
+        Ok(farm)
+    }
+
+    pub fn get(&self, role: &str) -> Option<&FarmMember> {
+        self.members.get(role)
+    }
+
+    pub fn get_mut(&mut self, role: &str) -> Option<&mut FarmMember> {
+        self.members.get_mut(role)
+    }
+
+    pub fn roles(&self) -> impl Iterator<Item = &str> {
+        self.members.keys().map(String::as_str)
+    }
+
+    /// Disconnect from all devices
+    pub fn teardown(self) {
+        for member in self.members.into_values() {
+            member.scope.teardown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = FarmConfig::parse(
+            "# Bench 3\n\npower_rail_scope = FleaScope-PSU\n  clock_scope=FleaScope-CLK  \n",
+        )
+        .unwrap();
+
+        let roles: Vec<_> = config.roles().collect();
+        assert_eq!(
+            roles,
+            vec![
+                ("power_rail_scope", "FleaScope-PSU"),
+                ("clock_scope", "FleaScope-CLK")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert!(matches!(
+            FarmConfig::parse("a = x\nnonsense\n"),
+            Err(FarmError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            FarmConfig::parse("a = \n"),
+            Err(FarmError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            FarmConfig::parse("a = x\na = y\n"),
+            Err(FarmError::DuplicateRole { .. })
+        ));
+    }
+
+    #[test]
+    fn test_connect_disconnects_on_failure() {
+        use crate::simulator::SimulatedDevice;
+
+        let psu = SimulatedDevice::new().hostname("FleaScope-PSU");
+        let clk = SimulatedDevice::new().hostname("FleaScope-OTHER");
+        let config = FarmConfig::parse(
+            "psu = FleaScope-PSU
+clk = FleaScope-CLK
+",
+        )
+        .unwrap();
+        let result = Farm::connect_with(&config, |device| {
+            let simulated = if device == "FleaScope-PSU" {
+                &psu
+            } else {
+                &clk
+            };
+            Ok(simulated.connect()?)
+        });
+
+        assert!(matches!(result, Err(FarmError::HealthCheckFailed { .. })));
+        for device in [&psu, &clk] {
+            assert!(device
+                .commands()
+                .ends_with(&["echo on".to_string(), "prompt on".to_string()]));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Field upgrades without the vendor tool.
+//!
+//! No released firmware accepts an image over the serial link, so upgrades go through the
+//! bootloader's USB mass-storage drive:
+//!
+//! 1. Load the image with `FirmwareImage::load`. Every record is checked, so a corrupted
+//!    download is refused before the device is touched.
+//! 2. Start the device in its bootloader as described in its manual. It shows up as a
+//!    USB drive instead of a serial port.
+//! 3. `flash_mass_storage` copies the image onto the drive and reads it back.
+//! 4. Eject the drive. The bootloader writes the image and restarts into the new
+//!    firmware, which is connected to as usual. `IdleFleaScope::device_info` tells
+//!    whether the upgrade took.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Bytes written between two progress reports
+const CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FirmwareError {
+    #[error("Failed to access firmware image: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {line} of the firmware image is not a valid S-record: {reason}")]
+    Malformed { line: usize, reason: &'static str },
+
+    #[error("Checksum mismatch in line {line} of the firmware image")]
+    Checksum { line: usize },
+
+    #[error("Firmware image has no termination record")]
+    NoTermination,
+
+    #[error("The image read back from the drive differs from the one written")]
+    VerifyFailed,
+}
+
+/// A firmware image in Motorola S-record format, as read by the bootloader.
+///
+/// Every record's checksum is verified when parsing, so a corrupted file is refused before
+/// anything is written to the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareImage {
+    records: Vec<String>,
+    data_len: usize,
+}
+
+impl FirmwareImage {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FirmwareError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, FirmwareError> {
+        let mut records = Vec::new();
+        let mut data_len = 0;
+        let mut terminated = false;
+        for (index, record) in text.lines().map(str::trim).enumerate() {
+            if record.is_empty() {
+                continue;
+            }
+            let line = index + 1;
+            let malformed = |reason| FirmwareError::Malformed { line, reason };
+
+            let kind = record
+                .strip_prefix('S')
+                .and_then(|rest| rest.chars().next())
+                .ok_or_else(|| malformed("missing S prefix"))?;
+            let address_len = match kind {
+                '0' | '1' | '5' | '9' => 2,
+                '2' | '6' | '8' => 3,
+                '3' | '7' => 4,
+                _ => return Err(malformed("unknown record type")),
+            };
+            let bytes = decode_hex(&record[2..]).ok_or_else(|| malformed("invalid hex"))?;
+            let (&count, rest) = bytes.split_first().ok_or_else(|| malformed("empty"))?;
+            if usize::from(count) != rest.len() || rest.len() <= address_len {
+                return Err(malformed("wrong byte count"));
+            }
+            let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if sum != 0xff {
+                return Err(FirmwareError::Checksum { line });
+            }
+
+            match kind {
+                '1'..='3' => data_len += rest.len() - address_len - 1,
+                '7'..='9' => terminated = true,
+                _ => {}
+            }
+            records.push(record.to_string());
+        }
+
+        if !terminated {
+            return Err(FirmwareError::NoTermination);
+        }
+        Ok(Self { records, data_len })
+    }
+
+    /// Number of bytes to be written to flash
+    pub fn data_len(&self) -> usize {
+        self.data_len
+    }
+
+    pub fn records(&self) -> &[String] {
+        &self.records
+    }
+
+    /// The image as an S-record file
+    fn to_file_contents(&self) -> String {
+        let mut contents = self.records.join("\r\n");
+        contents.push_str("\r\n");
+        contents
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Progress reported by `flash_mass_storage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashProgress {
+    /// `written` of `total` bytes were copied to the drive
+    Writing { written: usize, total: usize },
+    /// Everything was written, reading it back
+    Verifying,
+}
+
+/// Copy `image` to `destination`, a file on the drive of a device in its bootloader, and
+/// read it back to verify it. See the module docs for the whole upgrade.
+///
+/// ```rust,no_run
+/// use fleascope_rs::firmware::{self, FirmwareImage, FlashProgress};
+///
+/// let image = FirmwareImage::load("fleascope.S19")?;
+/// firmware::flash_mass_storage(&image, "/media/BOOTLOADER/fleascope.S19", |progress| {
+///     if let FlashProgress::Writing { written, total } = progress {
+///         println!("{written}/{total}");
+///     }
+/// })?;
+/// // Now eject the drive and connect to the restarted device
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn flash_mass_storage(
+    image: &FirmwareImage,
+    destination: impl AsRef<Path>,
+    mut progress: impl FnMut(FlashProgress),
+) -> Result<(), FirmwareError> {
+    profiling::scope!("firmware::flash_mass_storage");
+
+    let contents = image.to_file_contents();
+    let total = contents.len();
+    let mut file = File::create(&destination)?;
+    let mut written = 0;
+    for chunk in contents.as_bytes().chunks(CHUNK_SIZE) {
+        file.write_all(chunk)?;
+        written += chunk.len();
+        progress(FlashProgress::Writing { written, total });
+    }
+    // The bootloader may start flashing as soon as the drive is ejected
+    file.sync_all()?;
+    drop(file);
+
+    progress(FlashProgress::Verifying);
+    let mut read_back = String::with_capacity(total);
+    File::open(&destination)?.read_to_string(&mut read_back)?;
+    if read_back != contents {
+        return Err(FirmwareError::VerifyFailed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE: &str = "S00600004844521B\r\n\
+        S1130000285F245F2212226A000424290008237C2A\r\n\
+        S9030000FC\r\n";
+
+    #[test]
+    fn test_parse_image() {
+        let image = FirmwareImage::parse(IMAGE).unwrap();
+        assert_eq!(image.records().len(), 3);
+        assert_eq!(image.data_len(), 16);
+
+        let corrupted = IMAGE.replace("285F", "285E");
+        assert!(matches!(
+            FirmwareImage::parse(&corrupted),
+            Err(FirmwareError::Checksum { line: 2 })
+        ));
+        assert!(matches!(
+            FirmwareImage::parse("S1130000285F245F2212226A000424290008237C2A"),
+            Err(FirmwareError::NoTermination)
+        ));
+        assert!(matches!(
+            FirmwareImage::parse("hello"),
+            Err(FirmwareError::Malformed { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_flash_mass_storage() {
+        let path =
+            std::env::temp_dir().join(format!("fleascope_firmware_{}.S19", std::process::id()));
+        let image = FirmwareImage::parse(IMAGE).unwrap();
+        let mut reports = Vec::new();
+        flash_mass_storage(&image, &path, |progress| reports.push(progress)).unwrap();
+
+        let written = FirmwareImage::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, image);
+        assert_eq!(
+            reports,
+            [
+                FlashProgress::Writing {
+                    written: IMAGE.len(),
+                    total: IMAGE.len()
+                },
+                FlashProgress::Verifying
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::flea_scope::{parse_device_integer, IdleFleaScope};
+use crate::serial_terminal::IdleFleaTerminal;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FlashVarError {
+    #[error("{0:?} is not a valid variable name")]
+    InvalidName(String),
+
+    #[error("Variable {name} is not declared in flash")]
+    NotDeclared { name: String },
+
+    #[error("Could not parse value of {name}: {raw:?}")]
+    ParseFailed { name: String, raw: String },
+
+    #[error("Value {value} of {name} doesn't fit the requested type")]
+    OutOfRange { name: String, value: i32 },
+
+    #[error("Device rejected {command:?}: {response}")]
+    Rejected { command: String, response: String },
+}
+
+/// Integer variables kept in the device's flash across power cycles, e.g. calibrations or
+/// settings for a startup script.
+///
+/// Variables are declared on first use; a fresh variable reads as 0. The autorun script
+/// isn't managed here, only variables.
+///
+/// ```rust,no_run
+/// use fleascope_rs::IdleFleaScope;
+///
+/// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let mut vars = scope.flash_vars();
+/// vars.set("bench_id", 3)?;
+/// let enabled = vars.get::<i32>("logging_on")? != 0;
+/// let all = vars.read(&["bench_id", "cal_zero_x1"])?;
+/// println!("{enabled} {all:?}");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct FlashVars<'a> {
+    serial: &'a mut IdleFleaTerminal,
+}
+
+impl<'a> FlashVars<'a> {
+    pub(crate) fn new(serial: &'a mut IdleFleaTerminal) -> Self {
+        Self { serial }
+    }
+
+    /// Read several variables in a single pipelined round-trip
+    pub fn read(&mut self, names: &[&str]) -> Result<BTreeMap<String, i32>, FlashVarError> {
+        profiling::scope!("FlashVars::read");
+
+        let mut commands = vec![declaration(names)?];
+        commands.extend(names.iter().map(|name| format!("print {name}")));
+        let commands: Vec<&str> = commands.iter().map(String::as_str).collect();
+        let responses = self.serial.exec_many(&commands, None);
+
+        // Redeclaring only complains, that's how existing variables are found
+        let declared = responses.first().map_or(&[][..], Vec::as_slice);
+        if let Some(line) = String::from_utf8_lossy(declared)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.contains("already declared"))
+        {
+            return Err(FlashVarError::Rejected {
+                command: commands[0].to_string(),
+                response: line.to_string(),
+            });
+        }
+
+        names
+            .iter()
+            .zip(&commands[1..])
+            .zip(
+                responses
+                    .iter()
+                    .skip(1)
+                    .chain(std::iter::repeat(&Vec::new())),
+            )
+            .map(|((name, command), response)| {
+                let raw = String::from_utf8_lossy(response).trim().to_string();
+                let value = match error_reply(&raw) {
+                    Some(_) if raw.contains("not declared") || raw.contains("undeclared") => {
+                        return Err(FlashVarError::NotDeclared {
+                            name: (*name).to_string(),
+                        })
+                    }
+                    Some(error) => {
+                        return Err(FlashVarError::Rejected {
+                            command: (*command).to_string(),
+                            response: error.to_string(),
+                        })
+                    }
+                    None => parse_device_integer(response),
+                };
+                let value = value.ok_or_else(|| FlashVarError::ParseFailed {
+                    name: (*name).to_string(),
+                    raw,
+                })?;
+                Ok(((*name).to_string(), value))
+            })
+            .collect()
+    }
+
+    /// Read a variable as any type an `i32` converts into, e.g. `u16` or `i64`
+    pub fn get<T: TryFrom<i32>>(&mut self, name: &str) -> Result<T, FlashVarError> {
+        let value = self.read(&[name])?.remove(name).unwrap_or_default();
+        T::try_from(value).map_err(|_| FlashVarError::OutOfRange {
+            name: name.to_string(),
+            value,
+        })
+    }
+
+    pub fn set(&mut self, name: &str, value: impl Into<i32>) -> Result<(), FlashVarError> {
+        profiling::scope!("FlashVars::set");
+
+        let assignment = format!("{name} = {}", value.into());
+        let responses = self
+            .serial
+            .exec_many(&[&declaration(&[name])?, &assignment], None);
+        match responses.get(1) {
+            Some(response) if response.trim_ascii().is_empty() => Ok(()),
+            response => Err(FlashVarError::Rejected {
+                command: assignment,
+                response: String::from_utf8_lossy(response.map_or(&[][..], Vec::as_slice))
+                    .trim()
+                    .to_string(),
+            }),
+        }
+    }
+}
+
+/// The firmware's message if `response` is an error rather than a value, e.g.
+/// `var 'x' not declared` or `error: ...`
+fn error_reply(response: &str) -> Option<&str> {
+    let response = response.trim();
+    let is_error = response.starts_with("error")
+        || response.contains("not declared")
+        || response.contains("undeclared");
+    is_error.then_some(response)
+}
+
+/// `dim` command declaring all `names` as flash variables
+fn declaration(names: &[&str]) -> Result<String, FlashVarError> {
+    if let Some(name) = names.iter().find(|name| !is_identifier(name)) {
+        return Err(FlashVarError::InvalidName((*name).to_string()));
+    }
+    let declarations: Vec<String> = names
+        .iter()
+        .map(|name| format!("{name} as flash"))
+        .collect();
+    Ok(format!("dim {}", declarations.join(", ")))
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl IdleFleaScope {
+    /// Variables persisted in the device's flash, see `FlashVars`
+    pub fn flash_vars(&mut self) -> FlashVars<'_> {
+        FlashVars::new(self.serial_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{RecordedSession, SimulatedDevice};
+
+    #[test]
+    fn test_flash_vars() {
+        let device = SimulatedDevice::new().variable("bench_id", 7);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let mut vars = scope.flash_vars();
+
+        assert_eq!(vars.get::<u16>("bench_id").unwrap(), 7);
+        // Fresh variables start at 0
+        assert_eq!(vars.get::<i32>("run_count").unwrap(), 0);
+        vars.set("run_count", 42).unwrap();
+        vars.set("offset", -5).unwrap();
+
+        let all = vars.read(&["bench_id", "run_count", "offset"]).unwrap();
+        assert_eq!(
+            all.into_iter().collect::<Vec<_>>(),
+            [
+                ("bench_id".to_string(), 7),
+                ("offset".to_string(), -5),
+                ("run_count".to_string(), 42)
+            ]
+        );
+        assert_eq!(
+            vars.get::<u8>("offset"),
+            Err(FlashVarError::OutOfRange {
+                name: "offset".to_string(),
+                value: -5
+            })
+        );
+        assert_eq!(
+            vars.set("x = 1\nreset", 0),
+            Err(FlashVarError::InvalidName("x = 1\nreset".to_string()))
+        );
+        assert!(!device.commands().iter().any(|c| c == "reset"));
+    }
+
+    #[test]
+    fn test_error_replies() {
+        let mut session = RecordedSession::new();
+        session.push("print bench_id", "error: flash read failed 12\r\n");
+        session.push("dim full as flash", "error: out of variable space\r\n");
+        let device = SimulatedDevice::new()
+            .variable("bench_id", 7)
+            .session(&session);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let mut vars = scope.flash_vars();
+
+        assert_eq!(
+            vars.get::<i32>("bench_id"),
+            Err(FlashVarError::Rejected {
+                command: "print bench_id".to_string(),
+                response: "error: flash read failed 12".to_string()
+            })
+        );
+        assert!(matches!(
+            vars.read(&["full"]),
+            Err(FlashVarError::Rejected { command, .. }) if command == "dim full as flash"
+        ));
+    }
+}
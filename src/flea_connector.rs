@@ -1,4 +1,7 @@
-use crate::serial_terminal::{FleaTerminalError, IdleFleaTerminal, StatelessFleaTerminal};
+use crate::capabilities::FirmwareVersion;
+use crate::serial_terminal::{
+    FleaTerminalError, IdleFleaTerminal, StatelessFleaTerminal, TerminalDialect,
+};
 use std::thread;
 use std::time::Duration;
 
@@ -6,6 +9,20 @@ use std::time::Duration;
 pub struct FleaDevice {
     pub name: String,
     pub port: String,
+    hostname: Option<String>,
+    version: Option<String>,
+}
+
+impl FleaDevice {
+    /// Reported by the device, only set by `get_validated_devices`
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// Firmware version reported by the device, only set by `get_validated_devices`
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -26,24 +43,53 @@ pub enum FleaConnectorError {
 
     #[error("Device validation failed")]
     DeviceValidationFailed,
+
+    #[error("Several devices are named {hostname}, on {}", ports.join(", "))]
+    DuplicateHostname {
+        hostname: String,
+        ports: Vec<String>,
+    },
+
+    #[error("Firmware {found:?} is not supported, {required} or newer is required")]
+    UnsupportedFirmware {
+        /// The raw `ver` response
+        found: String,
+        required: FirmwareVersion,
+    },
 }
 
 pub struct FleaConnector;
 
 impl FleaConnector {
+    /// Number of `reset` commands sent to an unresponsive device before resorting to DTR/RTS
+    const SOFT_RESET_ATTEMPTS: u32 = 3;
+    /// How long `find_by_hostname` waits for each candidate to answer
+    const HOSTNAME_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
     /// Connect to a `FleaScope` device
     pub fn connect(
         name: Option<&str>,
         port: Option<&str>,
         _read_calibrations: bool,
+    ) -> Result<IdleFleaTerminal, FleaConnectorError> {
+        Self::connect_with_dialect(name, port, &TerminalDialect::default())
+    }
+
+    /// Connect to a device whose firmware uses different REPL conventions
+    pub fn connect_with_dialect(
+        name: Option<&str>,
+        port: Option<&str>,
+        dialect: &TerminalDialect,
     ) -> Result<IdleFleaTerminal, FleaConnectorError> {
         let terminal = if let Some(port) = port {
             log::debug!("Connecting to FleaScope on port {port}");
             Self::validate_port(name, port)?;
-            StatelessFleaTerminal::new(port)?.try_into().unwrap()
+            StatelessFleaTerminal::with_dialect(port, dialect.clone())?
+                .try_into()
+                .unwrap()
         } else {
             let device_name = name.unwrap_or("FleaScope");
-            Self::get_working_serial(device_name)?
+            Self::get_working_serial(device_name, dialect)?
         };
 
         Ok(terminal)
@@ -117,6 +163,8 @@ impl FleaConnector {
                         return Some(FleaDevice {
                             name: device_name,
                             port: port_info.port_name,
+                            hostname: None,
+                            version: None,
                         });
                     }
                 }
@@ -132,6 +180,110 @@ impl FleaConnector {
         Ok(Self::get_available_devices(name)?.collect())
     }
 
+    /// Like `get_available_devices_vec`, but opens every candidate port in parallel and
+    /// only returns devices that answer within `timeout`, together with their hostname and
+    /// firmware version.
+    ///
+    /// Ports that are busy (e.g. already opened by another program) are skipped.
+    pub fn get_validated_devices(
+        name: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Vec<FleaDevice>, FleaConnectorError> {
+        profiling::scope!("FleaConnector::get_validated_devices");
+
+        let candidates = Self::get_available_devices_vec(name)?;
+        let validated = thread::scope(|scope| {
+            // Spawn all threads before joining any, so the devices are queried in parallel
+            #[allow(clippy::needless_collect)]
+            let handles: Vec<_> = candidates
+                .into_iter()
+                .map(|device| scope.spawn(move || Self::query_device(device, timeout)))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok()?)
+                .collect()
+        });
+
+        Ok(validated)
+    }
+
+    /// Find the one device whose actual hostname is `hostname`, asking every candidate.
+    ///
+    /// Unlike the USB product name used by `connect`, this tells devices apart that share a
+    /// name, and fails with `DuplicateHostname` if several answer to it.
+    pub fn find_by_hostname(hostname: &str) -> Result<FleaDevice, FleaConnectorError> {
+        profiling::scope!("FleaConnector::find_by_hostname");
+
+        let devices = Self::get_validated_devices(None, Self::HOSTNAME_QUERY_TIMEOUT)?;
+        Self::pick_by_hostname(devices, hostname)
+    }
+
+    fn pick_by_hostname(
+        devices: Vec<FleaDevice>,
+        hostname: &str,
+    ) -> Result<FleaDevice, FleaConnectorError> {
+        let mut matching: Vec<FleaDevice> = devices
+            .into_iter()
+            .filter(|device| device.hostname.as_deref() == Some(hostname))
+            .collect();
+        match matching.len() {
+            0 => Err(FleaConnectorError::DeviceNotFound {
+                name: hostname.to_string(),
+            }),
+            1 => Ok(matching.remove(0)),
+            _ => Err(FleaConnectorError::DuplicateHostname {
+                hostname: hostname.to_string(),
+                ports: matching.into_iter().map(|device| device.port).collect(),
+            }),
+        }
+    }
+
+    /// Ask a device for its version and hostname. `None` if it doesn't answer.
+    fn query_device(mut device: FleaDevice, timeout: Duration) -> Option<FleaDevice> {
+        let result = StatelessFleaTerminal::new(&device.port)
+            .and_then(|terminal| terminal.initialize().map_err(|(_, e)| e))
+            .and_then(|mut terminal| Self::query_identity(&mut terminal, timeout));
+
+        match result {
+            Ok((version, hostname)) => {
+                device.version = Some(version);
+                device.hostname = Some(hostname);
+                Some(device)
+            }
+            Err(e) => {
+                log::debug!("Device on {} did not validate: {e}", device.port);
+                None
+            }
+        }
+    }
+
+    /// Version and hostname of the device behind `terminal`. Its echo setting is left alone,
+    /// so a device used by another program behaves as before.
+    fn query_identity(
+        terminal: &mut IdleFleaTerminal,
+        timeout: Duration,
+    ) -> Result<(String, String), FleaTerminalError> {
+        let mut query = |command: &str| {
+            terminal
+                .try_exec_sync(command, Some(timeout))
+                .map(|response| {
+                    let response = String::from_utf8_lossy(&response);
+                    // With echo on, the device repeats the command before answering
+                    let answer = match response.split_once('\n') {
+                        Some((echo, answer))
+                            if echo.trim() == command && !answer.trim().is_empty() =>
+                        {
+                            answer
+                        }
+                        _ => &response,
+                    };
+                    answer.trim().to_string()
+                })
+        };
+        Ok((query("ver")?, query("hostname")?))
+    }
+
     /// Get the port for a device with the given name
     fn get_device_port(name: &str) -> Result<String, FleaConnectorError> {
         log::debug!("Searching for FleaScope device with name {name}");
@@ -146,19 +298,35 @@ impl FleaConnector {
             })
     }
 
-    /// Get a working serial connection, retrying if necessary
-    fn get_working_serial(name: &str) -> Result<IdleFleaTerminal, FleaConnectorError> {
+    /// Get a working serial connection, retrying if necessary.
+    ///
+    /// Unresponsive devices are first rebooted with the `reset` command. If that doesn't
+    /// help, a DTR/RTS hardware reset is tried as a last resort before giving up.
+    fn get_working_serial(
+        name: &str,
+        dialect: &TerminalDialect,
+    ) -> Result<IdleFleaTerminal, FleaConnectorError> {
+        let mut attempt = 0;
         loop {
+            attempt += 1;
             let port_candidate = Self::get_device_port(name)?;
-            let serial = StatelessFleaTerminal::new(&port_candidate)?;
+            let serial = StatelessFleaTerminal::with_dialect(&port_candidate, dialect.clone())?;
 
             match serial.try_into() {
                 Ok(s) => break Ok(s),
-                Err((mut serial, FleaTerminalError::Timeout { .. })) => {
+                Err((mut serial, FleaTerminalError::Timeout { .. }))
+                    if attempt <= Self::SOFT_RESET_ATTEMPTS =>
+                {
                     log::debug!("Timeout during initialization, sending reset and retrying");
                     let _ = serial.send_reset(); // Ignore errors here
                     thread::sleep(Duration::from_secs(2));
-                    continue;
+                }
+                Err((mut serial, FleaTerminalError::Timeout { .. }))
+                    if attempt == Self::SOFT_RESET_ATTEMPTS + 1 =>
+                {
+                    log::debug!("Device still unresponsive, trying a hardware reset");
+                    let _ = serial.hard_reset(); // Ignore errors here
+                    thread::sleep(Duration::from_secs(2));
                 }
                 Err((_serial, e)) => return Err(e.into()),
             }
@@ -260,4 +428,66 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_pick_by_hostname() {
+        let device = |port: &str, hostname: &str| FleaDevice {
+            name: "FleaScope".to_string(),
+            port: port.to_string(),
+            hostname: Some(hostname.to_string()),
+            version: None,
+        };
+        let devices = vec![
+            device("/dev/ttyACM0", "bench-1"),
+            device("/dev/ttyACM1", "bench-2"),
+            device("/dev/ttyACM2", "bench-2"),
+        ];
+
+        let found = FleaConnector::pick_by_hostname(devices.clone(), "bench-1").unwrap();
+        assert_eq!(found.port, "/dev/ttyACM0");
+        assert_eq!(found.hostname(), Some("bench-1"));
+        assert!(matches!(
+            FleaConnector::pick_by_hostname(devices.clone(), "bench-3"),
+            Err(FleaConnectorError::DeviceNotFound { .. })
+        ));
+        let Err(FleaConnectorError::DuplicateHostname { ports, .. }) =
+            FleaConnector::pick_by_hostname(devices, "bench-2")
+        else {
+            unreachable!("bench-2 is taken twice")
+        };
+        assert_eq!(ports, ["/dev/ttyACM1", "/dev/ttyACM2"]);
+    }
+
+    #[test]
+    fn test_query_identity() {
+        use crate::simulator::{RecordedSession, SimulatedDevice};
+
+        let identify = |device: &SimulatedDevice| {
+            let mut terminal = StatelessFleaTerminal::from_port(
+                Box::new(device.port()),
+                TerminalDialect::default(),
+            )
+            .unwrap()
+            .initialize()
+            .unwrap();
+            FleaConnector::query_identity(&mut terminal, Duration::from_secs(1)).unwrap()
+        };
+        let expected = ("FleaScope v1.2.3".to_string(), "bench-1".to_string());
+
+        let device = SimulatedDevice::new()
+            .hostname("bench-1")
+            .version("FleaScope v1.2.3");
+        assert_eq!(identify(&device), expected);
+
+        // A device with echo on repeats the command, and keeps its echo setting
+        let mut session = RecordedSession::new();
+        session.push("ver", "ver\r\nFleaScope v1.2.3\r\n");
+        session.push("hostname", "hostname\r\nbench-1\r\n");
+        let echoing = SimulatedDevice::new().session(&session);
+        assert_eq!(identify(&echoing), expected);
+        assert!(!echoing
+            .commands()
+            .iter()
+            .any(|command| command.starts_with("echo")));
+    }
 }
@@ -1,9 +1,33 @@
+use crate::capabilities::{
+    Capabilities, DeviceInfo, Feature, UnsupportedByFirmware, MIN_SUPPORTED_FIRMWARE,
+};
+use crate::channel_labels::ChannelLabels;
+use crate::command::{CommandBuilder, CommandError};
+use crate::flash_vars::{FlashVarError, FlashVars};
 use crate::flea_connector::{FleaConnector, FleaConnectorError};
-use crate::serial_terminal::{BusyFleaTerminal, ConnectionLostError, IdleFleaTerminal};
-use crate::trigger_config::{DigitalTrigger, StringifiedTriggerConfig, TriggerConfig};
+use crate::serial_terminal::{
+    BusyFleaTerminal, CancellingFleaTerminal, FaultedFleaTerminal, FleaTerminalError,
+    IdleFleaTerminal, ReadInterrupted, TransportStats,
+};
+use crate::soft_trigger::trigger_points;
+#[cfg(any(feature = "dataframe", feature = "ndarray"))]
+use crate::trigger_config::DIGITAL_CHANNELS;
+use crate::trigger_config::{
+    AnalogTrigger, DigitalTrigger, ParseTriggerError, ProbeTrigger, StringifiedTriggerConfig,
+    Trigger, TriggerConfig,
+};
+#[cfg(feature = "dataframe")]
+use crate::unit_conversion::UnitConversion;
+use crate::units::Volts;
+#[cfg(feature = "dataframe")]
 use polars::prelude::*;
+use std::borrow::Cow;
+#[cfg(any(feature = "dataframe", feature = "serde"))]
+use std::fmt::Write as _;
 use std::io::Read;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProbeType {
@@ -21,6 +45,11 @@ impl ProbeType {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Waveform {
     Sine,
     Square,
@@ -39,6 +68,27 @@ impl Waveform {
     }
 }
 
+impl std::fmt::Display for Waveform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown waveform {0:?}, expected sine, square, triangle or ekg")]
+pub struct ParseWaveformError(pub String);
+
+impl std::str::FromStr for Waveform {
+    type Err = ParseWaveformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::Sine, Self::Square, Self::Triangle, Self::Ekg]
+            .into_iter()
+            .find(|waveform| waveform.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| ParseWaveformError(s.to_string()))
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CaptureConfigError {
     #[error("Time frame too large (max 3.49 seconds)")]
@@ -52,6 +102,408 @@ pub enum CaptureConfigError {
 
     #[error("Voltage out of range")]
     VoltageOutOfRange,
+
+    #[error("The probe is not calibrated")]
+    ProbeNotCalibrated,
+
+    #[error("No time frame given")]
+    MissingTimeFrame,
+
+    #[error("The pattern must be held for {held:?}, longer than the {captured:?} capture")]
+    HoldBeyondCapture { held: Duration, captured: Duration },
+
+    #[error("Hysteresis, qualifiers and hold times can't be combined with a delay")]
+    EmulatedWithDelay,
+
+    #[error("Invalid trigger: {0}")]
+    InvalidTrigger(#[from] ParseTriggerError),
+}
+
+/// All problems found while validating a `CaptureConfig`
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid capture configuration: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct InvalidCaptureConfig(pub Vec<CaptureConfigError>);
+
+/// A validated capture request, ready to be sent to the device any number of times.
+///
+/// ```rust,no_run
+/// use fleascope_rs::flea_scope::CaptureConfig;
+/// use fleascope_rs::{DigitalTrigger, IdleFleaScope};
+/// use std::time::Duration;
+///
+/// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = CaptureConfig::builder()
+///     .time_frame(Duration::from_millis(10))
+///     .trigger(DigitalTrigger::start_capturing_when().is_matching())
+///     .delay(Duration::from_millis(1))
+///     .build()?;
+/// let reading = scope.read(&config);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    time_frame: Duration,
+    delay: Duration,
+    plan: CapturePlan,
+    trigger: String,
+    delay_samples: u32,
+    command: String,
+    holdoff: Duration,
+    /// Trigger with conditions the firmware can't apply, checked in software
+    emulated: Option<Trigger>,
+    /// Time captured before the trigger, see `CaptureConfigBuilder::trigger_position`
+    pre_trigger: Duration,
+}
+
+impl CaptureConfig {
+    pub fn builder() -> CaptureConfigBuilder {
+        CaptureConfigBuilder::default()
+    }
+
+    pub fn time_frame(&self) -> Duration {
+        self.time_frame
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// The same capture, but starting right away: no trigger, no delay
+    fn immediate(&self) -> Self {
+        let trigger = DigitalTrigger::start_capturing_when()
+            .is_matching()
+            .into_trigger_fields()
+            .into_string();
+        Self {
+            delay: Duration::ZERO,
+            command: format!("scope {} {} 0", self.plan.number1, trigger),
+            trigger,
+            delay_samples: 0,
+            emulated: None,
+            pre_trigger: Duration::ZERO,
+            ..self.clone()
+        }
+    }
+
+    /// Capture `pre_trigger` more than `time_frame` right away, to be cropped around the
+    /// trigger in software
+    fn pre_buffered(&self, time_frame: Duration, pre_trigger: Duration) -> Self {
+        Self {
+            time_frame,
+            emulated: Some(self.trigger()),
+            pre_trigger,
+            ..self.immediate()
+        }
+    }
+
+    /// Time before the trigger in a pre-trigger capture, see
+    /// `CaptureConfigBuilder::trigger_position`
+    pub fn pre_trigger(&self) -> Duration {
+        self.pre_trigger
+    }
+
+    /// Minimum time between the end of a capture and arming the next one
+    pub fn holdoff(&self) -> Duration {
+        self.holdoff
+    }
+
+    /// The analog trigger if it has a hysteresis to be applied in software, see
+    /// `AnalogTriggerBuilder::hysteresis`
+    pub fn emulated_hysteresis(&self) -> Option<&AnalogTrigger> {
+        match &self.emulated {
+            Some(Trigger::Analog(trigger)) if trigger.hysteresis > 0 => Some(trigger),
+            _ => None,
+        }
+    }
+
+    /// The analog trigger if it has a digital qualifier to be checked in software, see
+    /// `AnalogTriggerBuilder::qualified_by`
+    pub fn emulated_qualifier(&self) -> Option<&AnalogTrigger> {
+        match &self.emulated {
+            Some(Trigger::Analog(trigger)) if trigger.qualifier.is_some() => Some(trigger),
+            _ => None,
+        }
+    }
+
+    /// The digital trigger if its pattern has to be held, checked in software, see
+    /// `BitTriggerBuilder::held_for`
+    pub fn emulated_hold(&self) -> Option<&DigitalTrigger> {
+        match &self.emulated {
+            Some(Trigger::Digital(trigger)) if trigger.held_for.is_some() => Some(trigger),
+            _ => None,
+        }
+    }
+
+    /// The trigger if any of its conditions are checked in software, see
+    /// `soft_trigger::emulated_conditions_hold`
+    pub fn emulated_trigger(&self) -> Option<&Trigger> {
+        self.emulated.as_ref()
+    }
+
+    /// Only `CaptureStream` checks the conditions of `emulated_trigger`, so warn the other
+    /// reads that they are ignored
+    fn warn_emulation_ignored(&self) {
+        if !self.pre_trigger.is_zero() {
+            log::warn!(
+                "Pre-trigger captures are only cropped around the trigger by CaptureStream, \
+                 this read returns the whole untriggered capture"
+            );
+        } else if let Some(trigger) = &self.emulated {
+            log::warn!(
+                "Trigger {trigger} has conditions the firmware can't check, they are only \
+                 applied by CaptureStream and ignored by this read"
+            );
+        }
+    }
+
+    /// Sample rate the device will capture at, in million samples per second
+    pub fn effective_msps(&self) -> f64 {
+        self.plan.effective_msps
+    }
+
+    pub fn plan(&self) -> CapturePlan {
+        self.plan
+    }
+
+    /// The configured trigger, recovered from the fields sent to the device
+    pub fn trigger(&self) -> Trigger {
+        if let Some(trigger) = &self.emulated {
+            return trigger.clone();
+        }
+        // `validate_capture` rejects fields that don't parse
+        Trigger::from_fields(&self.trigger).expect("trigger fields were validated")
+    }
+
+    /// Describe a capture taken with this configuration, received at `received_at`
+    fn metadata(&self, hostname: &str, received_at: SystemTime) -> CaptureMetadata {
+        CaptureMetadata {
+            requested_time_frame: self.time_frame,
+            actual_time_frame: self.plan.captured_duration(),
+            delay_samples: self.delay_samples,
+            trigger: self.trigger.clone(),
+            hostname: hostname.trim().to_string(),
+            triggered_at: received_at
+                .checked_sub(self.plan.captured_duration() + self.delay)
+                .unwrap_or(received_at),
+        }
+    }
+}
+
+/// Serialized form of `CaptureConfig`. Deserializing goes through the builder, so a saved
+/// setup is validated like a new one.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CaptureConfigSetup {
+    time_frame: Duration,
+    #[serde(default)]
+    delay: Duration,
+    trigger: Trigger,
+    #[serde(default)]
+    holdoff: Duration,
+    #[serde(default)]
+    pre_trigger: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CaptureConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CaptureConfigSetup {
+            time_frame: self.time_frame,
+            delay: self.delay,
+            trigger: self.trigger(),
+            holdoff: self.holdoff,
+            pre_trigger: self.pre_trigger,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CaptureConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let setup = CaptureConfigSetup::deserialize(deserializer)?;
+        let mut builder = Self::builder()
+            .time_frame(setup.time_frame)
+            .delay(setup.delay)
+            .trigger(setup.trigger)
+            .holdoff(setup.holdoff);
+        if !setup.pre_trigger.is_zero() {
+            builder.pre_trigger = Some(setup.pre_trigger);
+        }
+        builder.build().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Default)]
+#[must_use]
+pub struct CaptureConfigBuilder {
+    time_frame: Option<Duration>,
+    trigger: Option<StringifiedTriggerConfig>,
+    delay: Option<Duration>,
+    pre_trigger: Option<Duration>,
+    holdoff: Duration,
+}
+
+impl CaptureConfigBuilder {
+    pub fn time_frame(mut self, time_frame: Duration) -> Self {
+        self.time_frame = Some(time_frame);
+        self
+    }
+
+    /// Defaults to capturing immediately
+    pub fn trigger(mut self, trigger: impl TriggerConfig) -> Self {
+        self.trigger = Some(trigger.into_trigger_fields());
+        self
+    }
+
+    /// Time between the trigger and the start of the capture. Defaults to none.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self.pre_trigger = None;
+        self
+    }
+
+    /// Position of the capture relative to the trigger in seconds. Positive values are
+    /// the same as `delay`, negative ones request a pre-trigger view.
+    ///
+    /// No released firmware keeps samples from before the trigger, see
+    /// `Feature::PreTrigger`. A negative position instead captures the time frame plus the
+    /// position right away, and `CaptureStream` crops it to the time frame starting that
+    /// long before the trigger, skipping captures without room around one. The capture
+    /// holds the usual number of samples, so the sample rate is lower than for the time
+    /// frame alone, and the total has to fit the largest time frame. Other reads return
+    /// the whole untriggered capture.
+    pub fn trigger_position(mut self, seconds: f64) -> Self {
+        let offset = Duration::try_from_secs_f64(seconds.abs()).unwrap_or(Duration::MAX);
+        if seconds < 0.0 {
+            self.delay = None;
+            self.pre_trigger = Some(offset);
+        } else {
+            self.delay = Some(offset);
+            self.pre_trigger = None;
+        }
+        self
+    }
+
+    /// Don't rearm for this long after a capture, so repeated captures of a complex
+    /// repetitive waveform lock onto the same edge instead of any that matches the trigger.
+    ///
+    /// The firmware has no holdoff, so this is applied by engines capturing back-to-back,
+    /// `CaptureStream` and `TriggeredSession`. Defaults to none.
+    pub fn holdoff(mut self, holdoff: Duration) -> Self {
+        self.holdoff = holdoff;
+        self
+    }
+
+    /// Validate the configuration, reporting every problem at once
+    pub fn build(self) -> Result<CaptureConfig, InvalidCaptureConfig> {
+        let trigger = self.trigger.unwrap_or_else(|| {
+            DigitalTrigger::start_capturing_when()
+                .is_matching()
+                .into_trigger_fields()
+        });
+        let Some(time_frame) = self.time_frame else {
+            return Err(InvalidCaptureConfig(vec![
+                CaptureConfigError::MissingTimeFrame,
+            ]));
+        };
+        let pre_trigger = self.pre_trigger.unwrap_or_default();
+
+        let config = IdleFleaScope::validate_capture(
+            time_frame.saturating_add(pre_trigger),
+            trigger,
+            self.delay.unwrap_or_default(),
+        )
+        .map_err(InvalidCaptureConfig)?;
+        let config = if pre_trigger.is_zero() {
+            config
+        } else {
+            config.pre_buffered(time_frame, pre_trigger)
+        };
+        Ok(CaptureConfig {
+            holdoff: self.holdoff,
+            ..config
+        })
+    }
+}
+
+/// Sample timing the device will use for a time frame, computed without touching the device.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::CapturePlan;
+/// use std::time::Duration;
+///
+/// let plan = CapturePlan::for_time_frame(Duration::from_millis(10))?;
+/// assert_eq!(plan.total_samples(), 2000);
+/// assert!(plan.captured_duration() >= Duration::from_millis(9));
+/// # Ok::<(), fleascope_rs::flea_scope::CaptureConfigError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapturePlan {
+    number1: u32,
+    effective_msps: f64,
+}
+
+impl CapturePlan {
+    pub fn for_time_frame(time_frame: Duration) -> Result<Self, CaptureConfigError> {
+        if time_frame.as_secs_f64() > 3.49 {
+            return Err(CaptureConfigError::TimeFrameTooLarge);
+        }
+        if time_frame.as_secs() == 0 && time_frame.as_micros() < 111 {
+            return Err(CaptureConfigError::TimeFrameTooSmall);
+        }
+        let number1 = match IdleFleaScope::MSPS * (time_frame.as_micros() as u32)
+            / IdleFleaScope::TOTAL_SAMPLES
+        {
+            0 => return Err(CaptureConfigError::TimeFrameTooSmall),
+            n => n,
+        };
+        Self::from_number1(number1)
+    }
+
+    /// Plan for the first argument of the `scope` command
+    pub(crate) fn from_number1(number1: u32) -> Result<Self, CaptureConfigError> {
+        let prescaler = IdleFleaScope::number1_to_prescaler(number1)?;
+        Ok(Self {
+            number1,
+            effective_msps: IdleFleaScope::prescaler_to_effective_msps(prescaler),
+        })
+    }
+
+    /// Sample rate in million samples per second
+    pub fn effective_msps(&self) -> f64 {
+        self.effective_msps
+    }
+
+    /// Number of samples in a capture
+    pub fn total_samples(&self) -> u32 {
+        IdleFleaScope::TOTAL_SAMPLES
+    }
+
+    /// Time between two samples
+    pub fn resolution(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / (self.effective_msps * 1_000_000.0))
+    }
+
+    /// Time span actually covered by a capture. The prescaler is an integer, so this
+    /// differs slightly from the requested time frame.
+    pub fn captured_duration(&self) -> Duration {
+        Duration::from_secs_f64(
+            f64::from(self.total_samples()) / (self.effective_msps * 1_000_000.0),
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("Invalid capture configuration: {0}")]
+    Config(#[from] CaptureConfigError),
+
+    #[error("Serial terminal error: {0}")]
+    Terminal(#[from] FleaTerminalError),
+
+    #[error("{0}")]
+    UnsupportedByFirmware(#[from] UnsupportedByFirmware),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -65,34 +517,529 @@ pub enum CalibrationError {
     #[error("Signal to unstable")]
     UnstableSignal,
 
+    /// Boxed so the variant doesn't depend on the `dataframe` feature
     #[error("Failure while processing calibration data")]
-    CalibrationDataError(#[from] PolarsError),
+    CalibrationDataError(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Could not parse calibration capture: {0}")]
+    InvalidSamples(#[from] SampleParseError),
+
+    #[error("Calibration variable {name} is not declared in flash")]
+    NotDeclared { name: String },
+
+    #[error("Could not parse calibration value {raw:?}")]
+    ParseFailed { raw: String },
+
+    #[error("The calibration doesn't map to a finite voltage range")]
+    InvalidCalibration,
+
+    #[error("Could not access calibration in flash: {0}")]
+    Flash(FlashVarError),
+}
+
+#[cfg(feature = "dataframe")]
+impl From<PolarsError> for CalibrationError {
+    fn from(e: PolarsError) -> Self {
+        Self::CalibrationDataError(Box::new(e))
+    }
+}
+
+impl From<FlashVarError> for CalibrationError {
+    fn from(e: FlashVarError) -> Self {
+        match e {
+            FlashVarError::NotDeclared { name } => Self::NotDeclared { name },
+            FlashVarError::ParseFailed { raw, .. } => Self::ParseFailed { raw },
+            e => Self::Flash(e),
+        }
+    }
+}
+
+/// Parse an integer printed by the device, tolerating surrounding whitespace and line
+/// endings, an explicit `+` sign and integral values in decimal or scientific notation
+pub(crate) fn parse_device_integer(response: &[u8]) -> Option<i32> {
+    let text = std::str::from_utf8(response).ok()?;
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+
+    // `str::parse` accepts a leading `+` for both integers and floats
+    if let Ok(value) = text.parse() {
+        return Some(value);
+    }
+    let value: f64 = text.parse().ok()?;
+    let in_range = (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&value);
+    #[allow(clippy::cast_possible_truncation)]
+    (in_range && value.fract() == 0.0).then_some(value as i32)
 }
 
 pub struct ScopeReading {
     pub effective_msps: f64,
     pub data: Vec<u8>,
+    /// `None` for readings not taken by this crate, e.g. replayed from a file
+    pub metadata: Option<CaptureMetadata>,
+    /// `data` parsed while it was transferred, see `ReadingFleaScope::try_get_result`.
+    /// Empty to parse `data` on the first call to `samples`, which then keeps the result.
+    pub parsed: OnceLock<Vec<Sample>>,
+}
+
+/// How a capture was taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaptureMetadata {
+    pub requested_time_frame: Duration,
+    /// Time span actually covered, see `CapturePlan::captured_duration`
+    pub actual_time_frame: Duration,
+    pub delay_samples: u32,
+    /// Trigger fields as sent to the device
+    pub trigger: String,
+    pub hostname: String,
+    /// Approximate wall-clock time of the trigger: when the response arrived, minus the
+    /// capture and delay. The transfer time is not accounted for, so this is late by up to
+    /// a few tens of milliseconds.
+    pub triggered_at: SystemTime,
+}
+
+/// Prefix of the columns added by `CaptureMetadata::embed`
+pub const METADATA_COLUMN_PREFIX: &str = "meta_";
+
+impl CaptureMetadata {
+    /// Add the metadata as constant `meta_*` columns, so it survives concatenation and
+    /// export. Polars frames have no per-column metadata of their own.
+    #[cfg(feature = "dataframe")]
+    pub fn embed(&self, df: LazyFrame) -> LazyFrame {
+        let triggered_at = self
+            .triggered_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let column = |name: &str| format!("{METADATA_COLUMN_PREFIX}{name}");
+        df.with_columns([
+            lit(self.requested_time_frame.as_secs_f64()).alias(column("requested_time_frame")),
+            lit(self.actual_time_frame.as_secs_f64()).alias(column("actual_time_frame")),
+            lit(self.delay_samples).alias(column("delay_samples")),
+            lit(self.trigger.clone()).alias(column("trigger")),
+            lit(self.hostname.clone()).alias(column("hostname")),
+            lit(triggered_at).alias(column("triggered_at")),
+        ])
+    }
+}
+
+/// Reconstruct the device's response to `scope` from raw values and bitmaps
+#[cfg(any(feature = "dataframe", feature = "serde"))]
+pub(crate) fn device_response(samples: impl IntoIterator<Item = (f64, u16)>) -> Vec<u8> {
+    let mut data = String::new();
+    for (raw, bitmap) in samples {
+        writeln!(data, "{raw},{bitmap:#05x}").expect("writing to a String can't fail");
+    }
+    data.into_bytes()
+}
+
+/// Version of the `ScopeReading` serialization, see `ScopeReadingBundle`
+#[cfg(feature = "serde")]
+const BUNDLE_VERSION: u32 = 1;
+
+/// Serialized form of `ScopeReading`, e.g. to send captures to a browser UI.
+///
+/// The schema is stable: `{"version":1,"effective_msps":…,"raw":[…],"bitmap":[…],
+/// "metadata":…}` with one raw ADC value and one digital bitmap per sample, the sample
+/// times following from `effective_msps`. `metadata` is `CaptureMetadata` or `null`,
+/// durations and times as `secs`/`nanos` pairs. Incompatible changes bump `version`.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ScopeReadingBundle {
+    version: u32,
+    effective_msps: f64,
+    raw: Vec<f64>,
+    bitmap: Vec<u16>,
+    #[serde(default)]
+    metadata: Option<CaptureMetadata>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ScopeReading {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let samples = self.samples().map_err(serde::ser::Error::custom)?;
+        ScopeReadingBundle {
+            version: BUNDLE_VERSION,
+            effective_msps: self.effective_msps,
+            raw: samples.iter().map(|sample| sample.raw).collect(),
+            bitmap: samples.iter().map(|sample| sample.bitmap).collect(),
+            metadata: self.metadata.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ScopeReading {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bundle = ScopeReadingBundle::deserialize(deserializer)?;
+        if bundle.version != BUNDLE_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported capture version {}, expected {BUNDLE_VERSION}",
+                bundle.version
+            )));
+        }
+        if bundle.raw.len() != bundle.bitmap.len() {
+            return Err(serde::de::Error::custom(format!(
+                "{} raw values but {} bitmaps",
+                bundle.raw.len(),
+                bundle.bitmap.len()
+            )));
+        }
+        Ok(Self {
+            effective_msps: bundle.effective_msps,
+            data: device_response(bundle.raw.into_iter().zip(bundle.bitmap)),
+            metadata: bundle.metadata,
+            parsed: OnceLock::new(),
+        })
+    }
 }
 
 pub const RAW_COLUMN_NAME: &str = "bnc_raw";
 pub const CALIBRATED_COLUMN_NAME: &str = "bnc_calibrated";
 pub const BITMAP_COLUMN_NAME: &str = "bitmap";
 pub const TIME_COLUMN_NAME: &str = "time";
+/// Absolute time of a sample, see `ScopeReading::parse_csv_with_epoch`
+pub const TIMESTAMP_COLUMN_NAME: &str = "timestamp";
+/// Index of the capture within a `read_segments` result
+pub const SEGMENT_COLUMN_NAME: &str = "segment";
+/// Seconds from the start of `read_segments` until the segment's trigger, estimated from
+/// its arrival like `CaptureMetadata::triggered_at`
+pub const TRIGGER_TIME_COLUMN_NAME: &str = "trigger_time";
+
+/// One sample of a capture, as parsed by `ScopeReading::samples`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Seconds since the start of the capture
+    pub time: f64,
+    /// Uncalibrated ADC value, see `FleaProbe::raw_to_volts`
+    pub raw: f64,
+    /// States of the digital inputs, bit 0 is input 0
+    pub bitmap: u16,
+}
+
+impl Sample {
+    pub fn bit(&self, bit: usize) -> bool {
+        bit < 16 && (self.bitmap >> bit) & 1 == 1
+    }
+}
+
+/// A line of a capture that isn't a `raw,bitmap` pair
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Malformed sample {index} at byte {offset}: {line:?}")]
+pub struct SampleParseError {
+    pub index: u32,
+    /// Where the line starts in `ScopeReading::data`
+    pub offset: usize,
+    pub line: String,
+}
+
+/// How to treat lines that aren't `raw,bitmap` pairs, e.g. from a corrupted serial chunk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fail on the first one
+    #[default]
+    Strict,
+    /// Drop them and report them in a `ParseReport`. Every dropped line still takes up the
+    /// time of a sample, so the samples after it keep their timing.
+    Lenient,
+}
+
+/// Lines dropped by a lenient parse, see `ParseMode::Lenient`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    pub dropped: Vec<SampleParseError>,
+}
+
+impl ParseReport {
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped.len()
+    }
+}
+
+/// Why `ScopeReading::parse_csv_with` failed
+#[cfg(feature = "dataframe")]
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureParseError {
+    #[error(transparent)]
+    Samples(#[from] SampleParseError),
+
+    #[error(transparent)]
+    Polars(#[from] PolarsError),
+}
+
+/// A calibrated capture as plain vectors of equal length, see `ScopeReading::to_samples`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptureSamples {
+    /// Seconds since the start of the capture
+    pub time: Vec<f64>,
+    pub volts: Vec<f64>,
+    /// States of the digital inputs, bit 0 is input 0
+    pub bits: Vec<u16>,
+}
+
+/// A calibrated capture as arrays, see `ScopeReading::to_ndarray`
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureArrays {
+    /// One row per sample: seconds since the start of the capture, and volts
+    pub analog: ndarray::Array2<f64>,
+    /// One row per sample and one column per digital input
+    pub digital: ndarray::Array2<bool>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SamplesError {
+    #[error("{0}")]
+    Parse(#[from] SampleParseError),
+
+    #[error("{0}")]
+    Calibration(#[from] CalibrationError),
+}
+
+/// Whitespace, `\r` and NUL bytes the device may leave around values
+fn is_padding(c: char) -> bool {
+    c.is_whitespace() || c == '\0'
+}
+
+/// The lines of `data` with the offsets they start at, for `data` starting `offset` bytes
+/// into a response
+fn lines(data: &[u8], offset: usize) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut start = offset;
+    data.split(|&b| b == b'\n').map(move |line| {
+        let line_start = start;
+        start += line.len() + 1;
+        (line_start, line)
+    })
+}
+
+/// Turns the lines of a capture into samples, one line at a time
+struct SampleParser {
+    period: f64,
+    samples: Vec<Sample>,
+    /// Malformed lines dropped so far, see `ParseMode::Lenient`
+    dropped: usize,
+}
+
+impl SampleParser {
+    fn new(effective_msps: f64) -> Self {
+        Self {
+            period: 1.0 / (effective_msps * 1_000_000.0),
+            samples: Vec::with_capacity(IdleFleaScope::TOTAL_SAMPLES as usize),
+            dropped: 0,
+        }
+    }
+
+    /// Parse a line without its `\n`, starting at `offset` in the response. Blank lines
+    /// are skipped.
+    fn parse_line(&mut self, line: &[u8], offset: usize) -> Result<(), SampleParseError> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_matches(is_padding);
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let index = u32::try_from(self.samples.len() + self.dropped).unwrap_or(u32::MAX);
+        let invalid = || SampleParseError {
+            index,
+            offset,
+            line: line.to_string(),
+        };
+        let (raw, bitmap) = line.split_once(',').ok_or_else(invalid)?;
+        let raw = raw
+            .trim_matches(is_padding)
+            .parse()
+            .map_err(|_| invalid())?;
+        let bitmap = bitmap.trim_matches(is_padding);
+        let bitmap =
+            u16::from_str_radix(bitmap.trim_start_matches("0x"), 16).map_err(|_| invalid())?;
+        self.samples.push(Sample {
+            time: f64::from(index) * self.period,
+            raw,
+            bitmap,
+        });
+        Ok(())
+    }
+}
+
+/// Parses complete lines of a response while it is still arriving
+struct IncrementalParser {
+    parser: SampleParser,
+    /// Bytes of the response consumed so far, always at a line start
+    consumed: usize,
+    /// Set on the first malformed line, the reading is then parsed on demand instead
+    failed: bool,
+}
+
+impl IncrementalParser {
+    fn new(effective_msps: f64) -> Self {
+        Self {
+            parser: SampleParser::new(effective_msps),
+            consumed: 0,
+            failed: false,
+        }
+    }
+
+    /// Parse the complete lines of `response` not seen yet
+    fn feed(&mut self, response: &[u8]) {
+        profiling::scope!("IncrementalParser::feed");
+
+        let Some(end) = response.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let Some(new) = response.get(self.consumed..end) else {
+            return;
+        };
+        if !self.failed {
+            self.failed = lines(new, self.consumed)
+                .any(|(offset, line)| self.parser.parse_line(line, offset).is_err());
+        }
+        self.consumed = end + 1;
+    }
+
+    /// Parse the rest of the complete response
+    fn finish(mut self, data: &[u8]) -> Option<Vec<Sample>> {
+        if let Some(rest) = data.get(self.consumed..) {
+            self.failed |= lines(rest, self.consumed)
+                .any(|(offset, line)| self.parser.parse_line(line, offset).is_err());
+        }
+        (!self.failed).then_some(self.parser.samples)
+    }
+}
 
 impl ScopeReading {
+    /// Whether an auto (`~`) trigger actually fired, rather than timing out after 100 ms.
+    ///
+    /// The firmware doesn't report this, so it is derived from the first sample: a fired
+    /// trigger means the trigger condition held when the capture started, evaluated for the
+    /// trigger's behavior as `soft_trigger::trigger_points` does. Other triggers always
+    /// fire before the capture is returned. `None` if it can't be told, i.e. without
+    /// metadata or with a delay between trigger and capture.
+    pub fn was_triggered(&self) -> Option<bool> {
+        let metadata = self.metadata.as_ref()?;
+        if !metadata.trigger.starts_with('~') {
+            return Some(true);
+        }
+        if metadata.delay_samples > 0 {
+            return None;
+        }
+
+        let trigger = Trigger::from_fields(&metadata.trigger).ok()?;
+        let mut parser = SampleParser::new(self.effective_msps);
+        parser.parse_line(lines(&self.data, 0).next()?.1, 0).ok()?;
+        let first = parser.samples.first()?;
+        Some(!trigger_points(&trigger, std::slice::from_ref(first)).is_empty())
+    }
+
+    /// Parse into plain samples, without going through polars. Parsed once, later calls
+    /// return the same samples.
+    pub fn samples(&self) -> Result<&[Sample], SampleParseError> {
+        match self.samples_with(ParseMode::Strict)?.0 {
+            Cow::Borrowed(samples) => Ok(samples),
+            Cow::Owned(_) => unreachable!("strict parses are kept in parsed"),
+        }
+    }
+
+    /// Like `samples`, with a choice of how to treat malformed lines. Only a parse without
+    /// malformed lines is kept in `parsed`.
+    pub fn samples_with(
+        &self,
+        mode: ParseMode,
+    ) -> Result<(Cow<'_, [Sample]>, ParseReport), SampleParseError> {
+        profiling::scope!("samples_with");
+
+        if let Some(parsed) = self.parsed.get() {
+            return Ok((Cow::Borrowed(parsed), ParseReport::default()));
+        }
+        let mut parser = SampleParser::new(self.effective_msps);
+        let mut report = ParseReport::default();
+        for (offset, line) in lines(&self.data, 0) {
+            match (parser.parse_line(line, offset), mode) {
+                (Ok(()), _) => {}
+                (Err(e), ParseMode::Strict) => return Err(e),
+                (Err(e), ParseMode::Lenient) => {
+                    parser.dropped += 1;
+                    report.dropped.push(e);
+                }
+            }
+        }
+        if report.dropped.is_empty() {
+            return Ok((
+                Cow::Borrowed(self.parsed.get_or_init(|| parser.samples)),
+                report,
+            ));
+        }
+        Ok((Cow::Owned(parser.samples), report))
+    }
+
+    /// Time, calibrated voltage and digital inputs as plain vectors, for when a `DataFrame`
+    /// is more than needed. The probe's unit conversion isn't applied.
+    pub fn to_samples(&self, probe: &FleaProbe) -> Result<CaptureSamples, SamplesError> {
+        profiling::scope!("to_samples");
+
+        let samples = self.samples()?;
+        let mut result = CaptureSamples {
+            time: Vec::with_capacity(samples.len()),
+            volts: Vec::with_capacity(samples.len()),
+            bits: Vec::with_capacity(samples.len()),
+        };
+        for sample in samples {
+            result.time.push(sample.time);
+            result.volts.push(probe.raw_to_volts(sample.raw)?.get());
+            result.bits.push(sample.bitmap);
+        }
+        Ok(result)
+    }
+
+    /// Like `to_samples`, as a `(time, volts)` array and an array of the digital inputs
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self, probe: &FleaProbe) -> Result<CaptureArrays, SamplesError> {
+        profiling::scope!("to_ndarray");
+
+        let samples = self.to_samples(probe)?;
+        let rows = samples.time.len();
+        Ok(CaptureArrays {
+            analog: ndarray::Array2::from_shape_fn((rows, 2), |(row, column)| match column {
+                0 => samples.time[row],
+                _ => samples.volts[row],
+            }),
+            digital: ndarray::Array2::from_shape_fn((rows, DIGITAL_CHANNELS), |(row, bit)| {
+                (samples.bits[row] >> bit) & 1 == 1
+            }),
+        })
+    }
+
+    #[cfg(feature = "dataframe")]
     pub fn parse_csv(&self) -> Result<LazyFrame, PolarsError> {
         profiling::scope!("parse_csv");
 
+        if let Some(parsed) = self.parsed.get() {
+            return Self::samples_frame(parsed);
+        }
+
         let df = CsvReadOptions::default()
             .with_has_header(false)
             .into_reader_with_file_handle(std::io::Cursor::new(&self.data))
             .finish()?
             .lazy()
             .select([
+                // Go through strings so stray whitespace or a `\r` doesn't turn values into nulls
                 col("column_1")
-                    .alias(RAW_COLUMN_NAME)
-                    .cast(DataType::Float64),
-                col("column_2").alias(BITMAP_COLUMN_NAME),
+                    .cast(DataType::String)
+                    .str()
+                    .strip_chars(lit(NULL))
+                    .cast(DataType::Float64)
+                    .alias(RAW_COLUMN_NAME),
+                // Parsed once here, so digital analysis works on integers
+                col("column_2")
+                    .cast(DataType::String)
+                    .str()
+                    .strip_chars(lit(NULL))
+                    .str()
+                    .strip_prefix(lit("0x"))
+                    .str()
+                    .to_integer(lit(16), false)
+                    .cast(DataType::UInt32)
+                    .alias(BITMAP_COLUMN_NAME),
             ])
             .with_row_index("row_index", Some(0))
             .with_columns([
@@ -110,34 +1057,87 @@ impl ScopeReading {
         Ok(df)
     }
 
+    /// Like `parse_csv`, with a choice of how to treat malformed lines. A strict parse
+    /// reports the byte offset of the first one, a lenient one drops them.
+    ///
+    /// ```rust
+    /// use fleascope_rs::flea_scope::{ParseMode, ScopeReading};
+    /// use std::sync::OnceLock;
+    ///
+    /// let reading = ScopeReading {
+    ///     effective_msps: 1.0,
+    ///     data: b"2048,0x000\n20\xff8,0x0\n2048,0x001\n".to_vec(),
+    ///     metadata: None,
+    ///     parsed: OnceLock::new(),
+    /// };
+    /// let (df, report) = reading.parse_csv_with(ParseMode::Lenient)?;
+    /// assert_eq!(df.collect()?.height(), 2);
+    /// assert_eq!(report.dropped[0].offset, 11);
+    /// assert!(reading.parse_csv_with(ParseMode::Strict).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "dataframe")]
+    pub fn parse_csv_with(
+        &self,
+        mode: ParseMode,
+    ) -> Result<(LazyFrame, ParseReport), CaptureParseError> {
+        profiling::scope!("parse_csv_with");
+
+        let (samples, report) = self.samples_with(mode)?;
+        Ok((Self::samples_frame(&samples)?, report))
+    }
+
+    #[cfg(feature = "dataframe")]
+    fn samples_frame(samples: &[Sample]) -> Result<LazyFrame, PolarsError> {
+        let time: Vec<f64> = samples.iter().map(|sample| sample.time).collect();
+        let raw: Vec<f64> = samples.iter().map(|sample| sample.raw).collect();
+        let bitmap: Vec<u32> = samples
+            .iter()
+            .map(|sample| u32::from(sample.bitmap))
+            .collect();
+        Ok(df!(
+            TIME_COLUMN_NAME => time,
+            RAW_COLUMN_NAME => raw,
+            BITMAP_COLUMN_NAME => bitmap,
+        )?
+        .lazy())
+    }
+
+    /// Like `parse_csv`, with an additional absolute `timestamp` column counting from
+    /// `start`, e.g. the reading's `metadata.triggered_at`. Lets long-running loggers
+    /// correlate captures with other instruments.
+    #[cfg(feature = "dataframe")]
+    pub fn parse_csv_with_epoch(&self, start: SystemTime) -> Result<LazyFrame, PolarsError> {
+        profiling::scope!("parse_csv_with_epoch");
+
+        let start = start
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|e| polars_err!(ComputeError: "epoch before 1970: {e}"))?;
+        let start_us = i64::try_from(start.as_micros())
+            .map_err(|e| polars_err!(ComputeError: "epoch out of range: {e}"))?;
+
+        Ok(self.parse_csv()?.with_column(
+            // Times are never negative, so adding 0.5 before truncating rounds to nearest
+            ((col(TIME_COLUMN_NAME) * lit(1_000_000.0) + lit(0.5)).cast(DataType::Int64)
+                + lit(start_us))
+            .cast(DataType::Datetime(TimeUnit::Microseconds, None))
+            .alias(TIMESTAMP_COLUMN_NAME),
+        ))
+    }
+
     /// Extract bits from bitmap column
+    #[cfg(feature = "dataframe")]
     pub fn extract_bits(mut df: &mut DataFrame) -> Result<&DataFrame, PolarsError> {
         profiling::scope!("extract_bits");
 
-        let bitmap_column = df.column(BITMAP_COLUMN_NAME)?;
-        let bitmap_strings = bitmap_column.str()?;
-
-        // Parse hex strings and extract bits
-        let mut bit_columns: Vec<Vec<bool>> = vec![Vec::new(); 10];
-
-        for bitmap_opt in bitmap_strings {
-            if let Some(bitmap_str) = bitmap_opt {
-                let bitmap_str = bitmap_str.trim_start_matches("0x");
-                if let Ok(bitmap_val) = u32::from_str_radix(bitmap_str, 16) {
-                    for (bit, column) in bit_columns.iter_mut().enumerate().take(10) {
-                        column.push((bitmap_val >> bit) & 1 == 1);
-                    }
-                } else {
-                    // Default to false for unparseable values
-                    for column in bit_columns.iter_mut().take(10) {
-                        column.push(false);
-                    }
-                }
-            } else {
-                // Default to false for null values
-                for column in bit_columns.iter_mut().take(10) {
-                    column.push(false);
-                }
+        let bitmaps = df.column(BITMAP_COLUMN_NAME)?.u32()?;
+
+        let mut bit_columns: Vec<Vec<bool>> = vec![Vec::new(); DIGITAL_CHANNELS];
+        for bitmap in bitmaps {
+            // Default to false for null values
+            let bitmap = bitmap.unwrap_or_default();
+            for (bit, column) in bit_columns.iter_mut().enumerate() {
+                column.push((bitmap >> bit) & 1 == 1);
             }
         }
 
@@ -150,54 +1150,254 @@ impl ScopeReading {
     }
 }
 
+/// Progress of a capture, reported by `IdleFleaScope::read_with_events`
+pub enum CaptureEvent<'a> {
+    /// The capture command was sent, the device is waiting for the trigger
+    Armed,
+    /// The trigger fired and the capture is complete, the transfer starts
+    Triggered,
+    /// Bytes of the response received so far, see `TRANSFER_BYTES_ESTIMATE`
+    TransferProgress(usize),
+    Complete(&'a ScopeReading),
+}
+
+/// Approximate size of a capture's CSV response, for progress reporting
+pub const TRANSFER_BYTES_ESTIMATE: usize = 24_000;
+
 pub struct ReadingFleaScope {
-    _ver: String,
+    ver: String,
     hostname: String,
+    waveform: Option<(Waveform, i32)>,
+    channel_labels: ChannelLabels,
     serial: BusyFleaTerminal,
-    effective_msps: f64,
+    config: CaptureConfig,
+    parser: IncrementalParser,
 }
 
 impl ReadingFleaScope {
+    /// Check once whether the capture is complete.
+    ///
+    /// Complete lines are parsed as they arrive, so the reading's `parsed` samples are
+    /// ready when the prompt is, instead of parsing the whole capture afterwards.
+    #[allow(clippy::result_large_err)]
     pub fn try_get_result(
         mut self,
-    ) -> Result<Result<(IdleFleaScope, ScopeReading), Self>, ConnectionLostError> {
+    ) -> Result<Result<(IdleFleaScope, ScopeReading), Self>, ReadInterrupted> {
         profiling::scope!("try_get_result");
 
         match self.serial.try_get_result() {
             Ok(r) => match r {
-                Ok((data, idle_terminal)) => Ok(Ok((
-                    IdleFleaScope {
-                        serial: idle_terminal,
-                        _ver: self._ver,
-                        hostname: self.hostname,
-                    },
-                    ScopeReading {
-                        effective_msps: self.effective_msps,
-                        data,
-                    },
-                ))),
+                Ok((data, idle_terminal)) => {
+                    let metadata = self.config.metadata(&self.hostname, SystemTime::now());
+                    let parsed = self.parser.finish(&data);
+                    Ok(Ok((
+                        IdleFleaScope {
+                            serial: idle_terminal,
+                            ver: self.ver,
+                            hostname: self.hostname,
+                            waveform: self.waveform,
+                            channel_labels: self.channel_labels,
+                        },
+                        ScopeReading {
+                            effective_msps: self.config.effective_msps(),
+                            data,
+                            metadata: Some(metadata),
+                            parsed: parsed.map_or_else(OnceLock::new, OnceLock::from),
+                        },
+                    )))
+                }
                 Err(busy_terminal) => {
                     self.serial = busy_terminal;
+                    self.parser.feed(self.serial.response());
                     Ok(Err(self))
                 }
             },
             Err(e) => Err(e),
         }
     }
-    pub fn cancel(self) -> IdleFleaScope {
-        let idle_serial = self.serial.cancel();
-        IdleFleaScope {
+    /// Bytes of the capture transferred so far. A full capture is roughly
+    /// `TRANSFER_BYTES_ESTIMATE` bytes long.
+    pub fn progress(&self) -> usize {
+        self.serial.progress()
+    }
+
+    /// The response received so far
+    pub(crate) fn received(&self) -> &[u8] {
+        self.serial.response()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn cancel(self) -> Result<IdleFleaScope, (FaultedFleaTerminal, FleaTerminalError)> {
+        let idle_serial = self.serial.cancel()?;
+        Ok(IdleFleaScope {
             serial: idle_serial,
-            _ver: self._ver,
+            ver: self.ver,
             hostname: self.hostname,
+            waveform: self.waveform,
+            channel_labels: self.channel_labels,
+        })
+    }
+
+    /// Non-blocking variant of `cancel`, poll the result with `CancellingFleaScope::try_finish`
+    #[allow(clippy::result_large_err)]
+    pub fn cancel_async(
+        self,
+    ) -> Result<CancellingFleaScope, (FaultedFleaTerminal, FleaTerminalError)> {
+        Ok(CancellingFleaScope {
+            serial: self.serial.cancel_async()?,
+            ver: self.ver,
+            hostname: self.hostname,
+            waveform: self.waveform,
+            channel_labels: self.channel_labels,
+        })
+    }
+
+    /// Complete the capture now, like the "Force" button of a bench scope.
+    ///
+    /// The firmware can't fire an armed trigger, so the capture is cancelled and retaken
+    /// over the same time frame with an immediate trigger and no delay.
+    #[allow(clippy::result_large_err)]
+    pub fn force_trigger(self) -> Result<Self, ForceTriggerError> {
+        profiling::scope!("ReadingFleaScope::force_trigger");
+
+        let config = self.config.immediate();
+        let idle = self
+            .cancel()
+            .map_err(|(faulted, e)| ForceTriggerError::CancelFailed(faulted, e))?;
+        idle.read_async_with(&config)
+            .map_err(|(idle, e)| ForceTriggerError::RearmFailed(idle, e))
+    }
+
+    /// Block until the capture is complete, `deadline` has passed or `cancel` is set, e.g.
+    /// by another thread holding the same `Arc<AtomicBool>`.
+    ///
+    /// Polling backs off up to `MAX_WAIT_BACKOFF` while no data arrives, but never sleeps
+    /// past the deadline. A cancelled capture is interrupted, a timed out one keeps running.
+    #[allow(clippy::result_large_err)]
+    pub fn wait_until(
+        mut self,
+        deadline: Instant,
+        cancel: &AtomicBool,
+    ) -> Result<WaitOutcome, WaitError> {
+        profiling::scope!("ReadingFleaScope::wait_until");
+
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return match self.cancel() {
+                    Ok(idle) => Ok(WaitOutcome::Cancelled(idle)),
+                    Err((faulted, e)) => Err(WaitError::CancelFailed(faulted, e)),
+                };
+            }
+
+            let progress = self.progress();
+            self = match self.try_get_result() {
+                Ok(Ok((idle, reading))) => return Ok(WaitOutcome::Ready(idle, reading)),
+                Ok(Err(still_reading)) => still_reading,
+                Err(e) => return Err(WaitError::Interrupted(e)),
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(WaitOutcome::TimedOut(self));
+            }
+            if self.progress() == progress {
+                std::thread::sleep(backoff.min(deadline - now));
+                backoff = (backoff * 2).min(MAX_WAIT_BACKOFF);
+            } else {
+                backoff = Duration::from_millis(1);
+            }
+        }
+    }
+}
+
+/// Longest sleep between polls of `ReadingFleaScope::wait_until`
+pub const MAX_WAIT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How `ReadingFleaScope::wait_until` returned
+pub enum WaitOutcome {
+    Ready(IdleFleaScope, ScopeReading),
+    /// The deadline passed first. The capture is still running.
+    TimedOut(ReadingFleaScope),
+    /// The cancel token was set and the capture was interrupted
+    Cancelled(IdleFleaScope),
+}
+
+/// Why `ReadingFleaScope::wait_until` failed
+#[derive(Debug, thiserror::Error)]
+pub enum WaitError {
+    #[error("Capture interrupted: {0}")]
+    Interrupted(#[from] ReadInterrupted),
+
+    #[error("Could not cancel the capture: {1}")]
+    CancelFailed(FaultedFleaTerminal, #[source] FleaTerminalError),
+}
+
+/// Why `ReadingFleaScope::force_trigger` failed
+#[derive(thiserror::Error)]
+#[allow(clippy::large_enum_variant)]
+pub enum ForceTriggerError {
+    #[error("Could not cancel the capture: {1}")]
+    CancelFailed(FaultedFleaTerminal, #[source] FleaTerminalError),
+
+    /// The capture was cancelled, but the immediate one couldn't be started
+    #[error("Cancelled the capture, but could not start an immediate one: {1}")]
+    RearmFailed(IdleFleaScope, #[source] FleaTerminalError),
+}
+
+// `IdleFleaScope` has no `Debug`, the scope is left out
+impl std::fmt::Debug for ForceTriggerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CancelFailed(terminal, e) => f
+                .debug_tuple("CancelFailed")
+                .field(terminal)
+                .field(e)
+                .finish(),
+            Self::RearmFailed(_, e) => f
+                .debug_tuple("RearmFailed")
+                .field(e)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+pub struct CancellingFleaScope {
+    ver: String,
+    hostname: String,
+    serial: CancellingFleaTerminal,
+    waveform: Option<(Waveform, i32)>,
+    channel_labels: ChannelLabels,
+}
+
+impl CancellingFleaScope {
+    #[allow(clippy::result_large_err)]
+    pub fn try_finish(
+        mut self,
+    ) -> Result<Result<IdleFleaScope, Self>, (FaultedFleaTerminal, FleaTerminalError)> {
+        match self.serial.try_finish()? {
+            Ok(serial) => Ok(Ok(IdleFleaScope {
+                serial,
+                ver: self.ver,
+                hostname: self.hostname,
+                waveform: self.waveform,
+                channel_labels: self.channel_labels,
+            })),
+            Err(cancelling) => {
+                self.serial = cancelling;
+                Ok(Err(self))
+            }
         }
     }
 }
 
 pub struct IdleFleaScope {
     serial: IdleFleaTerminal,
-    _ver: String,
+    ver: String,
     hostname: String,
+    /// Last generator setting the device accepted, `None` if off
+    waveform: Option<(Waveform, i32)>,
+    channel_labels: ChannelLabels,
 }
 
 impl IdleFleaScope {
@@ -207,22 +1407,68 @@ impl IdleFleaScope {
     const INTERLEAVE: u32 = 5; // number of ADCs interleaved
     const TOTAL_SAMPLES: u32 = 2000;
 
-    /// Connect to a `FleaScope` device
+    /// Connect to a `FleaScope` device. Fails with `UnsupportedFirmware` for firmware older
+    /// than `MIN_SUPPORTED_FIRMWARE` or with an unrecognized version, see
+    /// `connect_allow_unsupported`.
     pub fn connect(
         name: Option<&str>,
         port: Option<&str>,
         read_calibrations: bool,
     ) -> Result<(Self, FleaProbe, FleaProbe), FleaConnectorError> {
         let serial = FleaConnector::connect(name, port, true)?;
+        let scope = Self::new(serial);
+        scope.require_supported_firmware()?;
+        Ok(scope.attach_probes(read_calibrations))
+    }
+
+    /// Like `connect`, but also to unsupported or unrecognized firmware, e.g. development
+    /// builds. Only a warning is logged, expect commands to fail.
+    pub fn connect_allow_unsupported(
+        name: Option<&str>,
+        port: Option<&str>,
+        read_calibrations: bool,
+    ) -> Result<(Self, FleaProbe, FleaProbe), FleaConnectorError> {
+        let serial = FleaConnector::connect(name, port, true)?;
+        Ok(Self::with_probes(serial, read_calibrations))
+    }
+
+    /// Like `connect_allow_unsupported`, but over an already initialized terminal, e.g. a
+    /// simulated one
+    pub fn with_probes(
+        serial: IdleFleaTerminal,
+        read_calibrations: bool,
+    ) -> (Self, FleaProbe, FleaProbe) {
+        Self::new(serial).attach_probes(read_calibrations)
+    }
+
+    /// Fail with `UnsupportedFirmware` unless `is_firmware_supported`
+    pub fn require_supported_firmware(&self) -> Result<(), FleaConnectorError> {
+        if self.is_firmware_supported() {
+            Ok(())
+        } else {
+            Err(FleaConnectorError::UnsupportedFirmware {
+                found: self.ver.trim().to_string(),
+                required: MIN_SUPPORTED_FIRMWARE,
+            })
+        }
+    }
+
+    fn attach_probes(mut self, read_calibrations: bool) -> (Self, FleaProbe, FleaProbe) {
         let mut x1 = FleaProbe::new(ProbeType::X1);
         let mut x10 = FleaProbe::new(ProbeType::X10);
 
-        let mut scope = Self::new(serial);
         if read_calibrations {
-            x1.read_calibration_from_flash(&mut scope.serial);
-            x10.read_calibration_from_flash(&mut scope.serial);
+            // A fresh device has no calibration yet, that's no reason to fail connecting
+            for probe in [&mut x1, &mut x10] {
+                if let Err(e) = probe.read_calibration_from_flash(&mut self.serial) {
+                    log::warn!(
+                        "Probe x{} left uncalibrated: {e}",
+                        probe.multiplier.to_multiplier()
+                    );
+                }
+            }
         }
-        Ok((scope, x1, x10))
+        (self, x1, x10)
     }
 
     /// Create a new `FleaScope` from an existing terminal connection
@@ -232,7 +1478,12 @@ impl IdleFleaScope {
 
         let ver = String::from_utf8(serial.exec_sync("ver", None)).expect("Failed to read version");
         log::debug!("FleaScope version: {ver}");
-        // TODO: check if version is compatible
+        if !Capabilities::from_version_response(&ver).is_firmware_supported() {
+            log::warn!(
+                "Firmware {} is older than {MIN_SUPPORTED_FIRMWARE} or unrecognized, things may break",
+                ver.trim()
+            );
+        }
 
         let hostname =
             String::from_utf8(serial.exec_sync("hostname", None)).expect("Failed to read hostname");
@@ -241,15 +1492,55 @@ impl IdleFleaScope {
 
         Self {
             serial,
-            _ver: ver,
+            ver,
             hostname,
+            waveform: None,
+            channel_labels: ChannelLabels::default(),
         }
     }
 
     /// Set the waveform generator
     pub fn set_waveform(&mut self, waveform: Waveform, hz: i32) {
-        self.serial
+        let response = self
+            .serial
             .exec_sync(&format!("wave {} {}", waveform.as_str(), hz), None);
+        self.record_waveform(&response, Some((waveform, hz)));
+    }
+
+    /// Turn the waveform generator off
+    pub fn waveform_off(&mut self) {
+        let response = self.serial.exec_sync("wave off", None);
+        self.record_waveform(&response, None);
+    }
+
+    /// Shape and frequency the generator is outputting, `None` if it is off or wasn't set
+    /// since connecting.
+    ///
+    /// The firmware can't be asked for its current setting, so this is the last one the
+    /// device accepted through this scope.
+    pub fn waveform_status(&self) -> Option<(Waveform, i32)> {
+        self.waveform
+    }
+
+    /// Name the digital channels of this scope, see `ChannelLabels`
+    pub fn set_channel_labels(&mut self, labels: ChannelLabels) {
+        self.channel_labels = labels;
+    }
+
+    pub fn channel_labels(&self) -> &ChannelLabels {
+        &self.channel_labels
+    }
+
+    /// Remember `setting` if the device answered a `wave` command without complaint
+    pub(crate) fn record_waveform(&mut self, response: &[u8], setting: Option<(Waveform, i32)>) {
+        if response.trim_ascii().is_empty() {
+            self.waveform = setting;
+        } else {
+            log::warn!(
+                "Generator setting {setting:?} rejected: {}",
+                String::from_utf8_lossy(response).trim()
+            );
+        }
     }
 
     /// Convert number1 to prescaler value
@@ -279,67 +1570,287 @@ impl IdleFleaScope {
         time_frame: Duration,
         trigger_fields: StringifiedTriggerConfig,
         delay: Option<Duration>,
-    ) -> Result<(f64, String), CaptureConfigError> {
+    ) -> Result<CaptureConfig, CaptureConfigError> {
         profiling::scope!("prepare_read_command");
 
-        let delay = delay.unwrap_or(Duration::from_millis(0));
+        Self::validate_capture(time_frame, trigger_fields, delay.unwrap_or_default())
+            .map_err(|mut errors| errors.swap_remove(0))
+    }
 
-        // Validate time frame
-        if time_frame.as_secs_f64() > 3.49 {
-            return Err(CaptureConfigError::TimeFrameTooLarge);
-        }
-        if time_frame.as_secs() == 0 && time_frame.as_micros() < 111 {
-            return Err(CaptureConfigError::TimeFrameTooSmall);
+    /// Run the checks of `read_sync` and friends without touching the device, e.g. to show
+    /// validation errors live in a settings dialog. Triggers are validated when they are
+    /// built, so only the timing is checked here.
+    pub fn validate(
+        time_frame: Duration,
+        delay: Option<Duration>,
+    ) -> Result<CapturePlan, CaptureConfigError> {
+        let trigger_fields = DigitalTrigger::start_capturing_when()
+            .is_matching()
+            .into_trigger_fields();
+        Ok(Self::prepare_read_command(time_frame, trigger_fields, delay)?.plan)
+    }
+
+    /// Check all capture parameters, collecting every error instead of stopping at the first
+    fn validate_capture(
+        time_frame: Duration,
+        trigger_fields: StringifiedTriggerConfig,
+        delay: Duration,
+    ) -> Result<CaptureConfig, Vec<CaptureConfigError>> {
+        let mut errors = Vec::new();
+
+        // Public constructors like `AnalogTrigger::new` don't check the level range
+        if let Err(e) = Trigger::from_fields(trigger_fields.as_str()) {
+            errors.push(e.into());
         }
 
+        let timing = match CapturePlan::for_time_frame(time_frame) {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+
         // Validate delay
         if delay.as_secs_f64() > 1.0 {
-            return Err(CaptureConfigError::DelayTooLarge);
+            errors.push(CaptureConfigError::DelayTooLarge);
+        }
+        // The delay in samples depends on the sample rate, so it can only be checked for a
+        // valid time frame
+        let delay_samples =
+            timing.map(|plan| (delay.as_micros() as f64 * plan.effective_msps) as u32);
+        if delay_samples.is_some_and(|samples| samples > 1_000_000)
+            && !errors
+                .iter()
+                .any(|e| matches!(e, CaptureConfigError::DelayTooLarge))
+        {
+            errors.push(CaptureConfigError::DelayTooLarge);
         }
 
-        let number1 = Self::MSPS * (time_frame.as_micros() as u32) / Self::TOTAL_SAMPLES;
-        if number1 == 0 {
-            return Err(CaptureConfigError::TimeFrameTooSmall);
+        // A pattern hold is checked on the capture, so it has to fit
+        let held_for = match trigger_fields.emulated() {
+            Some(Trigger::Digital(trigger)) => trigger.held_for,
+            _ => None,
+        };
+        if let (Some(held), Some(plan)) = (held_for, timing) {
+            let captured = plan.captured_duration();
+            if held > captured {
+                errors.push(CaptureConfigError::HoldBeyondCapture { held, captured });
+            }
         }
 
-        let prescaler = Self::number1_to_prescaler(number1)?;
-        let effective_msps = Self::prescaler_to_effective_msps(prescaler);
+        // The conditions checked in software need the trigger at the start of the capture
+        if trigger_fields.emulated().is_some() && !delay.is_zero() {
+            errors.push(CaptureConfigError::EmulatedWithDelay);
+        }
 
-        let delay_samples = (delay.as_micros() as f64 * effective_msps) as u32;
-        if delay_samples > 1_000_000 {
-            return Err(CaptureConfigError::DelayTooLarge);
+        match (timing, delay_samples) {
+            (Some(plan), Some(delay_samples)) if errors.is_empty() => {
+                let emulated = trigger_fields.emulated().cloned();
+                let trigger = trigger_fields.into_string();
+                Ok(CaptureConfig {
+                    time_frame,
+                    delay,
+                    plan,
+                    command: format!("scope {} {} {}", plan.number1, trigger, delay_samples),
+                    trigger,
+                    delay_samples,
+                    holdoff: Duration::ZERO,
+                    emulated,
+                    pre_trigger: Duration::ZERO,
+                })
+            }
+            _ => Err(errors),
         }
-        Ok((
-            effective_msps,
-            format!(
-                "scope {} {} {}",
-                number1,
-                trigger_fields.into_string(),
-                delay_samples
-            ),
-        ))
     }
 
     /// Raw data read from the oscilloscope
+    #[allow(clippy::result_large_err)]
     pub fn read_async(
         self,
         time_frame: Duration,
         trigger_fields: StringifiedTriggerConfig,
         delay: Option<Duration>,
-    ) -> Result<ReadingFleaScope, (Self, CaptureConfigError)> {
+    ) -> Result<ReadingFleaScope, (Self, CaptureError)> {
+        let buffer = Vec::with_capacity(TRANSFER_BYTES_ESTIMATE);
+        self.read_async_into(time_frame, trigger_fields, delay, buffer)
+    }
+
+    /// Like `read_async`, but reuses `buffer` (e.g. the `data` of a previous reading) for
+    /// the response, saving allocations in acquisition loops
+    #[allow(clippy::result_large_err)]
+    pub fn read_async_into(
+        self,
+        time_frame: Duration,
+        trigger_fields: StringifiedTriggerConfig,
+        delay: Option<Duration>,
+        buffer: Vec<u8>,
+    ) -> Result<ReadingFleaScope, (Self, CaptureError)> {
         profiling::scope!("read_async");
 
-        match Self::prepare_read_command(time_frame, trigger_fields, delay) {
-            Ok((effective_msps, command)) => {
-                let data = self.serial.exec_async(&command);
-                Ok(ReadingFleaScope {
-                    _ver: self._ver,
+        let config = match Self::prepare_read_command(time_frame, trigger_fields, delay) {
+            Ok(config) => config,
+            Err(e) => return Err((self, e.into())),
+        };
+        config.warn_emulation_ignored();
+        match self.serial.exec_async_into(&config.command, buffer) {
+            Ok(data) => Ok(ReadingFleaScope {
+                ver: self.ver,
+                hostname: self.hostname,
+                waveform: self.waveform,
+                channel_labels: self.channel_labels,
+                serial: data,
+                parser: IncrementalParser::new(config.effective_msps()),
+                config,
+            }),
+            Err((serial, e)) => Err((
+                Self {
+                    serial,
+                    ver: self.ver,
                     hostname: self.hostname,
-                    serial: data,
-                    effective_msps,
-                })
-            }
-            Err(e) => Err((self, e)),
+                    waveform: self.waveform,
+                    channel_labels: self.channel_labels,
+                },
+                e.into(),
+            )),
+        }
+    }
+
+    /// Capture with a configuration validated by `CaptureConfig::builder()`
+    pub fn read(&mut self, config: &CaptureConfig) -> ScopeReading {
+        profiling::scope!("read");
+
+        config.warn_emulation_ignored();
+        let data = self.serial.exec_sync(&config.command, None);
+        ScopeReading {
+            effective_msps: config.effective_msps(),
+            data,
+            metadata: Some(config.metadata(&self.hostname, SystemTime::now())),
+            parsed: OnceLock::new(),
+        }
+    }
+
+    /// Like `read`, reporting the phases of the capture to `handler`, e.g. to show "waiting
+    /// for trigger" distinctly from "transferring" in a UI
+    pub fn read_with_events(
+        &mut self,
+        config: &CaptureConfig,
+        mut handler: impl FnMut(CaptureEvent<'_>),
+    ) -> Result<ScopeReading, FleaTerminalError> {
+        profiling::scope!("read_with_events");
+
+        config.warn_emulation_ignored();
+        // The device only starts talking once the capture is done
+        let mut triggered = false;
+        let data = self
+            .serial
+            .try_exec_with_progress(&config.command, None, |received| {
+                if received == 0 {
+                    handler(CaptureEvent::Armed);
+                    return;
+                }
+                if !triggered {
+                    triggered = true;
+                    handler(CaptureEvent::Triggered);
+                }
+                handler(CaptureEvent::TransferProgress(received));
+            })?;
+        let reading = ScopeReading {
+            effective_msps: config.effective_msps(),
+            data,
+            metadata: Some(config.metadata(&self.hostname, SystemTime::now())),
+            parsed: OnceLock::new(),
+        };
+        handler(CaptureEvent::Complete(&reading));
+        Ok(reading)
+    }
+
+    /// Acquire `n` consecutive captures as fast as possible and concatenate them.
+    ///
+    /// All capture commands are queued on the device at once, so there is no host
+    /// round-trip between segments. Each row carries its `segment` index and the
+    /// `trigger_time` of its segment, see `TRIGGER_TIME_COLUMN_NAME`.
+    #[cfg(feature = "dataframe")]
+    pub fn read_segments(
+        &mut self,
+        n: usize,
+        config: &CaptureConfig,
+    ) -> Result<DataFrame, PolarsError> {
+        profiling::scope!("read_segments");
+
+        if n == 0 {
+            return Ok(DataFrame::empty());
+        }
+        config.warn_emulation_ignored();
+
+        let commands = vec![config.command.as_str(); n];
+        let started = Instant::now();
+        let started_wall_clock = SystemTime::now();
+        let responses = self.serial.exec_many_timed(&commands, None);
+
+        let segments = responses
+            .into_iter()
+            .zip(0u32..)
+            .map(|((data, completed), segment)| {
+                let received_at = started_wall_clock + completed.duration_since(started);
+                let metadata = config.metadata(&self.hostname, received_at);
+                let trigger_time = metadata
+                    .triggered_at
+                    .duration_since(started_wall_clock)
+                    .unwrap_or_default();
+                let reading = ScopeReading {
+                    effective_msps: config.effective_msps(),
+                    data,
+                    metadata: Some(metadata),
+                    parsed: OnceLock::new(),
+                };
+                Ok(reading.parse_csv()?.with_columns([
+                    lit(segment).alias(SEGMENT_COLUMN_NAME),
+                    lit(trigger_time.as_secs_f64()).alias(TRIGGER_TIME_COLUMN_NAME),
+                ]))
+            })
+            .collect::<Result<Vec<_>, PolarsError>>()?;
+
+        concat(segments, UnionArgs::default())?.collect()
+    }
+
+    /// Non-blocking variant of `read`
+    #[allow(clippy::result_large_err)]
+    pub fn read_async_with(
+        self,
+        config: &CaptureConfig,
+    ) -> Result<ReadingFleaScope, (Self, FleaTerminalError)> {
+        config.warn_emulation_ignored();
+        self.read_async_unfiltered(config)
+    }
+
+    /// `read_async_with` for `CaptureStream`, which checks the emulated conditions itself
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn read_async_unfiltered(
+        self,
+        config: &CaptureConfig,
+    ) -> Result<ReadingFleaScope, (Self, FleaTerminalError)> {
+        let buffer = Vec::with_capacity(TRANSFER_BYTES_ESTIMATE);
+        match self.serial.exec_async_into(&config.command, buffer) {
+            Ok(serial) => Ok(ReadingFleaScope {
+                ver: self.ver,
+                hostname: self.hostname,
+                waveform: self.waveform,
+                channel_labels: self.channel_labels,
+                serial,
+                config: config.clone(),
+                parser: IncrementalParser::new(config.effective_msps()),
+            }),
+            Err((serial, e)) => Err((
+                Self {
+                    serial,
+                    ver: self.ver,
+                    hostname: self.hostname,
+                    waveform: self.waveform,
+                    channel_labels: self.channel_labels,
+                },
+                e,
+            )),
         }
     }
 
@@ -351,50 +1862,150 @@ impl IdleFleaScope {
     ) -> Result<ScopeReading, CaptureConfigError> {
         profiling::scope!("read_sync");
 
-        let (effective_msps, command) =
-            Self::prepare_read_command(time_frame, trigger_fields, delay)?;
+        let config = Self::prepare_read_command(time_frame, trigger_fields, delay)?;
+        config.warn_emulation_ignored();
 
-        let data = self.serial.exec_sync(&command, None);
+        let data = self.serial.exec_sync(&config.command, None);
         Ok(ScopeReading {
-            effective_msps,
+            effective_msps: config.effective_msps(),
             data,
+            metadata: Some(config.metadata(&self.hostname, SystemTime::now())),
+            parsed: OnceLock::new(),
         })
     }
 
-    pub fn stream(self) -> StreamingScope {
-        StreamingScope {
-            _ver: self._ver,
-            hostname: self.hostname,
-            serial: self.serial.exec_async("stream"),
+    /// Like `read_sync`, for any trigger. Analog levels given in volts are converted with
+    /// `probe`'s calibration, which has to match the probe the signal is connected to.
+    ///
+    /// ```rust,no_run
+    /// use fleascope_rs::units::Volts;
+    /// use fleascope_rs::{AnalogTrigger, IdleFleaScope};
+    /// use std::time::Duration;
+    ///
+    /// let (mut scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+    /// let trigger = AnalogTrigger::start_capturing_when(Volts::new(1.2)?).rising_edge();
+    /// let reading = scope.read_with(&x1, trigger, Duration::from_millis(5), None)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_with(
+        &mut self,
+        probe: &FleaProbe,
+        trigger: impl ProbeTrigger,
+        time_frame: Duration,
+        delay: Option<Duration>,
+    ) -> Result<ScopeReading, CaptureConfigError> {
+        profiling::scope!("read_with");
+
+        let trigger_fields = trigger.resolve(probe)?.into_trigger_fields();
+        self.read_sync(time_frame, trigger_fields, delay)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn stream(self) -> Result<StreamingScope, (Self, CaptureError)> {
+        if let Err(e) = self.capabilities().require(Feature::Streaming) {
+            return Err((self, e.into()));
+        }
+        match self.serial.exec_async("stream") {
+            Ok(serial) => Ok(StreamingScope {
+                ver: self.ver,
+                hostname: self.hostname,
+                waveform: self.waveform,
+                channel_labels: self.channel_labels,
+                serial,
+            }),
+            Err((serial, e)) => Err((
+                Self {
+                    serial,
+                    ver: self.ver,
+                    hostname: self.hostname,
+                    waveform: self.waveform,
+                    channel_labels: self.channel_labels,
+                },
+                e.into(),
+            )),
+        }
+    }
+
+    /// Features supported by the device's firmware
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_version_response(&self.ver)
+    }
+
+    /// Whether the firmware is recent enough for this library, see `MIN_SUPPORTED_FIRMWARE`
+    pub fn is_firmware_supported(&self) -> bool {
+        self.capabilities().is_firmware_supported()
+    }
+
+    /// Firmware version and hostname as reported when connecting
+    pub fn device_info(&self) -> DeviceInfo {
+        let capabilities = self.capabilities();
+        DeviceInfo {
+            firmware: capabilities.version(),
+            raw_version: self.ver.trim().to_string(),
+            hostname: self.hostname.trim().to_string(),
         }
     }
 
-    /// Set the hostname
-    pub fn set_hostname(&mut self, hostname: &str) {
-        self.serial.exec_sync(&format!("hostname {hostname}"), None);
+    /// Set the hostname. It must be a single word without control characters.
+    ///
+    /// Name-based discovery needs unique hostnames, see `FleaConnector::find_by_hostname`.
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<(), CommandError> {
+        let command = CommandBuilder::new("hostname").arg(hostname)?.build();
+        self.serial.exec_sync(&command, None);
         self.hostname = hostname.to_string();
+        Ok(())
+    }
+
+    /// Send a command to the device shell and return its output
+    pub(crate) fn exec_raw(&mut self, command: &str) -> Vec<u8> {
+        self.serial.exec_sync(command, None)
+    }
+
+    pub(crate) fn serial_mut(&mut self) -> &mut IdleFleaTerminal {
+        &mut self.serial
     }
 
+    /// Traffic counters of the serial link, e.g. to see whether transfer dominates capture time
+    pub fn transport_stats(&self) -> &TransportStats {
+        self.serial.transport_stats()
+    }
+
+    pub fn reset_transport_stats(&mut self) {
+        self.serial.reset_transport_stats();
+    }
+
+    /// Hostname reported by the device
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// Hand the shell back for interactive use with the dialect's `teardown_commands`
     pub fn teardown(mut self) {
-        let _ = self.serial.exec_sync("echo on", None);
-        let _ = self.serial.exec_sync("prompt on", None);
+        for command in self.serial.dialect().teardown_commands.clone() {
+            let _ = self.serial.exec_sync(&command, None);
+        }
     }
 }
 
 pub struct StreamingScope {
-    _ver: String,
+    ver: String,
     hostname: String,
     serial: BusyFleaTerminal,
+    waveform: Option<(Waveform, i32)>,
+    channel_labels: ChannelLabels,
 }
 
 impl StreamingScope {
-    pub fn stop(self) -> IdleFleaScope {
-        let serial = self.serial.cancel();
-        IdleFleaScope {
+    #[allow(clippy::result_large_err)]
+    pub fn stop(self) -> Result<IdleFleaScope, (FaultedFleaTerminal, FleaTerminalError)> {
+        let serial = self.serial.cancel()?;
+        Ok(IdleFleaScope {
             serial,
-            _ver: self._ver,
+            ver: self.ver,
             hostname: self.hostname,
-        }
+            waveform: self.waveform,
+            channel_labels: self.channel_labels,
+        })
     }
 
     pub fn read(&mut self, n: usize) -> Result<Vec<u16>, std::io::Error> {
@@ -413,6 +2024,8 @@ pub struct FleaProbe {
     multiplier: ProbeType,
     cal_zero: Option<f64>, // value for 0V
     cal_3v3: Option<f64>,  // value-diff 0V - 3.3V
+    #[cfg(feature = "dataframe")]
+    unit_conversion: Option<UnitConversion>,
 }
 
 impl Clone for FleaProbe {
@@ -421,6 +2034,8 @@ impl Clone for FleaProbe {
             multiplier: self.multiplier,
             cal_zero: self.cal_zero,
             cal_3v3: self.cal_3v3,
+            #[cfg(feature = "dataframe")]
+            unit_conversion: self.unit_conversion.clone(),
         }
     }
 }
@@ -432,45 +2047,32 @@ impl FleaProbe {
             multiplier,
             cal_zero: None,
             cal_3v3: None,
+            #[cfg(feature = "dataframe")]
+            unit_conversion: None,
         }
     }
 
-    pub fn read_calibration_from_flash(&mut self, serial: &mut IdleFleaTerminal) {
-        let dim_result = String::from_utf8(serial.exec_sync(
-            &format!(
-                "dim cal_zero_x{} as flash, cal_3v3_x{} as flash",
-                self.multiplier.to_multiplier(),
-                self.multiplier.to_multiplier()
-            ),
-            None,
-        ))
-        .expect("Failed to read calibration from flash");
+    /// Attach a sensor transfer function, applied by `apply_calibration` after the voltage
+    #[cfg(feature = "dataframe")]
+    pub fn set_unit_conversion(&mut self, conversion: Option<UnitConversion>) {
+        self.unit_conversion = conversion;
+    }
 
-        let expected_response = format!(
-            "var 'cal_zero_x{}' already declared at this scope\r\nvar 'cal_3v3_x{}' already declared at this scope",
-            self.multiplier.to_multiplier(), self.multiplier.to_multiplier()
-        );
+    #[cfg(feature = "dataframe")]
+    pub fn unit_conversion(&self) -> Option<&UnitConversion> {
+        self.unit_conversion.as_ref()
+    }
 
-        if dim_result == expected_response {
-            log::debug!("Variables for calibration already declared. Reading values.");
-        }
+    pub fn read_calibration_from_flash(
+        &mut self,
+        serial: &mut IdleFleaTerminal,
+    ) -> Result<(), CalibrationError> {
+        let (zero_name, v3v3_name) = self.flash_variable_names();
 
-        let cal_zero_raw: i32 = String::from_utf8(serial.exec_sync(
-            &format!("print cal_zero_x{}", self.multiplier.to_multiplier()),
-            None,
-        ))
-        .expect("Failed to read cal_zero_x value")
-        .trim()
-        .parse()
-        .expect("Failed to parse cal_zero_x value");
-        let cal_3v3_raw: i32 = String::from_utf8(serial.exec_sync(
-            &format!("print cal_3v3_x{}", self.multiplier.to_multiplier()),
-            None,
-        ))
-        .expect("Failed to read cal_3v3_x value")
-        .trim()
-        .parse()
-        .expect("Failed to parse cal_3v3_x value");
+        // A single pipelined round-trip keeps connection setup fast. It fails unless both
+        // values are read, so a failed read leaves the previous calibration untouched.
+        let values = FlashVars::new(serial).read(&[&zero_name, &v3v3_name])?;
+        let (cal_zero_raw, cal_3v3_raw) = (values[&zero_name], values[&v3v3_name]);
 
         self.cal_zero = Some(f64::from(cal_zero_raw - 1000) + 2048.0);
         self.cal_3v3 =
@@ -482,6 +2084,7 @@ impl FleaProbe {
             self.cal_zero,
             self.cal_3v3
         );
+        Ok(())
     }
 
     /// Set calibration values manually
@@ -504,33 +2107,35 @@ impl FleaProbe {
         let v3v3_value =
             (cal_3v3.mul_add(f64::from(self.multiplier.to_multiplier()), 1000.0) + 0.5) as i32;
 
-        scope.serial.exec_sync(
-            &format!(
-                "cal_zero_x{} = {}",
-                self.multiplier.to_multiplier(),
-                zero_value
-            ),
-            None,
-        );
-        scope.serial.exec_sync(
-            &format!(
-                "cal_3v3_x{} = {}",
-                self.multiplier.to_multiplier(),
-                v3v3_value
-            ),
-            None,
-        );
-
+        let (zero_name, v3v3_name) = self.flash_variable_names();
+        let mut vars = scope.flash_vars();
+        vars.set(&zero_name, zero_value)?;
+        vars.set(&v3v3_name, v3v3_value)?;
         Ok(())
     }
 
+    /// Flash variables holding the zero and 3.3 V calibration points
+    fn flash_variable_names(&self) -> (String, String) {
+        let multiplier = self.multiplier.to_multiplier();
+        (
+            format!("cal_zero_x{multiplier}"),
+            format!("cal_3v3_x{multiplier}"),
+        )
+    }
+
+    #[cfg(feature = "dataframe")]
     pub fn apply_calibration(&self, df: LazyFrame) -> LazyFrame {
         profiling::scope!("apply_calibration");
 
-        df.with_column(
+        let df = df.with_column(
             self.raw_to_voltage(col(RAW_COLUMN_NAME))
                 .alias(CALIBRATED_COLUMN_NAME),
-        )
+        );
+
+        match &self.unit_conversion {
+            Some(conversion) => conversion.apply(df),
+            None => df,
+        }
     }
 
     /// Read a stable value for calibration purposes
@@ -545,12 +2150,9 @@ impl FleaProbe {
         let bnc_values: Vec<f64> = scope
             .read_sync(Duration::from_millis(20), trigger_fields, None)
             .expect("This should not fail, as we are reading a stable value for calibration")
-            .parse_csv()?
-            .select([col(RAW_COLUMN_NAME)])
-            .collect()?
-            .column(RAW_COLUMN_NAME)?
-            .f64()?
-            .into_no_null_iter()
+            .samples()?
+            .iter()
+            .map(|sample| sample.raw)
             .collect();
 
         let min_val = bnc_values.iter().fold(f64::INFINITY, |a, &b| a.min(b));
@@ -564,7 +2166,38 @@ impl FleaProbe {
         Ok(mean)
     }
 
+    /// Lowest and highest raw value the ADC can report
+    const ADC_RANGE: (f64, f64) = (0.0, 4095.0);
+
+    /// Lowest and highest voltage this probe can measure, e.g. for validating trigger
+    /// levels or choosing plot axis limits
+    pub fn measurable_range(&self) -> Result<(Volts, Volts), CalibrationError> {
+        let cal_zero = self
+            .cal_zero
+            .ok_or(CalibrationError::NoCalibrationPresent)?;
+        let cal_3v3 = self.cal_3v3.ok_or(CalibrationError::NoCalibrationPresent)?;
+
+        let to_voltage = |raw: f64| (raw - cal_zero) / cal_3v3 * 3.3;
+        let (low, high) = (to_voltage(Self::ADC_RANGE.0), to_voltage(Self::ADC_RANGE.1));
+        let volts =
+            |value: f64| Volts::new(value).map_err(|_| CalibrationError::InvalidCalibration);
+        Ok((volts(low.min(high))?, volts(low.max(high))?))
+    }
+
+    /// Convert a raw ADC value to volts, like `apply_calibration` but for plain samples.
+    /// The unit conversion isn't applied.
+    pub fn raw_to_volts(&self, raw: f64) -> Result<Volts, CalibrationError> {
+        let cal_zero = self
+            .cal_zero
+            .ok_or(CalibrationError::NoCalibrationPresent)?;
+        let cal_3v3 = self.cal_3v3.ok_or(CalibrationError::NoCalibrationPresent)?;
+
+        Volts::new((raw - cal_zero) / cal_3v3 * Volts::CALIBRATION_REFERENCE.get())
+            .map_err(|_| CalibrationError::InvalidCalibration)
+    }
+
     /// Convert raw ADC value to voltage
+    #[cfg(feature = "dataframe")]
     pub fn raw_to_voltage(&self, raw_value: Expr) -> Expr {
         let cal_zero = self.cal_zero.expect("Calibration for 0V is not set");
         let cal_3v3 = self.cal_3v3.expect("Calibration for 3.3V is not set");
@@ -573,18 +2206,18 @@ impl FleaProbe {
     }
 
     /// Convert voltage to raw ADC value
-    pub fn voltage_to_raw(&self, voltage: f64) -> f64 {
+    pub fn voltage_to_raw(&self, voltage: Volts) -> f64 {
         let cal_zero = self.cal_zero.expect("Calibration for 0V is not set");
         let cal_3v3 = self.cal_3v3.expect("Calibration for 3.3V is not set");
 
-        (voltage / 3.3).mul_add(cal_3v3, cal_zero)
+        (voltage.get() / Volts::CALIBRATION_REFERENCE.get()).mul_add(cal_3v3, cal_zero)
     }
 
     /// Calibrate for 0V
     pub fn calibrate_0(&mut self, scope: &mut IdleFleaScope) -> Result<f64, CalibrationError> {
         // Try to preserve existing 3.3V calibration if available
         let raw_value_3v3 = if let (Some(_), Some(_)) = (self.cal_zero, self.cal_3v3) {
-            Some(self.voltage_to_raw(3.3))
+            Some(self.voltage_to_raw(Volts::CALIBRATION_REFERENCE))
         } else {
             None
         };
@@ -617,6 +2250,7 @@ impl FleaProbe {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::trigger_config::{AnalogTrigger, AnalogTriggerBehavior, BitState};
 
     #[test]
     fn test_waveform_as_str() {
@@ -631,4 +2265,863 @@ mod tests {
         assert!(IdleFleaScope::number1_to_prescaler(100).is_ok());
         assert!(IdleFleaScope::number1_to_prescaler(0).is_err());
     }
+
+    #[test]
+    fn test_capture_plan() {
+        let plan = CapturePlan::for_time_frame(Duration::from_millis(10)).unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        assert!((plan.effective_msps() - config.effective_msps()).abs() < f64::EPSILON);
+        let error = plan.captured_duration().as_secs_f64() - 0.010;
+        assert!(error.abs() < 0.001, "{:?}", plan.captured_duration());
+        assert!(plan.resolution() < Duration::from_micros(10));
+
+        assert!(matches!(
+            CapturePlan::for_time_frame(Duration::from_secs(4)),
+            Err(CaptureConfigError::TimeFrameTooLarge)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_capture_metadata() {
+        let device = crate::simulator::SimulatedDevice::new().hostname("bench-3");
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .delay(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let before = SystemTime::now();
+        let reading = scope.read(&config);
+        let metadata = reading.metadata.as_ref().unwrap();
+        assert_eq!(metadata.hostname, "bench-3");
+        assert_eq!(metadata.requested_time_frame, Duration::from_millis(10));
+        assert_eq!(
+            metadata.actual_time_frame,
+            config.plan().captured_duration()
+        );
+        assert_eq!(metadata.trigger, "0x00 0x00");
+        assert!(metadata.delay_samples > 0);
+        assert!(metadata.triggered_at < before);
+
+        let df = metadata
+            .embed(reading.parse_csv().unwrap())
+            .collect()
+            .unwrap();
+        let hostname = df.column("meta_hostname").unwrap().str().unwrap().get(0);
+        assert_eq!(hostname, Some("bench-3"));
+    }
+
+    #[test]
+    fn test_read_with_events() {
+        let (mut scope, _x1, _x10) = crate::simulator::SimulatedDevice::new().connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let mut events = Vec::new();
+        let reading = scope
+            .read_with_events(&config, |event| {
+                events.push(match event {
+                    CaptureEvent::Armed => "armed".to_string(),
+                    CaptureEvent::Triggered => "triggered".to_string(),
+                    CaptureEvent::TransferProgress(bytes) => format!("{bytes}"),
+                    CaptureEvent::Complete(reading) => format!("complete {}", reading.data.len()),
+                });
+            })
+            .unwrap();
+
+        assert_eq!(events[..2], ["armed", "triggered"]);
+        assert_eq!(
+            events.last().unwrap(),
+            &format!("complete {}", reading.data.len())
+        );
+        // Progress includes the prompt, which isn't part of the reading
+        let progress = &events[events.len() - 2];
+        assert_eq!(progress, &format!("{}", reading.data.len() + 2));
+    }
+
+    #[test]
+    fn test_reading_progress() {
+        let (mut scope, _x1, _x10) = crate::simulator::SimulatedDevice::new().connect().unwrap();
+        scope.serial_mut().set_chunk_size(1024);
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let mut reading = scope.read_async_with(&config).ok().unwrap();
+        assert_eq!(reading.progress(), 0);
+        let mut reports = Vec::new();
+        let data = loop {
+            match reading.try_get_result().unwrap() {
+                Ok((_, reading)) => break reading.data,
+                Err(still_reading) => reading = still_reading,
+            }
+            reports.push(reading.progress());
+        };
+        assert!(reports.len() > 1);
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+        // Progress includes the prompt, which isn't part of the reading
+        assert!(*reports.last().unwrap() < data.len() + 2);
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_parse_csv_with_epoch() {
+        let reading = ScopeReading {
+            effective_msps: 1.0,
+            data: b"2048,0x000\n2048,0x000\n2048,0x000\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let df = reading
+            .parse_csv_with_epoch(start)
+            .unwrap()
+            .select([col(TIMESTAMP_COLUMN_NAME).cast(DataType::Int64)])
+            .collect()
+            .unwrap();
+        let timestamps: Vec<i64> = df
+            .column(TIMESTAMP_COLUMN_NAME)
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        let start_us = 1_700_000_000_000_000;
+        assert_eq!(timestamps, [start_us, start_us + 1, start_us + 2]);
+    }
+
+    #[test]
+    fn test_was_triggered() {
+        let reading = |trigger: &str, delay_samples, data: &[u8]| ScopeReading {
+            effective_msps: 1.0,
+            data: data.to_vec(),
+            metadata: Some(CaptureMetadata {
+                requested_time_frame: Duration::from_millis(1),
+                actual_time_frame: Duration::from_millis(1),
+                delay_samples,
+                trigger: trigger.to_string(),
+                hostname: String::new(),
+                triggered_at: SystemTime::UNIX_EPOCH,
+            }),
+            parsed: OnceLock::new(),
+        };
+
+        let fired = reading("~0x01 0x03", 0, b"2048,0x005\r\n2048,0x000\r\n");
+        let timed_out = reading("~0x01 0x03", 0, b"2048,0x004\r\n2048,0x001\r\n");
+        assert_eq!(fired.was_triggered(), Some(true));
+        assert_eq!(timed_out.was_triggered(), Some(false));
+
+        assert_eq!(
+            reading("~600 0", 0, b"2400,0x000\r\n").was_triggered(),
+            Some(true)
+        );
+        assert_eq!(
+            reading("~600 0", 0, b"2048,0x000\r\n").was_triggered(),
+            Some(false)
+        );
+
+        assert_eq!(
+            reading("+600 0", 0, b"0,0x000\r\n").was_triggered(),
+            Some(true)
+        );
+        assert_eq!(reading("~600 0", 10, b"0,0x000\r\n").was_triggered(), None);
+        assert_eq!(
+            ScopeReading {
+                metadata: None,
+                parsed: OnceLock::new(),
+                ..fired
+            }
+            .was_triggered(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let plan = IdleFleaScope::validate(Duration::from_millis(10), None).unwrap();
+        assert_eq!(
+            plan,
+            CapturePlan::for_time_frame(Duration::from_millis(10)).unwrap()
+        );
+        assert!(matches!(
+            IdleFleaScope::validate(Duration::from_millis(10), Some(Duration::from_secs(2))),
+            Err(CaptureConfigError::DelayTooLarge)
+        ));
+        assert!(matches!(
+            IdleFleaScope::validate(Duration::from_micros(10), None),
+            Err(CaptureConfigError::TimeFrameTooSmall)
+        ));
+    }
+
+    #[test]
+    fn test_incremental_parsing() {
+        let device = crate::simulator::SimulatedDevice::new().signal(|t| t * 1e6);
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let mut reading = scope.read_async_with(&config).ok().unwrap();
+        let reading = loop {
+            match reading.try_get_result().unwrap() {
+                Ok((_, data)) => break data,
+                Err(still_reading) => reading = still_reading,
+            }
+        };
+        let parsed = reading.parsed.get().unwrap().clone();
+        let on_demand = ScopeReading {
+            parsed: OnceLock::new(),
+            ..reading
+        };
+        assert_eq!(parsed, on_demand.samples().unwrap());
+        // Parsed on the first call, later calls hand out the same samples
+        assert!(std::ptr::eq(
+            on_demand.samples().unwrap(),
+            on_demand.parsed.get().unwrap().as_slice()
+        ));
+
+        let mut parser = IncrementalParser::new(1.0);
+        parser.feed(b"2048,0x000\n20");
+        parser.feed(b"2048,0x000\n2049,0x001\n");
+        assert_eq!(parser.consumed, 22);
+        let samples = parser
+            .finish(b"2048,0x000\n2049,0x001\n2050,0x002")
+            .unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[2].bitmap, 0x002);
+
+        let mut parser = IncrementalParser::new(1.0);
+        parser.feed(b"error: oops\n");
+        assert!(parser.finish(b"error: oops\n").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_parse_csv_from_parsed_samples() {
+        let reading = ScopeReading {
+            effective_msps: 1.0,
+            data: b"2048,0x000\r\n2148,0x005\r\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let parsed = ScopeReading {
+            parsed: OnceLock::from(reading.samples().unwrap().to_vec()),
+            ..ScopeReading {
+                effective_msps: 1.0,
+                data: Vec::new(),
+                metadata: None,
+                parsed: OnceLock::new(),
+            }
+        };
+        let expected = reading.parse_csv().unwrap().collect().unwrap();
+        let actual = parsed.parse_csv().unwrap().collect().unwrap();
+        assert!(actual.equals(&expected));
+    }
+
+    #[test]
+    fn test_wait_until() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+
+        let reading = scope.read_async_with(&config).ok().unwrap();
+        let outcome = reading.wait_until(deadline, &cancel).unwrap();
+        let WaitOutcome::Ready(scope, reading) = outcome else {
+            unreachable!("capture didn't complete");
+        };
+        assert_eq!(reading.samples().unwrap().len(), 2000);
+
+        cancel.store(true, Ordering::Relaxed);
+        let reading = scope.read_async_with(&config).ok().unwrap();
+        let outcome = reading.wait_until(deadline, &cancel).unwrap();
+        let WaitOutcome::Cancelled(mut scope) = outcome else {
+            unreachable!("capture wasn't cancelled");
+        };
+        assert!(!scope.read(&config).data.is_empty());
+    }
+
+    #[test]
+    fn test_force_trigger() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .delay(Duration::from_micros(100))
+            .trigger(
+                DigitalTrigger::start_capturing_when()
+                    .bit0(BitState::High)
+                    .starts_matching()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let reading = scope.read_async_with(&config).ok().unwrap();
+        let Ok(forced) = reading.force_trigger() else {
+            unreachable!("force trigger failed");
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let outcome = forced
+            .wait_until(deadline, &AtomicBool::new(false))
+            .unwrap();
+        let WaitOutcome::Ready(_scope, reading) = outcome else {
+            unreachable!("forced capture didn't complete");
+        };
+        assert_eq!(reading.samples().unwrap().len(), 2000);
+
+        let captures: Vec<_> = device
+            .commands()
+            .into_iter()
+            .filter(|command| command.starts_with("scope "))
+            .collect();
+        assert_eq!(captures.len(), 2);
+        assert_eq!(captures[0], config.command);
+        let plan = config.plan();
+        assert_eq!(captures[1], format!("scope {} 0x00 0x00 0", plan.number1));
+    }
+
+    #[test]
+    fn test_capture_config_builder() {
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .delay(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        assert!(config.command.starts_with("scope "));
+        assert!(config.effective_msps() > 0.0);
+
+        let errors = CaptureConfig::builder()
+            .time_frame(Duration::from_secs(5))
+            .delay(Duration::from_secs(2))
+            .build()
+            .unwrap_err()
+            .0;
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                CaptureConfigError::TimeFrameTooLarge,
+                CaptureConfigError::DelayTooLarge
+            ]
+        ));
+
+        assert!(matches!(
+            CaptureConfig::builder().build().unwrap_err().0.as_slice(),
+            [CaptureConfigError::MissingTimeFrame]
+        ));
+
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .trigger_position(0.001)
+            .build()
+            .unwrap();
+        assert_eq!(config.delay(), Duration::from_millis(1));
+        let trigger = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .starts_matching()
+            .unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .trigger(trigger.clone())
+            .trigger_position(-0.002)
+            .build()
+            .unwrap();
+        assert_eq!(config.pre_trigger(), Duration::from_millis(2));
+        assert_eq!(config.time_frame(), Duration::from_millis(10));
+        assert!(config.plan().captured_duration() >= Duration::from_millis(11));
+        // Captured right away, the trigger is found in software
+        assert!(config.command.ends_with(" 0x00 0x00 0"));
+        assert_eq!(config.trigger(), Trigger::Digital(trigger));
+        assert!(matches!(
+            CaptureConfig::builder()
+                .time_frame(Duration::from_secs(3))
+                .trigger_position(-1.0)
+                .build()
+                .unwrap_err()
+                .0
+                .as_slice(),
+            [CaptureConfigError::TimeFrameTooLarge]
+        ));
+
+        // Levels from the public constructor aren't range checked until here
+        assert!(matches!(
+            CaptureConfig::builder()
+                .time_frame(Duration::from_millis(10))
+                .trigger(AnalogTrigger::new(2000, AnalogTriggerBehavior::Level))
+                .build()
+                .unwrap_err()
+                .0
+                .as_slice(),
+            [CaptureConfigError::InvalidTrigger(ParseTriggerError::InvalidLevel(level))]
+                if level == "2000"
+        ));
+
+        let held = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .held_for(Duration::from_millis(20))
+            .starts_matching()
+            .unwrap();
+        assert!(matches!(
+            CaptureConfig::builder()
+                .time_frame(Duration::from_millis(10))
+                .trigger(held)
+                .build()
+                .unwrap_err()
+                .0
+                .as_slice(),
+            [CaptureConfigError::HoldBeyondCapture { .. }]
+        ));
+
+        let qualified = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).qualified_by(
+            &DigitalTrigger::start_capturing_when()
+                .bit3(BitState::High)
+                .is_matching(),
+        );
+        assert!(matches!(
+            CaptureConfig::builder()
+                .time_frame(Duration::from_millis(10))
+                .trigger(qualified)
+                .delay(Duration::from_millis(1))
+                .build()
+                .unwrap_err()
+                .0
+                .as_slice(),
+            [CaptureConfigError::EmulatedWithDelay]
+        ));
+    }
+
+    #[test]
+    fn test_measurable_range() {
+        let mut probe = FleaProbe::new(ProbeType::X1);
+        assert!(probe.measurable_range().is_err());
+
+        probe.set_calibration(2048.0, 1000.0);
+        let (low, high) = probe.measurable_range().unwrap();
+        assert!((low.get() - -6.7584).abs() < 1e-9);
+        assert!((high.get() - 6.7551).abs() < 1e-9);
+
+        probe.set_calibration(2048.0, 0.0);
+        assert!(matches!(
+            probe.measurable_range(),
+            Err(CalibrationError::InvalidCalibration)
+        ));
+    }
+
+    #[test]
+    fn test_parse_device_integer() {
+        assert_eq!(parse_device_integer(b"1234"), Some(1234));
+        assert_eq!(parse_device_integer(b" -12\r\n"), Some(-12));
+        assert_eq!(parse_device_integer(b"+7\0"), Some(7));
+        assert_eq!(parse_device_integer(b"1.5e3"), Some(1500));
+        assert_eq!(parse_device_integer(b"1000.0"), Some(1000));
+        assert_eq!(parse_device_integer(b"1.5"), None);
+        assert_eq!(parse_device_integer(b"++1"), None);
+        assert_eq!(parse_device_integer(b"1e10"), None);
+        assert_eq!(parse_device_integer(b"var 'x' not declared"), None);
+        assert_eq!(parse_device_integer(b""), None);
+    }
+
+    #[test]
+    fn test_parse_device_integer_roundtrip() {
+        let decorations = [("", ""), (" ", "\r\n"), ("\t", " \n"), ("", "\0")];
+        for value in (-5000..5000).step_by(37).chain([i32::MIN, i32::MAX]) {
+            for (prefix, suffix) in decorations {
+                for text in [
+                    format!("{value}"),
+                    format!("{value:+}"),
+                    format!("{value}.0"),
+                ] {
+                    let response = format!("{prefix}{text}{suffix}");
+                    assert_eq!(
+                        parse_device_integer(response.as_bytes()),
+                        Some(value),
+                        "{response:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_device_info() {
+        let device = crate::simulator::SimulatedDevice::new()
+            .hostname("bench-3")
+            .version("FleaScope v1.2.3");
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let info = scope.device_info();
+        assert_eq!(info.hostname, "bench-3");
+        assert_eq!(info.raw_version, "FleaScope v1.2.3");
+        assert_eq!(
+            info.firmware,
+            Some(crate::capabilities::FirmwareVersion::new(1, 2, 3))
+        );
+        assert!(scope.is_firmware_supported());
+        assert!(scope.require_supported_firmware().is_ok());
+
+        let old = crate::simulator::SimulatedDevice::new().version("FleaScope v0.9");
+        let (scope, _x1, _x10) = old.connect().unwrap();
+        assert!(matches!(
+            scope.require_supported_firmware(),
+            Err(FleaConnectorError::UnsupportedFirmware { found, .. }) if found == "FleaScope v0.9"
+        ));
+    }
+
+    #[test]
+    fn test_set_hostname() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+
+        scope.set_hostname("bench-3_a").unwrap();
+        assert_eq!(scope.hostname(), "bench-3_a");
+        assert_eq!(device.commands().last().unwrap(), "hostname bench-3_a");
+
+        assert_eq!(scope.set_hostname(""), Err(CommandError::Empty));
+        assert!(matches!(
+            scope.set_hostname("bench 3"),
+            Err(CommandError::Whitespace { .. })
+        ));
+        assert!(matches!(
+            scope.set_hostname("bench\nreset"),
+            Err(CommandError::ControlCharacter {
+                character: '\n',
+                ..
+            })
+        ));
+        assert_eq!(scope.hostname(), "bench-3_a");
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_read_segments() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let df = scope.read_segments(3, &config).unwrap();
+        let scope_commands = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert_eq!(scope_commands, 3);
+
+        let segments = df
+            .clone()
+            .lazy()
+            .group_by_stable([col(SEGMENT_COLUMN_NAME)])
+            .agg([
+                len().alias("rows"),
+                col(TRIGGER_TIME_COLUMN_NAME).min().alias("first"),
+                col(TRIGGER_TIME_COLUMN_NAME).max().alias("last"),
+            ])
+            .collect()
+            .unwrap();
+        let column = |name: &str| segments.column(name).unwrap().clone();
+        let indices: Vec<u32> = column(SEGMENT_COLUMN_NAME)
+            .cast(&DataType::UInt32)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(indices, [0, 1, 2]);
+        let rows: Vec<u32> = column("rows").u32().unwrap().into_no_null_iter().collect();
+        assert!(rows.iter().all(|&rows| rows > 0));
+        assert_eq!(df.height(), rows.iter().sum::<u32>() as usize);
+
+        // One trigger time per segment, in acquisition order
+        let first: Vec<f64> = column("first").f64().unwrap().into_no_null_iter().collect();
+        let last: Vec<f64> = column("last").f64().unwrap().into_no_null_iter().collect();
+        assert_eq!(first, last);
+        assert!(first.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(first.iter().all(|&time| time >= 0.0));
+
+        assert_eq!(scope.read_segments(0, &config).unwrap().height(), 0);
+    }
+
+    #[test]
+    fn test_teardown() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        scope.teardown();
+        assert_eq!(
+            device.commands()[device.commands().len() - 2..],
+            ["echo on", "prompt on"]
+        );
+
+        let dialect = crate::serial_terminal::TerminalDialect {
+            teardown_commands: vec!["prompt on".to_string()],
+            ..crate::serial_terminal::TerminalDialect::crlf()
+        };
+        let device = crate::simulator::SimulatedDevice::new();
+        let terminal = crate::serial_terminal::StatelessFleaTerminal::from_port(
+            Box::new(device.port()),
+            dialect,
+        )
+        .unwrap()
+        .initialize()
+        .map_err(|(_, e)| e)
+        .unwrap();
+        let (scope, _x1, _x10) = IdleFleaScope::with_probes(terminal, false);
+        let sent = device.commands().len();
+        scope.teardown();
+        assert_eq!(device.commands()[sent..], ["prompt on"]);
+    }
+
+    #[test]
+    fn test_failed_calibration_read_keeps_calibration() {
+        let device = crate::simulator::SimulatedDevice::new().calibrated(2048, 1000);
+        let (mut scope, mut x1, _x10) = device.connect().unwrap();
+        let calibration = (x1.cal_zero, x1.cal_3v3);
+        assert!(calibration.0.is_some() && calibration.1.is_some());
+
+        let mut session = crate::simulator::RecordedSession::new();
+        session.push("print cal_3v3_x1", "error: flash read failed\r\n");
+        let _device = device.session(&session);
+        assert!(x1.read_calibration_from_flash(scope.serial_mut()).is_err());
+        assert_eq!((x1.cal_zero, x1.cal_3v3), calibration);
+    }
+
+    #[test]
+    fn test_read_with() {
+        let device = crate::simulator::SimulatedDevice::new().calibrated(2048, 1000);
+        let (mut scope, x1, _x10) = device.connect().unwrap();
+        let volts = Volts::new(1.0).unwrap();
+        let level = AnalogTrigger::start_capturing_when(volts)
+            .into_trigger(&x1)
+            .unwrap()
+            .level;
+
+        let trigger = AnalogTrigger::start_capturing_when(volts).rising_edge();
+        let reading = scope
+            .read_with(&x1, trigger, Duration::from_millis(1), None)
+            .unwrap();
+        assert!(!reading.data.is_empty());
+        let command = device.commands().pop().unwrap();
+        assert!(command.contains(&format!(" +{level} 0 ")), "{command}");
+
+        let digital = DigitalTrigger::start_capturing_when().is_matching();
+        assert!(scope
+            .read_with(&x1, digital, Duration::from_millis(1), None)
+            .is_ok());
+        assert!(matches!(
+            scope.read_with(
+                &FleaProbe::new(ProbeType::X1),
+                AnalogTrigger::start_capturing_when(volts),
+                Duration::from_millis(1),
+                None
+            ),
+            Err(CaptureConfigError::ProbeNotCalibrated)
+        ));
+    }
+
+    #[test]
+    fn test_waveform_from_str() {
+        assert_eq!("Triangle".parse(), Ok(Waveform::Triangle));
+        assert_eq!(Waveform::Ekg.to_string().parse(), Ok(Waveform::Ekg));
+        assert_eq!(
+            "noise".parse::<Waveform>(),
+            Err(ParseWaveformError("noise".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_capture_config_serde() {
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .delay(Duration::from_micros(500))
+            .trigger(AnalogTrigger::new(300, AnalogTriggerBehavior::Rising))
+            .holdoff(Duration::from_millis(2))
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: CaptureConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.command, config.command);
+        assert_eq!(restored.holdoff(), config.holdoff());
+
+        // Saved setups are validated like new ones
+        let too_long = json.replace("\"secs\":0,\"nanos\":10000000", "\"secs\":5,\"nanos\":0");
+        assert_ne!(too_long, json);
+        assert!(serde_json::from_str::<CaptureConfig>(&too_long).is_err());
+        let out_of_range = json.replace("\"level\":300", "\"level\":2000");
+        assert_ne!(out_of_range, json);
+        assert!(serde_json::from_str::<CaptureConfig>(&out_of_range).is_err());
+
+        let pre_triggered = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .trigger(AnalogTrigger::new(300, AnalogTriggerBehavior::Rising))
+            .trigger_position(-0.001)
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&pre_triggered).unwrap();
+        let restored: CaptureConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.command, pre_triggered.command);
+        assert_eq!(restored.pre_trigger(), Duration::from_millis(1));
+        assert_eq!(restored.trigger(), pre_triggered.trigger());
+
+        assert_eq!(serde_json::to_string(&Waveform::Sine).unwrap(), "\"sine\"");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_scope_reading_serde() {
+        let reading = ScopeReading {
+            effective_msps: 2.0,
+            data: b"2048,0x000\n2148,0x205\n".to_vec(),
+            metadata: Some(CaptureMetadata {
+                requested_time_frame: Duration::from_micros(1),
+                actual_time_frame: Duration::from_micros(1),
+                delay_samples: 0,
+                trigger: "~0x01 0x01".to_string(),
+                hostname: "bench".to_string(),
+                triggered_at: SystemTime::UNIX_EPOCH
+                    + Duration::from_nanos(1_700_000_000_000_000_001),
+            }),
+            parsed: OnceLock::new(),
+        };
+        let json = serde_json::to_string(&reading).unwrap();
+        assert!(json.starts_with(
+            r#"{"version":1,"effective_msps":2.0,"raw":[2048.0,2148.0],"bitmap":[0,517],"#
+        ));
+        let restored: ScopeReading = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.data, reading.data);
+        assert_eq!(restored.metadata, reading.metadata);
+        assert_eq!(restored.was_triggered(), Some(false));
+
+        let newer = json.replace(r#""version":1"#, r#""version":2"#);
+        assert!(serde_json::from_str::<ScopeReading>(&newer).is_err());
+        let truncated = json.replace("[0,517]", "[0]");
+        assert!(serde_json::from_str::<ScopeReading>(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_waveform_status() {
+        let session =
+            crate::simulator::RecordedSession::parse("> wave triangle -5\nerror: bad frequency\n");
+        let device = crate::simulator::SimulatedDevice::new().session(&session);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        assert_eq!(scope.waveform_status(), None);
+
+        scope.set_waveform(Waveform::Sine, 1000);
+        assert_eq!(scope.waveform_status(), Some((Waveform::Sine, 1000)));
+
+        // Rejected settings leave the generator as it was
+        scope.set_waveform(Waveform::Triangle, -5);
+        assert_eq!(scope.waveform_status(), Some((Waveform::Sine, 1000)));
+
+        scope.waveform_off();
+        assert_eq!(device.commands().last().unwrap(), "wave off");
+        assert_eq!(scope.waveform_status(), None);
+    }
+
+    #[test]
+    fn test_samples() {
+        let reading = ScopeReading {
+            effective_msps: 0.5,
+            data: b"2048,0x000\r\n 2148 , 0x005\r\n1e3,0x3ff\r\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let samples = reading.samples().unwrap();
+        let raw: Vec<f64> = samples.iter().map(|sample| sample.raw).collect();
+        assert_eq!(raw, [2048.0, 2148.0, 1000.0]);
+        assert_eq!(samples[1].bitmap, 0x005);
+        assert!(samples[1].bit(2) && !samples[1].bit(1));
+        assert!((samples[2].time - 4e-6).abs() < 1e-12);
+
+        let garbage = ScopeReading {
+            effective_msps: 1.0,
+            data: b"2048,0x000\nerror: oops\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let error = garbage.samples().unwrap_err();
+        assert_eq!((error.index, error.offset), (1, 11));
+
+        // Dropped lines keep their time slot
+        let corrupted = ScopeReading {
+            effective_msps: 1.0,
+            data: b"2048,0x000\r\n2048;0x0\r\n2049,0x001\r\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let (samples, report) = corrupted.samples_with(ParseMode::Lenient).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert!((samples[1].time - 2e-6).abs() < 1e-12);
+        assert_eq!(report.dropped_lines(), 1);
+        assert_eq!(report.dropped[0].offset, 12);
+        assert_eq!(report.dropped[0].line, "2048;0x0");
+
+        let mut probe = FleaProbe::new(ProbeType::X1);
+        assert!(probe.raw_to_volts(2048.0).is_err());
+        assert!(matches!(
+            reading.to_samples(&probe),
+            Err(SamplesError::Calibration(_))
+        ));
+        probe.set_calibration(2048.0, 1000.0);
+        let calibrated = reading.to_samples(&probe).unwrap();
+        assert_eq!(calibrated.time.len(), 3);
+        assert!((calibrated.volts[1] - 0.33).abs() < 1e-9);
+        assert_eq!(calibrated.bits, [0x000, 0x005, 0x3ff]);
+        #[cfg(feature = "ndarray")]
+        {
+            let arrays = reading.to_ndarray(&probe).unwrap();
+            assert_eq!(arrays.analog.dim(), (3, 2));
+            assert!((arrays.analog[[1, 0]] - 2e-6).abs() < 1e-12);
+            assert!((arrays.analog[[1, 1]] - 0.33).abs() < 1e-9);
+            assert_eq!(arrays.digital.dim(), (3, DIGITAL_CHANNELS));
+            assert!(arrays.digital[[1, 2]] && !arrays.digital[[1, 1]]);
+            assert!(arrays.digital.row(2).iter().all(|&bit| bit));
+        }
+        assert_eq!(
+            probe.raw_to_volts(3048.0).unwrap(),
+            Volts::CALIBRATION_REFERENCE
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_parse_csv_tolerates_whitespace() {
+        let reading = ScopeReading {
+            effective_msps: 1.0,
+            data: b"2048,0x000\r\n 2148 , 0x005\r\n1e3,0x3ff\r\n".to_vec(),
+            metadata: None,
+            parsed: OnceLock::new(),
+        };
+        let df = reading.parse_csv().unwrap().collect().unwrap();
+
+        let raw: Vec<Option<f64>> = df
+            .column(RAW_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(raw, vec![Some(2048.0), Some(2148.0), Some(1000.0)]);
+        let bitmap: Vec<Option<u32>> = df
+            .column(BITMAP_COLUMN_NAME)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(bitmap, vec![Some(0x000), Some(0x005), Some(0x3ff)]);
+    }
 }
@@ -0,0 +1,271 @@
+use crate::capabilities::{Feature, UnsupportedByFirmware};
+use crate::flea_scope::{IdleFleaScope, Waveform};
+use crate::units::Volts;
+use std::fmt::Write;
+
+/// Lowest and highest voltage the generator's DAC can output
+pub const OUTPUT_RANGE: (Volts, Volts) = (Volts::ZERO, Volts::CALIBRATION_REFERENCE);
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum WaveformConfigError {
+    #[error("Frequency must be positive, got {0} Hz")]
+    FrequencyOutOfRange(i32),
+
+    #[error("Output swings from {low} to {high}, beyond the generator's 0 V to 3.3 V")]
+    OutputOutOfRange { low: Volts, high: Volts },
+
+    #[error("Duty cycle must be between 0 and 1 (exclusive), got {0}")]
+    DutyCycleOutOfRange(f64),
+
+    #[error("Only square waves have a duty cycle")]
+    DutyCycleNeedsSquare,
+}
+
+/// All problems found while validating a `WaveformConfig`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Invalid waveform configuration: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct InvalidWaveformConfig(pub Vec<WaveformConfigError>);
+
+/// Signal generator settings, validated against the hardware limits.
+///
+/// Amplitude, offset and duty cycle need a firmware supporting
+/// `Feature::WaveformShaping`; without them, the plain `wave` command is sent.
+///
+/// ```rust,no_run
+/// use fleascope_rs::generator::WaveformConfig;
+/// use fleascope_rs::units::Volts;
+/// use fleascope_rs::{IdleFleaScope, Waveform};
+///
+/// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = WaveformConfig::builder(Waveform::Square, 1000)
+///     .amplitude(Volts::new(1.0)?)
+///     .offset(Volts::new(1.65)?)
+///     .duty_cycle(0.25)
+///     .build()?;
+/// scope.set_waveform_config(&config)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaveformConfig {
+    waveform: Waveform,
+    hz: i32,
+    amplitude: Option<Volts>,
+    offset: Option<Volts>,
+    duty_cycle: Option<f64>,
+}
+
+impl WaveformConfig {
+    pub fn builder(waveform: Waveform, hz: i32) -> WaveformConfigBuilder {
+        WaveformConfigBuilder {
+            waveform,
+            hz,
+            amplitude: None,
+            offset: None,
+            duty_cycle: None,
+        }
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn hz(&self) -> i32 {
+        self.hz
+    }
+
+    /// Whether anything beyond shape and frequency was configured
+    pub fn is_shaped(&self) -> bool {
+        self.amplitude.is_some() || self.offset.is_some() || self.duty_cycle.is_some()
+    }
+
+    /// Device command for these settings. Amplitude and offset are sent in millivolts,
+    /// the duty cycle in percent; unset values get the full-swing defaults.
+    pub fn command(&self) -> String {
+        let mut command = format!("wave {} {}", self.waveform.as_str(), self.hz);
+        if self.is_shaped() {
+            let (amplitude, offset) = self.swing();
+            let millivolts = |volts: Volts| (volts.get() * 1000.0).round() as i32;
+            let _ = write!(
+                command,
+                " {} {} {}",
+                millivolts(amplitude),
+                millivolts(offset),
+                (self.duty_cycle.unwrap_or(0.5) * 100.0).round() as i32,
+            );
+        }
+        command
+    }
+
+    /// Peak-to-peak amplitude and center voltage, defaulting to the full output range
+    fn swing(&self) -> (Volts, Volts) {
+        (
+            self.amplitude.unwrap_or(Volts::from_millivolts(3300)),
+            self.offset.unwrap_or(Volts::from_millivolts(1650)),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct WaveformConfigBuilder {
+    waveform: Waveform,
+    hz: i32,
+    amplitude: Option<Volts>,
+    offset: Option<Volts>,
+    duty_cycle: Option<f64>,
+}
+
+impl WaveformConfigBuilder {
+    /// Peak-to-peak amplitude. Defaults to the full output range.
+    pub fn amplitude(mut self, amplitude: Volts) -> Self {
+        self.amplitude = Some(amplitude);
+        self
+    }
+
+    /// Voltage the signal is centered on. Defaults to the middle of the output range.
+    pub fn offset(mut self, offset: Volts) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Fraction of the period a square wave is high. Defaults to 0.5.
+    pub fn duty_cycle(mut self, duty_cycle: f64) -> Self {
+        self.duty_cycle = Some(duty_cycle);
+        self
+    }
+
+    /// Validate the configuration, reporting every problem at once
+    pub fn build(self) -> Result<WaveformConfig, InvalidWaveformConfig> {
+        let config = WaveformConfig {
+            waveform: self.waveform,
+            hz: self.hz,
+            amplitude: self.amplitude,
+            offset: self.offset,
+            duty_cycle: self.duty_cycle,
+        };
+
+        let mut errors = Vec::new();
+        if config.hz <= 0 {
+            errors.push(WaveformConfigError::FrequencyOutOfRange(config.hz));
+        }
+
+        let (amplitude, offset) = config.swing();
+        let half = amplitude.get().abs() / 2.0;
+        let (low, high) = (offset.get() - half, offset.get() + half);
+        if low < OUTPUT_RANGE.0.get() || high > OUTPUT_RANGE.1.get() {
+            errors.push(WaveformConfigError::OutputOutOfRange {
+                low: Volts::new(low).unwrap_or_default(),
+                high: Volts::new(high).unwrap_or_default(),
+            });
+        }
+
+        if let Some(duty_cycle) = config.duty_cycle {
+            if !(duty_cycle > 0.0 && duty_cycle < 1.0) {
+                errors.push(WaveformConfigError::DutyCycleOutOfRange(duty_cycle));
+            }
+            if config.waveform != Waveform::Square {
+                errors.push(WaveformConfigError::DutyCycleNeedsSquare);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(InvalidWaveformConfig(errors))
+        }
+    }
+}
+
+impl IdleFleaScope {
+    /// Set the waveform generator, including amplitude, offset and duty cycle if given.
+    ///
+    /// Fails with `UnsupportedByFirmware` instead of sending shaping parameters the
+    /// firmware doesn't understand.
+    pub fn set_waveform_config(
+        &mut self,
+        config: &WaveformConfig,
+    ) -> Result<(), UnsupportedByFirmware> {
+        profiling::scope!("IdleFleaScope::set_waveform_config");
+
+        if config.is_shaped() {
+            self.capabilities().require(Feature::WaveformShaping)?;
+        }
+        let response = self.exec_raw(&config.command());
+        self.record_waveform(&response, Some((config.waveform, config.hz)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveform_config() {
+        let plain = WaveformConfig::builder(Waveform::Sine, 1000)
+            .build()
+            .unwrap();
+        assert!(!plain.is_shaped());
+        assert_eq!(plain.command(), "wave sine 1000");
+
+        let shaped = WaveformConfig::builder(Waveform::Square, 500)
+            .amplitude(Volts::from_millivolts(1000))
+            .offset(Volts::from_millivolts(1650))
+            .duty_cycle(0.25)
+            .build()
+            .unwrap();
+        assert_eq!(shaped.command(), "wave square 500 1000 1650 25");
+
+        let offset_only = WaveformConfig::builder(Waveform::Triangle, 10)
+            .offset(Volts::from_millivolts(1650))
+            .build()
+            .unwrap();
+        assert_eq!(offset_only.command(), "wave triangle 10 3300 1650 50");
+    }
+
+    #[test]
+    fn test_waveform_config_limits() {
+        let errors = WaveformConfig::builder(Waveform::Sine, 0)
+            .amplitude(Volts::from_millivolts(2000))
+            .offset(Volts::from_millivolts(3000))
+            .duty_cycle(1.5)
+            .build()
+            .unwrap_err()
+            .0;
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                WaveformConfigError::FrequencyOutOfRange(0),
+                WaveformConfigError::OutputOutOfRange { .. },
+                WaveformConfigError::DutyCycleOutOfRange(_),
+                WaveformConfigError::DutyCycleNeedsSquare,
+            ]
+        ));
+
+        // The full swing is the default and fits exactly
+        assert!(WaveformConfig::builder(Waveform::Ekg, 60)
+            .amplitude(Volts::from_millivolts(3300))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_set_waveform_config() {
+        let device = crate::simulator::SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+
+        let plain = WaveformConfig::builder(Waveform::Sine, 1000)
+            .build()
+            .unwrap();
+        scope.set_waveform_config(&plain).unwrap();
+        assert_eq!(device.commands().last().unwrap(), "wave sine 1000");
+
+        let shaped = WaveformConfig::builder(Waveform::Square, 1000)
+            .duty_cycle(0.1)
+            .build()
+            .unwrap();
+        let error = scope.set_waveform_config(&shaped).unwrap_err();
+        assert_eq!(error.feature, Feature::WaveformShaping);
+        assert_eq!(device.commands().last().unwrap(), "wave sine 1000");
+    }
+}
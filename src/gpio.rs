@@ -0,0 +1,143 @@
+use crate::flea_scope::{CaptureConfig, IdleFleaScope, InvalidCaptureConfig, SampleParseError};
+use std::time::Duration;
+
+/// Digital pins that can be driven, 0 to 8. The capture bitmap has one more bit, which
+/// isn't a pin.
+pub const PIN_COUNT: u8 = 9;
+
+/// Level to drive a digital pin to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinMode {
+    High,
+    Low,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GpioError {
+    #[error("No digital pin {0}, the FleaScope has pins 0 to {max}", max = PIN_COUNT - 1)]
+    NoSuchPin(u8),
+
+    #[error("Device rejected {command:?}: {response}")]
+    Rejected { command: String, response: String },
+
+    #[error(transparent)]
+    Config(#[from] InvalidCaptureConfig),
+
+    #[error("Failed to read the pin states")]
+    Samples(#[from] SampleParseError),
+
+    #[error("No samples to read the pin states from")]
+    NoSamples,
+}
+
+impl IdleFleaScope {
+    /// Drive a digital pin high or low.
+    ///
+    /// The pin is bound to the device variable `gpio<pin>` with
+    /// `dim gpio<pin> as pin d<pin> for digital output`. The firmware can't unbind a
+    /// variable, so the pin stays an output until the device restarts.
+    ///
+    /// ```rust,no_run
+    /// use fleascope_rs::gpio::PinMode;
+    /// use fleascope_rs::IdleFleaScope;
+    ///
+    /// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+    /// scope.set_pin(3, PinMode::High)?;
+    /// assert!(scope.read_pins()? & 1 << 3 != 0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_pin(&mut self, pin: u8, mode: PinMode) -> Result<(), GpioError> {
+        profiling::scope!("IdleFleaScope::set_pin");
+
+        if pin >= PIN_COUNT {
+            return Err(GpioError::NoSuchPin(pin));
+        }
+        let name = format!("gpio{pin}");
+        // Declaring the variable again only complains, the binding stays the same
+        let dim_command = format!("dim {name} as pin d{pin} for digital output");
+        let response = String::from_utf8_lossy(&self.exec_raw(&dim_command)).into_owned();
+        if !response.trim().is_empty() && !response.contains("already declared") {
+            return Err(GpioError::Rejected {
+                command: dim_command,
+                response: response.trim().to_string(),
+            });
+        }
+
+        let level = match mode {
+            PinMode::High => 1,
+            PinMode::Low => 0,
+        };
+        let set_command = format!("{name} = {level}");
+        let response = String::from_utf8_lossy(&self.exec_raw(&set_command)).into_owned();
+        if response.trim().is_empty() {
+            Ok(())
+        } else {
+            Err(GpioError::Rejected {
+                command: set_command,
+                response: response.trim().to_string(),
+            })
+        }
+    }
+
+    /// Current state of all digital pins, bit 0 is pin 0. Includes pins driven by `set_pin`.
+    ///
+    /// Takes a short untriggered capture, as the capture bitmap is the one view the firmware
+    /// gives of all pins at once.
+    pub fn read_pins(&mut self) -> Result<u16, GpioError> {
+        profiling::scope!("IdleFleaScope::read_pins");
+
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()?;
+        let last = self
+            .read(&config)
+            .samples()?
+            .last()
+            .ok_or(GpioError::NoSamples)?
+            .bitmap;
+        Ok(last & ((1 << PIN_COUNT) - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimulatedDevice;
+
+    #[test]
+    fn test_set_pin() {
+        let device = SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+
+        scope.set_pin(3, PinMode::High).unwrap();
+        scope.set_pin(3, PinMode::Low).unwrap();
+        let commands = device.commands();
+        let gpio: Vec<_> = commands
+            .iter()
+            .skip_while(|command| !command.starts_with("dim gpio"))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            gpio,
+            [
+                "dim gpio3 as pin d3 for digital output",
+                "gpio3 = 1",
+                "dim gpio3 as pin d3 for digital output",
+                "gpio3 = 0",
+            ]
+        );
+
+        assert!(matches!(
+            scope.set_pin(PIN_COUNT, PinMode::High),
+            Err(GpioError::NoSuchPin(9))
+        ));
+    }
+
+    #[test]
+    fn test_read_pins() {
+        // Bit 9 is not a pin and gets masked
+        let device = SimulatedDevice::new().digital(|_| 0b10_0000_0101);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        assert_eq!(scope.read_pins().unwrap(), 0b101);
+    }
+}
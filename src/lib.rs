@@ -12,7 +12,11 @@
 //! - **Trigger configuration**: Digital and analog triggers with builder patterns
 //! - **Data acquisition**: Raw oscilloscope data reading with automatic time indexing
 //! - **Calibration management**: Read/write probe calibrations from/to device flash
+//! - **Unit conversion**: Per-probe sensor transfer functions producing physical-unit columns
 //! - **`DataFrame` output**: Uses `polars` for efficient data handling instead of pandas
+//!   (`dataframe` feature, on by default; plain samples are always available)
+//! - **Digital I/O**: Drive and read the digital pins as a simple bench I/O controller
+//! - **Background thread**: Optional channel-based engine owning the serial port
 //! - **Type safety**: Strong typing and error handling throughout
 //!
 //! ## Examples
@@ -33,8 +37,8 @@
 //! // Read data using default auto trigger
 //! let trigger = DigitalTrigger::start_capturing_when().is_matching().into_trigger_fields();
 //! let reading = scope.read_sync(Duration::from_millis(10), trigger, None)?;
-//! let data = reading.parse_csv()?;
-//! println!("Captured {} samples", data.collect()?.height());
+//! let samples = reading.samples()?;
+//! println!("Captured {} samples", samples.len());
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
 //!
@@ -46,10 +50,11 @@
 //! let trigger = DigitalTrigger::start_capturing_when()
 //!     .bit0(BitState::High)
 //!     .bit1(BitState::Low)
-//!     .starts_matching();
+//!     .starts_matching()?;
 //!
 //! let trigger_fields = trigger.into_trigger_fields();
 //! println!("Digital trigger: {}", trigger_fields.into_string());
+//! # Ok::<(), fleascope_rs::DigitalTriggerError>(())
 //! ```
 //!
 //! ### Analog Trigger
@@ -75,10 +80,10 @@
 //! // Read with digital trigger
 //! let digital_trigger = DigitalTrigger::start_capturing_when()
 //!     .bit0(BitState::High)
-//!     .starts_matching()
+//!     .starts_matching()?
 //!     .into_trigger_fields();
 //! let reading = scope.read_sync(Duration::from_millis(5), digital_trigger, None)?;
-//! let data = reading.parse_csv()?;
+//! let samples = reading.samples()?;
 //!
 //! // Read with analog trigger (using raw ADC value)
 //! let analog_trigger = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising)
@@ -88,14 +93,14 @@
 //!     analog_trigger,
 //!     Some(Duration::from_micros(500))
 //! )?;
-//! let data = reading.parse_csv()?;
+//! let samples = reading.samples()?;
 //!
 //! // You can also read without specific bit patterns (auto trigger)
 //! let auto_trigger = DigitalTrigger::start_capturing_when()
 //!     .is_matching()
 //!     .into_trigger_fields();
 //! let reading = scope.read_sync(Duration::from_millis(5), auto_trigger, None)?;
-//! let data = reading.parse_csv()?;
+//! let samples = reading.samples()?;
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
 //!
@@ -125,19 +130,100 @@
 //! ```
 //! ```
 
+pub mod actions;
+#[cfg(feature = "dataframe")]
+pub mod alignment;
+#[cfg(feature = "dataframe")]
+pub mod analog;
+#[cfg(feature = "dataframe")]
+pub mod analysis;
+#[cfg(feature = "dataframe")]
+pub mod auto_setup;
+pub mod broadcast;
+pub mod capabilities;
+#[cfg(feature = "dataframe")]
+pub mod capture_frame;
+#[cfg(feature = "dataframe")]
+pub mod capture_stream;
+pub mod channel_labels;
+pub mod command;
+#[cfg(feature = "dataframe")]
+pub mod decoders;
+#[cfg(feature = "dataframe")]
+pub mod digital;
+#[cfg(feature = "dataframe")]
+pub mod drift_logger;
+#[cfg(feature = "dataframe")]
+pub mod dsp;
+#[cfg(feature = "dataframe")]
+pub mod export;
+pub mod farm;
+pub mod firmware;
+pub mod flash_vars;
 pub mod flea_connector;
 pub mod flea_scope;
+pub mod generator;
+pub mod gpio;
+#[cfg(feature = "dataframe")]
+pub mod math_channel;
+pub mod measurements;
+pub mod nth_event;
+#[cfg(feature = "dataframe")]
+pub mod persistence;
+#[cfg(feature = "dataframe")]
+pub mod power;
+pub mod prelude;
+#[cfg(feature = "dataframe")]
+pub mod reference_waveform;
+pub mod scope_thread;
+pub mod sequence_trigger;
 pub mod serial_terminal;
+pub mod session;
+#[cfg(feature = "dataframe")]
+pub mod session_recorder;
+#[cfg(any(test, feature = "test-support"))]
+pub mod simulator;
+#[cfg(feature = "dataframe")]
+pub mod sink;
+pub mod soft_trigger;
+#[cfg(feature = "dataframe")]
+pub mod stats;
+#[cfg(feature = "dataframe")]
+pub mod tdr;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod timebase;
+#[cfg(feature = "dataframe")]
+pub mod tone;
 pub mod trigger_config;
+#[cfg(feature = "dataframe")]
+pub mod unit_conversion;
+pub mod units;
 
 // Re-export the main types for convenience
 pub use trigger_config::{
     AnalogTrigger, AnalogTriggerBehavior, AnalogTriggerBuilder, BitState, BitTriggerBuilder,
-    DigitalTrigger, DigitalTriggerBehavior, Trigger,
+    DigitalTrigger, DigitalTriggerBehavior, DigitalTriggerError, ProbeTrigger, Trigger,
 };
 
-pub use serial_terminal::{FleaTerminalError, IdleFleaTerminal, StatelessFleaTerminal};
+pub use serial_terminal::{
+    CancellingFleaTerminal, FaultedFleaTerminal, FleaTerminalError, IdleFleaTerminal, RawRepl,
+    ReadInterrupted, StatelessFleaTerminal, TerminalDialect, TransportStats,
+};
 
 pub use flea_connector::{FleaConnector, FleaConnectorError, FleaDevice};
 
-pub use flea_scope::{FleaProbe, IdleFleaScope, ProbeType, Waveform};
+pub use farm::{Farm, FarmConfig, FarmError};
+
+pub use flea_scope::{CaptureConfig, CapturePlan, FleaProbe, IdleFleaScope, ProbeType, Waveform};
+
+#[cfg(feature = "dataframe")]
+pub use capture_frame::CaptureFrame;
+
+pub use scope_thread::{CaptureChunk, Command, Response, ScopeThread, ScopeThreadError};
+
+#[cfg(feature = "dataframe")]
+pub use math_channel::MathChannel;
+
+#[cfg(feature = "dataframe")]
+pub use unit_conversion::UnitConversion;
@@ -0,0 +1,225 @@
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+use polars::prelude::*;
+use std::fmt;
+use std::sync::Arc;
+
+type Operation = dyn Fn(Expr) -> Expr + Send + Sync;
+
+/// A computed channel derived from the calibrated voltage, like the math function of a
+/// bench scope.
+///
+/// The result is added as a new column named by the channel. A reference capture taken
+/// earlier can be subtracted, which gives differential measurements with a single
+/// channel: capture A, capture B, then A − B.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+/// use fleascope_rs::math_channel::MathChannel;
+/// use polars::prelude::*;
+///
+/// let a = df!(TIME_COLUMN_NAME => [0.0, 1.0, 2.0], CALIBRATED_COLUMN_NAME => [1.0, 2.0, 3.0])?;
+/// let b = df!(TIME_COLUMN_NAME => [0.0, 1.0, 2.0], CALIBRATED_COLUMN_NAME => [0.5, 0.5, 0.5])?;
+///
+/// let difference = MathChannel::minus_reference("a_minus_b", &b)?;
+/// let df = difference.apply(a.lazy()).collect()?;
+/// let volts: Vec<_> = df.column("a_minus_b")?.f64()?.into_no_null_iter().collect();
+/// assert_eq!(volts, [0.5, 1.5, 2.5]);
+/// # Ok::<(), PolarsError>(())
+/// ```
+#[derive(Clone)]
+pub struct MathChannel {
+    name: String,
+    operation: Arc<Operation>,
+}
+
+impl MathChannel {
+    /// Channel computed by an arbitrary polars expression transform of the calibrated
+    /// voltage
+    pub fn new<F>(name: &str, operation: F) -> Self
+    where
+        F: Fn(Expr) -> Expr + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            operation: Arc::new(operation),
+        }
+    }
+
+    /// `volts * scale + offset`
+    pub fn scaled(name: &str, scale: f64, offset: f64) -> Self {
+        Self::new(name, move |volts| volts * lit(scale) + lit(offset))
+    }
+
+    /// The voltage minus the calibrated voltage of `reference`, e.g. a capture of the other
+    /// side of a differential signal or of a known good board.
+    ///
+    /// The reference is linearly interpolated at each sample time, so the captures may
+    /// differ in length and sample rate. Samples outside the reference are null.
+    pub fn minus_reference(name: &str, reference: &DataFrame) -> Result<Self, PolarsError> {
+        let reference = Arc::new(time_and_values(reference, CALIBRATED_COLUMN_NAME)?);
+        Ok(Self::new(name, move |volts| {
+            let reference = Arc::clone(&reference);
+            let interpolated = col(TIME_COLUMN_NAME).map(
+                move |time| {
+                    let (ref_time, ref_volts) = reference.as_ref();
+                    let values: Float64Chunked = time
+                        .f64()?
+                        .into_iter()
+                        .map(|t| interpolate(ref_time, ref_volts, t?))
+                        .collect();
+                    Ok(Some(values.into_column()))
+                },
+                GetOutput::from_type(DataType::Float64),
+            );
+            volts - interpolated
+        }))
+    }
+
+    /// Rate of change in V/s, between each sample and the one before. Null for the first
+    /// sample.
+    pub fn derivative(name: &str) -> Self {
+        Self::new(name, |volts| {
+            let time = col(TIME_COLUMN_NAME);
+            (volts.clone() - volts.shift(lit(1))) / (time.clone() - time.shift(lit(1)))
+        })
+    }
+
+    /// Trapezoidal integral in V·s since the first sample
+    pub fn integral(name: &str) -> Self {
+        Self::new(name, |volts| {
+            let time = col(TIME_COLUMN_NAME);
+            let area = (volts.clone() + volts.shift(lit(1))) / lit(2.0)
+                * (time.clone() - time.shift(lit(1)));
+            area.fill_null(lit(0.0)).cum_sum(false)
+        })
+    }
+
+    /// Name of the column produced by [`Self::apply`]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Expression computing the channel from the given voltage expression
+    pub fn to_expr(&self, volts: Expr) -> Expr {
+        (self.operation)(volts).alias(self.name.as_str())
+    }
+
+    /// Add the channel's column to a calibrated frame
+    pub fn apply(&self, df: LazyFrame) -> LazyFrame {
+        profiling::scope!("MathChannel::apply");
+
+        df.with_column(self.to_expr(col(CALIBRATED_COLUMN_NAME)))
+    }
+}
+
+impl fmt::Debug for MathChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MathChannel")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Time and `column` of the samples of `df` holding both, e.g. leaving out the gaps of a
+/// computed channel
+pub(crate) fn time_and_values(
+    df: &DataFrame,
+    column: &str,
+) -> Result<(Vec<f64>, Vec<f64>), PolarsError> {
+    let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+    let values = df.column(column)?.f64()?;
+    Ok(time
+        .into_iter()
+        .zip(values)
+        .filter_map(|(time, value)| Some((time?, value?)))
+        .unzip())
+}
+
+/// Linear interpolation of `ys` over ascending `xs` at `x`, `None` outside of `xs`
+pub(crate) fn interpolate(xs: &[f64], ys: &[f64], x: f64) -> Option<f64> {
+    if !(*xs.first()?..=*xs.last()?).contains(&x) {
+        return None;
+    }
+    let upper = xs.partition_point(|&xi| xi < x);
+    if upper == 0 {
+        return ys.first().copied();
+    }
+    let (x0, x1) = (xs[upper - 1], xs[upper]);
+    let (y0, y1) = (ys[upper - 1], ys[upper]);
+    Some(y0 + (y1 - y0) * (x - x0) / (x1 - x0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(channel: &MathChannel, time: &[f64], volts: &[f64]) -> Vec<Option<f64>> {
+        let df = df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts)
+            .unwrap()
+            .lazy();
+        channel
+            .apply(df)
+            .collect()
+            .unwrap()
+            .column(channel.name())
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let xs = [0.0, 1.0, 2.0];
+        let ys = [0.0, 10.0, 30.0];
+        assert_eq!(interpolate(&xs, &ys, 0.0), Some(0.0));
+        assert_eq!(interpolate(&xs, &ys, 0.5), Some(5.0));
+        assert_eq!(interpolate(&xs, &ys, 1.5), Some(20.0));
+        assert_eq!(interpolate(&xs, &ys, 2.0), Some(30.0));
+        assert_eq!(interpolate(&xs, &ys, 2.5), None);
+        assert_eq!(interpolate(&[], &[], 0.0), None);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let channel = MathChannel::scaled("doubled", 2.0, -1.0);
+        assert_eq!(
+            compute(&channel, &[0.0, 1.0], &[1.0, 2.0]),
+            [Some(1.0), Some(3.0)]
+        );
+    }
+
+    #[test]
+    fn test_minus_reference() {
+        // Half the sample rate and starting later than the capture
+        let reference = df!(
+            TIME_COLUMN_NAME => [1.0, 3.0, 5.0],
+            CALIBRATED_COLUMN_NAME => [1.0, 3.0, 1.0],
+        )
+        .unwrap();
+        let channel = MathChannel::minus_reference("difference", &reference).unwrap();
+        assert_eq!(
+            compute(&channel, &[0.0, 1.0, 2.0, 3.0, 4.0], &[4.0; 5]),
+            [None, Some(3.0), Some(2.0), Some(1.0), Some(2.0)]
+        );
+    }
+
+    #[test]
+    fn test_derivative_and_integral() {
+        let time = [0.0, 0.5, 1.0, 1.5];
+        let ramp = [0.0, 1.0, 2.0, 3.0];
+
+        let derivative = MathChannel::derivative("slope_V_per_s");
+        assert_eq!(
+            compute(&derivative, &time, &ramp),
+            [None, Some(2.0), Some(2.0), Some(2.0)]
+        );
+
+        let integral = MathChannel::integral("area_Vs");
+        assert_eq!(
+            compute(&integral, &time, &ramp),
+            [Some(0.0), Some(0.25), Some(1.0), Some(2.25)]
+        );
+    }
+}
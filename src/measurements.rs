@@ -0,0 +1,778 @@
+#[cfg(feature = "dataframe")]
+use crate::flea_scope::CALIBRATED_COLUMN_NAME;
+#[cfg(feature = "dataframe")]
+use crate::math_channel::time_and_values;
+#[cfg(feature = "dataframe")]
+use polars::prelude::*;
+
+/// Signals with a smaller peak-to-peak amplitude are treated as DC, without edges
+const MIN_AMPLITUDE_VOLTS: f64 = 0.05;
+/// Edges spanning fewer sample intervals are limited by the sample rate
+const MIN_EDGE_SAMPLES: f64 = 3.0;
+/// Highest harmonic included in distortion metrics, if below the Nyquist frequency
+#[cfg(feature = "dataframe")]
+const MAX_HARMONIC: u32 = 10;
+/// Periods of the fundamental the distortion metrics need to separate it from its harmonics
+const MIN_PERIODS: f64 = 10.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MeasurementError {
+    #[cfg(feature = "dataframe")]
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("The capture holds no samples")]
+    NoSamples,
+
+    #[error("No fundamental frequency found in the capture")]
+    NoFundamental,
+
+    #[error("The capture holds only {periods:.1} periods of the fundamental, at least {MIN_PERIODS} are needed")]
+    TooFewPeriods { periods: f64 },
+}
+
+/// How far a measurement can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    High,
+    /// Computed, but e.g. from a single period, or from an edge only a few samples long
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub value: f64,
+    pub confidence: Confidence,
+}
+
+impl Measurement {
+    fn new(value: f64, reliable: bool) -> Self {
+        let confidence = if reliable {
+            Confidence::High
+        } else {
+            Confidence::Low
+        };
+        Self { value, confidence }
+    }
+
+    pub fn is_reliable(&self) -> bool {
+        self.confidence == Confidence::High
+    }
+}
+
+/// The automatic measurements of a bench scope, computed from one capture.
+///
+/// Voltages are in volts, times in seconds. Measurements that need edges or periods are
+/// `None` when the capture doesn't show them, e.g. for DC. Levels are measured at 10 %,
+/// 50 % and 90 % between the base and top of the signal, the medians of the samples below
+/// and above the middle.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+/// use fleascope_rs::measurements::Measurements;
+/// use polars::prelude::*;
+///
+/// // 1 kHz square wave at 25 % duty cycle, sampled at 100 kHz
+/// let time: Vec<f64> = (0..1000).map(|i| f64::from(i) * 1e-5).collect();
+/// let volts: Vec<f64> = (0..1000).map(|i| if i % 100 < 25 { 3.3 } else { 0.0 }).collect();
+/// let df = df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts)?;
+///
+/// let measurements = Measurements::from_dataframe(&df)?;
+/// let frequency = measurements.frequency.unwrap();
+/// assert!((frequency.value - 1000.0).abs() < 1e-6 && frequency.is_reliable());
+/// assert!((measurements.duty_cycle.unwrap().value - 0.25).abs() < 1e-6);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurements {
+    pub vpp: Measurement,
+    pub vrms: Measurement,
+    pub vavg: Measurement,
+    pub frequency: Option<Measurement>,
+    pub period: Option<Measurement>,
+    /// Fraction of the period the signal is above the 50 % level, from 0 to 1
+    pub duty_cycle: Option<Measurement>,
+    /// Mean 10 % to 90 % time of the rising edges
+    pub rise_time: Option<Measurement>,
+    /// Mean 90 % to 10 % time of the falling edges
+    pub fall_time: Option<Measurement>,
+    /// How far the maximum exceeds the top level, as a fraction of the amplitude
+    pub overshoot: Option<Measurement>,
+}
+
+/// A transition through the middle of the signal
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    /// First sample beyond the hysteresis band
+    index: usize,
+    rising: bool,
+    /// When the 50 % level was crossed
+    time: f64,
+    /// 10 % to 90 % duration, if the edge is complete within the capture
+    transition: Option<f64>,
+}
+
+impl Measurements {
+    /// Measure the calibrated voltage column of a capture, see
+    /// `FleaProbe::apply_calibration`. Missing and NaN samples are left out.
+    #[cfg(feature = "dataframe")]
+    pub fn from_dataframe(df: &DataFrame) -> Result<Self, MeasurementError> {
+        profiling::scope!("Measurements::from_dataframe");
+
+        let (time, volts) = columns(df)?;
+        Self::from_samples(&time, &volts).ok_or(MeasurementError::NoSamples)
+    }
+
+    /// Measure evenly spaced samples taken at `time`, `None` if there are none. The samples
+    /// must not be NaN.
+    pub fn from_samples(time: &[f64], volts: &[f64]) -> Option<Self> {
+        let len = time.len().min(volts.len());
+        let (time, volts) = (&time[..len], &volts[..len]);
+        let min = volts.iter().copied().reduce(f64::min)?;
+        let max = volts.iter().copied().reduce(f64::max)?;
+
+        let (base, top) = levels(volts, f64::midpoint(min, max));
+        let edges = if max - min < MIN_AMPLITUDE_VOLTS {
+            Vec::new()
+        } else {
+            edges(time, volts, min, max, base, top)
+        };
+        let rising: Vec<&Edge> = edges.iter().filter(|edge| edge.rising).collect();
+        let periods = rising.len().saturating_sub(1);
+
+        // Whole periods keep a partial one from biasing the average, DC needs none
+        let dc = edges.is_empty() && max - min < MIN_AMPLITUDE_VOLTS;
+        let window = match (rising.first(), rising.last()) {
+            (Some(first), Some(last)) if periods >= 1 => &volts[first.index..last.index],
+            _ => volts,
+        };
+        let whole = dc || periods >= 1;
+        #[allow(clippy::cast_precision_loss)]
+        let count = window.len() as f64;
+        let vavg = window.iter().sum::<f64>() / count;
+        let vrms = (window.iter().map(|v| v * v).sum::<f64>() / count).sqrt();
+
+        let period = match (rising.first(), rising.last()) {
+            (Some(first), Some(last)) if periods >= 1 => {
+                #[allow(clippy::cast_precision_loss)]
+                let period = (last.time - first.time) / periods as f64;
+                Some(Measurement::new(period, periods >= 2))
+            }
+            _ => None,
+        };
+
+        let (mut high, mut total) = (0.0, 0.0);
+        for pair in rising.windows(2) {
+            let (start, end) = (pair[0].time, pair[1].time);
+            if let Some(fall) = edges
+                .iter()
+                .find(|edge| !edge.rising && edge.time > start && edge.time < end)
+            {
+                high += fall.time - start;
+                total += end - start;
+            }
+        }
+        let duty_cycle = (total > 0.0).then(|| Measurement::new(high / total, periods >= 2));
+
+        #[allow(clippy::cast_precision_loss)]
+        let interval = (time[len - 1] - time[0]) / (len.max(2) - 1) as f64;
+        let transition = |rising: bool| {
+            let durations: Vec<f64> = edges
+                .iter()
+                .filter(|edge| edge.rising == rising)
+                .filter_map(|edge| edge.transition)
+                .collect();
+            #[allow(clippy::cast_precision_loss)]
+            let mean = durations.iter().sum::<f64>() / durations.len() as f64;
+            (!durations.is_empty())
+                .then(|| Measurement::new(mean, mean >= MIN_EDGE_SAMPLES * interval))
+        };
+        let rise_time = transition(true);
+        let fall_time = transition(false);
+
+        // An undersampled edge likely misses the peak of the overshoot as well
+        let overshoot = (!edges.is_empty() && top > base).then(|| {
+            Measurement::new(
+                (max - top) / (top - base),
+                rise_time.is_some_and(|rise| rise.is_reliable()),
+            )
+        });
+
+        Some(Self {
+            vpp: Measurement::new(max - min, whole),
+            vrms: Measurement::new(vrms, whole),
+            vavg: Measurement::new(vavg, whole),
+            frequency: period
+                .map(|period| Measurement::new(1.0 / period.value, period.is_reliable())),
+            period,
+            duty_cycle,
+            rise_time,
+            fall_time,
+            overshoot,
+        })
+    }
+}
+
+/// A voltage statistic polars can compute within a query plan, see
+/// `CaptureFrame::measurements`. Edges and periods need the whole capture, see
+/// `Measurements`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoltageMeasurement {
+    Min,
+    Max,
+    Vpp,
+    Vavg,
+    Vrms,
+}
+
+impl VoltageMeasurement {
+    pub const ALL: [Self; 5] = [Self::Min, Self::Max, Self::Vpp, Self::Vavg, Self::Vrms];
+
+    /// Name of the column holding the measurement
+    pub fn column_name(self) -> &'static str {
+        match self {
+            Self::Min => "vmin",
+            Self::Max => "vmax",
+            Self::Vpp => "vpp",
+            Self::Vavg => "vavg",
+            Self::Vrms => "vrms",
+        }
+    }
+
+    /// Aggregation of the calibrated voltage column. Missing and NaN samples are left out.
+    #[cfg(feature = "dataframe")]
+    pub fn expr(self) -> Expr {
+        let volts = col(CALIBRATED_COLUMN_NAME).filter(col(CALIBRATED_COLUMN_NAME).is_not_nan());
+        match self {
+            Self::Min => volts.min(),
+            Self::Max => volts.max(),
+            Self::Vpp => volts.clone().max() - volts.min(),
+            Self::Vavg => volts.mean(),
+            Self::Vrms => (volts.clone() * volts).mean().sqrt(),
+        }
+        .alias(self.column_name())
+    }
+}
+
+/// Distortion metrics of a test tone, see `distortion`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distortion {
+    /// The fundamental in Hz, refined to the nearest spectral peak
+    pub fundamental: f64,
+    /// Total harmonic distortion: the RMS sum of the 2nd to 10th harmonic relative to the
+    /// fundamental, e.g. 0.01 for 1 %
+    pub thd: f64,
+    /// Signal to noise ratio in dB: the fundamental relative to everything but the
+    /// fundamental and its harmonics
+    pub snr: f64,
+    /// Signal to noise and distortion ratio in dB: the fundamental relative to everything
+    /// else, harmonics included
+    pub sinad: f64,
+}
+
+/// THD, SNR and SINAD of the calibrated voltage column, from a single fit of the
+/// fundamental and its harmonics.
+///
+/// `fundamental` is the frequency of the test tone in Hz, e.g. of the built-in generator,
+/// detected from the zero crossings if `None`. Either way it is refined to the nearest
+/// spectral peak. Harmonics above the Nyquist frequency are left out.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+/// use fleascope_rs::measurements;
+/// use polars::prelude::*;
+/// use std::f64::consts::TAU;
+///
+/// // 1 kHz with 1 % of third harmonic, sampled at 100 kHz
+/// let time: Vec<f64> = (0..10_000).map(|i| f64::from(i) * 1e-5).collect();
+/// let volts: Vec<f64> = time
+///     .iter()
+///     .map(|t| (TAU * 1e3 * t).sin() + 0.01 * (TAU * 3e3 * t).sin())
+///     .collect();
+/// let df = df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts)?;
+///
+/// let distortion = measurements::distortion(&df, Some(1000.0))?;
+/// assert!((distortion.thd - 0.01).abs() < 1e-4);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(feature = "dataframe")]
+pub fn distortion(
+    df: &DataFrame,
+    fundamental: Option<f64>,
+) -> Result<Distortion, MeasurementError> {
+    profiling::scope!("measurements::distortion");
+    let powers = Powers::from_dataframe(df, fundamental)?;
+    Ok(Distortion {
+        fundamental: powers.frequency,
+        thd: (powers.harmonics / powers.fundamental).sqrt(),
+        snr: decibels(powers.fundamental, powers.noise),
+        sinad: decibels(powers.fundamental, powers.noise + powers.harmonics),
+    })
+}
+
+#[cfg(feature = "dataframe")]
+fn decibels(signal: f64, noise: f64) -> f64 {
+    10.0 * (signal / noise.max(f64::MIN_POSITIVE)).log10()
+}
+
+/// Time and calibrated voltage of the samples holding both, leaving out missing and NaN
+/// samples
+#[cfg(feature = "dataframe")]
+fn columns(df: &DataFrame) -> Result<(Vec<f64>, Vec<f64>), PolarsError> {
+    let (time, volts) = time_and_values(df, CALIBRATED_COLUMN_NAME)?;
+    Ok(time
+        .into_iter()
+        .zip(volts)
+        .filter(|(time, volts)| !time.is_nan() && !volts.is_nan())
+        .unzip())
+}
+
+/// Power of the parts of a signal, in V²
+#[cfg(feature = "dataframe")]
+#[derive(Debug, Clone, Copy)]
+struct Powers {
+    /// Frequency of the fundamental in Hz
+    frequency: f64,
+    fundamental: f64,
+    harmonics: f64,
+    /// What remains without DC, the fundamental and its harmonics
+    noise: f64,
+}
+
+#[cfg(feature = "dataframe")]
+impl Powers {
+    /// Fit sinusoids at the fundamental and its harmonics to the capture, the residual is
+    /// the noise
+    fn from_dataframe(df: &DataFrame, fundamental: Option<f64>) -> Result<Self, MeasurementError> {
+        let (time, volts) = columns(df)?;
+        let len = time.len().min(volts.len());
+        if len < 2 {
+            return Err(MeasurementError::NoSamples);
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let sample_rate = (len - 1) as f64 / (time[len - 1] - time[0]);
+        let fundamental = match fundamental {
+            Some(fundamental) => fundamental,
+            None => {
+                Measurements::from_samples(&time, &volts)
+                    .and_then(|measurements| measurements.frequency)
+                    .ok_or(MeasurementError::NoFundamental)?
+                    .value
+            }
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let periods = fundamental * len as f64 / sample_rate;
+        if periods.is_nan() || periods < MIN_PERIODS {
+            return Err(MeasurementError::TooFewPeriods { periods });
+        }
+
+        // Weighting keeps the sinusoids apart when the capture doesn't hold a whole number
+        // of periods
+        let window = blackman_harris(len);
+        let gain: f64 = window.iter().sum();
+        let mean = volts.iter().zip(&window).map(|(v, w)| v * w).sum::<f64>() / gain;
+        let mut residual: Vec<f64> = volts[..len].iter().map(|v| v - mean).collect();
+        let fit = |residual: &[f64], frequency: f64| {
+            let step = std::f64::consts::TAU * frequency / sample_rate;
+            let (mut cos, mut sin) = (0.0, 0.0);
+            for (index, (v, w)) in residual.iter().zip(&window).enumerate() {
+                #[allow(clippy::cast_precision_loss)]
+                let phase = step * index as f64;
+                cos += v * w * phase.cos();
+                sin += v * w * phase.sin();
+            }
+            (2.0 * cos / gain, 2.0 * sin / gain)
+        };
+        let power = |(cos, sin): (f64, f64)| cos.mul_add(cos, sin * sin) / 2.0;
+
+        // The nominal frequency is off by the timebase error, search a bin around it
+        #[allow(clippy::cast_precision_loss)]
+        let bin = sample_rate / len as f64;
+        let fundamental = golden_section_max(
+            |frequency| power(fit(&residual, frequency)),
+            fundamental - bin,
+            fundamental + bin,
+        );
+
+        let mut powers = Self {
+            frequency: fundamental,
+            fundamental: 0.0,
+            harmonics: 0.0,
+            noise: 0.0,
+        };
+        let frequencies = (1..=MAX_HARMONIC)
+            .map(|harmonic| fundamental * f64::from(harmonic))
+            .take_while(|&frequency| frequency < sample_rate / 2.0);
+        for (harmonic, frequency) in frequencies.enumerate() {
+            let (cos, sin) = fit(&residual, frequency);
+            let step = std::f64::consts::TAU * frequency / sample_rate;
+            for (index, v) in residual.iter_mut().enumerate() {
+                #[allow(clippy::cast_precision_loss)]
+                let phase = step * index as f64;
+                *v -= cos.mul_add(phase.cos(), sin * phase.sin());
+            }
+            if harmonic == 0 {
+                powers.fundamental = power((cos, sin));
+            } else {
+                powers.harmonics += power((cos, sin));
+            }
+        }
+        powers.noise = residual
+            .iter()
+            .zip(&window)
+            .map(|(v, w)| v * v * w)
+            .sum::<f64>()
+            / gain;
+        Ok(powers)
+    }
+}
+
+/// 4-term Blackman-Harris window, its side lobes are below -92 dB
+#[cfg(feature = "dataframe")]
+fn blackman_harris(len: usize) -> Vec<f64> {
+    #[allow(clippy::cast_precision_loss)]
+    let span = len.max(2) as f64 - 1.0;
+    (0..len)
+        .map(|index| {
+            #[allow(clippy::cast_precision_loss)]
+            let x = std::f64::consts::TAU * index as f64 / span;
+            [0.358_75, -0.488_29, 0.141_28, -0.011_68]
+                .into_iter()
+                .zip(0u32..)
+                .map(|(coefficient, k)| coefficient * (f64::from(k) * x).cos())
+                .sum()
+        })
+        .collect()
+}
+
+/// Where `f` peaks in `low..high`, assuming a single peak
+#[cfg(feature = "dataframe")]
+fn golden_section_max(f: impl Fn(f64) -> f64, mut low: f64, mut high: f64) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..40 {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if f(left) < f(right) {
+            low = left;
+        } else {
+            high = right;
+        }
+    }
+    f64::midpoint(low, high)
+}
+
+/// Base and top level: the medians of the samples below and above `mid`
+fn levels(volts: &[f64], mid: f64) -> (f64, f64) {
+    let median = |mut values: Vec<f64>| {
+        values.sort_by(f64::total_cmp);
+        values.get(values.len() / 2).copied()
+    };
+    let below = median(volts.iter().copied().filter(|&v| v < mid).collect());
+    let above = median(volts.iter().copied().filter(|&v| v >= mid).collect());
+    (below.unwrap_or(mid), above.unwrap_or(mid))
+}
+
+/// Frequency of evenly spaced samples from their rising crossings of the middle of their
+/// range, see `flips`. Also returns the number of whole periods between the first and last
+/// crossing; without one, there is no frequency.
+pub(crate) fn rising_crossing_frequency(volts: &[f64], sample_rate: f64) -> (Option<f64>, usize) {
+    let (Some(min), Some(max)) = (
+        volts.iter().copied().reduce(f64::min),
+        volts.iter().copied().reduce(f64::max),
+    ) else {
+        return (None, 0);
+    };
+    let mid = f64::midpoint(min, max);
+    #[allow(clippy::cast_precision_loss)]
+    let time: Vec<f64> = (0..volts.len())
+        .map(|index| index as f64 / sample_rate)
+        .collect();
+
+    // Sub-sample times of the crossings, the edges rarely align with samples
+    let crossings: Vec<f64> = flips(volts, min, max)
+        .windows(2)
+        .filter(|pair| volts[pair[1].0] > mid)
+        .filter_map(|pair| crossing_before(&time, volts, pair[0].0, pair[1].0, mid, true))
+        .collect();
+    let periods = crossings.len().saturating_sub(1);
+    match (crossings.first(), crossings.last()) {
+        (Some(first), Some(last)) if periods >= 1 && last > first => {
+            #[allow(clippy::cast_precision_loss)]
+            let frequency = periods as f64 / (last - first);
+            (Some(frequency), periods)
+        }
+        _ => (None, periods),
+    }
+}
+
+/// Samples at which the signal passes through the middle of its range, with a hysteresis
+/// of 10 % of the range so noise doesn't add transitions. Each is the first sample beyond
+/// the hysteresis band, together with whether it is a transition; the first only tells
+/// where the signal starts.
+fn flips(volts: &[f64], min: f64, max: f64) -> Vec<(usize, bool)> {
+    let mid = f64::midpoint(min, max);
+    let hysteresis = (max - min) * 0.1;
+
+    let mut flips = Vec::new();
+    let mut high = None;
+    for (index, &v) in volts.iter().enumerate() {
+        if v > mid + hysteresis && high != Some(true) {
+            flips.push((index, high.is_some()));
+            high = Some(true);
+        } else if v < mid - hysteresis && high != Some(false) {
+            flips.push((index, high.is_some()));
+            high = Some(false);
+        }
+    }
+    flips
+}
+
+/// Transitions through the middle, see `flips`
+fn edges(time: &[f64], volts: &[f64], min: f64, max: f64, base: f64, top: f64) -> Vec<Edge> {
+    let mid = f64::midpoint(min, max);
+    let flips = flips(volts, min, max);
+
+    let low_level = (top - base).mul_add(0.1, base);
+    let high_level = (top - base).mul_add(0.9, base);
+    let mut edges = Vec::new();
+    for (position, &(index, is_edge)) in flips.iter().enumerate() {
+        // The first flip only tells where the signal starts
+        if !is_edge {
+            continue;
+        }
+        let rising = volts[index] > mid;
+        let previous = flips[position - 1].0;
+        let next = flips
+            .get(position + 1)
+            .map_or(volts.len(), |&(next, _)| next);
+        let (start, end) = if rising {
+            (low_level, high_level)
+        } else {
+            (high_level, low_level)
+        };
+        let Some(time_at_mid) = crossing_before(
+            time,
+            volts,
+            previous,
+            index,
+            f64::midpoint(base, top),
+            rising,
+        ) else {
+            continue;
+        };
+        let transition = crossing_before(time, volts, previous, index, start, rising)
+            .zip(crossing_after(time, volts, index, next, end, rising))
+            .map(|(started, ended)| ended - started);
+        edges.push(Edge {
+            index,
+            rising,
+            time: time_at_mid,
+            transition,
+        });
+    }
+    edges
+}
+
+/// Last time in `from..to` the signal passes `level` in the direction of the edge
+fn crossing_before(
+    time: &[f64],
+    volts: &[f64],
+    from: usize,
+    to: usize,
+    level: f64,
+    rising: bool,
+) -> Option<f64> {
+    let before = |v: f64| if rising { v <= level } else { v >= level };
+    let last = (from..to).rev().find(|&index| before(volts[index]))?;
+    Some(interpolate(time, volts, last, level))
+}
+
+/// First time in `from..to` the signal reaches `level` in the direction of the edge
+fn crossing_after(
+    time: &[f64],
+    volts: &[f64],
+    from: usize,
+    to: usize,
+    level: f64,
+    rising: bool,
+) -> Option<f64> {
+    let reached = |v: f64| if rising { v >= level } else { v <= level };
+    let first = (from..to).find(|&index| reached(volts[index]))?;
+    Some(interpolate(time, volts, first.checked_sub(1)?, level))
+}
+
+/// Time at which the line from sample `index` to the next one passes `level`
+fn interpolate(time: &[f64], volts: &[f64], index: usize, level: f64) -> f64 {
+    let (v0, v1) = (volts[index], volts[index + 1]);
+    let (t0, t1) = (time[index], time[index + 1]);
+    if (v1 - v0).abs() < f64::EPSILON {
+        t0
+    } else {
+        t0 + (t1 - t0) * (level - v0) / (v1 - v0)
+    }
+}
+
+#[cfg(all(test, feature = "dataframe"))]
+mod tests {
+    use super::*;
+    use crate::flea_scope::TIME_COLUMN_NAME;
+
+    const SAMPLE_RATE: f64 = 1_000_000.0;
+
+    fn time(len: usize) -> Vec<f64> {
+        (0..u32::try_from(len).unwrap())
+            .map(|i| f64::from(i) / SAMPLE_RATE)
+            .collect()
+    }
+
+    /// 10 kHz pulse from 0 V to 3 V with `ramp` samples per edge and a one-sample
+    /// overshoot to 3.3 V after the rising edge
+    fn pulse(periods: usize, ramp: usize) -> Vec<f64> {
+        let mut volts = Vec::new();
+        for _ in 0..periods {
+            let step = 3.0 / f64::from(u32::try_from(ramp).unwrap());
+            volts.extend(std::iter::repeat_n(0.0, 40));
+            volts.extend((0..ramp).map(|i| step * f64::from(u32::try_from(i).unwrap())));
+            volts.push(3.3);
+            volts.extend(std::iter::repeat_n(3.0, 29 - ramp));
+            volts.extend((0..ramp).map(|i| 3.0 - step * f64::from(u32::try_from(i).unwrap())));
+            volts.extend(std::iter::repeat_n(0.0, 30 - ramp));
+        }
+        volts
+    }
+
+    #[test]
+    fn test_pulse() {
+        let volts = pulse(5, 10);
+        let measurements = Measurements::from_samples(&time(volts.len()), &volts).unwrap();
+
+        let period = measurements.period.unwrap();
+        assert!((period.value - 100e-6).abs() < 1e-9);
+        assert!(period.is_reliable());
+        assert!((measurements.frequency.unwrap().value - 10_000.0).abs() < 1e-3);
+        assert!((measurements.vpp.value - 3.3).abs() < 1e-9);
+        assert!(measurements.vpp.is_reliable());
+
+        // 10 % to 90 % of a 10 µs linear ramp
+        let rise = measurements.rise_time.unwrap();
+        assert!((rise.value - 8e-6).abs() < 1e-9);
+        assert!(rise.is_reliable());
+        assert!((measurements.fall_time.unwrap().value - 8e-6).abs() < 1e-9);
+        assert!((measurements.overshoot.unwrap().value - 0.1).abs() < 1e-9);
+        // 50 % crossings are halfway down each ramp, 30 µs apart
+        assert!((measurements.duty_cycle.unwrap().value - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_confidence() {
+        // A single period and steep edges are measured with low confidence
+        let volts = pulse(2, 1);
+        let measurements = Measurements::from_samples(&time(volts.len()), &volts).unwrap();
+        assert_eq!(measurements.period.unwrap().confidence, Confidence::Low);
+        assert_eq!(measurements.rise_time.unwrap().confidence, Confidence::Low);
+        assert_eq!(measurements.overshoot.unwrap().confidence, Confidence::Low);
+        assert!(measurements.vrms.is_reliable());
+
+        // Less than a period: levels are uncertain, edges and periods missing
+        let volts = &pulse(1, 10)[..45];
+        let measurements = Measurements::from_samples(&time(volts.len()), volts).unwrap();
+        assert_eq!(measurements.vavg.confidence, Confidence::Low);
+        assert!(measurements.period.is_none());
+        assert!(measurements.duty_cycle.is_none());
+    }
+
+    #[test]
+    fn test_sine_and_dc() {
+        let volts: Vec<f64> = time(1000)
+            .iter()
+            .map(|t| 2.0f64.mul_add((std::f64::consts::TAU * 5_000.0 * t).sin(), 1.0))
+            .collect();
+        let measurements = Measurements::from_samples(&time(1000), &volts).unwrap();
+        assert!((measurements.frequency.unwrap().value - 5_000.0).abs() < 1.0);
+        assert!((measurements.vavg.value - 1.0).abs() < 1e-3);
+        // sqrt(1² + 2² / 2)
+        assert!((measurements.vrms.value - 3f64.sqrt()).abs() < 1e-3);
+
+        let dc = vec![1.2; 100];
+        let measurements = Measurements::from_samples(&time(100), &dc).unwrap();
+        assert!(measurements.vavg.is_reliable());
+        assert!(measurements.frequency.is_none());
+        assert!(measurements.overshoot.is_none());
+
+        assert!(Measurements::from_samples(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn test_from_dataframe() {
+        let volts = pulse(3, 10);
+        let df = df!(
+            TIME_COLUMN_NAME => time(volts.len()),
+            CALIBRATED_COLUMN_NAME => volts,
+        )
+        .unwrap();
+        let measurements = Measurements::from_dataframe(&df).unwrap();
+        assert!((measurements.period.unwrap().value - 100e-6).abs() < 1e-9);
+
+        // Missing and NaN samples are left out
+        let gaps = df
+            .clone()
+            .lazy()
+            .with_column(
+                when(col(TIME_COLUMN_NAME).lt(lit(20e-6)))
+                    .then(lit(NULL))
+                    .when(col(TIME_COLUMN_NAME).lt(lit(40e-6)))
+                    .then(lit(f64::NAN))
+                    .otherwise(col(CALIBRATED_COLUMN_NAME))
+                    .alias(CALIBRATED_COLUMN_NAME),
+            )
+            .collect()
+            .unwrap();
+        let measurements = Measurements::from_dataframe(&gaps).unwrap();
+        assert!(measurements.vpp.value.is_finite());
+        assert!((measurements.period.unwrap().value - 100e-6).abs() < 1e-9);
+
+        let empty = df.head(Some(0));
+        assert!(matches!(
+            Measurements::from_dataframe(&empty),
+            Err(MeasurementError::NoSamples)
+        ));
+    }
+
+    #[test]
+    fn test_distortion() {
+        use std::f64::consts::TAU;
+
+        // 1 kHz with 1 % third harmonic and uniform noise of ±10 mV, not a whole number
+        // of periods
+        let time: Vec<f64> = (0..10_050).map(|i| f64::from(i) * 1e-5).collect();
+        let mut seed = 1u32;
+        let volts: Vec<f64> = time
+            .iter()
+            .map(|t| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = (f64::from(seed) / f64::from(u32::MAX)).mul_add(0.02, -0.01);
+                0.01f64.mul_add((TAU * 3e3 * t).sin(), (TAU * 1e3 * t).sin()) + noise + 1.5
+            })
+            .collect();
+        let df = df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts).unwrap();
+
+        // Fundamental 0.5 V², harmonic 5e-5 V², noise 0.01² / 3 V²
+        let detected = distortion(&df, None).unwrap();
+        assert!((detected.fundamental - 1000.0).abs() < 0.1, "{detected:?}");
+        assert!((detected.thd - 0.01).abs() < 5e-4, "{detected:?}");
+        let given = distortion(&df, Some(1000.0)).unwrap();
+        assert!((given.snr - 41.76).abs() < 0.1, "{given:?}");
+        assert!((given.sinad - 37.78).abs() < 0.1, "{given:?}");
+
+        assert!(matches!(
+            distortion(&df.head(Some(500)), Some(1000.0)),
+            Err(MeasurementError::TooFewPeriods { .. })
+        ));
+        let dc =
+            df!(TIME_COLUMN_NAME => [0.0, 1e-5, 2e-5], CALIBRATED_COLUMN_NAME => [1.0; 3]).unwrap();
+        assert!(matches!(
+            distortion(&dc, None),
+            Err(MeasurementError::NoFundamental)
+        ));
+    }
+}
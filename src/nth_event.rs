@@ -0,0 +1,132 @@
+use crate::flea_scope::Sample;
+use crate::soft_trigger::masks;
+use crate::trigger_config::{DigitalTrigger, DigitalTriggerBehavior};
+use std::num::NonZeroUsize;
+
+/// Counts occurrences of a digital pattern across consecutive captures and reports every
+/// Nth one, for periodic bursts where only every k-th event matters.
+///
+/// The pattern and edge come from a `DigitalTrigger`: `starts_matching` counts the pattern
+/// appearing, `stops_matching` it disappearing, the others count like `starts_matching`.
+/// The captures themselves should be untriggered, so that the events between two Nth ones
+/// are seen. Events during the transfer between captures are missed.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::Sample;
+/// use fleascope_rs::nth_event::NthEvent;
+/// use fleascope_rs::{BitState, DigitalTrigger};
+/// use std::num::NonZeroUsize;
+///
+/// let pattern = DigitalTrigger::start_capturing_when().bit0(BitState::High).starts_matching()?;
+/// let mut third = NthEvent::new(&pattern, NonZeroUsize::new(3).unwrap());
+/// let samples: Vec<Sample> = [0, 1, 0, 1, 0, 1]
+///     .into_iter()
+///     .enumerate()
+///     .map(|(i, bitmap)| Sample { time: i as f64, raw: 0.0, bitmap })
+///     .collect();
+/// assert_eq!(third.feed(&samples), [5]);
+/// # Ok::<(), fleascope_rs::DigitalTriggerError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct NthEvent {
+    mask: u16,
+    value: u16,
+    on_release: bool,
+    n: NonZeroUsize,
+    count: usize,
+    /// Whether the last sample fed matched, so events spanning two captures count once
+    matched: Option<bool>,
+}
+
+impl NthEvent {
+    pub fn new(pattern: &DigitalTrigger, n: NonZeroUsize) -> Self {
+        let (mask, value) = masks(&pattern.bit_states);
+        Self {
+            mask,
+            value,
+            on_release: pattern.behavior == DigitalTriggerBehavior::Stop,
+            n,
+            count: 0,
+            matched: None,
+        }
+    }
+
+    /// Count the events in the next capture. Returns the indices of the samples at which
+    /// an Nth event occurred, empty if none did in this capture. Counting restarts after
+    /// every Nth event.
+    pub fn feed(&mut self, samples: &[Sample]) -> Vec<usize> {
+        let mut nth = Vec::new();
+        for (index, sample) in samples.iter().enumerate() {
+            let matches = sample.bitmap & self.mask == self.value;
+            let was = self.matched.replace(matches);
+            let event = match was {
+                Some(was) => was != matches && matches != self.on_release,
+                // Only the first sample ever seen: a pattern already present isn't an edge
+                None => false,
+            };
+            if event {
+                self.count += 1;
+                if self.count == self.n.get() {
+                    self.count = 0;
+                    nth.push(index);
+                }
+            }
+        }
+        nth
+    }
+
+    /// Events counted since the last Nth one
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trigger_config::BitState;
+
+    fn samples(bitmaps: &[u16]) -> Vec<Sample> {
+        (0u32..)
+            .zip(bitmaps)
+            .map(|(i, &bitmap)| Sample {
+                time: f64::from(i),
+                raw: 0.0,
+                bitmap,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nth_event_across_captures() {
+        let pattern = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .bit1(BitState::Low)
+            .starts_matching()
+            .unwrap();
+        let mut counter = NthEvent::new(&pattern, NonZeroUsize::new(2).unwrap());
+
+        // Already matching at the start, then one rising edge
+        assert!(counter.feed(&samples(&[1, 1, 0, 1])).is_empty());
+        assert_eq!(counter.count(), 1);
+        // Still high across the capture boundary, then the second edge; bit 1 blocks one
+        assert_eq!(counter.feed(&samples(&[1, 0, 3, 0, 1])), [4]);
+        assert_eq!(counter.count(), 0);
+        // Five edges in one capture: the 2nd and the 4th are reported, the 5th is counted
+        assert_eq!(
+            counter.feed(&samples(&[0, 1, 0, 1, 0, 1, 0, 1, 0, 1])),
+            [3, 7]
+        );
+        assert_eq!(counter.count(), 1);
+    }
+
+    #[test]
+    fn test_nth_event_on_release() {
+        let pattern = DigitalTrigger::start_capturing_when()
+            .bit2(BitState::High)
+            .stops_matching()
+            .unwrap();
+        let mut counter = NthEvent::new(&pattern, NonZeroUsize::new(1).unwrap());
+        assert_eq!(counter.feed(&samples(&[0, 4, 4, 0, 4, 0])), [3, 5]);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+use polars::prelude::*;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+
+pub const VOLTS_COLUMN_NAME: &str = "volts";
+pub const COUNT_COLUMN_NAME: &str = "count";
+
+/// Density of samples over time and voltage, accumulated from successive captures.
+///
+/// Like the persistence display of a bench scope, rare anomalies stand out as faint
+/// traces next to the dense normal waveform. The grid is `width` time bins by `height`
+/// voltage bins, samples outside the ranges are ignored.
+///
+/// ```rust
+/// use fleascope_rs::persistence::Persistence;
+/// use polars::prelude::*;
+/// use std::num::NonZeroUsize;
+///
+/// let mut persistence = Persistence::new(
+///     0.0..4.0,
+///     0.0..4.0,
+///     NonZeroUsize::new(4).unwrap(),
+///     NonZeroUsize::new(2).unwrap(),
+/// );
+/// let capture = df!("time" => [0.5, 1.5, 2.5, 3.5], "bnc_calibrated" => [1.0, 1.0, 3.0, 1.0])?;
+/// persistence.add(&capture)?;
+/// persistence.add(&capture)?;
+///
+/// // The top row holds the highest voltages
+/// assert_eq!(persistence.buffer(), [0, 0, 2, 0, 2, 2, 0, 2]);
+/// # Ok::<(), PolarsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Persistence {
+    time: Range<f64>,
+    volts: Range<f64>,
+    width: usize,
+    height: usize,
+    counts: Vec<u32>,
+    captures: usize,
+}
+
+impl Persistence {
+    pub fn new(
+        time: Range<f64>,
+        volts: Range<f64>,
+        width: NonZeroUsize,
+        height: NonZeroUsize,
+    ) -> Self {
+        Self {
+            time,
+            volts,
+            width: width.get(),
+            height: height.get(),
+            counts: vec![0; width.get() * height.get()],
+            captures: 0,
+        }
+    }
+
+    /// Add the calibrated samples of a capture
+    pub fn add(&mut self, df: &DataFrame) -> Result<(), PolarsError> {
+        profiling::scope!("Persistence::add");
+
+        let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+        let volts = df.column(CALIBRATED_COLUMN_NAME)?.f64()?;
+        // Rolling captures are single chunks, which avoids copying them
+        if let (Ok(time), Ok(volts)) = (time.cont_slice(), volts.cont_slice()) {
+            self.add_samples(time, volts);
+        } else {
+            let time: Vec<f64> = time.into_no_null_iter().collect();
+            let volts: Vec<f64> = volts.into_no_null_iter().collect();
+            self.add_samples(&time, &volts);
+        }
+        Ok(())
+    }
+
+    /// Add samples given as separate time and voltage slices
+    pub fn add_samples(&mut self, time: &[f64], volts: &[f64]) {
+        let bin = |value: f64, range: &Range<f64>, bins: usize| {
+            #[allow(clippy::cast_precision_loss)]
+            let bins = bins as f64;
+            let position = (value - range.start) / (range.end - range.start) * bins;
+            // Also rejects NaN
+            if !(0.0..bins).contains(&position) {
+                return None;
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Some(position as usize)
+        };
+        for (&t, &v) in time.iter().zip(volts) {
+            if let (Some(column), Some(row)) = (
+                bin(t, &self.time, self.width),
+                bin(v, &self.volts, self.height),
+            ) {
+                let index = (self.height - 1 - row) * self.width + column;
+                self.counts[index] = self.counts[index].saturating_add(1);
+            }
+        }
+        self.captures += 1;
+    }
+
+    /// Forget all captures
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.captures = 0;
+    }
+
+    /// Number of captures added since the last `clear`
+    pub fn captures(&self) -> usize {
+        self.captures
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Sample counts row by row, `width` per row, starting with the highest voltages as in
+    /// an image
+    pub fn buffer(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Count of the bin at time bin `column` and voltage bin `row`, counted from the top
+    pub fn get(&self, column: usize, row: usize) -> Option<u32> {
+        if column >= self.width {
+            return None;
+        }
+        self.counts.get(row * self.width + column).copied()
+    }
+
+    /// `time`, `volts` and `count` columns for every bin holding samples, with the bin
+    /// centers as coordinates
+    pub fn to_dataframe(&self) -> Result<DataFrame, PolarsError> {
+        profiling::scope!("Persistence::to_dataframe");
+
+        #[allow(clippy::cast_precision_loss)]
+        let center = |index: usize, range: &Range<f64>, bins: usize| {
+            (range.end - range.start).mul_add((index as f64 + 0.5) / bins as f64, range.start)
+        };
+        let (mut time, mut volts, mut count) = (Vec::new(), Vec::new(), Vec::new());
+        for (index, &samples) in self.counts.iter().enumerate() {
+            if samples == 0 {
+                continue;
+            }
+            let (row, column) = (index / self.width, index % self.width);
+            time.push(center(column, &self.time, self.width));
+            volts.push(center(self.height - 1 - row, &self.volts, self.height));
+            count.push(samples);
+        }
+        DataFrame::new(vec![
+            Column::new(TIME_COLUMN_NAME.into(), time),
+            Column::new(VOLTS_COLUMN_NAME.into(), volts),
+            Column::new(COUNT_COLUMN_NAME.into(), count),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persistence() -> Persistence {
+        Persistence::new(
+            0.0..1.0,
+            -1.0..1.0,
+            NonZeroUsize::new(2).unwrap(),
+            NonZeroUsize::new(4).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let mut persistence = persistence();
+        persistence.add_samples(&[0.1, 0.6, 0.9], &[-0.9, 0.9, 0.2]);
+        // Out of range or NaN samples are ignored
+        persistence.add_samples(&[0.1, 1.0, -0.1, 0.2], &[-0.9, 0.0, 0.0, f64::NAN]);
+
+        assert_eq!(persistence.captures(), 2);
+        assert_eq!(persistence.buffer(), [0, 1, 0, 1, 0, 0, 2, 0]);
+        assert_eq!(persistence.get(0, 3), Some(2));
+        assert_eq!(persistence.get(2, 0), None);
+
+        let df = persistence.to_dataframe().unwrap();
+        assert_eq!(df.height(), 3);
+        let counts: Vec<u32> = df
+            .column(COUNT_COLUMN_NAME)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(counts, [1, 1, 2]);
+        let volts: Vec<f64> = df
+            .column(VOLTS_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(volts, [0.75, 0.25, -0.75]);
+
+        persistence.clear();
+        assert!(persistence.buffer().iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_add_frame() {
+        let mut persistence = persistence();
+        let first = df!(TIME_COLUMN_NAME => [0.1], CALIBRATED_COLUMN_NAME => [0.9]).unwrap();
+        let second = df!(TIME_COLUMN_NAME => [0.6], CALIBRATED_COLUMN_NAME => [0.9]).unwrap();
+        // A frame of several chunks takes the copying path
+        let mut both = first.clone();
+        both.vstack_mut(&second).unwrap();
+
+        persistence.add(&first).unwrap();
+        persistence.add(&both).unwrap();
+        assert_eq!(persistence.get(0, 0), Some(2));
+        assert_eq!(persistence.get(1, 0), Some(1));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::flea_scope::TIME_COLUMN_NAME;
+use crate::math_channel::{interpolate, time_and_values};
+use polars::prelude::*;
+
+pub const POWER_COLUMN_NAME: &str = "power_W";
+
+#[derive(Debug, thiserror::Error)]
+pub enum PowerError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("The voltage and current captures don't overlap in time")]
+    NoOverlap,
+}
+
+/// Result of combining a voltage and a current capture
+#[derive(Debug, Clone)]
+pub struct PowerAnalysis {
+    /// `time`, voltage, current (resampled to the voltage time base) and `power_W` columns
+    pub frame: DataFrame,
+    /// Mean power over the record, in watts
+    pub average_power: f64,
+    pub peak_power: f64,
+    /// Energy over the record, in joules
+    pub energy: f64,
+}
+
+/// Multiply a voltage capture with a current capture, e.g. from two scopes or a scope with
+/// a shunt or current probe (see `UnitConversion::shunt`).
+///
+/// Both captures are expected to share a trigger, so their `time` columns start at the same
+/// instant. The current is linearly interpolated onto the voltage time base, which lets the
+/// two captures use different sample rates. Only the overlapping part of the record is kept,
+/// and samples missing a value, e.g. the gaps of a computed channel, are left out.
+pub fn analyze(
+    voltage: &DataFrame,
+    voltage_column: &str,
+    current: &DataFrame,
+    current_column: &str,
+) -> Result<PowerAnalysis, PowerError> {
+    profiling::scope!("power::analyze");
+
+    let (v_time, volts) = time_and_values(voltage, voltage_column)?;
+    let (i_time, amps) = time_and_values(current, current_column)?;
+
+    let mut time = Vec::with_capacity(v_time.len());
+    let mut aligned_volts = Vec::with_capacity(v_time.len());
+    let mut aligned_amps = Vec::with_capacity(v_time.len());
+    for (&t, &v) in v_time.iter().zip(&volts) {
+        if let Some(i) = interpolate(&i_time, &amps, t) {
+            time.push(t);
+            aligned_volts.push(v);
+            aligned_amps.push(i);
+        }
+    }
+    if time.is_empty() {
+        return Err(PowerError::NoOverlap);
+    }
+
+    let power: Vec<f64> = aligned_volts
+        .iter()
+        .zip(&aligned_amps)
+        .map(|(v, i)| v * i)
+        .collect();
+
+    // Trapezoidal integration over the (possibly non-uniform) time base
+    let energy: f64 = time
+        .windows(2)
+        .zip(power.windows(2))
+        .map(|(t, p)| (t[1] - t[0]) * (p[0] + p[1]) / 2.0)
+        .sum();
+    let duration = time[time.len() - 1] - time[0];
+    let average_power = if duration > 0.0 {
+        energy / duration
+    } else {
+        power[0]
+    };
+    let peak_power = power.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    let frame = DataFrame::new(vec![
+        Column::new(TIME_COLUMN_NAME.into(), time),
+        Column::new(voltage_column.into(), aligned_volts),
+        Column::new(current_column.into(), aligned_amps),
+        Column::new(POWER_COLUMN_NAME.into(), power),
+    ])?;
+
+    Ok(PowerAnalysis {
+        frame,
+        average_power,
+        peak_power,
+        energy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_with_different_sample_rates() {
+        let voltage = df!(
+            TIME_COLUMN_NAME => [0.0, 0.5, 1.0, 1.5, 2.0, 2.5],
+            "voltage_V" => [5.0, 5.0, 5.0, 5.0, 5.0, 5.0],
+        )
+        .unwrap();
+        let current = df!(
+            TIME_COLUMN_NAME => [0.0, 1.0, 2.0],
+            "current_A" => [0.0, 1.0, 2.0],
+        )
+        .unwrap();
+
+        let analysis = analyze(&voltage, "voltage_V", &current, "current_A").unwrap();
+
+        // The sample at 2.5 s is outside the current record
+        assert_eq!(analysis.frame.height(), 5);
+        assert!((analysis.peak_power - 10.0).abs() < 1e-12);
+        // P = 5 W/s * t over 2 s
+        assert!((analysis.energy - 10.0).abs() < 1e-12);
+        assert!((analysis.average_power - 5.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_power_skips_missing_values() {
+        let voltage = df!(
+            TIME_COLUMN_NAME => [0.0, 1.0, 2.0],
+            "voltage_V" => [Some(5.0), None, Some(5.0)],
+        )
+        .unwrap();
+        let current = df!(
+            TIME_COLUMN_NAME => [0.0, 0.5, 2.0],
+            "current_A" => [Some(1.0), None, Some(3.0)],
+        )
+        .unwrap();
+
+        let analysis = analyze(&voltage, "voltage_V", &current, "current_A").unwrap();
+        assert_eq!(analysis.frame.height(), 2);
+        assert!((analysis.peak_power - 15.0).abs() < 1e-12);
+
+        let empty = df!(TIME_COLUMN_NAME => [0.0], "i" => [None::<f64>]).unwrap();
+        assert!(matches!(
+            analyze(&voltage, "voltage_V", &empty, "i"),
+            Err(PowerError::NoOverlap)
+        ));
+    }
+
+    #[test]
+    fn test_no_overlap() {
+        let voltage = df!(TIME_COLUMN_NAME => [5.0, 6.0], "v" => [1.0, 1.0]).unwrap();
+        let current = df!(TIME_COLUMN_NAME => [0.0, 1.0], "i" => [1.0, 1.0]).unwrap();
+        assert!(matches!(
+            analyze(&voltage, "v", &current, "i"),
+            Err(PowerError::NoOverlap)
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+//! The types and traits to connect to a scope and capture in one import. Analysis
+//! tools are imported from their modules.
+//!
+//! ```rust,no_run
+//! use fleascope_rs::prelude::*;
+//! use std::time::Duration;
+//!
+//! let (mut scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+//! let config = CaptureConfig::builder()
+//!     .time_frame(Duration::from_millis(10))
+//!     .trigger(AnalogTrigger::start_capturing_when(Volts::new(1.0)?).rising_edge().into_trigger(&x1)?)
+//!     .build()?;
+//! # #[cfg(feature = "dataframe")]
+//! let df = scope.read(&config).frame()?.calibrated(&x1).collect()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub use crate::actions::{ActionError, CaptureAction};
+pub use crate::capabilities::{
+    Capabilities, DeviceInfo, Feature, FirmwareVersion, UnsupportedByFirmware,
+};
+#[cfg(feature = "dataframe")]
+pub use crate::capture_frame::CaptureFrame;
+#[cfg(feature = "dataframe")]
+pub use crate::capture_stream::{CaptureStream, CaptureStreamError};
+pub use crate::channel_labels::{ChannelLabelError, ChannelLabels};
+pub use crate::command::{CommandBuilder, CommandError};
+pub use crate::flash_vars::{FlashVarError, FlashVars};
+pub use crate::flea_connector::{FleaConnector, FleaConnectorError, FleaDevice};
+#[cfg(feature = "ndarray")]
+pub use crate::flea_scope::CaptureArrays;
+#[cfg(feature = "dataframe")]
+pub use crate::flea_scope::CaptureParseError;
+pub use crate::flea_scope::{
+    CalibrationError, CaptureConfig, CaptureConfigError, CaptureError, CaptureEvent,
+    CaptureMetadata, CapturePlan, CaptureSamples, FleaProbe, ForceTriggerError, IdleFleaScope,
+    InvalidCaptureConfig, ParseMode, ParseReport, ParseWaveformError, ProbeType, ReadingFleaScope,
+    Sample, SampleParseError, SamplesError, ScopeReading, StreamingScope, WaitError, WaitOutcome,
+    Waveform,
+};
+pub use crate::generator::{InvalidWaveformConfig, WaveformConfig, WaveformConfigError};
+pub use crate::gpio::{GpioError, PinMode};
+pub use crate::nth_event::NthEvent;
+pub use crate::sequence_trigger::{SequenceTrigger, SequenceTriggerError};
+pub use crate::serial_terminal::{
+    FleaTerminalError, IdleFleaTerminal, ReadInterrupted, StatelessFleaTerminal, TerminalDialect,
+};
+#[cfg(feature = "dataframe")]
+pub use crate::sink::{CaptureSink, SinkError};
+pub use crate::timebase::{TimebaseCalibration, TimebaseError};
+pub use crate::trigger_config::{
+    AnalogTrigger, AnalogTriggerBehavior, BitState, DigitalTrigger, DigitalTriggerBehavior,
+    DigitalTriggerError, ParseTriggerError, ProbeTrigger, Trigger, TriggerConfig,
+};
+#[cfg(feature = "dataframe")]
+pub use crate::unit_conversion::UnitConversion;
+pub use crate::units::Volts;
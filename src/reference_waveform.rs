@@ -0,0 +1,297 @@
+use crate::alignment::best_shift;
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+use crate::math_channel::{interpolate, time_and_values};
+use polars::prelude::*;
+use std::time::Duration;
+
+pub const REFERENCE_COLUMN_NAME: &str = "reference";
+pub const ERROR_COLUMN_NAME: &str = "error";
+
+/// Share of the reference duration searched for the best alignment by default
+const DEFAULT_MAX_SHIFT: f64 = 0.05;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReferenceError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("The capture holds no samples")]
+    Empty,
+
+    #[error("The capture doesn't overlap the reference in time")]
+    NoOverlap,
+}
+
+/// A known good capture, e.g. of a golden board, to compare later captures against.
+///
+/// With the `serde` feature it can be saved and loaded in any serde format.
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+/// use fleascope_rs::reference_waveform::ReferenceWaveform;
+/// use polars::prelude::*;
+///
+/// let golden = df!(TIME_COLUMN_NAME => [0.0, 1.0, 2.0], CALIBRATED_COLUMN_NAME => [0.0, 3.3, 0.0])?;
+/// let reference = ReferenceWaveform::from_capture(&golden)?;
+///
+/// let capture = df!(TIME_COLUMN_NAME => [0.0, 1.0, 2.0], CALIBRATED_COLUMN_NAME => [0.0, 3.2, 0.0])?;
+/// let deviation = reference.compare(&capture)?;
+/// assert!(deviation.is_within(0.15));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReferenceWaveform {
+    time: Vec<f64>,
+    volts: Vec<f64>,
+    /// Largest time shift searched when aligning a capture, in seconds
+    max_shift: f64,
+}
+
+/// Result of `ReferenceWaveform::compare`
+#[derive(Debug, Clone)]
+pub struct Deviation {
+    /// Seconds added to the capture's `time` to align it with the reference
+    pub offset: f64,
+    /// `time`, `bnc_calibrated`, `reference` and `error` columns for the samples of the
+    /// capture the reference covers after alignment
+    pub frame: DataFrame,
+    /// Largest absolute error, in volts
+    pub max_deviation: f64,
+    /// Capture time of the largest error
+    pub max_deviation_time: f64,
+    pub rms_deviation: f64,
+}
+
+impl Deviation {
+    /// Whether no sample deviates more than `volts` from the reference
+    pub fn is_within(&self, volts: f64) -> bool {
+        self.max_deviation <= volts
+    }
+}
+
+impl ReferenceWaveform {
+    /// Store the calibrated voltage of `df` as the reference
+    pub fn from_capture(df: &DataFrame) -> Result<Self, ReferenceError> {
+        let (time, volts) = samples(df)?;
+        let duration = time[time.len() - 1] - time[0];
+        Ok(Self {
+            time,
+            volts,
+            max_shift: duration * DEFAULT_MAX_SHIFT,
+        })
+    }
+
+    /// Largest time shift searched when aligning a capture, 5 % of the reference by
+    /// default. Zero compares at the captured times, e.g. for captures sharing a trigger.
+    #[must_use]
+    pub fn max_shift(mut self, max_shift: Duration) -> Self {
+        self.max_shift = max_shift.as_secs_f64();
+        self
+    }
+
+    /// Align the calibrated voltage of `df` with the reference and compute the error of
+    /// every sample.
+    ///
+    /// The capture is shifted in steps of its sample interval to where the mean squared
+    /// error is smallest, while at least half of it is covered by the reference. Unlike
+    /// `analysis::align`, which correlates normalized signals, the error itself is
+    /// minimized: a capture matching the reference in shape but not in level must not
+    /// look aligned. Samples without a voltage are left out.
+    pub fn compare(&self, df: &DataFrame) -> Result<Deviation, ReferenceError> {
+        profiling::scope!("ReferenceWaveform::compare");
+
+        let (time, volts) = samples(df)?;
+        let interval = match time.as_slice() {
+            [first, .., last] => {
+                #[allow(clippy::cast_precision_loss)]
+                let interval = (last - first) / (time.len() - 1) as f64;
+                interval
+            }
+            _ => 0.0,
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let steps = if interval > 0.0 {
+            (self.max_shift / interval).floor() as isize
+        } else {
+            0
+        };
+
+        let errors = |offset: f64| {
+            time.iter()
+                .zip(&volts)
+                .filter_map(|(&t, &v)| {
+                    let expected = interpolate(&self.time, &self.volts, t + offset)?;
+                    Some((t, v, expected))
+                })
+                .collect::<Vec<_>>()
+        };
+        let mean_squared = |offset: f64| {
+            let errors = errors(offset);
+            (errors.len() * 2 >= time.len()).then(|| {
+                #[allow(clippy::cast_precision_loss)]
+                let count = errors.len() as f64;
+                errors
+                    .iter()
+                    .map(|(_, v, expected)| (v - expected).powi(2))
+                    .sum::<f64>()
+                    / count
+            })
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let offset_of = |step: isize| step as f64 * interval;
+        let step = best_shift(steps, |step| {
+            mean_squared(offset_of(step)).map(|error| -error)
+        });
+        let offset = offset_of(step.unwrap_or(0));
+
+        let aligned = errors(offset);
+        if aligned.is_empty() {
+            return Err(ReferenceError::NoOverlap);
+        }
+        let error: Vec<f64> = aligned
+            .iter()
+            .map(|(_, v, expected)| v - expected)
+            .collect();
+        let (max_deviation_time, max_deviation) = aligned
+            .iter()
+            .zip(&error)
+            .map(|((t, _, _), error)| (*t, error.abs()))
+            .fold((0.0, f64::NEG_INFINITY), |max, point| {
+                if point.1 > max.1 {
+                    point
+                } else {
+                    max
+                }
+            });
+        #[allow(clippy::cast_precision_loss)]
+        let rms_deviation = (error.iter().map(|e| e * e).sum::<f64>() / error.len() as f64).sqrt();
+
+        let frame = DataFrame::new(vec![
+            Column::new(
+                TIME_COLUMN_NAME.into(),
+                aligned.iter().map(|(t, _, _)| *t).collect::<Vec<f64>>(),
+            ),
+            Column::new(
+                CALIBRATED_COLUMN_NAME.into(),
+                aligned.iter().map(|(_, v, _)| *v).collect::<Vec<f64>>(),
+            ),
+            Column::new(
+                REFERENCE_COLUMN_NAME.into(),
+                aligned
+                    .iter()
+                    .map(|(_, _, expected)| *expected)
+                    .collect::<Vec<f64>>(),
+            ),
+            Column::new(ERROR_COLUMN_NAME.into(), error),
+        ])?;
+
+        Ok(Deviation {
+            offset,
+            frame,
+            max_deviation,
+            max_deviation_time,
+            rms_deviation,
+        })
+    }
+}
+
+/// Samples of `df` with both a time and a voltage
+fn samples(df: &DataFrame) -> Result<(Vec<f64>, Vec<f64>), ReferenceError> {
+    let (time, volts) = time_and_values(df, CALIBRATED_COLUMN_NAME)?;
+    if time.is_empty() {
+        return Err(ReferenceError::Empty);
+    }
+    Ok((time, volts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1 V pulse from 20 µs to 40 µs, sampled at 1 MHz and delayed by `delay` samples
+    fn pulse(delay: i32) -> DataFrame {
+        let time: Vec<f64> = (0..100).map(|i| f64::from(i) * 1e-6).collect();
+        let volts: Vec<f64> = (0..100)
+            .map(|i| {
+                if (20..40).contains(&(i - delay)) {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts).unwrap()
+    }
+
+    #[test]
+    fn test_compare_aligned() {
+        let reference = ReferenceWaveform::from_capture(&pulse(0)).unwrap();
+
+        // A capture 3 µs late is moved back onto the reference
+        let deviation = reference.compare(&pulse(3)).unwrap();
+        assert!((deviation.offset + 3e-6).abs() < 1e-12);
+        assert!(deviation.is_within(1e-12));
+        assert_eq!(deviation.frame.height(), 97);
+
+        // Without shifting, the edges don't line up
+        let fixed = reference.max_shift(Duration::ZERO);
+        let deviation = fixed.compare(&pulse(3)).unwrap();
+        assert!(deviation.offset.abs() < 1e-12);
+        assert!((deviation.max_deviation - 1.0).abs() < 1e-12);
+        assert!((deviation.max_deviation_time - 20e-6).abs() < 1e-12);
+        assert!((deviation.rms_deviation - 0.06f64.sqrt()).abs() < 1e-12);
+        assert!(!deviation.is_within(0.5));
+    }
+
+    #[test]
+    fn test_compare_errors() {
+        let reference = ReferenceWaveform::from_capture(&pulse(0)).unwrap();
+        let later = df!(TIME_COLUMN_NAME => [1.0], CALIBRATED_COLUMN_NAME => [0.0]).unwrap();
+        assert!(matches!(
+            reference.compare(&later),
+            Err(ReferenceError::NoOverlap)
+        ));
+        assert!(matches!(
+            ReferenceWaveform::from_capture(&pulse(0).head(Some(0))),
+            Err(ReferenceError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_compare_skips_nulls() {
+        let reference = ReferenceWaveform::from_capture(&pulse(0)).unwrap();
+        let mut volts: Vec<Option<f64>> = pulse(0)
+            .column(CALIBRATED_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        volts[30] = None;
+        let mut with_gap = pulse(0);
+        with_gap
+            .with_column(Column::new(CALIBRATED_COLUMN_NAME.into(), volts))
+            .unwrap();
+        let deviation = reference.compare(&with_gap).unwrap();
+        assert!(deviation.offset.abs() < 1e-12);
+        assert!(deviation.is_within(1e-12));
+        assert_eq!(deviation.frame.height(), 99);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let golden = df!(
+            TIME_COLUMN_NAME => [0.0, 0.5, 1.0],
+            CALIBRATED_COLUMN_NAME => [0.25, 3.25, 0.25],
+        )
+        .unwrap();
+        let reference = ReferenceWaveform::from_capture(&golden)
+            .unwrap()
+            .max_shift(Duration::from_millis(250));
+        let json = serde_json::to_string(&reference).unwrap();
+        let restored: ReferenceWaveform = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, reference);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::capabilities::UnsupportedByFirmware;
+use crate::command::CommandError;
+use crate::flea_scope::{
+    CaptureConfigError, CaptureError, IdleFleaScope, ReadingFleaScope, ScopeReading, Waveform,
+};
+use crate::serial_terminal::{FleaTerminalError, ReadInterrupted};
+use crate::trigger_config::StringifiedTriggerConfig;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, SendError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Requests handled by the background thread, in the order they were sent
+pub enum Command {
+    Capture {
+        time_frame: Duration,
+        trigger_fields: StringifiedTriggerConfig,
+        delay: Option<Duration>,
+    },
+    /// Abort the capture currently in flight. Ignored when idle.
+    Cancel,
+    SetWaveform(Waveform, i32),
+    SetHostname(String),
+}
+
+/// Messages sent back by the background thread, one per `Command`. A `Capture` is
+/// preceded by a `CaptureChunk` whenever more of its transfer arrived.
+pub enum Response {
+    CaptureChunk(CaptureChunk),
+    Reading(ScopeReading),
+    Cancelled,
+    Done,
+    Error(ScopeThreadError),
+}
+
+/// Part of a capture's response, sent while it is transferred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureChunk {
+    /// Bytes received since the previous chunk
+    pub data: Vec<u8>,
+    /// Bytes of the capture received so far. A full capture is roughly
+    /// `TRANSFER_BYTES_ESTIMATE` bytes long.
+    pub received: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScopeThreadError {
+    #[error("Invalid capture configuration: {0}")]
+    CaptureConfig(#[from] CaptureConfigError),
+
+    #[error("Connection lost while capturing")]
+    ConnectionLost,
+
+    #[error("Device rebooted while capturing")]
+    DeviceRebooted,
+
+    #[error("Failed to cancel capture: {0}")]
+    CancelFailed(FleaTerminalError),
+
+    #[error("Invalid command: {0}")]
+    InvalidCommand(#[from] CommandError),
+
+    #[error("Serial terminal error: {0}")]
+    Terminal(FleaTerminalError),
+
+    #[error("{0}")]
+    UnsupportedByFirmware(#[from] UnsupportedByFirmware),
+}
+
+impl From<CaptureError> for ScopeThreadError {
+    fn from(e: CaptureError) -> Self {
+        match e {
+            CaptureError::Config(e) => Self::CaptureConfig(e),
+            CaptureError::Terminal(e) => Self::Terminal(e),
+            CaptureError::UnsupportedByFirmware(e) => Self::UnsupportedByFirmware(e),
+        }
+    }
+}
+
+/// Owns an `IdleFleaScope` on a dedicated thread and talks to it through channels.
+///
+/// This is an alternative to polling `ReadingFleaScope::try_get_result` from the caller's
+/// thread: commands are queued on a `Sender<Command>` and results arrive on a
+/// `Receiver<Response>`, which can be waited on or multiplexed with other channels.
+pub struct ScopeThread {
+    commands: Sender<Command>,
+    responses: Receiver<Response>,
+    handle: JoinHandle<Option<IdleFleaScope>>,
+}
+
+impl ScopeThread {
+    /// Move the scope onto a new thread
+    pub fn spawn(scope: IdleFleaScope) -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (response_tx, responses) = mpsc::channel();
+
+        let handle = thread::Builder::new()
+            .name("fleascope".to_string())
+            .spawn(move || Self::run(scope, &command_rx, &response_tx))
+            .expect("Failed to spawn FleaScope thread");
+
+        Self {
+            commands,
+            responses,
+            handle,
+        }
+    }
+
+    /// Queue a command for the background thread
+    pub fn send(&self, command: Command) -> Result<(), SendError<Command>> {
+        self.commands.send(command)
+    }
+
+    /// Additional handle for sending commands, e.g. from a UI thread
+    pub fn sender(&self) -> Sender<Command> {
+        self.commands.clone()
+    }
+
+    pub fn responses(&self) -> &Receiver<Response> {
+        &self.responses
+    }
+
+    /// Stop the thread and get the scope back.
+    /// Returns `None` if the connection was lost or a capture could not be cancelled.
+    pub fn join(self) -> Option<IdleFleaScope> {
+        drop(self.commands);
+        self.handle.join().ok().flatten()
+    }
+
+    fn run(
+        mut scope: IdleFleaScope,
+        commands: &Receiver<Command>,
+        responses: &Sender<Response>,
+    ) -> Option<IdleFleaScope> {
+        let mut pending = VecDeque::new();
+
+        loop {
+            let command = match pending.pop_front() {
+                Some(command) => command,
+                None => match commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => break,
+                },
+            };
+
+            let response = match command {
+                Command::Capture {
+                    time_frame,
+                    trigger_fields,
+                    delay,
+                } => match scope.read_async(time_frame, trigger_fields, delay) {
+                    Ok(reading) => {
+                        match Self::wait_for_reading(reading, commands, responses, &mut pending) {
+                            Ok((idle, response)) => {
+                                scope = idle;
+                                response
+                            }
+                            Err(e) => {
+                                let _ = responses.send(Response::Error(e));
+                                return None;
+                            }
+                        }
+                    }
+                    Err((idle, e)) => {
+                        scope = idle;
+                        Response::Error(e.into())
+                    }
+                },
+                Command::Cancel => Response::Cancelled,
+                Command::SetWaveform(waveform, hz) => {
+                    scope.set_waveform(waveform, hz);
+                    Response::Done
+                }
+                Command::SetHostname(hostname) => match scope.set_hostname(&hostname) {
+                    Ok(()) => Response::Done,
+                    Err(e) => Response::Error(e.into()),
+                },
+            };
+
+            if responses.send(response).is_err() {
+                break;
+            }
+        }
+
+        Some(scope)
+    }
+
+    /// Poll the capture until it completes, reporting the transfer as `CaptureChunk`s and
+    /// queueing commands that arrive in the meantime
+    fn wait_for_reading(
+        mut reading: ReadingFleaScope,
+        commands: &Receiver<Command>,
+        responses: &Sender<Response>,
+        pending: &mut VecDeque<Command>,
+    ) -> Result<(IdleFleaScope, Response), ScopeThreadError> {
+        profiling::scope!("ScopeThread::wait_for_reading");
+
+        let mut reported = 0;
+        loop {
+            match reading.try_get_result() {
+                Ok(Ok((idle, data))) => return Ok((idle, Response::Reading(data))),
+                Ok(Err(busy)) => reading = busy,
+                Err(ReadInterrupted::ConnectionLost) => {
+                    return Err(ScopeThreadError::ConnectionLost)
+                }
+                Err(ReadInterrupted::DeviceRebooted(_)) => {
+                    return Err(ScopeThreadError::DeviceRebooted)
+                }
+            }
+
+            let received = reading.received();
+            if received.len() > reported {
+                // Nobody listening is no reason to stop, `run` notices with the reading
+                let _ = responses.send(Response::CaptureChunk(CaptureChunk {
+                    data: received[reported..].to_vec(),
+                    received: received.len(),
+                }));
+                reported = received.len();
+            }
+
+            match commands.try_recv() {
+                Ok(Command::Cancel) | Err(TryRecvError::Disconnected) => {
+                    let idle = reading
+                        .cancel()
+                        .map_err(|(_faulted, e)| ScopeThreadError::CancelFailed(e))?;
+                    return Ok((idle, Response::Cancelled));
+                }
+                Ok(command) => pending.push_back(command),
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{Fault, SimulatedDevice};
+    use crate::trigger_config::{DigitalTrigger, TriggerConfig};
+
+    fn capture() -> Command {
+        Command::Capture {
+            time_frame: Duration::from_millis(1),
+            trigger_fields: DigitalTrigger::start_capturing_when()
+                .is_matching()
+                .into_trigger_fields(),
+            delay: None,
+        }
+    }
+
+    #[test]
+    fn test_capture_chunks() {
+        // Holding back the prompt keeps the capture in flight after its data arrived
+        let device =
+            SimulatedDevice::new().fault("scope", Fault::DelayPrompt(Duration::from_millis(100)));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let thread = ScopeThread::spawn(scope);
+        thread.send(capture()).unwrap();
+        thread
+            .send(Command::SetHostname("bench".to_string()))
+            .unwrap();
+
+        let mut streamed = Vec::new();
+        let reading = loop {
+            match thread.responses().recv().unwrap() {
+                Response::CaptureChunk(chunk) => {
+                    streamed.extend(chunk.data);
+                    assert_eq!(chunk.received, streamed.len());
+                }
+                Response::Reading(reading) => break reading,
+                _ => unreachable!("the capture is answered first"),
+            }
+        };
+        assert!(!streamed.is_empty());
+        assert!(reading.data.starts_with(&streamed));
+        assert_eq!(reading.samples().unwrap().len(), 2000);
+        // Commands sent during the capture run after it
+        assert!(matches!(thread.responses().recv(), Ok(Response::Done)));
+
+        let scope = thread.join().unwrap();
+        assert_eq!(scope.hostname(), "bench");
+        assert!(device.commands().iter().any(|c| c.starts_with("scope ")));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let device =
+            SimulatedDevice::new().fault("scope", Fault::DelayPrompt(Duration::from_secs(5)));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let thread = ScopeThread::spawn(scope);
+        thread.send(capture()).unwrap();
+        thread.send(Command::Cancel).unwrap();
+        let cancelled = thread
+            .responses()
+            .iter()
+            .find(|response| !matches!(response, Response::CaptureChunk(_)));
+        assert!(matches!(cancelled, Some(Response::Cancelled)));
+        assert!(thread.join().is_some());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::flea_scope::{
+    CaptureConfig, IdleFleaScope, InvalidCaptureConfig, Sample, SampleParseError, ScopeReading,
+};
+use crate::soft_trigger::digital_trigger_points;
+use crate::trigger_config::{DigitalTrigger, DigitalTriggerBehavior};
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SequenceTriggerError {
+    #[error(transparent)]
+    Config(#[from] InvalidCaptureConfig),
+
+    #[error("The {window:?} window doesn't fit the {captured:?} capture")]
+    WindowBeyondCapture {
+        window: Duration,
+        captured: Duration,
+    },
+
+    #[error("Could not parse capture: {0}")]
+    Samples(#[from] SampleParseError),
+
+    #[error("Second pattern not seen after the first in {attempts} captures")]
+    NotSeen { attempts: usize },
+}
+
+/// Trigger on digital pattern A, but only accept captures in which pattern B follows
+/// within a window.
+///
+/// The device triggers on A; B is checked on the returned bitmaps and the capture retaken
+/// if it's missing, e.g. to catch a protocol start followed by a particular payload.
+///
+/// ```rust,no_run
+/// use fleascope_rs::sequence_trigger::SequenceTrigger;
+/// use fleascope_rs::{BitState, DigitalTrigger, IdleFleaScope};
+/// use std::time::Duration;
+///
+/// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let trigger = SequenceTrigger::new(
+///     DigitalTrigger::start_capturing_when().bit0(BitState::Low).starts_matching()?,
+///     &DigitalTrigger::start_capturing_when().bit1(BitState::High).is_matching(),
+///     Duration::from_micros(200),
+/// );
+/// let reading = scope.read_sequence(&trigger, Duration::from_millis(1), 100)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SequenceTrigger {
+    first: DigitalTrigger,
+    then: DigitalTrigger,
+    within: Duration,
+}
+
+impl SequenceTrigger {
+    /// Arm on `first` and look for `then` up to `within` after it. `then` fires as a
+    /// trigger would: `starts_matching` needs the pattern to appear, `stops_matching` to
+    /// disappear, the others to be present, and a hold time has to pass within the window.
+    pub fn new(first: DigitalTrigger, then: &DigitalTrigger, within: Duration) -> Self {
+        Self {
+            first,
+            then: then.clone(),
+            within,
+        }
+    }
+
+    /// Whether the second pattern fires after the first sample and within the window
+    pub fn matches(&self, samples: &[Sample]) -> bool {
+        let within = self.within.as_secs_f64();
+        let window = &samples[..samples.partition_point(|sample| sample.time <= within)];
+        // The pattern present at the trigger itself doesn't count, an edge into the next
+        // sample does
+        let from = match self.then.behavior {
+            DigitalTriggerBehavior::Auto | DigitalTriggerBehavior::While => 1,
+            DigitalTriggerBehavior::Start | DigitalTriggerBehavior::Stop => 0,
+        };
+        !digital_trigger_points(&self.then, &window[from.min(window.len())..]).is_empty()
+    }
+}
+
+impl IdleFleaScope {
+    /// Capture until a capture triggered by the first pattern also shows the second one,
+    /// giving up after `max_attempts` captures
+    pub fn read_sequence(
+        &mut self,
+        trigger: &SequenceTrigger,
+        time_frame: Duration,
+        max_attempts: usize,
+    ) -> Result<ScopeReading, SequenceTriggerError> {
+        profiling::scope!("IdleFleaScope::read_sequence");
+
+        let config = CaptureConfig::builder()
+            .time_frame(time_frame)
+            .trigger(trigger.first.clone())
+            .build()?;
+        let captured = config.plan().captured_duration();
+        if trigger.within > captured {
+            return Err(SequenceTriggerError::WindowBeyondCapture {
+                window: trigger.within,
+                captured,
+            });
+        }
+
+        for attempt in 1..=max_attempts {
+            let reading = self.read(&config);
+            if trigger.matches(reading.samples()?) {
+                return Ok(reading);
+            }
+            log::debug!("Second pattern missing in capture {attempt}, rearming");
+        }
+        Err(SequenceTriggerError::NotSeen {
+            attempts: max_attempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimulatedDevice;
+    use crate::trigger_config::BitState;
+
+    fn trigger(within: Duration) -> SequenceTrigger {
+        SequenceTrigger::new(
+            DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .starts_matching()
+                .unwrap(),
+            &DigitalTrigger::start_capturing_when()
+                .bit1(BitState::High)
+                .bit2(BitState::Low)
+                .is_matching(),
+            within,
+        )
+    }
+
+    #[test]
+    fn test_read_sequence() {
+        // Bit 1 rises 500 µs into the capture
+        let device = SimulatedDevice::new().digital(|t| if t > 500e-6 { 0b011 } else { 0b001 });
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let time_frame = Duration::from_millis(2);
+
+        let reading = scope
+            .read_sequence(&trigger(Duration::from_millis(1)), time_frame, 3)
+            .unwrap();
+        assert!(!reading.data.is_empty());
+
+        assert!(matches!(
+            scope.read_sequence(&trigger(Duration::from_micros(200)), time_frame, 3),
+            Err(SequenceTriggerError::NotSeen { attempts: 3 })
+        ));
+        let captures = device
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("scope "))
+            .count();
+        assert_eq!(captures, 4);
+
+        assert!(matches!(
+            scope.read_sequence(&trigger(Duration::from_millis(5)), time_frame, 3),
+            Err(SequenceTriggerError::WindowBeyondCapture { .. })
+        ));
+    }
+
+    #[test]
+    fn test_matches_needs_cleared_bits() {
+        let sample = |time, bitmap| Sample {
+            time,
+            raw: 0.0,
+            bitmap,
+        };
+        let trigger = trigger(Duration::from_millis(1));
+        assert!(trigger.matches(&[sample(0.0, 0b001), sample(1e-4, 0b010)]));
+        assert!(!trigger.matches(&[sample(0.0, 0b001), sample(1e-4, 0b110)]));
+        // The pattern at the trigger itself doesn't count
+        assert!(!trigger.matches(&[sample(0.0, 0b010)]));
+    }
+
+    #[test]
+    fn test_matches_honors_behavior() {
+        let sample = |time, bitmap| Sample {
+            time,
+            raw: 0.0,
+            bitmap,
+        };
+        let starts = SequenceTrigger::new(
+            DigitalTrigger::start_capturing_when().is_matching(),
+            &DigitalTrigger::start_capturing_when()
+                .bit1(BitState::High)
+                .starts_matching()
+                .unwrap(),
+            Duration::from_millis(1),
+        );
+        // Bit 1 is already high at the trigger and never rises
+        assert!(!starts.matches(&[sample(0.0, 0b010), sample(1e-4, 0b010)]));
+        assert!(starts.matches(&[sample(0.0, 0b000), sample(1e-4, 0b010)]));
+        // Rising after the window
+        assert!(!starts.matches(&[sample(0.0, 0b000), sample(2e-3, 0b010)]));
+
+        let stops = SequenceTrigger::new(
+            DigitalTrigger::start_capturing_when().is_matching(),
+            &DigitalTrigger::start_capturing_when()
+                .bit1(BitState::High)
+                .stops_matching()
+                .unwrap(),
+            Duration::from_millis(1),
+        );
+        assert!(stops.matches(&[sample(0.0, 0b010), sample(1e-4, 0b000)]));
+        assert!(!stops.matches(&[sample(0.0, 0b000), sample(1e-4, 0b010)]));
+    }
+}
@@ -1,20 +1,140 @@
+// The typestate terminals hand themselves back on errors, so their `Err` variants are as
+// large as the terminal. Errors are rare, boxing the terminal isn't worth it.
+#![allow(clippy::result_large_err)]
+
 use serialport::SerialPort;
-use std::collections::VecDeque;
 use std::io::{ErrorKind, Read, Write};
 use std::time::{Duration, Instant};
 
-const PROMPT: &[u8] = b"> ";
+/// REPL conventions of the firmware running on the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalDialect {
+    /// Bytes the device prints when it is ready for the next command
+    pub prompt: Vec<u8>,
+    /// Terminator appended to every command
+    pub line_ending: String,
+    /// Commands sent once after connecting, before the terminal is considered idle
+    pub init_commands: Vec<String>,
+    /// Commands handing the shell back to a human, sent by `IdleFleaScope::teardown`
+    pub teardown_commands: Vec<String>,
+    /// Text printed by the firmware on boot. Seeing it mid-command means the device rebooted.
+    pub boot_banner: Option<Vec<u8>>,
+}
+
+impl Default for TerminalDialect {
+    fn default() -> Self {
+        Self {
+            prompt: b"> ".to_vec(),
+            line_ending: "\n".to_string(),
+            init_commands: vec!["prompt on".to_string()],
+            teardown_commands: vec!["echo on".to_string(), "prompt on".to_string()],
+            boot_banner: Some(b"Welcome to".to_vec()),
+        }
+    }
+}
+
+impl TerminalDialect {
+    /// The default dialect, but terminating commands with `\r\n` for firmware builds that
+    /// require strict CRLF line endings
+    pub fn crlf() -> Self {
+        Self {
+            line_ending: "\r\n".to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Number of CTRL-C attempts before `BusyFleaTerminal::cancel` gives up
+const CANCEL_ATTEMPTS: u32 = 3;
+const CANCEL_ATTEMPT_TIMEOUT: Duration = Duration::from_millis(500);
+/// Time the device needs to reboot after a `reset`
+const RESET_DURATION: Duration = Duration::from_secs(2);
+const HARD_RESET_PULSE: Duration = Duration::from_millis(100);
+/// Time the device gets to switch its baud rate before we follow
+const BAUD_SWITCH_DELAY: Duration = Duration::from_millis(50);
+const BAUD_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Default size of a single read from the serial port
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Counters for the traffic over the serial link
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportStats {
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    /// Number of non-empty reads from the serial port
+    pub chunks_read: u64,
+    pub largest_chunk: usize,
+    /// Number of commands that ran until the prompt came back
+    pub commands: u64,
+    pub total_round_trip: Duration,
+    pub max_round_trip: Duration,
+    pub last_round_trip: Option<Duration>,
+}
+
+impl TransportStats {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean_chunk_size(&self) -> Option<f64> {
+        (self.chunks_read > 0).then(|| self.bytes_read as f64 / self.chunks_read as f64)
+    }
+
+    pub fn mean_round_trip(&self) -> Option<Duration> {
+        (self.commands > 0).then(|| self.total_round_trip / self.commands as u32)
+    }
+
+    fn record_chunk(&mut self, bytes: usize) {
+        if bytes > 0 {
+            self.bytes_read += bytes as u64;
+            self.chunks_read += 1;
+            self.largest_chunk = self.largest_chunk.max(bytes);
+        }
+    }
+
+    fn record_round_trip(&mut self, commands: usize, round_trip: Duration) {
+        self.commands += commands as u64;
+        self.total_round_trip += round_trip;
+        self.max_round_trip = self.max_round_trip.max(round_trip);
+        self.last_round_trip = Some(round_trip);
+    }
+}
 
 #[derive(Debug)]
 pub struct StatelessFleaTerminal {
     serial: Box<dyn SerialPort>,
+    dialect: TerminalDialect,
+    stats: Box<TransportStats>,
+    /// Reused for every read, its length is the chunk size
+    read_buffer: Vec<u8>,
 }
 
 pub struct IdleFleaTerminal {
     inner: StatelessFleaTerminal,
 }
 
-pub struct ConnectionLostError;
+/// Why a running command ended without its response
+#[derive(Debug, thiserror::Error)]
+pub enum ReadInterrupted {
+    #[error("Connection lost")]
+    ConnectionLost,
+
+    /// The device rebooted mid-command. The terminal has to be initialized again.
+    #[error("Device rebooted")]
+    DeviceRebooted(StatelessFleaTerminal),
+}
+
+/// Failure of a single read while waiting for a response
+enum ChunkError {
+    ConnectionLost,
+    DeviceRebooted,
+}
+
+impl From<ChunkError> for FleaTerminalError {
+    fn from(e: ChunkError) -> Self {
+        match e {
+            ChunkError::ConnectionLost => Self::ConnectionLost,
+            ChunkError::DeviceRebooted => Self::DeviceRebooted,
+        }
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum FleaTerminalError {
@@ -29,18 +149,47 @@ pub enum FleaTerminalError {
 
     #[error("Connection lost while waiting for response")]
     ConnectionLost,
+
+    #[error("Device rebooted while waiting for response")]
+    DeviceRebooted,
+
+    #[error("The dialect's prompt must not be empty")]
+    EmptyPrompt,
+
+    #[error("Device answers neither at {previous} nor at {target} baud")]
+    BaudRateLost { previous: u32, target: u32 },
 }
 
 impl StatelessFleaTerminal {
     /// Create a new `FleaTerminal` instance
     pub fn new(port: &str) -> Result<Self, FleaTerminalError> {
+        Self::with_dialect(port, TerminalDialect::default())
+    }
+
+    /// Create a new `FleaTerminal` instance talking to firmware with the given REPL conventions
+    pub fn with_dialect(port: &str, dialect: TerminalDialect) -> Result<Self, FleaTerminalError> {
         profiling::scope!("StatelessFleaTerminal::new");
 
         let serial = serialport::new(port, 9600)
             .timeout(Duration::from_millis(70))
             .open()?;
+        Self::from_port(serial, dialect)
+    }
 
-        let mut terminal = Self { serial };
+    /// Talk to the device over an already opened port, e.g. a TCP bridge or a simulator
+    pub fn from_port(
+        serial: Box<dyn SerialPort>,
+        dialect: TerminalDialect,
+    ) -> Result<Self, FleaTerminalError> {
+        if dialect.prompt.is_empty() {
+            return Err(FleaTerminalError::EmptyPrompt);
+        }
+        let mut terminal = Self {
+            serial,
+            dialect,
+            stats: Box::default(),
+            read_buffer: vec![0; DEFAULT_CHUNK_SIZE],
+        };
 
         terminal.flush()?;
         Ok(terminal)
@@ -70,26 +219,29 @@ impl StatelessFleaTerminal {
         Ok(())
     }
 
-    fn read_chunk(&mut self, response: &mut Vec<u8>) -> Result<bool, ConnectionLostError> {
-        let mut read_buffer = [0u8; 1024]; // Read in chunks
+    fn read_chunk(&mut self, response: &mut Vec<u8>) -> Result<bool, ChunkError> {
         profiling::scope!("read_chunk");
-        match self.serial.read(&mut read_buffer) {
+        match self.serial.read(&mut self.read_buffer) {
             Ok(bytes_read) if bytes_read > 0 => {
                 profiling::scope!("process_chunk_data");
 
-                response.extend_from_slice(&read_buffer[..bytes_read]);
+                self.stats.record_chunk(bytes_read);
+                let previous_len = response.len();
+                response.extend_from_slice(&self.read_buffer[..bytes_read]);
 
-                // Check if we have the prompt at the end
-                if response.len() >= PROMPT.len() {
-                    let potential_prompt = &response[response.len() - PROMPT.len()..];
-                    if potential_prompt == PROMPT {
-                        Ok(true)
-                    } else {
-                        Ok(false)
+                if let Some(banner) = &self.dialect.boot_banner {
+                    // The banner may straddle two chunks
+                    let search_from = previous_len.saturating_sub(banner.len());
+                    if response[search_from..]
+                        .windows(banner.len())
+                        .any(|window| window == banner.as_slice())
+                    {
+                        log::warn!("Boot banner received, device rebooted mid-command");
+                        return Err(ChunkError::DeviceRebooted);
                     }
-                } else {
-                    Ok(false)
                 }
+
+                Ok(ends_with_prompt(response, &self.dialect.prompt))
             }
             Ok(_) => {
                 // No data available right now, but no error
@@ -99,8 +251,8 @@ impl StatelessFleaTerminal {
                 // Timeout is expected in non-blocking reads
                 Ok(false)
             }
-            Err(e) if e.kind() == ErrorKind::BrokenPipe => Err(ConnectionLostError),
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(ConnectionLostError),
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Err(ChunkError::ConnectionLost),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(ChunkError::ConnectionLost),
             Err(e) => {
                 tracing::info!("Serial read error (kind: {:?})...{e}", e.kind());
                 panic!("Serial read error: {e}");
@@ -112,15 +264,27 @@ impl StatelessFleaTerminal {
         &mut self,
         command: &str,
         timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, FleaTerminalError> {
+        self.exec_with_progress(command, timeout, &mut |_| {})
+    }
+
+    /// Like `exec_sync`, calling `on_progress` with the number of bytes received so far:
+    /// once with 0 after the command was sent, then after every chunk
+    fn exec_with_progress(
+        &mut self,
+        command: &str,
+        timeout: Option<Duration>,
+        on_progress: &mut dyn FnMut(usize),
     ) -> Result<Vec<u8>, FleaTerminalError> {
         profiling::scope!("exec_sync");
 
         {
             profiling::scope!("serial_write_command");
             // Send command
-            let command_with_newline = format!("{command}\n");
-            self.serial.write_all(command_with_newline.as_bytes())?;
+            let command_with_newline = format!("{command}{}", self.dialect.line_ending);
+            self.write(command_with_newline.as_bytes())?;
         }
+        on_progress(0);
 
         // Read response until prompt
         profiling::scope!("serial_read_response");
@@ -130,10 +294,13 @@ impl StatelessFleaTerminal {
 
         loop {
             profiling::scope!("serial_read_chunk");
-            match self.read_chunk(&mut response) {
-                Ok(true) => break,
-                Ok(false) => {}
-                Err(ConnectionLostError) => return Err(FleaTerminalError::ConnectionLost),
+            let previous_len = response.len();
+            let complete = self.read_chunk(&mut response)?;
+            if response.len() > previous_len {
+                on_progress(response.len());
+            }
+            if complete {
+                break;
             }
             if let Some(t) = timeout {
                 if now.elapsed() >= t {
@@ -142,40 +309,255 @@ impl StatelessFleaTerminal {
             }
         }
 
+        self.stats.record_round_trip(1, now.elapsed());
+
         // Remove the prompt from the end and convert to string
-        let response_without_prompt = &response[..response.len() - PROMPT.len()];
+        let response_without_prompt = &response[..response.len() - self.dialect.prompt.len()];
 
         Ok(response_without_prompt.to_vec())
     }
 
+    /// Write all commands at once and collect one response per command
+    fn exec_many(
+        &mut self,
+        commands: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Vec<u8>>, FleaTerminalError> {
+        Ok(self
+            .exec_many_timed(commands, timeout)?
+            .into_iter()
+            .map(|(response, _)| response)
+            .collect())
+    }
+
+    /// Like `exec_many`, additionally returning when each response was complete
+    fn exec_many_timed(
+        &mut self,
+        commands: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(Vec<u8>, Instant)>, FleaTerminalError> {
+        profiling::scope!("exec_many");
+
+        {
+            profiling::scope!("serial_write_commands");
+            let mut batch = String::new();
+            for command in commands {
+                batch.push_str(command);
+                batch.push_str(&self.dialect.line_ending);
+            }
+            self.write(batch.as_bytes())?;
+        }
+
+        profiling::scope!("serial_read_responses");
+
+        let mut responses = Vec::with_capacity(commands.len());
+        let mut completed = Vec::with_capacity(commands.len());
+        let mut response = Vec::new();
+        let now = Instant::now();
+
+        while responses.len() < commands.len() {
+            profiling::scope!("serial_read_chunk");
+            // The prompt is not necessarily at the end of a chunk, so don't rely on the return value
+            self.read_chunk(&mut response)?;
+            split_responses(&mut response, &self.dialect.prompt, &mut responses);
+            completed.resize(responses.len(), Instant::now());
+            if let Some(t) = timeout {
+                if now.elapsed() >= t {
+                    return Err(FleaTerminalError::Timeout { timeout: t });
+                }
+            }
+        }
+
+        self.stats.record_round_trip(commands.len(), now.elapsed());
+
+        Ok(responses.into_iter().zip(completed).collect())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        self.serial.write_all(bytes)?;
+        self.stats.bytes_written += bytes.len() as u64;
+        Ok(())
+    }
+
+    pub fn transport_stats(&self) -> &TransportStats {
+        &self.stats
+    }
+
+    /// Maximum number of bytes requested per read. Larger chunks mean fewer syscalls
+    /// during bulk transfers.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.read_buffer.resize(chunk_size.max(1), 0);
+    }
+
+    pub fn reset_transport_stats(&mut self) {
+        *self.stats = TransportStats::default();
+    }
+
     /// Send CTRL-C character
     pub fn send_ctrl_c(&mut self) -> Result<(), FleaTerminalError> {
-        self.serial.write_all(&[0x03])?;
+        self.write(&[0x03])?;
         Ok(())
     }
 
     /// Send reset command
     pub fn send_reset(&mut self) -> Result<(), FleaTerminalError> {
-        self.serial.write_all(b"reset\n")?;
+        let reset = format!("reset{}", self.dialect.line_ending);
+        self.write(reset.as_bytes())?;
         Ok(())
     }
+
+    /// Ask the device to switch to `target` baud and follow it.
+    ///
+    /// Returns the baud rate in effect afterwards: `target` on success, or the previous rate
+    /// if the firmware didn't answer at the new one. Only returns once the device answered
+    /// at that rate.
+    fn negotiate_baud(&mut self, target: u32) -> Result<u32, FleaTerminalError> {
+        profiling::scope!("negotiate_baud");
+
+        let previous = self.serial.baud_rate()?;
+        if previous == target {
+            return Ok(target);
+        }
+
+        log::debug!("Switching baud rate from {previous} to {target}");
+        let command = format!("baud {target}{}", self.dialect.line_ending);
+        self.write(command.as_bytes())?;
+        self.serial.flush()?;
+        std::thread::sleep(BAUD_SWITCH_DELAY);
+
+        self.serial.set_baud_rate(target)?;
+        self.flush()?;
+        if self.exec_sync("", Some(BAUD_PROBE_TIMEOUT)).is_ok() {
+            return Ok(target);
+        }
+
+        // Either the firmware ignored the command or the device switched and the probe got
+        // lost. Only settle on a rate the device answers at, so the two don't end up apart.
+        log::debug!("No prompt at {target} baud, falling back to {previous}");
+        for rate in [previous, target] {
+            self.serial.set_baud_rate(rate)?;
+            self.flush()?;
+            if self.exec_sync("", Some(BAUD_PROBE_TIMEOUT)).is_ok() {
+                return Ok(rate);
+            }
+        }
+        self.serial.set_baud_rate(previous)?;
+        Err(FleaTerminalError::BaudRateLost { previous, target })
+    }
+
+    /// Reboot the MCU by pulsing DTR/RTS, for firmware that no longer answers CTRL-C or `reset`
+    pub fn hard_reset(&mut self) -> Result<(), FleaTerminalError> {
+        log::debug!("Pulsing DTR/RTS to reset the device");
+        self.serial.write_data_terminal_ready(false)?;
+        self.serial.write_request_to_send(true)?;
+        std::thread::sleep(HARD_RESET_PULSE);
+        self.serial.write_request_to_send(false)?;
+        self.serial.write_data_terminal_ready(true)?;
+        Ok(())
+    }
+
+    /// Read one chunk, keeping only the last few bytes in `tail`.
+    /// Returns whether they are the prompt.
+    fn read_prompt_tail(&mut self, tail: &mut Vec<u8>) -> Result<bool, FleaTerminalError> {
+        match self.serial.read(&mut self.read_buffer) {
+            Ok(bytes_read) => {
+                self.stats.record_chunk(bytes_read);
+                tail.extend_from_slice(&self.read_buffer[..bytes_read]);
+                // Only the last few bytes matter for finding the prompt
+                tail.drain(..tail.len().saturating_sub(self.dialect.prompt.len()));
+                Ok(tail.ends_with(&self.dialect.prompt))
+            }
+            Err(e) if e.kind() == ErrorKind::TimedOut => Ok(false), // Timeout is expected in non-blocking reads
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Run the connection handshake, e.g. after the device rebooted
+    pub fn initialize(self) -> Result<IdleFleaTerminal, (Self, FleaTerminalError)> {
+        self.try_into()
+    }
+
+    pub fn dialect(&self) -> &TerminalDialect {
+        &self.dialect
+    }
 }
 
 impl IdleFleaTerminal {
-    pub fn exec_async(mut self, command: &str) -> BusyFleaTerminal {
+    /// Send `command` without waiting for its response.
+    /// If it can't be sent, e.g. because the cable was unplugged, the terminal is handed back.
+    pub fn exec_async(self, command: &str) -> Result<BusyFleaTerminal, (Self, FleaTerminalError)> {
+        self.exec_async_into(command, Vec::new())
+    }
+
+    /// Like `exec_async`, but collects the response into `buffer`, e.g. the data of a
+    /// previous response, to avoid reallocating in tight loops
+    pub fn exec_async_into(
+        mut self,
+        command: &str,
+        mut buffer: Vec<u8>,
+    ) -> Result<BusyFleaTerminal, (Self, FleaTerminalError)> {
         profiling::scope!("IdleFleaTerminal::exec_async");
 
-        let command_with_newline = format!("{command}\n");
-        self.inner
-            .serial
-            .write_all(command_with_newline.as_bytes())
-            .expect("Failed to write command to serial port");
+        let command_with_newline = format!("{command}{}", self.inner.dialect.line_ending);
+        if let Err(e) = self.inner.write(command_with_newline.as_bytes()) {
+            return Err((self, e.into()));
+        }
 
-        BusyFleaTerminal {
+        buffer.clear();
+        Ok(BusyFleaTerminal {
             inner: self.inner,
-            response: Vec::new(),
-        }
+            response: buffer,
+            started: Instant::now(),
+        })
     }
+
+    /// Switch the device and the port to a faster baud rate, falling back to the current
+    /// rate if the firmware doesn't support it. Returns the rate in effect afterwards.
+    pub fn negotiate_baud(&mut self, target: u32) -> Result<u32, FleaTerminalError> {
+        self.inner.negotiate_baud(target)
+    }
+
+    pub fn baud_rate(&self) -> Result<u32, FleaTerminalError> {
+        Ok(self.inner.serial.baud_rate()?)
+    }
+
+    pub fn transport_stats(&self) -> &TransportStats {
+        self.inner.transport_stats()
+    }
+
+    pub fn dialect(&self) -> &TerminalDialect {
+        self.inner.dialect()
+    }
+
+    pub fn reset_transport_stats(&mut self) {
+        self.inner.reset_transport_stats();
+    }
+
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.inner.set_chunk_size(chunk_size);
+    }
+
+    /// Like `exec_sync`, but returns errors (e.g. a timeout) instead of panicking
+    pub fn try_exec_sync(
+        &mut self,
+        command: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, FleaTerminalError> {
+        self.inner.exec_sync(command, timeout)
+    }
+
+    /// Like `try_exec_sync`, reporting the number of response bytes received so far to
+    /// `on_progress`: 0 once the command is sent, then after every chunk
+    pub fn try_exec_with_progress(
+        &mut self,
+        command: &str,
+        timeout: Option<Duration>,
+        mut on_progress: impl FnMut(usize),
+    ) -> Result<Vec<u8>, FleaTerminalError> {
+        self.inner
+            .exec_with_progress(command, timeout, &mut on_progress)
+    }
+
     pub fn exec_sync(&mut self, command: &str, timeout: Option<Duration>) -> Vec<u8> {
         profiling::scope!("IdleFleaTerminal::exec_sync");
 
@@ -183,6 +565,95 @@ impl IdleFleaTerminal {
             .exec_sync(command, timeout)
             .expect("Failed to execute command")
     }
+
+    /// Pipelined variant of `exec_sync`, saving a round-trip per command
+    pub fn exec_many(&mut self, commands: &[&str], timeout: Option<Duration>) -> Vec<Vec<u8>> {
+        profiling::scope!("IdleFleaTerminal::exec_many");
+
+        self.inner
+            .exec_many(commands, timeout)
+            .expect("Failed to execute commands")
+    }
+
+    #[cfg(feature = "dataframe")]
+    pub(crate) fn exec_many_timed(
+        &mut self,
+        commands: &[&str],
+        timeout: Option<Duration>,
+    ) -> Vec<(Vec<u8>, Instant)> {
+        self.inner
+            .exec_many_timed(commands, timeout)
+            .expect("Failed to execute commands")
+    }
+
+    /// Hand the device's shell over to an interactive console
+    pub fn into_raw_repl(self) -> RawRepl {
+        RawRepl {
+            tail: self.inner.dialect.prompt.clone(),
+            inner: self.inner,
+        }
+    }
+}
+
+/// Passthrough to the device's shell, e.g. for an embedded console running `dim`, `print`
+/// or custom scripts.
+///
+/// The output is handed out unmodified, but the prompt is still tracked so the terminal
+/// can be turned back into an `IdleFleaTerminal` with `into_idle()`.
+pub struct RawRepl {
+    inner: StatelessFleaTerminal,
+    /// Last bytes received, long enough to spot the prompt
+    tail: Vec<u8>,
+}
+
+impl RawRepl {
+    /// Send a line of input, the dialect's line ending is appended
+    pub fn send_line(&mut self, line: &str) -> Result<(), FleaTerminalError> {
+        let line = format!("{line}{}", self.inner.dialect.line_ending);
+        self.inner.write(line.as_bytes())?;
+        self.tail.clear();
+        Ok(())
+    }
+
+    /// Output received since the last poll. Empty if nothing arrived within the port timeout.
+    pub fn poll_output(&mut self) -> Result<Vec<u8>, FleaTerminalError> {
+        profiling::scope!("RawRepl::poll_output");
+
+        let mut read_buffer = [0u8; 1024];
+        match self.inner.serial.read(&mut read_buffer) {
+            Ok(bytes_read) => {
+                self.inner.stats.record_chunk(bytes_read);
+                let output = &read_buffer[..bytes_read];
+                self.tail.extend_from_slice(output);
+                let prompt_len = self.inner.dialect.prompt.len();
+                self.tail
+                    .drain(..self.tail.len().saturating_sub(prompt_len));
+                Ok(output.to_vec())
+            }
+            Err(e) if e.kind() == ErrorKind::TimedOut => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the device is waiting for input
+    pub fn is_at_prompt(&self) -> bool {
+        self.tail.ends_with(&self.inner.dialect.prompt)
+    }
+
+    /// Leave the console. If a command is still running it is interrupted like
+    /// `BusyFleaTerminal::cancel`.
+    pub fn into_idle(self) -> Result<IdleFleaTerminal, (FaultedFleaTerminal, FleaTerminalError)> {
+        if self.is_at_prompt() {
+            return Ok(IdleFleaTerminal { inner: self.inner });
+        }
+
+        BusyFleaTerminal {
+            inner: self.inner,
+            response: Vec::new(),
+            started: Instant::now(),
+        }
+        .cancel()
+    }
 }
 impl TryFrom<StatelessFleaTerminal> for IdleFleaTerminal {
     type Error = (StatelessFleaTerminal, FleaTerminalError);
@@ -199,9 +670,11 @@ impl TryFrom<StatelessFleaTerminal> for IdleFleaTerminal {
             return Err((value, e));
         }
 
-        log::debug!("Turning on prompt");
-        if let Err(e) = value.exec_sync("prompt on", Some(Duration::from_secs(1))) {
-            return Err((value, e));
+        for command in value.dialect.init_commands.clone() {
+            log::debug!("Sending init command {command:?}");
+            if let Err(e) = value.exec_sync(&command, Some(Duration::from_secs(1))) {
+                return Err((value, e));
+            }
         }
 
         if let Err(e) = value.flush() {
@@ -211,58 +684,153 @@ impl TryFrom<StatelessFleaTerminal> for IdleFleaTerminal {
     }
 }
 
+/// A terminal in an unknown state after a failed operation.
+///
+/// The device may still be busy or may have hung; use `reinitialize()` to retry the
+/// connection handshake or `reset()` to reboot the device first.
+#[derive(Debug)]
+pub struct FaultedFleaTerminal {
+    inner: StatelessFleaTerminal,
+}
+
+impl FaultedFleaTerminal {
+    /// Run the connection handshake again
+    pub fn reinitialize(self) -> Result<IdleFleaTerminal, (Self, FleaTerminalError)> {
+        profiling::scope!("FaultedFleaTerminal::reinitialize");
+
+        IdleFleaTerminal::try_from(self.inner).map_err(|(inner, e)| (Self { inner }, e))
+    }
+
+    /// Reboot the device, wait for it to come back and run the connection handshake
+    pub fn reset(mut self) -> Result<IdleFleaTerminal, (Self, FleaTerminalError)> {
+        profiling::scope!("FaultedFleaTerminal::reset");
+
+        if let Err(e) = self.inner.send_reset() {
+            return Err((self, e));
+        }
+        self.reinitialize_after_reset()
+    }
+
+    /// Like `reset()`, but reboots the MCU via DTR/RTS for firmware that doesn't respond at all
+    pub fn hard_reset(mut self) -> Result<IdleFleaTerminal, (Self, FleaTerminalError)> {
+        profiling::scope!("FaultedFleaTerminal::hard_reset");
+
+        if let Err(e) = self.inner.hard_reset() {
+            return Err((self, e));
+        }
+        self.reinitialize_after_reset()
+    }
+
+    fn reinitialize_after_reset(mut self) -> Result<IdleFleaTerminal, (Self, FleaTerminalError)> {
+        std::thread::sleep(RESET_DURATION);
+        if let Err(e) = self.inner.flush() {
+            return Err((self, e));
+        }
+        self.reinitialize()
+    }
+
+    pub fn into_stateless(self) -> StatelessFleaTerminal {
+        self.inner
+    }
+}
+
 pub struct BusyFleaTerminal {
     inner: StatelessFleaTerminal,
     response: Vec<u8>,
+    started: Instant,
 }
 
 impl BusyFleaTerminal {
-    pub fn cancel(mut self) -> IdleFleaTerminal {
-        self.inner.send_ctrl_c().expect("Failed to send CTRL-C");
-        const PROMPT_LEN: usize = PROMPT.len();
-        const BUFFER_LEN: usize = 1024;
-        let mut prompt_buffer = VecDeque::with_capacity(PROMPT_LEN);
-        let mut read_buffer = [0u8; BUFFER_LEN];
-        loop {
-            match self.inner.serial.read(&mut read_buffer) {
-                Ok(bytes_read) if bytes_read >= PROMPT_LEN => {
-                    prompt_buffer =
-                        VecDeque::from(read_buffer[bytes_read - PROMPT_LEN..bytes_read].to_vec());
-                }
-                Ok(bytes_read) if bytes_read > 0 => {
-                    for _i in 0..bytes_read {
-                        prompt_buffer.pop_front();
-                    }
-                    prompt_buffer.extend(&read_buffer[..bytes_read]);
-                }
-                Ok(_) => continue, // No data available right now, but no error
-                Err(e) if e.kind() == ErrorKind::TimedOut => continue, // Timeout is expected in non-blocking reads
-                Err(e) => panic!("Serial read error: {e}"),
+    /// Interrupt the running command with CTRL-C and wait for the prompt.
+    ///
+    /// CTRL-C is resent a few times if the prompt does not show up. If the device still
+    /// doesn't answer, or the serial port fails, the terminal is handed back as
+    /// `FaultedFleaTerminal` for recovery.
+    pub fn cancel(mut self) -> Result<IdleFleaTerminal, (FaultedFleaTerminal, FleaTerminalError)> {
+        profiling::scope!("BusyFleaTerminal::cancel");
+
+        match self.cancel_with_retries() {
+            Ok(()) => Ok(IdleFleaTerminal { inner: self.inner }),
+            Err(e) => Err((FaultedFleaTerminal { inner: self.inner }, e)),
+        }
+    }
+
+    fn cancel_with_retries(&mut self) -> Result<(), FleaTerminalError> {
+        for attempt in 1..=CANCEL_ATTEMPTS {
+            self.inner.send_ctrl_c()?;
+            if self.wait_for_prompt(CANCEL_ATTEMPT_TIMEOUT)? {
+                self.inner.flush()?;
+                return Ok(());
             }
-            // Check if we have the prompt at the end
-            if prompt_buffer.len() == PROMPT.len()
-                && prompt_buffer.iter().copied().eq(PROMPT.iter().copied())
-            {
-                break;
+            log::debug!("No prompt after CTRL-C (attempt {attempt}/{CANCEL_ATTEMPTS})");
+        }
+
+        Err(FleaTerminalError::Timeout {
+            timeout: CANCEL_ATTEMPT_TIMEOUT * CANCEL_ATTEMPTS,
+        })
+    }
+
+    /// Discard incoming data until the prompt arrives. Returns `false` on timeout.
+    fn wait_for_prompt(&mut self, timeout: Duration) -> Result<bool, FleaTerminalError> {
+        let mut tail = Vec::new();
+        let now = Instant::now();
+
+        while now.elapsed() < timeout {
+            if self.inner.read_prompt_tail(&mut tail)? {
+                return Ok(true);
             }
         }
-        self.inner.flush().expect("Failed to flush serial port");
-        IdleFleaTerminal { inner: self.inner }
+
+        Ok(false)
     }
 
-    fn into_result(self) -> (Vec<u8>, IdleFleaTerminal) {
+    /// Start interrupting the running command with CTRL-C without waiting for the prompt.
+    ///
+    /// Poll the returned terminal with `CancellingFleaTerminal::try_finish`, e.g. from a
+    /// GUI event loop.
+    pub fn cancel_async(
+        mut self,
+    ) -> Result<CancellingFleaTerminal, (FaultedFleaTerminal, FleaTerminalError)> {
+        profiling::scope!("BusyFleaTerminal::cancel_async");
+
+        if let Err(e) = self.inner.send_ctrl_c() {
+            return Err((FaultedFleaTerminal { inner: self.inner }, e));
+        }
+        Ok(CancellingFleaTerminal {
+            inner: self.inner,
+            tail: Vec::new(),
+            attempt: 1,
+            attempt_started: Instant::now(),
+        })
+    }
+
+    /// Number of response bytes received so far, e.g. for a transfer progress bar
+    pub fn progress(&self) -> usize {
+        self.response.len()
+    }
+
+    /// The response received so far, e.g. to parse complete lines before the prompt arrives
+    pub(crate) fn response(&self) -> &[u8] {
+        &self.response
+    }
+
+    fn into_result(mut self) -> (Vec<u8>, IdleFleaTerminal) {
         profiling::scope!("BusyFleaTerminal::into_result");
 
-        // Remove the prompt from the end and convert to string
-        let response_without_prompt = &self.response[..self.response.len() - PROMPT.len()];
-        let response_str = response_without_prompt.to_vec();
+        self.inner
+            .stats
+            .record_round_trip(1, self.started.elapsed());
+
+        // Remove the prompt from the end, keeping the buffer's allocation
+        self.response
+            .truncate(self.response.len() - self.inner.dialect.prompt.len());
 
-        (response_str, IdleFleaTerminal { inner: self.inner })
+        (self.response, IdleFleaTerminal { inner: self.inner })
     }
 
     pub fn try_get_result(
         mut self,
-    ) -> Result<Result<(Vec<u8>, IdleFleaTerminal), Self>, ConnectionLostError> {
+    ) -> Result<Result<(Vec<u8>, IdleFleaTerminal), Self>, ReadInterrupted> {
         profiling::scope!("BusyFleaTerminal::try_get_result");
 
         // There are 24000 bytes tranferred right now which takes 24ms at 1 MB/s
@@ -276,19 +844,309 @@ impl BusyFleaTerminal {
         // - Fix whatever takes increasing amount of time on the device
         // - Improve transfer speed by • encoding as bytes, • drop digital channels?
         // - Live sending of data. Seems like data is way faster than data transfer
+        // Complete lines are parsed while waiting, see `ReadingFleaScope::try_get_result`
 
         match self.inner.read_chunk(&mut self.response) {
             Ok(true) => Ok(Ok(self.into_result())),
             Ok(false) => Ok(Err(self)),
-            Err(ConnectionLostError) => Err(ConnectionLostError),
+            Err(ChunkError::ConnectionLost) => Err(ReadInterrupted::ConnectionLost),
+            Err(ChunkError::DeviceRebooted) => Err(ReadInterrupted::DeviceRebooted(self.inner)),
         }
     }
 }
 
+/// A command being interrupted by `BusyFleaTerminal::cancel_async`
+pub struct CancellingFleaTerminal {
+    inner: StatelessFleaTerminal,
+    tail: Vec<u8>,
+    attempt: u32,
+    attempt_started: Instant,
+}
+
+impl CancellingFleaTerminal {
+    /// Check once whether the prompt has returned, resending CTRL-C as `cancel()` would.
+    /// Blocks for at most the serial port's read timeout.
+    pub fn try_finish(
+        mut self,
+    ) -> Result<Result<IdleFleaTerminal, Self>, (FaultedFleaTerminal, FleaTerminalError)> {
+        profiling::scope!("CancellingFleaTerminal::try_finish");
+
+        match self.poll() {
+            Ok(true) => Ok(Ok(IdleFleaTerminal { inner: self.inner })),
+            Ok(false) => Ok(Err(self)),
+            Err(e) => Err((FaultedFleaTerminal { inner: self.inner }, e)),
+        }
+    }
+
+    fn poll(&mut self) -> Result<bool, FleaTerminalError> {
+        if self.inner.read_prompt_tail(&mut self.tail)? {
+            self.inner.flush()?;
+            return Ok(true);
+        }
+
+        if self.attempt_started.elapsed() >= CANCEL_ATTEMPT_TIMEOUT {
+            if self.attempt >= CANCEL_ATTEMPTS {
+                return Err(FleaTerminalError::Timeout {
+                    timeout: CANCEL_ATTEMPT_TIMEOUT * CANCEL_ATTEMPTS,
+                });
+            }
+            log::debug!(
+                "No prompt after CTRL-C (attempt {}/{CANCEL_ATTEMPTS})",
+                self.attempt
+            );
+            self.inner.send_ctrl_c()?;
+            self.attempt += 1;
+            self.attempt_started = Instant::now();
+        }
+        Ok(false)
+    }
+}
+
 impl Read for BusyFleaTerminal {
     fn read(&mut self, buffer: &mut [u8]) -> Result<usize, std::io::Error> {
         profiling::scope!("BusyFleaTerminal::read");
 
-        self.inner.serial.read(buffer)
+        let bytes_read = self.inner.serial.read(buffer)?;
+        self.inner.stats.record_chunk(bytes_read);
+        Ok(bytes_read)
+    }
+}
+
+/// Whether the prompt starts at `start` in `buffer`.
+///
+/// Only a prompt at the start of a line ends a response, so output that happens to
+/// contain the prompt mid-line is kept intact.
+fn is_prompt_at(buffer: &[u8], start: usize, prompt: &[u8]) -> bool {
+    buffer[start..].starts_with(prompt) && (start == 0 || buffer[start - 1] == b'\n')
+}
+
+/// Whether `buffer` is a complete response, ending with the prompt, see `is_prompt_at`
+pub(crate) fn ends_with_prompt(buffer: &[u8], prompt: &[u8]) -> bool {
+    buffer
+        .len()
+        .checked_sub(prompt.len())
+        .is_some_and(|start| is_prompt_at(buffer, start, prompt))
+}
+
+/// Move every prompt-terminated response at the start of `buffer` into `responses`,
+/// see `is_prompt_at`. `prompt` must not be empty.
+pub(crate) fn split_responses(buffer: &mut Vec<u8>, prompt: &[u8], responses: &mut Vec<Vec<u8>>) {
+    debug_assert!(!prompt.is_empty(), "empty prompt");
+    while let Some(end) = (0..=buffer.len().saturating_sub(prompt.len()))
+        .find(|&start| is_prompt_at(buffer, start, prompt))
+    {
+        responses.push(buffer[..end].to_vec());
+        buffer.drain(..end + prompt.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{Fault, SimulatedDevice};
+
+    fn idle_terminal(device: &SimulatedDevice, dialect: TerminalDialect) -> IdleFleaTerminal {
+        StatelessFleaTerminal::from_port(Box::new(device.port()), dialect)
+            .unwrap()
+            .initialize()
+            .map_err(|(_, e)| e)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reboot_banner() {
+        let device = SimulatedDevice::new()
+            .version("1.2.3")
+            .fault("ver", Fault::DelayPrompt(Duration::from_secs(5)));
+        let mut busy = idle_terminal(&device, TerminalDialect::default())
+            .exec_async("ver")
+            .map_err(|(_, e)| e)
+            .unwrap();
+        device.reboot();
+
+        let interrupted = loop {
+            match busy.try_get_result() {
+                Ok(Err(pending)) => busy = pending,
+                Ok(Ok(_)) => break None,
+                Err(e) => break Some(e),
+            }
+        };
+        let Some(ReadInterrupted::DeviceRebooted(rebooted)) = interrupted else {
+            unreachable!("the reboot was not detected");
+        };
+
+        // Back to the handshake, after which the device answers as before
+        let mut idle = rebooted.initialize().map_err(|(_, e)| e).unwrap();
+        assert_eq!(idle.exec_sync("ver", None), b"1.2.3\r\n");
+    }
+
+    #[test]
+    fn test_without_banner() {
+        // A banner in the response of a command is only a reboot if the dialect has one
+        let device = SimulatedDevice::new();
+        let mut idle = idle_terminal(&device, TerminalDialect::default());
+        assert!(matches!(
+            idle.try_exec_sync("reset", None),
+            Err(FleaTerminalError::DeviceRebooted)
+        ));
+
+        let dialect = TerminalDialect {
+            boot_banner: None,
+            ..TerminalDialect::default()
+        };
+        let mut idle = idle_terminal(&SimulatedDevice::new(), dialect);
+        let response = idle.try_exec_sync("reset", None).unwrap();
+        assert!(response.starts_with(b"Welcome to"));
+        assert_eq!(idle.exec_sync("echo off", None), b"");
+    }
+
+    #[test]
+    fn test_raw_repl() {
+        let device = SimulatedDevice::new()
+            .variable("bench_id", 7)
+            .fault("ver", Fault::DelayPrompt(Duration::from_secs(5)));
+        let mut repl = idle_terminal(&device, TerminalDialect::default()).into_raw_repl();
+        assert!(repl.is_at_prompt());
+
+        // Output is passed through unmodified, prompt included
+        repl.send_line("print bench_id").unwrap();
+        let mut output = Vec::new();
+        while !repl.is_at_prompt() {
+            output.extend(repl.poll_output().unwrap());
+        }
+        assert_eq!(output, b"7\r\n> ");
+
+        // Leaving while a command runs interrupts it
+        repl.send_line("ver").unwrap();
+        let started = Instant::now();
+        assert!(!repl.poll_output().unwrap().is_empty());
+        assert!(!repl.is_at_prompt());
+        let mut idle = repl.into_idle().ok().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(idle.exec_sync("print bench_id", None), b"7\r\n");
+    }
+
+    #[test]
+    fn test_cancel_async() {
+        let device = SimulatedDevice::new()
+            .version("1.2.3")
+            .fault("ver", Fault::DelayPrompt(Duration::from_secs(5)));
+        let busy = idle_terminal(&device, TerminalDialect::default())
+            .exec_async("ver")
+            .ok()
+            .unwrap();
+
+        let started = Instant::now();
+        let mut cancelling = busy.cancel_async().ok().unwrap();
+        let mut idle = loop {
+            match cancelling.try_finish().ok().unwrap() {
+                Ok(idle) => break idle,
+                Err(still_cancelling) => cancelling = still_cancelling,
+            }
+        };
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(idle.exec_sync("ver", None), b"1.2.3\r\n");
+
+        // Without the device, the terminal is handed back for recovery
+        let busy = idle.exec_async("ver").ok().unwrap();
+        device.disconnect();
+        assert!(busy.cancel_async().is_err());
+    }
+
+    #[test]
+    fn test_progress() {
+        let device = SimulatedDevice::new().version("FleaScope v1.2.3");
+        let mut idle = idle_terminal(&device, TerminalDialect::default());
+        idle.set_chunk_size(4);
+
+        let mut reports = Vec::new();
+        let response = idle
+            .try_exec_with_progress("ver", None, |bytes| reports.push(bytes))
+            .unwrap();
+        assert_eq!(response, b"FleaScope v1.2.3\r\n");
+        assert_eq!(reports, [0, 4, 8, 12, 16, 20]);
+
+        let mut busy = idle.exec_async("ver").ok().unwrap();
+        assert_eq!(busy.progress(), 0);
+        let mut reports = Vec::new();
+        let response = loop {
+            match busy.try_get_result().ok().unwrap() {
+                Ok((response, _idle)) => break response,
+                Err(still_busy) => busy = still_busy,
+            }
+            reports.push(busy.progress());
+        };
+        assert_eq!(response, b"FleaScope v1.2.3\r\n");
+        assert_eq!(reports, [4, 8, 12, 16]);
+    }
+
+    #[test]
+    fn test_split_responses() {
+        let mut responses = Vec::new();
+        let mut buffer = b"1000\r\n> > 1330\r\n> 20".to_vec();
+
+        split_responses(&mut buffer, b"> ", &mut responses);
+        assert_eq!(
+            responses,
+            vec![b"1000\r\n".to_vec(), Vec::new(), b"1330\r\n".to_vec()]
+        );
+        assert_eq!(buffer, b"20");
+
+        // A prompt in the middle of a line is part of the output
+        buffer.extend_from_slice(b"48 -> 4096\r\n> ");
+        split_responses(&mut buffer, b"> ", &mut responses);
+        assert_eq!(responses.last().unwrap(), b"2048 -> 4096\r\n");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_ends_with_prompt() {
+        assert!(ends_with_prompt(b"> ", b"> "));
+        assert!(ends_with_prompt(b"1000\r\n> ", b"> "));
+        assert!(!ends_with_prompt(b"2048 -> ", b"> "));
+        assert!(!ends_with_prompt(b" ", b"> "));
+    }
+
+    #[test]
+    fn test_transport_stats() {
+        let mut stats = TransportStats::default();
+        assert_eq!(stats.mean_chunk_size(), None);
+        assert_eq!(stats.mean_round_trip(), None);
+
+        stats.record_chunk(1024);
+        stats.record_chunk(0);
+        stats.record_chunk(512);
+        stats.record_round_trip(1, Duration::from_millis(10));
+        stats.record_round_trip(3, Duration::from_millis(30));
+
+        assert_eq!(stats.bytes_read, 1536);
+        assert_eq!(stats.chunks_read, 2);
+        assert_eq!(stats.largest_chunk, 1024);
+        assert_eq!(stats.mean_chunk_size(), Some(768.0));
+        assert_eq!(stats.mean_round_trip(), Some(Duration::from_millis(10)));
+        assert_eq!(stats.max_round_trip, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_negotiate_baud() {
+        use crate::simulator::{Fault, RecordedSession, SimulatedDevice};
+
+        let device = SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let serial = scope.serial_mut();
+        assert_eq!(serial.negotiate_baud(115_200).unwrap(), 115_200);
+        assert_eq!(serial.baud_rate().unwrap(), 115_200);
+
+        // The device switched, but the prompt of the first probe got lost
+        let _device = device.fault("", Fault::DelayPrompt(Duration::from_secs(1)));
+        assert_eq!(serial.negotiate_baud(230_400).unwrap(), 230_400);
+        assert!(!serial.exec_sync("ver", None).is_empty());
+
+        // Firmware without the command stays at its rate
+        let mut session = RecordedSession::new();
+        session.push("baud 460800", "error: unknown command\r\n");
+        let device = SimulatedDevice::new().session(&session);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        assert_eq!(scope.serial_mut().negotiate_baud(460_800).unwrap(), 9600);
     }
 }
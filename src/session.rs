@@ -0,0 +1,235 @@
+use crate::flea_scope::{CaptureConfig, IdleFleaScope, ScopeReading};
+use crate::serial_terminal::{FleaTerminalError, ReadInterrupted};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Could not start capture: {0}")]
+    Start(FleaTerminalError),
+
+    #[error("Could not cancel capture waiting for its trigger: {0}")]
+    CancelFailed(FleaTerminalError),
+
+    #[error("Connection lost while capturing")]
+    ConnectionLost,
+
+    #[error("Device rebooted while capturing")]
+    DeviceRebooted,
+}
+
+/// What happened during a `TriggeredSession`
+#[derive(Debug, Default)]
+pub struct SessionSummary {
+    pub captures: usize,
+    /// Number of times the trigger didn't fire within the per-trigger timeout
+    pub timeouts: usize,
+    /// Whether the session deadline cancelled a capture still waiting for its trigger.
+    /// Such a capture is not counted in `timeouts`.
+    pub cancelled_at_deadline: bool,
+    /// The error that ended the session early, if any
+    pub error: Option<SessionError>,
+    pub elapsed: Duration,
+}
+
+/// Unattended triggered logging with bounded waiting.
+///
+/// Each capture waits at most `trigger_timeout` for its trigger before being cancelled and
+/// rearmed, and the whole session ends at `deadline` at the latest, so a trigger that never
+/// fires can't hang the program.
+///
+/// ```rust,no_run
+/// use fleascope_rs::session::TriggeredSession;
+/// use fleascope_rs::{CaptureConfig, IdleFleaScope};
+/// use std::time::Duration;
+///
+/// let (scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = CaptureConfig::builder().time_frame(Duration::from_millis(1)).build()?;
+/// let (scope, summary) = TriggeredSession::new(config)
+///     .trigger_timeout(Duration::from_secs(10))
+///     .deadline(Duration::from_secs(3600))
+///     .run(scope, |reading| println!("{} bytes", reading.data.len()));
+/// println!("{} captures, {} timeouts", summary.captures, summary.timeouts);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[must_use]
+pub struct TriggeredSession {
+    config: CaptureConfig,
+    trigger_timeout: Duration,
+    deadline: Duration,
+    max_captures: Option<usize>,
+}
+
+impl TriggeredSession {
+    pub fn new(config: CaptureConfig) -> Self {
+        Self {
+            config,
+            trigger_timeout: Duration::from_secs(1),
+            deadline: Duration::MAX,
+            max_captures: None,
+        }
+    }
+
+    /// Maximum time to wait for a single trigger
+    pub fn trigger_timeout(mut self, timeout: Duration) -> Self {
+        self.trigger_timeout = timeout;
+        self
+    }
+
+    /// Maximum duration of the whole session
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn max_captures(mut self, max_captures: usize) -> Self {
+        self.max_captures = Some(max_captures);
+        self
+    }
+
+    /// Capture until the deadline, the capture limit or an error.
+    ///
+    /// The scope is handed back unless the connection was lost or couldn't be recovered.
+    pub fn run(
+        &self,
+        mut scope: IdleFleaScope,
+        mut on_capture: impl FnMut(&ScopeReading),
+    ) -> (Option<IdleFleaScope>, SessionSummary) {
+        profiling::scope!("TriggeredSession::run");
+
+        let started = Instant::now();
+        let session_end = started.checked_add(self.deadline);
+        let mut summary = SessionSummary::default();
+        let mut rearm_at = None;
+
+        let scope = loop {
+            if let Some(rearm_at) = rearm_at {
+                let until = session_end.map_or(rearm_at, |end: Instant| end.min(rearm_at));
+                std::thread::sleep(until.saturating_duration_since(Instant::now()));
+            }
+            let now = Instant::now();
+            if session_end.is_some_and(|end| now >= end)
+                || self.max_captures.is_some_and(|max| summary.captures >= max)
+            {
+                break Some(scope);
+            }
+
+            let mut reading = match scope.read_async_with(&self.config) {
+                Ok(reading) => reading,
+                Err((idle, e)) => {
+                    summary.error = Some(SessionError::Start(e));
+                    break Some(idle);
+                }
+            };
+            let trigger_end = now.checked_add(self.trigger_timeout);
+            let capture_end = [trigger_end, session_end].into_iter().flatten().min();
+
+            scope = loop {
+                match reading.try_get_result() {
+                    Ok(Ok((idle, data))) => {
+                        summary.captures += 1;
+                        rearm_at = Instant::now().checked_add(self.config.holdoff());
+                        on_capture(&data);
+                        break idle;
+                    }
+                    Ok(Err(still_reading)) => {
+                        let now = Instant::now();
+                        if capture_end.is_some_and(|end| now >= end) {
+                            let timed_out = trigger_end.is_some_and(|end| now >= end);
+                            match still_reading.cancel() {
+                                Ok(idle) if timed_out => {
+                                    summary.timeouts += 1;
+                                    break idle;
+                                }
+                                Ok(idle) => {
+                                    summary.cancelled_at_deadline = true;
+                                    break idle;
+                                }
+                                Err((_, e)) => {
+                                    summary.error = Some(SessionError::CancelFailed(e));
+                                    summary.elapsed = started.elapsed();
+                                    return (None, summary);
+                                }
+                            }
+                        }
+                        reading = still_reading;
+                    }
+                    Err(ReadInterrupted::ConnectionLost) => {
+                        summary.error = Some(SessionError::ConnectionLost);
+                        summary.elapsed = started.elapsed();
+                        return (None, summary);
+                    }
+                    Err(ReadInterrupted::DeviceRebooted(_)) => {
+                        summary.error = Some(SessionError::DeviceRebooted);
+                        summary.elapsed = started.elapsed();
+                        return (None, summary);
+                    }
+                }
+            };
+        };
+
+        summary.elapsed = started.elapsed();
+        (scope, summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{Fault, SimulatedDevice};
+
+    fn config() -> CaptureConfig {
+        CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_max_captures() {
+        let device = SimulatedDevice::new();
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let mut readings = 0;
+        let (scope, summary) = TriggeredSession::new(config())
+            .max_captures(3)
+            .run(scope, |_| readings += 1);
+
+        assert!(scope.is_some());
+        assert_eq!((summary.captures, readings), (3, 3));
+        assert_eq!(summary.timeouts, 0);
+        assert!(summary.error.is_none());
+    }
+
+    #[test]
+    fn test_trigger_timeout() {
+        // The first trigger never fires, the rearmed capture completes
+        let device =
+            SimulatedDevice::new().fault("scope", Fault::DelayPrompt(Duration::from_secs(5)));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let (scope, summary) = TriggeredSession::new(config())
+            .trigger_timeout(Duration::from_millis(100))
+            .max_captures(1)
+            .run(scope, |_| {});
+
+        assert!(scope.is_some());
+        assert_eq!((summary.captures, summary.timeouts), (1, 1));
+        assert!(!summary.cancelled_at_deadline);
+        assert!(summary.elapsed < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_deadline_is_not_a_timeout() {
+        let device =
+            SimulatedDevice::new().fault("scope", Fault::DelayPrompt(Duration::from_secs(5)));
+        let (scope, _x1, _x10) = device.connect().unwrap();
+        let (scope, summary) = TriggeredSession::new(config())
+            .trigger_timeout(Duration::from_secs(10))
+            .deadline(Duration::from_millis(100))
+            .run(scope, |_| {});
+
+        assert!(scope.is_some());
+        assert_eq!((summary.captures, summary.timeouts), (0, 0));
+        assert!(summary.cancelled_at_deadline);
+        assert!(summary.error.is_none());
+        assert!(summary.elapsed < Duration::from_secs(5));
+    }
+}
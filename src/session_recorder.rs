@@ -0,0 +1,498 @@
+use crate::capture_frame::CaptureFrame;
+use crate::capture_stream::CaptureStreamError;
+#[cfg(feature = "parquet")]
+use crate::export::parquet::{self, ArchiveError};
+use crate::export::{self, LoadError};
+use crate::flea_scope::{CaptureMetadata, FleaProbe, ScopeReading};
+use crate::sink::{CaptureSink, SinkError};
+use polars::prelude::*;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+const FILE_PREFIX: &str = "capture_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecorderError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not access recording: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Capture stream failed: {0}")]
+    Stream(#[from] CaptureStreamError),
+
+    #[error("Could not load capture: {0}")]
+    Load(#[from] LoadError),
+
+    #[cfg(feature = "parquet")]
+    #[error("Could not access archived capture: {0}")]
+    Archive(#[from] ArchiveError),
+}
+
+impl From<SinkError> for RecorderError {
+    fn from(e: SinkError) -> Self {
+        match e {
+            SinkError::Data(e) => Self::Data(e),
+            SinkError::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+/// File format of the captures stored by a `SessionRecorder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    Csv,
+    /// Smaller and faster to load, and keeps the column types
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl RecordFormat {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// A capture on disk, see `SessionRecorder::stored`. Its metadata is returned by
+/// `export::read_csv` or `export::parquet::read` on `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCapture {
+    pub recorded_at: SystemTime,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+impl StoredCapture {
+    pub fn load(&self) -> Result<DataFrame, RecorderError> {
+        profiling::scope!("StoredCapture::load");
+
+        let df = match self
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            #[cfg(feature = "parquet")]
+            Some("parquet") => parquet::read(&self.path)?.0,
+            _ => export::read_csv(&self.path)?.0,
+        };
+        Ok(df)
+    }
+}
+
+/// Persists a stream of captures to a directory, keeping only the most recent ones.
+///
+/// Each capture is one file named after the time it was recorded, so a recorder opened on
+/// an existing directory picks up where the last one stopped. Retention is by age, relative
+/// to the newest capture, and by total size; the newest capture is always kept.
+///
+/// ```rust,no_run
+/// use fleascope_rs::capture_stream::CaptureStream;
+/// use fleascope_rs::session_recorder::SessionRecorder;
+/// use fleascope_rs::{CaptureConfig, IdleFleaScope};
+/// use std::sync::atomic::AtomicBool;
+/// use std::time::{Duration, SystemTime};
+///
+/// let (scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = CaptureConfig::builder().time_frame(Duration::from_millis(1)).build()?;
+/// let mut recorder = SessionRecorder::open("soak")?.max_age(Duration::from_hours(2));
+/// let stop = AtomicBool::new(false);
+/// recorder.record(CaptureStream::new(scope, &config, Some(&x1)), &stop)?;
+///
+/// let now = SystemTime::now();
+/// for (recorded_at, df) in recorder.query(now - Duration::from_secs(60)..now)? {
+///     println!("{recorded_at:?}: {} samples", df.height());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[must_use]
+pub struct SessionRecorder {
+    directory: PathBuf,
+    format: RecordFormat,
+    max_age: Option<Duration>,
+    max_bytes: Option<u64>,
+    /// Oldest first
+    stored: VecDeque<StoredCapture>,
+}
+
+impl SessionRecorder {
+    /// Record into `directory`, creating it if needed and indexing the captures already in it
+    pub fn open(directory: impl Into<PathBuf>) -> Result<Self, RecorderError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        let mut stored = Vec::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let Some(nanos) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.strip_prefix(FILE_PREFIX)?.parse().ok())
+            else {
+                continue;
+            };
+            stored.push(StoredCapture {
+                recorded_at: SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos),
+                path,
+                bytes: entry.metadata()?.len(),
+            });
+        }
+        stored.sort_by_key(|capture| capture.recorded_at);
+
+        Ok(Self {
+            directory,
+            format: RecordFormat::Csv,
+            max_age: None,
+            max_bytes: None,
+            stored: stored.into(),
+        })
+    }
+
+    pub fn format(mut self, format: RecordFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Drop captures recorded more than `max_age` before the newest one
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Drop the oldest captures while all together take more than `max_bytes`
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Store a capture recorded now
+    pub fn store(&mut self, df: &DataFrame) -> Result<(), RecorderError> {
+        self.store_at(df, SystemTime::now())
+    }
+
+    /// Store a capture recorded at `recorded_at`. The sample rate is taken from its time
+    /// column.
+    pub fn store_at(
+        &mut self,
+        df: &DataFrame,
+        recorded_at: SystemTime,
+    ) -> Result<(), RecorderError> {
+        profiling::scope!("SessionRecorder::store_at");
+
+        let effective_msps = export::sample_rate_msps(df)?;
+        self.write(df, effective_msps, None, recorded_at)
+    }
+
+    /// Store a calibrated reading with its metadata, recorded when it was triggered
+    pub fn store_reading(
+        &mut self,
+        reading: &ScopeReading,
+        probe: &FleaProbe,
+    ) -> Result<(), RecorderError> {
+        profiling::scope!("SessionRecorder::store_reading");
+
+        let df = reading.frame()?.calibrated(probe).collect()?;
+        let recorded_at = reading
+            .metadata
+            .as_ref()
+            .map_or_else(SystemTime::now, |metadata| metadata.triggered_at);
+        self.write(
+            &df,
+            reading.effective_msps,
+            reading.metadata.as_ref(),
+            recorded_at,
+        )
+    }
+
+    fn write(
+        &mut self,
+        df: &DataFrame,
+        effective_msps: f64,
+        metadata: Option<&CaptureMetadata>,
+        recorded_at: SystemTime,
+    ) -> Result<(), RecorderError> {
+        let nanos = recorded_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = self.directory.join(format!(
+            "{FILE_PREFIX}{nanos:020}.{}",
+            self.format.extension()
+        ));
+        match self.format {
+            RecordFormat::Csv => export::write_csv(df, &path, effective_msps, metadata)?,
+            // The time column already holds the sample rate
+            #[cfg(feature = "parquet")]
+            RecordFormat::Parquet => parquet::write(df, &path, metadata)?,
+        }
+        let capture = StoredCapture {
+            recorded_at,
+            bytes: std::fs::metadata(&path)?.len(),
+            path,
+        };
+        // A capture recorded at the same time was overwritten
+        self.stored.retain(|stored| stored.path != capture.path);
+        // Captures normally arrive in order, but `store_at` may be given any time
+        let index = self
+            .stored
+            .partition_point(|stored| stored.recorded_at <= recorded_at);
+        self.stored.insert(index, capture);
+        self.evict()
+    }
+
+    fn evict(&mut self) -> Result<(), RecorderError> {
+        let Some(newest) = self.stored.back().map(|capture| capture.recorded_at) else {
+            return Ok(());
+        };
+        let mut total: u64 = self.stored.iter().map(|capture| capture.bytes).sum();
+        while self.stored.len() > 1 {
+            let oldest = &self.stored[0];
+            let too_old = self.max_age.is_some_and(|max_age| {
+                newest
+                    .duration_since(oldest.recorded_at)
+                    .is_ok_and(|age| age > max_age)
+            });
+            let too_big = self.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+            if !too_old && !too_big {
+                break;
+            }
+            total -= oldest.bytes;
+            std::fs::remove_file(&oldest.path)?;
+            self.stored.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Store every capture of `stream`, e.g. a `CaptureStream`, until it ends, fails or
+    /// `stop` is set. `stop` is checked before waiting for the next capture, so a capture
+    /// that arrives while it is set is still stored. Returns the number of captures stored.
+    pub fn record<I>(&mut self, stream: I, stop: &AtomicBool) -> Result<usize, RecorderError>
+    where
+        I: IntoIterator<Item = Result<DataFrame, CaptureStreamError>>,
+    {
+        profiling::scope!("SessionRecorder::record");
+
+        let mut stream = stream.into_iter();
+        let mut count = 0;
+        while !stop.load(Ordering::Relaxed) {
+            let Some(df) = stream.next() else {
+                break;
+            };
+            self.store(&df?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Captures currently kept, oldest first
+    pub fn stored(&self) -> impl Iterator<Item = &StoredCapture> {
+        self.stored.iter()
+    }
+
+    /// Total size of the captures currently kept
+    pub fn bytes(&self) -> u64 {
+        self.stored.iter().map(|capture| capture.bytes).sum()
+    }
+
+    /// Load the captures recorded within `range`, oldest first
+    pub fn query(
+        &self,
+        range: Range<SystemTime>,
+    ) -> Result<Vec<(SystemTime, DataFrame)>, RecorderError> {
+        profiling::scope!("SessionRecorder::query");
+
+        self.stored
+            .iter()
+            .filter(|capture| range.contains(&capture.recorded_at))
+            .map(|capture| Ok((capture.recorded_at, capture.load()?)))
+            .collect()
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+}
+
+impl CaptureSink for SessionRecorder {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        match self.store(&frame.clone().collect()?) {
+            Ok(()) => Ok(()),
+            Err(RecorderError::Data(e)) => Err(SinkError::Data(e)),
+            Err(RecorderError::Io(e)) => Err(SinkError::Io(e)),
+            Err(e) => Err(SinkError::Io(std::io::Error::other(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("fleascope_recorder_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn capture(value: f64) -> DataFrame {
+        df!(
+            "time" => [0.0, 1e-6],
+            "bnc_calibrated" => [value, value],
+            "bitmap" => [0u32, 5],
+        )
+        .unwrap()
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    #[test]
+    fn test_retention_by_age() {
+        let directory = directory("age");
+        let mut recorder = SessionRecorder::open(&directory)
+            .unwrap()
+            .max_age(Duration::from_mins(1));
+        for secs in [0u32, 30, 61, 90] {
+            recorder
+                .store_at(&capture(f64::from(secs)), at(u64::from(secs)))
+                .unwrap();
+        }
+        let kept: Vec<SystemTime> = recorder
+            .stored()
+            .map(|capture| capture.recorded_at)
+            .collect();
+        assert_eq!(kept, [at(30), at(61), at(90)]);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 3);
+
+        let queried = recorder.query(at(31)..at(90)).unwrap();
+        assert_eq!(queried.len(), 1);
+        assert_eq!(queried[0].0, at(61));
+        let volts = queried[0]
+            .1
+            .column("bnc_calibrated")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0);
+        assert_eq!(volts, Some(61.0));
+        assert!(queried[0].1.equals(&capture(61.0)));
+
+        // Reopening finds the same captures
+        let reopened = SessionRecorder::open(&directory).unwrap();
+        assert_eq!(reopened.stored().count(), 3);
+        assert_eq!(reopened.bytes(), recorder.bytes());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_retention_by_size() {
+        let directory = directory("size");
+        let mut recorder = SessionRecorder::open(&directory).unwrap();
+        recorder.store_at(&capture(1.0), at(0)).unwrap();
+        let size = recorder.bytes();
+
+        let mut recorder = recorder.max_bytes(2 * size);
+        for secs in 1..5 {
+            recorder.store_at(&capture(1.0), at(secs)).unwrap();
+        }
+        let kept: Vec<SystemTime> = recorder
+            .stored()
+            .map(|capture| capture.recorded_at)
+            .collect();
+        assert_eq!(kept, [at(3), at(4)]);
+
+        // The newest capture is kept even if it alone is too big
+        let mut recorder = recorder.max_bytes(0);
+        recorder.store_at(&capture(1.0), at(5)).unwrap();
+        assert_eq!(recorder.stored().count(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_record_stream() {
+        let directory = directory("stream");
+        let mut recorder = SessionRecorder::open(&directory).unwrap();
+        let stream = (0..3).map(|i| Ok(capture(f64::from(i))));
+        let stop = AtomicBool::new(false);
+        assert_eq!(recorder.record(stream, &stop).unwrap(), 3);
+        assert_eq!(recorder.stored().count(), 3);
+
+        let failing = [Ok(capture(0.0)), Err(CaptureStreamError::ConnectionLost)];
+        assert!(matches!(
+            recorder.record(failing, &stop),
+            Err(RecorderError::Stream(CaptureStreamError::ConnectionLost))
+        ));
+
+        // The capture arriving as `stop` is set is kept, and no further one is awaited
+        let mut polled = 0;
+        let stream = std::iter::from_fn(|| {
+            polled += 1;
+            stop.store(true, Ordering::Relaxed);
+            Some(Ok(capture(1.0)))
+        });
+        assert_eq!(recorder.record(stream, &stop).unwrap(), 1);
+        assert_eq!(polled, 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_store_reading() {
+        let directory = directory("reading");
+        let mut recorder = SessionRecorder::open(&directory).unwrap();
+        let metadata = CaptureMetadata {
+            requested_time_frame: Duration::from_micros(1),
+            actual_time_frame: Duration::from_micros(1),
+            delay_samples: 0,
+            trigger: "+0x01 0x01".to_string(),
+            hostname: "bench".to_string(),
+            triggered_at: at(7),
+        };
+        let reading = ScopeReading {
+            effective_msps: 2.0,
+            metadata: Some(metadata.clone()),
+            parsed: OnceLock::new(),
+            data: b"2048,0x000\n2148,0x005\n".to_vec(),
+        };
+        let mut probe = FleaProbe::new(crate::flea_scope::ProbeType::X1);
+        probe.set_calibration(2048.0, 1000.0);
+        recorder.store_reading(&reading, &probe).unwrap();
+
+        let stored = recorder.stored().next().unwrap();
+        assert_eq!(stored.recorded_at, at(7));
+        let (df, effective_msps, restored) = export::read_csv(&stored.path).unwrap();
+        assert!((effective_msps - 2.0).abs() < f64::EPSILON);
+        assert_eq!(restored, Some(metadata));
+        assert!(df.equals(
+            &reading
+                .frame()
+                .unwrap()
+                .calibrated(&probe)
+                .collect()
+                .unwrap()
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_format() {
+        let directory = directory("parquet");
+        let mut recorder = SessionRecorder::open(&directory)
+            .unwrap()
+            .format(RecordFormat::Parquet);
+        recorder.store_at(&capture(2.5), at(0)).unwrap();
+        let (_, df) = recorder.query(at(0)..at(1)).unwrap().remove(0);
+        assert!(df.equals(&capture(2.5)));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,974 @@
+//! A `FleaScope` without hardware, for integration tests of applications built on this crate.
+//!
+//! Available with the `test-support` feature. `SimulatedDevice` answers the shell commands
+//! this crate sends, generating captures from a signal function, and replays
+//! `RecordedSession` fixtures for everything else. `RecordingPort` records such fixtures
+//! from a real device. `SimulatedDevice::fault` scripts transfer failures to exercise
+//! recovery code.
+//!
+//! ```rust
+//! use fleascope_rs::simulator::SimulatedDevice;
+//! use fleascope_rs::CaptureConfig;
+//! use std::time::Duration;
+//!
+//! let device = SimulatedDevice::new()
+//!     .hostname("bench-3")
+//!     .signal(|t| 2048.0 + 1000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin());
+//! let (mut scope, _x1, _x10) = device.connect()?;
+//! assert_eq!(scope.hostname().trim(), "bench-3");
+//!
+//! let config = CaptureConfig::builder().time_frame(Duration::from_millis(5)).build()?;
+//! let df = scope.read(&config).parse_csv()?.collect()?;
+//! assert_eq!(df.height(), 2000);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::flea_scope::{CapturePlan, FleaProbe, IdleFleaScope};
+use crate::serial_terminal::{
+    split_responses, FleaTerminalError, StatelessFleaTerminal, TerminalDialect,
+};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const CTRL_C: u8 = 0x03;
+const BOOT_BANNER: &str = "Welcome to FleaScope (simulated)";
+
+type Signal = dyn Fn(f64) -> f64 + Send + Sync;
+type Digital = dyn Fn(f64) -> u16 + Send + Sync;
+
+/// Command/response pairs of a terminal session.
+///
+/// The transcript format has one `> command` line per command, followed by the lines of
+/// its response. Lines starting with `#` are comments.
+///
+/// ```text
+/// # bench-3, firmware v1.4
+/// > hostname
+/// bench-3
+/// > print cal_zero_x1
+/// 1012
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordedSession {
+    exchanges: Vec<(String, Vec<u8>)>,
+}
+
+impl RecordedSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: impl Into<String>, response: impl Into<Vec<u8>>) {
+        self.exchanges.push((command.into(), response.into()));
+    }
+
+    pub fn exchanges(&self) -> &[(String, Vec<u8>)] {
+        &self.exchanges
+    }
+
+    pub fn parse(transcript: &str) -> Self {
+        let mut session = Self::new();
+        for line in transcript.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(command) = line.strip_prefix("> ") {
+                session.push(command.trim_end(), Vec::new());
+            } else if let Some((_, response)) = session.exchanges.last_mut() {
+                response.extend_from_slice(line.as_bytes());
+                response.extend_from_slice(b"\r\n");
+            }
+        }
+        session
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn to_transcript(&self) -> String {
+        let mut transcript = String::new();
+        for (command, response) in &self.exchanges {
+            let _ = writeln!(transcript, "> {command}");
+            for line in String::from_utf8_lossy(response).lines() {
+                let _ = writeln!(transcript, "{line}");
+            }
+        }
+        transcript
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_transcript())
+    }
+}
+
+/// A failure injected into a response, see `SimulatedDevice::fault`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Lose every `nth` byte of the response, as an overrun UART would
+    DropBytes { every: NonZeroUsize },
+    /// Send the response right away but hold back the prompt for a while
+    DelayPrompt(Duration),
+    /// Insert a line of garbage after the first `after` lines of the response
+    GarbageLine { after: usize, line: Vec<u8> },
+    /// Send only the first `bytes` bytes of the response, then disconnect
+    DisconnectAfter(usize),
+}
+
+struct DeviceState {
+    hostname: String,
+    version: String,
+    variables: HashMap<String, i32>,
+    signal: Arc<Signal>,
+    digital: Arc<Digital>,
+    /// Recorded responses per command, replayed in order with the last one repeating
+    fixtures: HashMap<String, VecDeque<Vec<u8>>>,
+    prompt: Vec<u8>,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+    commands: Vec<String>,
+    /// Baud rate of the device, see `port_baud_rate`
+    baud_rate: u32,
+    /// Baud rate the port is set to. While it differs from the device's, nothing gets
+    /// through in either direction.
+    port_baud_rate: u32,
+    connected: bool,
+    /// Faults for the next commands with the given name, in order
+    faults: Vec<(String, Fault)>,
+    /// Bytes of `output` readable before the prompt held back by `Fault::DelayPrompt`,
+    /// and when it is released
+    held_prompt: Option<(usize, Instant)>,
+    /// Bytes of `output` readable before `Fault::DisconnectAfter` disconnects
+    disconnect_after: Option<usize>,
+}
+
+impl DeviceState {
+    fn handle_input(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match byte {
+                CTRL_C => {
+                    // Interrupts a command whose prompt is held back
+                    self.held_prompt = None;
+                    self.input.clear();
+                    self.output.extend(&self.prompt);
+                }
+                b'\n' => {
+                    let line = String::from_utf8_lossy(&self.input).trim().to_string();
+                    self.input.clear();
+                    let response = self.respond(&line);
+                    let name = line.split_whitespace().next().unwrap_or_default();
+                    let fault = self
+                        .faults
+                        .iter()
+                        .position(|(command, _)| command == name)
+                        .map(|index| self.faults.remove(index).1);
+                    self.commands.push(line);
+                    self.send(response, fault);
+                }
+                b'\r' => {}
+                _ => self.input.push(byte),
+            }
+        }
+    }
+
+    /// Queue `response` and the prompt for reading, with `fault` applied
+    fn send(&mut self, mut response: Vec<u8>, fault: Option<Fault>) {
+        match fault {
+            None => {}
+            Some(Fault::DropBytes { every }) => {
+                response = response
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| (index + 1) % every.get() != 0)
+                    .map(|(_, byte)| byte)
+                    .collect();
+            }
+            Some(Fault::DelayPrompt(delay)) => {
+                self.output.extend(response);
+                self.held_prompt = Some((self.output.len(), Instant::now() + delay));
+                self.output.extend(&self.prompt);
+                return;
+            }
+            Some(Fault::GarbageLine { after, mut line }) => {
+                let at = after.checked_sub(1).map_or(0, |last| {
+                    response
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, &byte)| byte == b'\n')
+                        .nth(last)
+                        .map_or(response.len(), |(index, _)| index + 1)
+                });
+                line.extend_from_slice(b"\r\n");
+                response.splice(at..at, line);
+            }
+            Some(Fault::DisconnectAfter(bytes)) => {
+                response.truncate(bytes);
+                self.output.extend(response);
+                self.disconnect_after = Some(self.output.len());
+                return;
+            }
+        }
+        self.output.extend(response);
+        self.output.extend(&self.prompt);
+    }
+
+    /// Bytes of `output` that can be read now
+    fn readable(&mut self) -> usize {
+        if let Some((_, release)) = self.held_prompt {
+            if Instant::now() >= release {
+                self.held_prompt = None;
+            }
+        }
+        [
+            self.held_prompt.map(|(held, _)| held),
+            self.disconnect_after,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(self.output.len(), usize::min)
+    }
+
+    /// Account for `n` bytes read from `output`
+    fn consume(&mut self, n: usize) {
+        self.output.drain(..n);
+        if let Some((held, _)) = &mut self.held_prompt {
+            *held = held.saturating_sub(n);
+        }
+        if let Some(remaining) = &mut self.disconnect_after {
+            *remaining = remaining.saturating_sub(n);
+        }
+    }
+
+    fn respond(&mut self, line: &str) -> Vec<u8> {
+        if let Some(responses) = self.fixtures.get_mut(line) {
+            let response = if responses.len() > 1 {
+                responses.pop_front()
+            } else {
+                responses.front().cloned()
+            };
+            if let Some(response) = response {
+                return response;
+            }
+        }
+
+        let mut words = line.split_whitespace();
+        let text = match (words.next(), words.next()) {
+            (None | Some("echo" | "prompt" | "wave"), _) => String::new(),
+            (Some("baud"), Some(rate)) => rate.parse().map_or_else(
+                |_| "error: invalid baud rate\r\n".to_string(),
+                |rate| {
+                    self.baud_rate = rate;
+                    String::new()
+                },
+            ),
+            (Some("ver"), None) => format!("{}\r\n", self.version),
+            (Some("hostname"), None) => format!("{}\r\n", self.hostname),
+            (Some("hostname"), Some(name)) => {
+                self.hostname = name.to_string();
+                String::new()
+            }
+            (Some("reset"), None) => format!("{BOOT_BANNER}\r\n"),
+            (Some("dim"), Some(_)) => self.dim(&line["dim".len()..]),
+            (Some("print"), Some(name)) => self.variables.get(name).map_or_else(
+                || format!("var '{name}' not declared\r\n"),
+                |value| format!("{value}\r\n"),
+            ),
+            (Some("scope"), Some(number1)) => return self.capture(number1),
+            (Some(name), Some("=")) => match (self.variables.get_mut(name), words.next()) {
+                (Some(variable), Some(value)) => value.parse().map_or_else(
+                    |_| "error: invalid value\r\n".to_string(),
+                    |value| {
+                        *variable = value;
+                        String::new()
+                    },
+                ),
+                _ => format!("var '{name}' not declared\r\n"),
+            },
+            _ => format!("error: unknown command {line:?}\r\n"),
+        };
+        text.into_bytes()
+    }
+
+    /// Declare variables, e.g. ` cal_zero_x1 as flash, cal_3v3_x1 as flash`
+    fn dim(&mut self, declarations: &str) -> String {
+        let mut messages = String::new();
+        for declaration in declarations.split(',') {
+            let Some(name) = declaration.split_whitespace().next() else {
+                continue;
+            };
+            if self.variables.contains_key(name) {
+                let _ = write!(messages, "var '{name}' already declared at this scope\r\n");
+            } else {
+                self.variables.insert(name.to_string(), 0);
+            }
+        }
+        messages
+    }
+
+    /// Samples of the signal functions as the `scope` command would print them. The trigger
+    /// and delay are ignored, the simulated capture always starts at `t = 0`.
+    fn capture(&self, number1: &str) -> Vec<u8> {
+        let Ok(Ok(plan)) = number1.parse().map(CapturePlan::from_number1) else {
+            return b"error: invalid time frame\r\n".to_vec();
+        };
+        let sample_period = 1.0 / (plan.effective_msps() * 1_000_000.0);
+        let mut csv = String::with_capacity(plan.total_samples() as usize * 12);
+        for sample in 0..plan.total_samples() {
+            let t = f64::from(sample) * sample_period;
+            let raw = (self.signal)(t).round().clamp(0.0, 4095.0);
+            let _ = write!(csv, "{raw},0x{:03x}\r\n", (self.digital)(t) & 0x3ff);
+        }
+        csv.into_bytes()
+    }
+}
+
+/// Simulated device, see the module documentation
+#[derive(Clone)]
+pub struct SimulatedDevice {
+    state: Arc<Mutex<DeviceState>>,
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(DeviceState {
+                hostname: "FleaScope".to_string(),
+                version: "FleaScope v1.0 (simulated)".to_string(),
+                variables: HashMap::new(),
+                signal: Arc::new(|_| 2048.0),
+                digital: Arc::new(|_| 0),
+                fixtures: HashMap::new(),
+                prompt: TerminalDialect::default().prompt,
+                input: Vec::new(),
+                output: VecDeque::new(),
+                commands: Vec::new(),
+                baud_rate: 9600,
+                port_baud_rate: 9600,
+                connected: true,
+                faults: Vec::new(),
+                held_prompt: None,
+                disconnect_after: None,
+            })),
+        }
+    }
+}
+
+impl SimulatedDevice {
+    /// A device with a constant mid-scale input, no calibration and the default dialect
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DeviceState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[must_use]
+    pub fn hostname(self, hostname: &str) -> Self {
+        self.lock().hostname = hostname.to_string();
+        self
+    }
+
+    /// Response to the `ver` command
+    #[must_use]
+    pub fn version(self, version: &str) -> Self {
+        self.lock().version = version.to_string();
+        self
+    }
+
+    /// Raw ADC value (0 to 4095) of the BNC input at `t` seconds into a capture
+    #[must_use]
+    pub fn signal(self, signal: impl Fn(f64) -> f64 + Send + Sync + 'static) -> Self {
+        self.lock().signal = Arc::new(signal);
+        self
+    }
+
+    /// State of the digital inputs at `t` seconds into a capture, bit 0 is the first input
+    #[must_use]
+    pub fn digital(self, digital: impl Fn(f64) -> u16 + Send + Sync + 'static) -> Self {
+        self.lock().digital = Arc::new(digital);
+        self
+    }
+
+    /// Declare a variable in the simulated flash, e.g. a probe calibration
+    #[must_use]
+    pub fn variable(self, name: &str, value: i32) -> Self {
+        self.lock().variables.insert(name.to_string(), value);
+        self
+    }
+
+    /// Calibrate both probes so that a raw value of `zero` reads 0 V and
+    /// `zero + counts_per_3v3` reads 3.3 V on the x1 probe
+    #[must_use]
+    pub fn calibrated(self, zero: i32, counts_per_3v3: i32) -> Self {
+        self.variable("cal_zero_x1", zero - 2048 + 1000)
+            .variable("cal_3v3_x1", counts_per_3v3 + 1000)
+            .variable("cal_zero_x10", zero - 2048 + 1000)
+            .variable("cal_3v3_x10", counts_per_3v3 * 10 + 1000)
+    }
+
+    /// Inject `fault` into the response to the next `command` with any arguments, e.g.
+    /// `"scope"` for the next capture or `""` for the next empty line. Each fault is used
+    /// once, in the order added.
+    ///
+    /// ```rust
+    /// use fleascope_rs::simulator::{Fault, SimulatedDevice};
+    /// use fleascope_rs::CaptureConfig;
+    /// use std::time::Duration;
+    ///
+    /// let device = SimulatedDevice::new();
+    /// let (mut scope, _x1, _x10) = device.connect()?;
+    /// let _device = device.fault("scope", Fault::GarbageLine { after: 10, line: b"#@!".to_vec() });
+    ///
+    /// let config = CaptureConfig::builder().time_frame(Duration::from_millis(5)).build()?;
+    /// assert!(scope.read(&config).samples().is_err());
+    /// assert!(scope.read(&config).samples().is_ok());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[must_use]
+    pub fn fault(self, command: &str, fault: Fault) -> Self {
+        self.lock().faults.push((command.to_string(), fault));
+        self
+    }
+
+    /// Answer the commands of `session` with their recorded responses. They take precedence
+    /// over the built-in commands.
+    #[must_use]
+    pub fn session(self, session: &RecordedSession) -> Self {
+        {
+            let mut state = self.lock();
+            for (command, response) in &session.exchanges {
+                state
+                    .fixtures
+                    .entry(command.clone())
+                    .or_default()
+                    .push_back(response.clone());
+            }
+        }
+        self
+    }
+
+    /// A port talking to this device. All ports share the device's state.
+    pub fn port(&self) -> SimulatedPort {
+        SimulatedPort {
+            device: self.clone(),
+            timeout: Duration::from_millis(70),
+        }
+    }
+
+    /// Open a terminal and run the usual connection handshake, like `IdleFleaScope::connect`
+    pub fn connect(&self) -> Result<(IdleFleaScope, FleaProbe, FleaProbe), FleaTerminalError> {
+        let terminal =
+            StatelessFleaTerminal::from_port(Box::new(self.port()), TerminalDialect::default())?;
+        let terminal = terminal.initialize().map_err(|(_, e)| e)?;
+        Ok(IdleFleaScope::with_probes(terminal, true))
+    }
+
+    /// Every command received so far, in order
+    pub fn commands(&self) -> Vec<String> {
+        self.lock().commands.clone()
+    }
+
+    /// Simulate unplugging the cable: reads and writes fail from now on
+    pub fn disconnect(&self) {
+        self.lock().connected = false;
+    }
+
+    /// Simulate a spontaneous reboot, printing the boot banner. Output not read yet, like
+    /// the rest of a running command's response, is lost.
+    pub fn reboot(&self) {
+        let mut state = self.lock();
+        state.input.clear();
+        state.output.clear();
+        state.held_prompt = None;
+        state.disconnect_after = None;
+        state.output.extend(format!("{BOOT_BANNER}\r\n").as_bytes());
+        drop(state);
+    }
+}
+
+/// `SerialPort` connected to a `SimulatedDevice`
+pub struct SimulatedPort {
+    device: SimulatedDevice,
+    timeout: Duration,
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(ErrorKind::BrokenPipe, "simulated device disconnected")
+}
+
+impl Read for SimulatedPort {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.device.lock();
+        if state.disconnect_after == Some(0) {
+            state.connected = false;
+            state.disconnect_after = None;
+        }
+        if !state.connected {
+            return Err(disconnected());
+        }
+        if state.port_baud_rate != state.baud_rate {
+            let garbled = state.output.len();
+            state.consume(garbled);
+        }
+        let readable = state.readable();
+        if readable == 0 {
+            let held = state.held_prompt.map(|(_, release)| release);
+            drop(state);
+            // Like a real port, wait out the timeout for the held-back prompt
+            if let Some(release) = held {
+                std::thread::sleep(
+                    release
+                        .saturating_duration_since(Instant::now())
+                        .min(self.timeout),
+                );
+            }
+            return Err(io::Error::new(ErrorKind::TimedOut, "no data"));
+        }
+        let n = buffer.len().min(readable);
+        for (target, byte) in buffer.iter_mut().zip(state.output.range(..n)) {
+            *target = *byte;
+        }
+        state.consume(n);
+        drop(state);
+        Ok(n)
+    }
+}
+
+impl Write for SimulatedPort {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut state = self.device.lock();
+        if !state.connected {
+            return Err(disconnected());
+        }
+        if state.port_baud_rate == state.baud_rate {
+            state.handle_input(bytes);
+        }
+        drop(state);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for SimulatedPort {
+    fn name(&self) -> Option<String> {
+        Some("simulated".to_string())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.device.lock().port_baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.device.lock().port_baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(u32::try_from(self.device.lock().readable()).unwrap_or(u32::MAX))
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        if matches!(buffer_to_clear, ClearBuffer::Input | ClearBuffer::All) {
+            let mut state = self.device.lock();
+            let pending = state.output.len();
+            state.consume(pending);
+            state.held_prompt = None;
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.device.port()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Wraps the port of a real device and records every exchange as a `RecordedSession`
+pub struct RecordingPort {
+    inner: Box<dyn SerialPort>,
+    prompt: Vec<u8>,
+    session: Arc<Mutex<RecordedSession>>,
+    pending_commands: VecDeque<String>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl RecordingPort {
+    pub fn new(inner: Box<dyn SerialPort>, dialect: &TerminalDialect) -> Self {
+        Self {
+            inner,
+            prompt: dialect.prompt.clone(),
+            session: Arc::default(),
+            pending_commands: VecDeque::new(),
+            input: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Handle to the recording, which keeps growing while the port is in use
+    pub fn session(&self) -> Arc<Mutex<RecordedSession>> {
+        Arc::clone(&self.session)
+    }
+}
+
+impl Read for RecordingPort {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buffer)?;
+        self.output.extend_from_slice(&buffer[..n]);
+        let mut responses = Vec::new();
+        split_responses(&mut self.output, &self.prompt, &mut responses);
+        for response in responses {
+            // Prompts without a command, e.g. after CTRL-C, are not part of the session
+            if let Some(command) = self.pending_commands.pop_front() {
+                self.session
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .push(command, response);
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Write for RecordingPort {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(bytes)?;
+        for &byte in &bytes[..n] {
+            match byte {
+                CTRL_C => {
+                    self.input.clear();
+                    self.pending_commands.clear();
+                    self.output.clear();
+                }
+                b'\n' => {
+                    let command = String::from_utf8_lossy(&self.input).trim().to_string();
+                    self.pending_commands.push_back(command);
+                    self.input.clear();
+                }
+                b'\r' => {}
+                _ => self.input.push(byte),
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl SerialPort for RecordingPort {
+    fn name(&self) -> Option<String> {
+        self.inner.name()
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        self.inner.baud_rate()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        self.inner.data_bits()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        self.inner.flow_control()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        self.inner.parity()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        self.inner.stop_bits()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.inner.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.inner.set_baud_rate(baud_rate)
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.inner.set_data_bits(data_bits)
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.inner.set_flow_control(flow_control)
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.inner.set_parity(parity)
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.inner.set_stop_bits(stop_bits)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_request_to_send(level)
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_data_terminal_ready(level)
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.inner.read_clear_to_send()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.inner.read_data_set_ready()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.inner.read_ring_indicator()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.inner.read_carrier_detect()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_write()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        self.inner.clear(buffer_to_clear)
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        self.inner.try_clone()
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.inner.set_break()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.inner.clear_break()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flea_scope::{CaptureConfig, ParseMode, Sample};
+    use crate::serial_terminal::{IdleFleaTerminal, ReadInterrupted};
+
+    #[test]
+    fn test_transcript_round_trip() {
+        let transcript = "# comment\n> hostname\nbench-3\n> dim x as flash\n> print x\n42\n";
+        let session = RecordedSession::parse(transcript);
+        assert_eq!(
+            session.exchanges()[0],
+            ("hostname".to_string(), b"bench-3\r\n".to_vec())
+        );
+        assert_eq!(session.exchanges()[1].1, b"");
+        assert_eq!(
+            session.to_transcript(),
+            "> hostname\nbench-3\n> dim x as flash\n> print x\n42\n"
+        );
+    }
+
+    #[test]
+    fn test_simulated_capture_and_calibration() {
+        let device = SimulatedDevice::new().calibrated(2048, 1000).signal(|t| {
+            if t < 0.005 {
+                2048.0
+            } else {
+                3048.0
+            }
+        });
+        let (mut scope, x1, _x10) = device.connect().unwrap();
+
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let reading = scope.read(&config);
+        let samples = reading.samples().unwrap();
+        let volts = |sample: &Sample| x1.raw_to_volts(sample.raw).unwrap().get();
+        assert_eq!(samples.len(), 2000);
+        assert!(volts(&samples[0]).abs() < 1e-9);
+        assert!((volts(&samples[1999]) - 3.3).abs() < 1e-9);
+        assert!(device.commands().iter().any(|c| c.starts_with("scope ")));
+    }
+
+    #[test]
+    fn test_fixture_and_recording() {
+        let session =
+            RecordedSession::parse("> ver\nStickOS v9.9\n> custom\nfirst\n> custom\nsecond\n");
+        let device = SimulatedDevice::new().session(&session);
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        assert_eq!(scope.capabilities().version().unwrap().major, 9);
+        drop(scope.exec_raw("custom"));
+
+        let dialect = TerminalDialect::default();
+        let recorder = RecordingPort::new(Box::new(device.port()), &dialect);
+        let recording = recorder.session();
+        let terminal = StatelessFleaTerminal::from_port(Box::new(recorder), dialect).unwrap();
+        let mut terminal = IdleFleaTerminal::try_from(terminal)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(terminal.exec_sync("custom", None), b"second\r\n");
+        assert_eq!(terminal.exec_sync("custom", None), b"second\r\n");
+
+        let last = recording.lock().unwrap().exchanges().last().cloned();
+        assert_eq!(
+            last.unwrap(),
+            ("custom".to_string(), b"second\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_faults() {
+        let device = SimulatedDevice::new();
+        let (mut scope, _x1, _x10) = device.connect().unwrap();
+        let config = CaptureConfig::builder()
+            .time_frame(Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        let device = device.fault(
+            "scope",
+            Fault::GarbageLine {
+                after: 3,
+                line: b"#@!".to_vec(),
+            },
+        );
+        let reading = scope.read(&config);
+        let (samples, report) = reading.samples_with(ParseMode::Lenient).unwrap();
+        assert_eq!(report.dropped[0].index, 3);
+        assert_eq!(samples.len(), 2000);
+
+        let device = device.fault(
+            "hostname",
+            Fault::DropBytes {
+                every: NonZeroUsize::new(2).unwrap(),
+            },
+        );
+        assert_eq!(scope.exec_raw("hostname"), b"FeSoe\n");
+        assert_eq!(scope.exec_raw("hostname"), b"FleaScope\r\n");
+
+        let device = device.fault("scope", Fault::DelayPrompt(Duration::from_millis(300)));
+        let mut reading = scope.read_async_with(&config).ok().unwrap();
+        let started = Instant::now();
+        let (scope, reading) = loop {
+            match reading.try_get_result().unwrap() {
+                Ok(done) => break done,
+                Err(still_reading) => reading = still_reading,
+            }
+        };
+        assert!(started.elapsed() >= Duration::from_millis(250));
+        assert_eq!(reading.samples().unwrap().len(), 2000);
+
+        let device = device.fault("scope", Fault::DisconnectAfter(100));
+        let mut reading = scope.read_async_with(&config).ok().unwrap();
+        let result = loop {
+            match reading.try_get_result() {
+                Ok(Err(still_reading)) => reading = still_reading,
+                result => break result,
+            }
+        };
+        assert!(matches!(result, Err(ReadInterrupted::ConnectionLost)));
+        assert!(device.port().write(b"ver\n").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::capture_frame::CaptureFrame;
+use polars::prelude::*;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not write capture: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Destination for captures, e.g. a file or a network connection.
+///
+/// Subsystems producing a series of captures (like `DriftLogger`) accept any number of
+/// sinks, so output destinations can be mixed and matched or implemented by users.
+pub trait CaptureSink: Send {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError>;
+}
+
+impl<F> CaptureSink for F
+where
+    F: FnMut(&CaptureFrame) -> Result<(), SinkError> + Send,
+{
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        self(frame)
+    }
+}
+
+/// Appends every capture to a CSV file, writing the header only once
+#[derive(Debug, Clone)]
+pub struct CsvSink {
+    path: PathBuf,
+}
+
+impl CsvSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CaptureSink for CsvSink {
+    fn consume(&mut self, frame: &CaptureFrame) -> Result<(), SinkError> {
+        profiling::scope!("CsvSink::consume");
+
+        let mut df = frame.clone().collect()?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        CsvWriter::new(&mut file)
+            .include_header(is_empty)
+            .finish(&mut df)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_sink_appends() {
+        let path = std::env::temp_dir().join(format!("fleascope_sink_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let frame = CaptureFrame::new(df!("time" => [0.0, 1.0], "v" => [1.5, 2.5]).unwrap().lazy());
+
+        let mut sink = CsvSink::new(&path);
+        sink.consume(&frame).unwrap();
+        sink.consume(&frame).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content, "time,v\n0.0,1.5\n1.0,2.5\n0.0,1.5\n1.0,2.5\n");
+    }
+}
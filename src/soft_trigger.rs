@@ -0,0 +1,473 @@
+//! Evaluate triggers in software on data that was already captured, e.g. to preview where
+//! a trigger would fire or to slice a long rolling capture at every event.
+//!
+//! The conditions follow the firmware: digital triggers compare the masked bitmap, analog
+//! levels are compared on the 10 most significant bits of the raw ADC value. Auto triggers
+//! behave like their level-sensitive counterparts, as there is no timeout in recorded data.
+
+use crate::flea_scope::Sample;
+#[cfg(feature = "dataframe")]
+use crate::flea_scope::{BITMAP_COLUMN_NAME, RAW_COLUMN_NAME, TIME_COLUMN_NAME};
+use crate::trigger_config::{
+    AnalogTrigger, AnalogTriggerBehavior, BitState, DigitalTrigger, DigitalTriggerBehavior,
+    Trigger, DIGITAL_CHANNELS,
+};
+#[cfg(feature = "dataframe")]
+use polars::prelude::*;
+use std::ops::Range;
+use std::time::Duration;
+
+/// Indices of the samples at which `trigger` fires
+///
+/// ```rust
+/// use fleascope_rs::flea_scope::Sample;
+/// use fleascope_rs::soft_trigger;
+/// use fleascope_rs::{BitState, DigitalTrigger, Trigger};
+///
+/// let samples: Vec<Sample> = [0, 1, 1, 0, 1]
+///     .into_iter()
+///     .enumerate()
+///     .map(|(i, bitmap)| Sample { time: i as f64, raw: 0.0, bitmap })
+///     .collect();
+/// let trigger = DigitalTrigger::start_capturing_when().bit0(BitState::High).starts_matching()?;
+/// assert_eq!(soft_trigger::trigger_points(&Trigger::from(trigger), &samples), [1, 4]);
+/// # Ok::<(), fleascope_rs::DigitalTriggerError>(())
+/// ```
+pub fn trigger_points(trigger: &Trigger, samples: &[Sample]) -> Vec<usize> {
+    match trigger {
+        Trigger::Analog(trigger) => analog_trigger_points(trigger, samples),
+        Trigger::Digital(trigger) => digital_trigger_points(trigger, samples),
+    }
+}
+
+/// Indices at which the bit pattern starts matching, stops matching, or, for `While` and
+/// `Auto`, the start of every run of matching samples including one at the very start.
+///
+/// With a hold time, the trigger moves to the end of the window in which the pattern has
+/// been held, the first sample at least the hold time after it started matching. Points
+/// at which the pattern changes within the window or that are too close to the end of the
+/// samples to tell are dropped.
+pub fn digital_trigger_points(trigger: &DigitalTrigger, samples: &[Sample]) -> Vec<usize> {
+    profiling::scope!("digital_trigger_points");
+
+    let (mask, value) = masks(&trigger.bit_states);
+    let edge = match trigger.behavior {
+        DigitalTriggerBehavior::Auto | DigitalTriggerBehavior::While => Edge::Entering,
+        DigitalTriggerBehavior::Start => Edge::Rising,
+        DigitalTriggerBehavior::Stop => Edge::Falling,
+    };
+    let points = edges(
+        samples.iter().map(|sample| sample.bitmap & mask == value),
+        edge,
+    );
+    let Some(held) = trigger.held_for else {
+        return points;
+    };
+    points
+        .into_iter()
+        .filter_map(|start| {
+            let until = samples[start].time + held.as_secs_f64();
+            let end = start + samples[start..].partition_point(|sample| sample.time < until);
+            (end < samples.len() && pattern_held(trigger, &samples[start..])).then_some(end)
+        })
+        .collect()
+}
+
+/// Whether the pattern of `trigger` doesn't change for its hold time from the first sample.
+///
+/// That is the window from where the firmware triggers, as the pattern starts matching,
+/// to the end of the hold time, where the trigger is. Triggers without one always pass.
+pub fn pattern_held(trigger: &DigitalTrigger, samples: &[Sample]) -> bool {
+    let (Some(held), Some(first)) = (trigger.held_for, samples.first()) else {
+        return true;
+    };
+    let (mask, value) = masks(&trigger.bit_states);
+    let until = first.time + held.as_secs_f64();
+    let end = samples.partition_point(|sample| sample.time < until);
+    samples[..=end.min(samples.len() - 1)]
+        .iter()
+        .all(|sample| sample.bitmap & mask == value)
+}
+
+/// Whether a capture without delay, starting at the trigger, passes the conditions the
+/// firmware can't check: the hysteresis and qualifier of analog triggers, and the hold time
+/// of digital ones
+pub fn emulated_conditions_hold(trigger: &Trigger, samples: &[Sample]) -> bool {
+    match trigger {
+        Trigger::Analog(trigger) => {
+            leaves_hysteresis_band(trigger, samples) && qualifier_holds(trigger, samples)
+        }
+        Trigger::Digital(trigger) => pattern_held(trigger, samples),
+    }
+}
+
+/// Samples of the `time_frame` long window starting `pre_trigger` before the trigger.
+///
+/// That is the first point at which `trigger` fires with that much room on both sides,
+/// `None` if there is none, see `CaptureConfigBuilder::trigger_position`.
+pub fn pre_trigger_window(
+    trigger: &Trigger,
+    samples: &[Sample],
+    pre_trigger: Duration,
+    time_frame: Duration,
+) -> Option<Range<usize>> {
+    let (first, last) = (samples.first()?.time, samples.last()?.time);
+    trigger_points(trigger, samples)
+        .into_iter()
+        .find_map(|point| {
+            let start = samples[point].time - pre_trigger.as_secs_f64();
+            let end = start + time_frame.as_secs_f64();
+            (start >= first && end <= last).then_some((start, end))
+        })
+        .map(|(start, end)| {
+            samples.partition_point(|sample| sample.time < start)
+                ..samples.partition_point(|sample| sample.time < end)
+        })
+}
+
+/// Indices at which the signal crosses the level upwards, downwards, or, for `Level` and
+/// `Auto`, the start of every run of samples at or above it including one at the very start.
+///
+/// Edges honor the trigger's hysteresis as `leaves_hysteresis_band` does, so a crossing
+/// only counts if the signal gets past the band before crossing back. With a qualifier,
+/// only the points at which the digital inputs match it are kept.
+pub fn analog_trigger_points(trigger: &AnalogTrigger, samples: &[Sample]) -> Vec<usize> {
+    profiling::scope!("analog_trigger_points");
+
+    let level = f64::from(trigger.level);
+    let above = samples.iter().map(|sample| sample.raw / 4.0 >= level);
+    let mut points = match trigger.behavior {
+        AnalogTriggerBehavior::Auto | AnalogTriggerBehavior::Level => edges(above, Edge::Entering),
+        AnalogTriggerBehavior::Rising => edges(above, Edge::Rising),
+        AnalogTriggerBehavior::Falling => edges(above, Edge::Falling),
+    };
+    points.retain(|&index| {
+        leaves_hysteresis_band(trigger, &samples[index..])
+            && qualifier_holds(trigger, &samples[index..])
+    });
+    points
+}
+
+/// Whether the digital inputs match the qualifier of `trigger` at the first sample, i.e. at
+/// the trigger of a capture without delay. Triggers without a qualifier always pass.
+pub fn qualifier_holds(trigger: &AnalogTrigger, samples: &[Sample]) -> bool {
+    let (Some(pattern), Some(sample)) = (&trigger.qualifier, samples.first()) else {
+        return true;
+    };
+    let (mask, value) = masks(pattern);
+    sample.bitmap & mask == value
+}
+
+/// Bits that are compared, and the values they must have
+pub(crate) fn masks(bit_states: &[BitState; DIGITAL_CHANNELS]) -> (u16, u16) {
+    let (mut mask, mut value) = (0u16, 0u16);
+    for (bit, state) in bit_states.iter().enumerate() {
+        match state {
+            BitState::High => {
+                mask |= 1 << bit;
+                value |= 1 << bit;
+            }
+            BitState::Low => mask |= 1 << bit,
+            BitState::DontCare => {}
+        }
+    }
+    (mask, value)
+}
+
+/// Whether a capture that starts at an edge of `trigger` leaves the hysteresis band before
+/// crossing back over the level.
+///
+/// This is the one definition of the hysteresis, `analog_trigger_points` uses it as well.
+/// Noise around the level fails this, so such captures can be dropped as a hysteresis in
+/// the firmware wouldn't have taken them. Level triggers, triggers without hysteresis and
+/// captures that end inside the band pass.
+pub fn leaves_hysteresis_band(trigger: &AnalogTrigger, samples: &[Sample]) -> bool {
+    let level = f64::from(trigger.level);
+    let band = f64::from(trigger.hysteresis);
+    let sign = match trigger.behavior {
+        _ if trigger.hysteresis == 0 => return true,
+        AnalogTriggerBehavior::Auto | AnalogTriggerBehavior::Level => return true,
+        AnalogTriggerBehavior::Rising => 1.0,
+        AnalogTriggerBehavior::Falling => -1.0,
+    };
+    for sample in samples {
+        // Distance past the level in the direction of the edge
+        let past = sign * (sample.raw / 4.0 - level);
+        if past >= band {
+            return true;
+        }
+        if past < 0.0 {
+            return false;
+        }
+    }
+    true
+}
+
+/// Like `trigger_points`, on a frame with the raw and bitmap columns of
+/// `ScopeReading::parse_csv`
+#[cfg(feature = "dataframe")]
+pub fn trigger_points_in_frame(
+    trigger: &Trigger,
+    df: &DataFrame,
+) -> Result<Vec<usize>, PolarsError> {
+    profiling::scope!("trigger_points_in_frame");
+
+    let (needs_raw, needs_bitmap, needs_time) = match trigger {
+        Trigger::Analog(trigger) => (true, trigger.qualifier.is_some(), false),
+        Trigger::Digital(trigger) => (false, true, trigger.held_for.is_some()),
+    };
+    let times = if needs_time {
+        df.column(TIME_COLUMN_NAME)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|time| time.unwrap_or(f64::NAN))
+            .collect()
+    } else {
+        vec![0.0; df.height()]
+    };
+    let raw = if needs_raw {
+        df.column(RAW_COLUMN_NAME)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|raw| raw.unwrap_or(f64::NAN))
+            .collect()
+    } else {
+        vec![0.0; df.height()]
+    };
+    let bitmaps = if needs_bitmap {
+        df.column(BITMAP_COLUMN_NAME)?
+            .u32()?
+            .into_iter()
+            .enumerate()
+            .map(|(index, bitmap)| {
+                bitmap
+                    .and_then(|bitmap| u16::try_from(bitmap).ok())
+                    .ok_or_else(
+                        || polars_err!(ComputeError: "invalid bitmap {bitmap:?} in row {index}"),
+                    )
+            })
+            .collect::<Result<_, PolarsError>>()?
+    } else {
+        vec![0; df.height()]
+    };
+    let samples: Vec<Sample> = times
+        .into_iter()
+        .zip(raw.into_iter().zip(bitmaps))
+        .map(|(time, (raw, bitmap))| Sample { time, raw, bitmap })
+        .collect();
+    Ok(trigger_points(trigger, &samples))
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    /// Condition becomes true, or already is at the first sample
+    Entering,
+    /// Condition becomes true
+    Rising,
+    /// Condition becomes false
+    Falling,
+}
+
+fn edges(conditions: impl Iterator<Item = bool>, edge: Edge) -> Vec<usize> {
+    let mut previous = None;
+    conditions
+        .enumerate()
+        .filter_map(|(index, condition)| {
+            let fires = match (edge, previous.replace(condition)) {
+                (Edge::Entering, None) => condition,
+                (Edge::Entering | Edge::Rising, Some(was)) => !was && condition,
+                (Edge::Falling, Some(was)) => was && !condition,
+                (Edge::Rising | Edge::Falling, None) => false,
+            };
+            fires.then_some(index)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(raw: &[f64], bitmaps: &[u16]) -> Vec<Sample> {
+        (0u32..)
+            .zip(raw.iter().zip(bitmaps))
+            .map(|(i, (&raw, &bitmap))| Sample {
+                time: f64::from(i),
+                raw,
+                bitmap,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_digital_trigger_points() {
+        let data = samples(&[0.0; 6], &[0b01, 0b01, 0b00, 0b11, 0b01, 0b00]);
+        let pattern = || {
+            DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .bit1(BitState::Low)
+        };
+
+        assert_eq!(
+            digital_trigger_points(&pattern().starts_matching().unwrap(), &data),
+            [4]
+        );
+        assert_eq!(
+            digital_trigger_points(&pattern().stops_matching().unwrap(), &data),
+            [2, 5]
+        );
+        assert_eq!(
+            digital_trigger_points(&pattern().is_matching(), &data),
+            [0, 4]
+        );
+    }
+
+    #[test]
+    fn test_pre_trigger_window() {
+        let data = samples(&[0.0; 8], &[0, 1, 0, 0, 1, 0, 0, 0]);
+        let trigger = Trigger::from(
+            DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .starts_matching()
+                .unwrap(),
+        );
+        let window = |pre, frame| {
+            pre_trigger_window(
+                &trigger,
+                &data,
+                Duration::from_secs(pre),
+                Duration::from_secs(frame),
+            )
+        };
+
+        // The trigger at 1 is too close to the start
+        assert_eq!(window(2, 3), Some(2..5));
+        assert_eq!(window(1, 3), Some(0..3));
+        assert_eq!(window(2, 6), None);
+    }
+
+    #[test]
+    fn test_analog_trigger_points() {
+        // Level 500 is raw 2000
+        let data = samples(&[2100.0, 1900.0, 2000.0, 2400.0, 1000.0], &[0; 5]);
+        let trigger = |behavior| AnalogTrigger::new(500, behavior);
+
+        assert_eq!(
+            analog_trigger_points(&trigger(AnalogTriggerBehavior::Rising), &data),
+            [2]
+        );
+        assert_eq!(
+            analog_trigger_points(&trigger(AnalogTriggerBehavior::Falling), &data),
+            [1, 4]
+        );
+        assert_eq!(
+            analog_trigger_points(&trigger(AnalogTriggerBehavior::Level), &data),
+            [0, 2]
+        );
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        // Noise around the level (raw 2000) after the first crossing
+        let data = samples(&[1900.0, 2010.0, 1995.0, 2010.0, 1800.0, 2100.0], &[0; 6]);
+        let rising = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising);
+        assert_eq!(analog_trigger_points(&rising, &data), [1, 3, 5]);
+        // Only the last crossing gets past the band before crossing back
+        let rising = rising.with_hysteresis(10);
+        assert_eq!(analog_trigger_points(&rising, &data), [5]);
+        let falling = AnalogTrigger::new(500, AnalogTriggerBehavior::Falling);
+        assert_eq!(analog_trigger_points(&falling, &data), [2, 4]);
+        let falling = falling.with_hysteresis(10);
+        assert_eq!(analog_trigger_points(&falling, &data), [4]);
+
+        assert!(!leaves_hysteresis_band(&rising, &data[1..]));
+        assert!(leaves_hysteresis_band(&rising, &data[5..]));
+        assert!(leaves_hysteresis_band(&falling, &data[4..]));
+        assert!(leaves_hysteresis_band(
+            &AnalogTrigger::new(500, AnalogTriggerBehavior::Rising),
+            &data[1..]
+        ));
+    }
+
+    #[test]
+    fn test_held_for() {
+        // Samples 1 µs apart, with a glitch at 2
+        let data: Vec<Sample> = (0u32..)
+            .zip([0, 1, 0, 1, 1, 1, 1, 0])
+            .map(|(i, bitmap)| Sample {
+                time: f64::from(i) * 1e-6,
+                raw: 0.0,
+                bitmap,
+            })
+            .collect();
+        let pattern = || DigitalTrigger::start_capturing_when().bit0(BitState::High);
+        assert_eq!(
+            digital_trigger_points(&pattern().starts_matching().unwrap(), &data),
+            [1, 3]
+        );
+        let held = pattern()
+            .held_for(std::time::Duration::from_micros(3))
+            .starts_matching()
+            .unwrap();
+        // The trigger is at the end of the window, 3 µs after the pattern started matching
+        assert_eq!(digital_trigger_points(&held, &data), [6]);
+        // Not long enough to see that the pattern is held
+        let held_long = pattern()
+            .held_for(std::time::Duration::from_micros(5))
+            .starts_matching()
+            .unwrap();
+        assert!(digital_trigger_points(&held_long, &data).is_empty());
+
+        assert!(!pattern_held(&held, &data[1..]));
+        assert!(emulated_conditions_hold(&held.into(), &data[3..]));
+    }
+
+    #[test]
+    fn test_qualifier() {
+        let data = samples(&[1900.0, 2100.0, 1900.0, 2100.0], &[0, 0b1000, 0, 0]);
+        let trigger = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).qualified_by(
+            &DigitalTrigger::start_capturing_when()
+                .bit3(BitState::High)
+                .is_matching(),
+        );
+        assert_eq!(analog_trigger_points(&trigger, &data), [1]);
+
+        assert!(qualifier_holds(&trigger, &data[1..]));
+        assert!(!qualifier_holds(&trigger, &data[3..]));
+        assert!(qualifier_holds(
+            &AnalogTrigger::new(500, AnalogTriggerBehavior::Rising),
+            &data[3..]
+        ));
+    }
+
+    #[cfg(feature = "dataframe")]
+    #[test]
+    fn test_trigger_points_in_frame() {
+        let reading = crate::flea_scope::ScopeReading {
+            effective_msps: 1.0,
+            data: b"2100,0x001\n1900,0x000\n2000,0x001\n".to_vec(),
+            metadata: None,
+            parsed: std::sync::OnceLock::new(),
+        };
+        let df = reading.parse_csv().unwrap().collect().unwrap();
+
+        let digital = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .starts_matching()
+            .unwrap();
+        assert_eq!(trigger_points_in_frame(&digital.into(), &df).unwrap(), [2]);
+        let analog = AnalogTrigger::new(500, AnalogTriggerBehavior::Falling);
+        assert_eq!(trigger_points_in_frame(&analog.into(), &df).unwrap(), [1]);
+        let qualified = AnalogTrigger::new(500, AnalogTriggerBehavior::Level).qualified_by(
+            &DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .is_matching(),
+        );
+        assert_eq!(
+            trigger_points_in_frame(&qualified.into(), &df).unwrap(),
+            [0, 2]
+        );
+    }
+}
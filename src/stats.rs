@@ -0,0 +1,276 @@
+//! Distributions and running statistics of a column, e.g. the noise and drift of a
+//! calibrated voltage.
+//!
+//! Statistics over many captures are merged from per-capture summaries, so a long
+//! stream is never held in memory.
+
+use crate::flea_scope::CALIBRATED_COLUMN_NAME;
+use polars::prelude::*;
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+pub const BIN_START_COLUMN_NAME: &str = "bin_start";
+pub const BIN_END_COLUMN_NAME: &str = "bin_end";
+pub const COUNT_COLUMN_NAME: &str = "count";
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Column {0:?} holds no values")]
+    Empty(String),
+}
+
+/// Counts of the values of a column in equally wide bins
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Bin boundaries, one more than there are bins
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// `bin_start`, `bin_end` and `count` columns, one row per bin
+    pub fn into_dataframe(self) -> Result<DataFrame, PolarsError> {
+        let starts = self.edges[..self.counts.len()].to_vec();
+        let ends = self.edges[1..].to_vec();
+        DataFrame::new(vec![
+            Column::new(BIN_START_COLUMN_NAME.into(), starts),
+            Column::new(BIN_END_COLUMN_NAME.into(), ends),
+            Column::new(COUNT_COLUMN_NAME.into(), self.counts),
+        ])
+    }
+}
+
+/// Distribution of `column` in `bins` equally wide bins spanning its values, e.g. the
+/// noise on `bnc_calibrated`. Nulls and NaN are skipped, the maximum falls in the last
+/// bin.
+///
+/// ```rust
+/// use fleascope_rs::stats::histogram;
+/// use polars::prelude::*;
+/// use std::num::NonZeroUsize;
+///
+/// let df = df!("bnc_calibrated" => [0.0, 0.1, 0.2, 0.9, 1.0])?;
+/// let histogram = histogram(&df, "bnc_calibrated", NonZeroUsize::new(2).unwrap())?;
+/// assert_eq!(histogram.edges, [0.0, 0.5, 1.0]);
+/// assert_eq!(histogram.counts, [3, 2]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn histogram(
+    df: &DataFrame,
+    column: &str,
+    bins: NonZeroUsize,
+) -> Result<Histogram, StatsError> {
+    profiling::scope!("stats::histogram");
+
+    let values = values(df, column)?;
+    let summary =
+        Summary::from_values(&values).ok_or_else(|| StatsError::Empty(column.to_string()))?;
+    // A constant column still gets bins of some width, centered on its value
+    let (low, high) = if summary.max > summary.min {
+        (summary.min, summary.max)
+    } else {
+        (summary.min - 0.5, summary.max + 0.5)
+    };
+
+    #[allow(clippy::cast_precision_loss)]
+    let width = (high - low) / bins.get() as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let edges = (0..=bins.get())
+        .map(|edge| width.mul_add(edge as f64, low))
+        .collect();
+    let mut counts = vec![0; bins.get()];
+    for value in values {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let bin = ((value - low) / width) as usize;
+        counts[bin.min(bins.get() - 1)] += 1;
+    }
+    Ok(Histogram { edges, counts })
+}
+
+/// Non-null, non-NaN values of `column` as `f64`
+fn values(df: &DataFrame, column: &str) -> Result<Vec<f64>, PolarsError> {
+    Ok(df
+        .column(column)?
+        .cast(&DataType::Float64)?
+        .f64()?
+        .into_iter()
+        .flatten()
+        .filter(|value| !value.is_nan())
+        .collect())
+}
+
+/// Minimum, maximum, mean and standard deviation of a set of values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Sum of squared deviations from the mean, kept so summaries can be merged
+    sum_of_squares: f64,
+}
+
+impl Summary {
+    /// `None` if there are no values
+    pub fn from_values(values: &[f64]) -> Option<Self> {
+        values
+            .iter()
+            .map(|&value| Self {
+                count: 1,
+                min: value,
+                max: value,
+                mean: value,
+                sum_of_squares: 0.0,
+            })
+            .reduce(|summary, value| summary.merge(&value))
+    }
+
+    /// Population standard deviation
+    pub fn std(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        (self.sum_of_squares / count).sqrt()
+    }
+
+    /// Summary of the values of both, without needing the values themselves
+    #[must_use]
+    pub fn merge(&self, other: &Self) -> Self {
+        let count = self.count + other.count;
+        #[allow(clippy::cast_precision_loss)]
+        let (n, m, total) = (self.count as f64, other.count as f64, count as f64);
+        let delta = other.mean - self.mean;
+        Self {
+            count,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            mean: delta.mul_add(m / total, self.mean),
+            sum_of_squares: (delta * delta)
+                .mul_add(n * m / total, self.sum_of_squares + other.sum_of_squares),
+        }
+    }
+}
+
+/// Statistics of a column over the last few captures of a stream, e.g. to watch the
+/// drift and noise of a supply over hours.
+///
+/// ```rust,no_run
+/// use fleascope_rs::capture_stream::CaptureStream;
+/// use fleascope_rs::stats::RollingStats;
+/// use fleascope_rs::{CaptureConfig, IdleFleaScope};
+/// use std::num::NonZeroUsize;
+/// use std::time::Duration;
+///
+/// let (scope, x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let config = CaptureConfig::builder().time_frame(Duration::from_millis(20)).build()?;
+/// let mut stats = RollingStats::new(NonZeroUsize::new(50).unwrap());
+/// for df in CaptureStream::new(scope, &config, Some(&x1)).take(500) {
+///     let summary = stats.feed(&df?)?;
+///     println!("{:.3} V ± {:.3} V", summary.mean, summary.std());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+    column: String,
+    window: NonZeroUsize,
+    captures: VecDeque<Summary>,
+}
+
+impl RollingStats {
+    /// Statistics of the calibrated voltage over the last `window` captures
+    pub fn new(window: NonZeroUsize) -> Self {
+        Self::of_column(CALIBRATED_COLUMN_NAME, window)
+    }
+
+    pub fn of_column(column: &str, window: NonZeroUsize) -> Self {
+        Self {
+            column: column.to_string(),
+            window,
+            captures: VecDeque::with_capacity(window.get()),
+        }
+    }
+
+    /// Add the next capture, dropping the oldest one beyond the window. Returns the
+    /// statistics over the window.
+    pub fn feed(&mut self, df: &DataFrame) -> Result<Summary, StatsError> {
+        profiling::scope!("RollingStats::feed");
+
+        let summary = Summary::from_values(&values(df, &self.column)?)
+            .ok_or_else(|| StatsError::Empty(self.column.clone()))?;
+        if self.captures.len() == self.window.get() {
+            self.captures.pop_front();
+        }
+        self.captures.push_back(summary);
+        Ok(self.summary().unwrap_or(summary))
+    }
+
+    /// Statistics over the captures in the window, `None` before the first one
+    pub fn summary(&self) -> Option<Summary> {
+        self.captures
+            .iter()
+            .copied()
+            .reduce(|summary, capture| summary.merge(&capture))
+    }
+
+    /// Statistics of each capture in the window, oldest first
+    pub fn captures(&self) -> impl Iterator<Item = &Summary> {
+        self.captures.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let df = df!(
+            CALIBRATED_COLUMN_NAME => [Some(1.0), Some(2.0), None, Some(2.5), Some(f64::NAN), Some(4.0)],
+            "bitmap" => [1u32, 1, 1, 1, 3, 3],
+        )
+        .unwrap();
+        let three = NonZeroUsize::new(3).unwrap();
+        let histogram = histogram(&df, CALIBRATED_COLUMN_NAME, three).unwrap();
+        assert_eq!(histogram.edges, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(histogram.counts, [1, 2, 1]);
+
+        let frame = histogram.into_dataframe().unwrap();
+        assert_eq!(frame.shape(), (3, 3));
+
+        // Integer columns work as well, a constant one gets a bin around its value
+        let two = NonZeroUsize::new(2).unwrap();
+        let constant = super::histogram(&df.head(Some(4)), "bitmap", two).unwrap();
+        assert_eq!(constant.edges, [0.5, 1.0, 1.5]);
+        assert_eq!(constant.counts, [0, 4]);
+
+        assert!(matches!(
+            super::histogram(&df.head(Some(0)), "bitmap", two),
+            Err(StatsError::Empty(_))
+        ));
+    }
+
+    #[test]
+    fn test_rolling_stats() {
+        let capture = |volts: &[f64]| df!(CALIBRATED_COLUMN_NAME => volts).unwrap();
+        let mut stats = RollingStats::new(NonZeroUsize::new(2).unwrap());
+
+        let summary = stats.feed(&capture(&[1.0, 3.0])).unwrap();
+        assert_eq!((summary.min, summary.max, summary.mean), (1.0, 3.0, 2.0));
+        assert!((summary.std() - 1.0).abs() < 1e-12);
+
+        // Merged summaries match the summary of all values
+        let summary = stats.feed(&capture(&[5.0, 7.0, 9.0])).unwrap();
+        let all = Summary::from_values(&[1.0, 3.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(summary.count, 5);
+        assert!((summary.mean - all.mean).abs() < 1e-12);
+        assert!((summary.std() - 8f64.sqrt()).abs() < 1e-12);
+
+        // The first capture leaves the window
+        let summary = stats.feed(&capture(&[11.0])).unwrap();
+        assert_eq!((summary.count, summary.min, summary.max), (4, 5.0, 11.0));
+        assert_eq!(stats.captures().count(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::flea_scope::{
+    CaptureConfigError, FleaProbe, IdleFleaScope, Waveform, CALIBRATED_COLUMN_NAME,
+    TIME_COLUMN_NAME,
+};
+use crate::trigger_config::{AnalogTrigger, TriggerConfig};
+use crate::units::Volts;
+use polars::prelude::*;
+use std::time::Duration;
+
+/// Speed of light in vacuum, m/s
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TdrError {
+    #[error("Invalid capture configuration: {0}")]
+    CaptureConfig(#[from] CaptureConfigError),
+
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("No incident edge found in the capture")]
+    NoIncidentEdge,
+}
+
+/// Settings for a time-domain reflectometry measurement
+#[derive(Debug, Clone)]
+pub struct TdrConfig {
+    /// Propagation speed in the cable relative to the speed of light (e.g. 0.66 for RG58)
+    pub velocity_factor: f64,
+    /// Frequency of the square wave driving the cable
+    pub edge_frequency_hz: i32,
+    pub time_frame: Duration,
+    /// Voltage the generator edge crosses, used for triggering
+    pub trigger_level: Volts,
+    /// Minimum step size of a reflection, relative to the incident step
+    pub reflection_threshold: f64,
+}
+
+impl Default for TdrConfig {
+    fn default() -> Self {
+        Self {
+            velocity_factor: 0.66,
+            edge_frequency_hz: 1000,
+            time_frame: Duration::from_micros(200),
+            trigger_level: Volts::from_millivolts(1650),
+            reflection_threshold: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TdrResult {
+    /// Time of the generator edge within the capture, in seconds
+    pub incident_time: f64,
+    /// Time of the first significant reflection, in seconds
+    pub reflection_time: Option<f64>,
+    /// Estimated distance to the impedance discontinuity, in meters
+    pub distance_m: Option<f64>,
+    /// Distance covered by one sample period, i.e. the best achievable resolution
+    pub resolution_m: f64,
+}
+
+/// Drive an edge from the waveform generator into the cable connected to the BNC input
+/// and estimate the distance to the first reflection.
+///
+/// At the scope's sample rate the resolution is in the order of meters, so this is only
+/// useful for finding faults in long cable runs.
+pub fn measure(
+    scope: &mut IdleFleaScope,
+    probe: &FleaProbe,
+    config: &TdrConfig,
+) -> Result<TdrResult, TdrError> {
+    profiling::scope!("tdr::measure");
+
+    scope.set_waveform(Waveform::Square, config.edge_frequency_hz);
+
+    let trigger = AnalogTrigger::start_capturing_when(config.trigger_level)
+        .rising_edge()
+        .into_trigger(probe)?
+        .into_trigger_fields();
+    let reading = scope.read_sync(config.time_frame, trigger, None)?;
+    let df = probe.apply_calibration(reading.parse_csv()?).collect()?;
+
+    analyze(&df, config)
+}
+
+/// Find the incident edge and its first reflection in a calibrated capture
+pub fn analyze(df: &DataFrame, config: &TdrConfig) -> Result<TdrResult, TdrError> {
+    let time: Vec<f64> = df
+        .column(TIME_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+    let volts: Vec<f64> = df
+        .column(CALIBRATED_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+
+    // steps[i] is the change from sample i to sample i + 1
+    let steps: Vec<f64> = volts.windows(2).map(|w| w[1] - w[0]).collect();
+    let largest_step = steps.iter().copied().fold(0.0, f64::max);
+    if largest_step <= 0.0 {
+        return Err(TdrError::NoIncidentEdge);
+    }
+
+    // The incident edge is the first large rise; an open cable end reflects an even larger one
+    let incident = steps
+        .iter()
+        .position(|step| *step >= largest_step / 2.0)
+        .ok_or(TdrError::NoIncidentEdge)?;
+    // The edge may span a couple of samples
+    let edge_len = steps[incident..]
+        .iter()
+        .take_while(|step| **step > 0.0)
+        .count();
+    let incident_amplitude: f64 = steps[incident..incident + edge_len].iter().sum();
+
+    let reflection = steps
+        .iter()
+        .enumerate()
+        .skip(incident + edge_len)
+        .find(|(_, step)| step.abs() >= incident_amplitude * config.reflection_threshold)
+        .map(|(index, _)| index);
+
+    let sample_period = time[1] - time[0];
+    let meters_per_second = SPEED_OF_LIGHT * config.velocity_factor;
+    let incident_time = time[incident + 1];
+    let reflection_time = reflection.map(|index| time[index + 1]);
+
+    Ok(TdrResult {
+        incident_time,
+        reflection_time,
+        // The edge travels to the discontinuity and back
+        distance_m: reflection_time.map(|t| (t - incident_time) * meters_per_second / 2.0),
+        resolution_m: sample_period * meters_per_second / 2.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(volts: Vec<f64>) -> DataFrame {
+        let time: Vec<f64> = (0..)
+            .map(|i: u32| f64::from(i) * 1e-7)
+            .take(volts.len())
+            .collect();
+        df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts).unwrap()
+    }
+
+    #[test]
+    fn test_open_cable_reflection() {
+        // Edge reaches sample 3, the reflection from the open end doubles the voltage at sample 7
+        let df = capture(vec![0.0, 0.0, 0.0, 1.0, 1.6, 1.6, 1.6, 3.2, 3.2]);
+        let config = TdrConfig {
+            velocity_factor: 1.0,
+            ..TdrConfig::default()
+        };
+
+        let result = analyze(&df, &config).unwrap();
+        assert!((result.incident_time - 3e-7).abs() < 1e-12);
+        assert!((result.reflection_time.unwrap() - 7e-7).abs() < 1e-12);
+        let distance = result.distance_m.unwrap();
+        assert!((distance - 4e-7 * SPEED_OF_LIGHT / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_no_edge() {
+        let df = capture(vec![1.0, 1.0, 0.5, 0.5]);
+        assert!(matches!(
+            analyze(&df, &TdrConfig::default()),
+            Err(TdrError::NoIncidentEdge)
+        ));
+    }
+}
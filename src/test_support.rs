@@ -0,0 +1,105 @@
+//! Helpers for testing applications built on this crate without hardware in the loop.
+//!
+//! Available with the `test-support` feature, together with the `simulator` module. The
+//! frame comparisons also need the `dataframe` feature.
+//!
+//! ```rust
+//! use fleascope_rs::test_support::{assert_frames_close, SimulatedDevice};
+//! use fleascope_rs::CaptureConfig;
+//! use polars::prelude::*;
+//! use std::time::Duration;
+//!
+//! let (mut scope, x1, _x10) = SimulatedDevice::new().calibrated(2048, 1000).connect()?;
+//! let config = CaptureConfig::builder().time_frame(Duration::from_millis(1)).build()?;
+//! let df = x1.apply_calibration(scope.read(&config).parse_csv()?).collect()?;
+//!
+//! let expected = df.clone().lazy().with_column(lit(0.0).alias("bnc_calibrated")).collect()?;
+//! assert_frames_close(&df, &expected, 1e-9);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub use crate::simulator::{RecordedSession, RecordingPort, SimulatedDevice, SimulatedPort};
+#[cfg(feature = "dataframe")]
+use polars::prelude::*;
+
+/// Compare two frames column by column. Numeric columns may differ by `tolerance`, all other
+/// columns must be equal. Describes the first difference on mismatch.
+#[cfg(feature = "dataframe")]
+pub fn frames_close(
+    actual: &DataFrame,
+    expected: &DataFrame,
+    tolerance: f64,
+) -> Result<(), String> {
+    let (actual_names, expected_names) = (actual.get_column_names(), expected.get_column_names());
+    if actual_names != expected_names {
+        return Err(format!(
+            "columns differ: {actual_names:?} != {expected_names:?}"
+        ));
+    }
+    if actual.height() != expected.height() {
+        return Err(format!(
+            "heights differ: {} != {}",
+            actual.height(),
+            expected.height()
+        ));
+    }
+
+    for (a, e) in actual.get_columns().iter().zip(expected.get_columns()) {
+        let name = a.name();
+        if !(a.dtype().is_primitive_numeric() && e.dtype().is_primitive_numeric()) {
+            if !a
+                .as_materialized_series()
+                .equals_missing(e.as_materialized_series())
+            {
+                return Err(format!("column {name} differs"));
+            }
+            continue;
+        }
+
+        let cast = |column: &Column| column.cast(&DataType::Float64).map_err(|e| e.to_string());
+        let (a, e) = (cast(a)?, cast(e)?);
+        let values = a.f64().map_err(|e| e.to_string())?.iter();
+        let expected_values = e.f64().map_err(|e| e.to_string())?.iter();
+        for (row, (a, e)) in values.zip(expected_values).enumerate() {
+            let close = match (a, e) {
+                (Some(a), Some(e)) => (a - e).abs() <= tolerance,
+                (None, None) => true,
+                _ => false,
+            };
+            if !close {
+                return Err(format!(
+                    "column {name} differs in row {row}: {a:?} != {e:?} (tolerance {tolerance})"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Panicking variant of `frames_close` for use in tests
+#[track_caller]
+#[cfg(feature = "dataframe")]
+pub fn assert_frames_close(actual: &DataFrame, expected: &DataFrame, tolerance: f64) {
+    let result = frames_close(actual, expected, tolerance);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+#[cfg(all(test, feature = "dataframe"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_close() {
+        let expected = df!("time" => [0.0, 1.0], "bitmap" => [0u32, 1]).unwrap();
+        let close = df!("time" => [0.0, 1.0005], "bitmap" => [0u32, 1]).unwrap();
+        let far = df!("time" => [0.0, 1.1], "bitmap" => [0u32, 1]).unwrap();
+        let other_bits = df!("time" => [0.0, 1.0], "bitmap" => [0u32, 2]).unwrap();
+
+        assert_frames_close(&close, &expected, 1e-3);
+        assert!(frames_close(&far, &expected, 1e-3)
+            .unwrap_err()
+            .contains("row 1"));
+        assert!(frames_close(&other_bits, &expected, 1e-3).is_err());
+        assert!(frames_close(&expected.head(Some(1)), &expected, 1e-3).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::flash_vars::FlashVarError;
+#[cfg(feature = "dataframe")]
+use crate::flea_scope::TIME_COLUMN_NAME;
+use crate::flea_scope::{CaptureConfigError, IdleFleaScope, SampleParseError};
+use crate::measurements::rising_crossing_frequency;
+use crate::trigger_config::{DigitalTrigger, TriggerConfig};
+#[cfg(feature = "dataframe")]
+use polars::prelude::*;
+use std::time::Duration;
+
+/// Flash variable holding the correction in parts per billion, the device only stores integers
+const FLASH_VARIABLE: &str = "cal_timebase_ppb";
+/// Long captures span many periods of the reference, averaging out edge jitter
+const MEASUREMENT_TIME_FRAME: Duration = Duration::from_millis(100);
+/// Minimum number of reference periods per capture for a usable measurement
+const MIN_PERIODS: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimebaseError {
+    #[error("Reference signal not found, got {periods} periods per capture")]
+    NoSignal { periods: usize },
+
+    #[error("Invalid capture configuration: {0}")]
+    CaptureConfig(#[from] CaptureConfigError),
+
+    #[cfg(feature = "dataframe")]
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Could not parse capture: {0}")]
+    Samples(#[from] SampleParseError),
+
+    #[error("Could not parse timebase calibration {raw:?}")]
+    ParseFailed { raw: String },
+
+    #[error("Could not access timebase calibration in flash: {0}")]
+    Flash(FlashVarError),
+}
+
+impl From<FlashVarError> for TimebaseError {
+    fn from(e: FlashVarError) -> Self {
+        match e {
+            FlashVarError::ParseFailed { raw, .. } => Self::ParseFailed { raw },
+            e => Self::Flash(e),
+        }
+    }
+}
+
+/// Correction of the device's sample clock against an accurate reference.
+///
+/// The nominal `effective_msps` assumes a perfect MCU crystal. Measuring a known frequency
+/// (e.g. a GPS-disciplined 1 kHz source) yields the actual deviation in ppm, which is stored
+/// in the device's flash and applied to the time column of later captures.
+///
+/// Each measurement captures 100 ms, i.e. 20 kS/s, so the reference needs to stay well
+/// below 10 kHz to get several samples per period.
+///
+/// ```rust,no_run
+/// use fleascope_rs::timebase::TimebaseCalibration;
+/// use fleascope_rs::IdleFleaScope;
+///
+/// let (mut scope, _x1, _x10) = IdleFleaScope::connect(None, None, true)?;
+/// let timebase = TimebaseCalibration::measure(&mut scope, 1_000.0, 5)?;
+/// println!("Timebase is off by {:.1} ppm", timebase.ppm());
+/// timebase.write_to_flash(&mut scope)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimebaseCalibration {
+    ppm: f64,
+}
+
+impl TimebaseCalibration {
+    pub fn from_ppm(ppm: f64) -> Self {
+        Self { ppm }
+    }
+
+    /// How much faster the device samples than nominal, in parts per million
+    pub fn ppm(&self) -> f64 {
+        self.ppm
+    }
+
+    /// Capture `reference_hz` on the BNC input `captures` times and derive the correction
+    pub fn measure(
+        scope: &mut IdleFleaScope,
+        reference_hz: f64,
+        captures: usize,
+    ) -> Result<Self, TimebaseError> {
+        profiling::scope!("TimebaseCalibration::measure");
+
+        let mut measured = Vec::with_capacity(captures);
+        for _ in 0..captures.max(1) {
+            let trigger = DigitalTrigger::start_capturing_when()
+                .is_matching()
+                .into_trigger_fields();
+            let reading = scope.read_sync(MEASUREMENT_TIME_FRAME, trigger, None)?;
+            let raw: Vec<f64> = reading.samples()?.iter().map(|sample| sample.raw).collect();
+            measured.push(measure_frequency(&raw, reading.effective_msps * 1e6)?);
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let measured_hz = measured.iter().sum::<f64>() / measured.len() as f64;
+        // A fast clock packs more samples into each period, so the nominal rate underestimates
+        // the signal's frequency by the same factor the clock is off
+        let ppm = (reference_hz / measured_hz - 1.0) * 1e6;
+        log::debug!("Timebase: measured {measured_hz} Hz for {reference_hz} Hz, {ppm:.3} ppm");
+        Ok(Self { ppm })
+    }
+
+    /// Sample rate actually achieved for a nominal `effective_msps`
+    pub fn corrected_msps(&self, effective_msps: f64) -> f64 {
+        effective_msps * self.factor()
+    }
+
+    /// Rescale the time column of a parsed capture
+    #[cfg(feature = "dataframe")]
+    pub fn apply(&self, df: LazyFrame) -> LazyFrame {
+        df.with_column((col(TIME_COLUMN_NAME) / lit(self.factor())).alias(TIME_COLUMN_NAME))
+    }
+
+    pub fn read_from_flash(scope: &mut IdleFleaScope) -> Result<Self, TimebaseError> {
+        // A fresh flash variable starts at 0, i.e. no correction
+        let ppb: i32 = scope.flash_vars().get(FLASH_VARIABLE)?;
+        Ok(Self {
+            ppm: f64::from(ppb) / 1000.0,
+        })
+    }
+
+    pub fn write_to_flash(&self, scope: &mut IdleFleaScope) -> Result<(), TimebaseError> {
+        #[allow(clippy::cast_possible_truncation)]
+        let ppb = (self.ppm * 1000.0).round() as i32;
+        scope.flash_vars().set(FLASH_VARIABLE, ppb)?;
+        Ok(())
+    }
+
+    fn factor(self) -> f64 {
+        1.0 + self.ppm / 1e6
+    }
+}
+
+/// Frequency of a periodic signal from its interpolated mid-level rising crossings
+fn measure_frequency(samples: &[f64], sample_rate: f64) -> Result<f64, TimebaseError> {
+    match rising_crossing_frequency(samples, sample_rate) {
+        (Some(frequency), periods) if periods >= MIN_PERIODS => Ok(frequency),
+        (_, periods) => Err(TimebaseError::NoSignal { periods }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, sample_rate: f64, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| {
+                let t = f64::from(u32::try_from(i).unwrap()) / sample_rate;
+                (2.0 * std::f64::consts::PI * frequency * t)
+                    .sin()
+                    .mul_add(1000.0, 2048.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_measure_frequency() {
+        let samples = sine(1234.5, 1_000_000.0, 100_000);
+        let measured = measure_frequency(&samples, 1_000_000.0).unwrap();
+        assert!((measured / 1234.5 - 1.0).abs() < 1e-6, "{measured}");
+
+        assert!(matches!(
+            measure_frequency(&[2048.0; 100], 1_000_000.0),
+            Err(TimebaseError::NoSignal { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "dataframe")]
+    fn test_apply_correction() {
+        // A clock 100 ppm fast makes the nominal time axis run 100 ppm slow
+        let timebase = TimebaseCalibration::from_ppm(100.0);
+        assert!((timebase.corrected_msps(1.0) - 1.0001).abs() < 1e-12);
+
+        let df = df!(TIME_COLUMN_NAME => [0.0, 1.0001]).unwrap().lazy();
+        let time = timebase.apply(df).collect().unwrap();
+        let time: Vec<f64> = time
+            .column(TIME_COLUMN_NAME)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert!((time[1] - 1.0).abs() < 1e-12);
+    }
+}
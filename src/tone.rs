@@ -0,0 +1,190 @@
+use crate::flea_scope::{CALIBRATED_COLUMN_NAME, TIME_COLUMN_NAME};
+use polars::prelude::*;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ToneError {
+    #[error("Failure while processing capture data")]
+    Data(#[from] PolarsError),
+
+    #[error("Capture is too short to determine the sample rate")]
+    TooShort,
+}
+
+/// Signal power at `frequency` over `samples`, using the Goertzel algorithm.
+///
+/// Much cheaper than a full FFT when only a handful of frequencies are of interest.
+#[allow(clippy::cast_precision_loss)]
+pub fn goertzel(samples: &[f64], sample_rate: f64, frequency: f64) -> f64 {
+    let coefficient = 2.0 * (std::f64::consts::TAU * frequency / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s0 = sample + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    // Normalize so the result doesn't depend on the block length
+    let n = samples.len().max(1) as f64;
+    (-coefficient * s1).mul_add(s2, s1.mul_add(s1, s2 * s2)) / (n * n)
+}
+
+/// Calibrated samples of a capture together with its sample rate
+fn samples(df: &DataFrame) -> Result<(Vec<f64>, f64), ToneError> {
+    let time = df.column(TIME_COLUMN_NAME)?.f64()?;
+    let (Some(t0), Some(t1)) = (time.get(0), time.get(1)) else {
+        return Err(ToneError::TooShort);
+    };
+    let volts: Vec<f64> = df
+        .column(CALIBRATED_COLUMN_NAME)?
+        .f64()?
+        .into_no_null_iter()
+        .collect();
+
+    // Remove the DC offset, it would otherwise leak into low frequency bins
+    #[allow(clippy::cast_precision_loss)]
+    let mean = volts.iter().sum::<f64>() / volts.len() as f64;
+    let volts = volts.into_iter().map(|v| v - mean).collect();
+    Ok((volts, 1.0 / (t1 - t0)))
+}
+
+/// Finds which of a set of tones is present in consecutive blocks of a capture
+#[derive(Debug, Clone)]
+pub struct ToneDetector {
+    frequencies: Vec<f64>,
+    block_duration: Duration,
+    /// Minimum power relative to the total block power for a tone to count as present
+    threshold: f64,
+}
+
+impl ToneDetector {
+    /// Detect `frequencies` (in Hz) in blocks of `block_duration`
+    pub fn new(frequencies: &[f64], block_duration: Duration) -> Self {
+        Self {
+            frequencies: frequencies.to_vec(),
+            block_duration,
+            threshold: 0.25,
+        }
+    }
+
+    #[must_use]
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// For every block, the index into `frequencies` of the strongest tone, if it is
+    /// strong enough
+    pub fn detect(&self, df: &DataFrame) -> Result<Vec<Option<usize>>, ToneError> {
+        profiling::scope!("ToneDetector::detect");
+
+        let (samples, sample_rate) = samples(df)?;
+        Ok(self.detect_samples(&samples, sample_rate))
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn detect_samples(&self, samples: &[f64], sample_rate: f64) -> Vec<Option<usize>> {
+        let block_len = ((self.block_duration.as_secs_f64() * sample_rate).round() as usize).max(1);
+
+        samples
+            .chunks_exact(block_len)
+            .map(|block| {
+                // A pure sine of amplitude A has a mean square of A²/2 and a Goertzel
+                // power of A²/4, so scale the total power accordingly
+                let total_power =
+                    block.iter().map(|s| s * s).sum::<f64>() / block.len() as f64 / 2.0;
+                self.frequencies
+                    .iter()
+                    .map(|&frequency| goertzel(block, sample_rate, frequency))
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .filter(|(_, power)| total_power > 0.0 && power / total_power >= self.threshold)
+                    .map(|(index, _)| index)
+            })
+            .collect()
+    }
+}
+
+/// Binary FSK demodulator, e.g. for Bell 202 style modems
+#[derive(Debug, Clone)]
+pub struct FskDemodulator {
+    detector: ToneDetector,
+}
+
+impl FskDemodulator {
+    /// `mark` encodes a `1`, `space` a `0`; one block is evaluated per symbol
+    pub fn new(mark: f64, space: f64, baud_rate: f64) -> Self {
+        Self {
+            detector: ToneDetector::new(
+                &[space, mark],
+                Duration::try_from_secs_f64(baud_rate.recip()).unwrap_or(Duration::MAX),
+            ),
+        }
+    }
+
+    /// One bit per symbol period; `None` where neither tone was found
+    pub fn demodulate(&self, df: &DataFrame) -> Result<Vec<Option<bool>>, ToneError> {
+        profiling::scope!("FskDemodulator::demodulate");
+
+        Ok(self
+            .detector
+            .detect(df)?
+            .into_iter()
+            .map(|tone| tone.map(|index| index == 1))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 100_000.0;
+
+    fn tone(frequency: f64, len: u32) -> Vec<f64> {
+        (0..len)
+            .map(|i| (std::f64::consts::TAU * frequency * f64::from(i) / SAMPLE_RATE).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_goertzel_peaks_at_tone() {
+        let samples = tone(1000.0, 1000);
+        let on = goertzel(&samples, SAMPLE_RATE, 1000.0);
+        let off = goertzel(&samples, SAMPLE_RATE, 3000.0);
+        assert!((on - 0.25).abs() < 1e-3);
+        assert!(off < 1e-3);
+    }
+
+    #[test]
+    fn test_fsk_demodulation() {
+        let bits = [true, false, true, true, false];
+        let volts: Vec<f64> = bits
+            .iter()
+            .flat_map(|&bit| tone(if bit { 1200.0 } else { 2200.0 }, 100))
+            .chain(std::iter::repeat_n(0.0, 100))
+            .collect();
+        let time: Vec<f64> = (0..volts.len())
+            .map(|i| f64::from(u32::try_from(i).unwrap()) / SAMPLE_RATE)
+            .collect();
+        let df = df!(TIME_COLUMN_NAME => time, CALIBRATED_COLUMN_NAME => volts).unwrap();
+
+        let demodulated = FskDemodulator::new(1200.0, 2200.0, 1000.0)
+            .demodulate(&df)
+            .unwrap();
+        assert_eq!(
+            demodulated,
+            vec![
+                Some(true),
+                Some(false),
+                Some(true),
+                Some(true),
+                Some(false),
+                None
+            ]
+        );
+    }
+}
@@ -1,4 +1,10 @@
+use crate::channel_labels::ChannelLabels;
+use crate::units::Volts;
 use crate::{flea_scope::CaptureConfigError, FleaProbe};
+use std::time::Duration;
+
+/// Number of digital inputs, as reported in the bitmap of every sample and usable in triggers
+pub const DIGITAL_CHANNELS: usize = 10;
 
 pub trait TriggerConfig {
     fn into_trigger_fields(self) -> StringifiedTriggerConfig;
@@ -6,15 +12,35 @@ pub trait TriggerConfig {
 
 pub struct StringifiedTriggerConfig {
     trigger_fields: String,
+    /// Trigger with conditions the firmware can't apply, to be checked on the captured samples
+    emulated: Option<Trigger>,
 }
 
 impl StringifiedTriggerConfig {
     pub fn into_string(self) -> String {
         self.trigger_fields
     }
+
+    /// The fields as sent to the device
+    pub fn as_str(&self) -> &str {
+        &self.trigger_fields
+    }
+
+    /// Read the fields back into the trigger they were made from
+    pub fn to_trigger(&self) -> Result<Trigger, ParseTriggerError> {
+        // The emulated conditions aren't part of the fields
+        self.emulated
+            .clone()
+            .map_or_else(|| Trigger::from_fields(&self.trigger_fields), Ok)
+    }
+
+    pub(crate) fn emulated(&self) -> Option<&Trigger> {
+        self.emulated.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BitState {
     High,
     Low,
@@ -22,6 +48,7 @@ pub enum BitState {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DigitalTriggerBehavior {
     Auto,
     While,
@@ -41,6 +68,7 @@ impl DigitalTriggerBehavior {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AnalogTriggerBehavior {
     Auto,
     Level,
@@ -59,19 +87,54 @@ impl AnalogTriggerBehavior {
     }
 }
 
+/// A digital trigger that can't be built, see `BitTriggerBuilder::try_set_bit`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DigitalTriggerError {
+    #[error("Bit index {0} out of range, must be between 0 and {max}", max = DIGITAL_CHANNELS - 1)]
+    NoSuchBit(usize),
+
+    #[error("No digital channel is labeled {0:?}")]
+    UnknownLabel(String),
+
+    #[error("Triggering on a {0:?} edge needs at least one bit to be High or Low")]
+    EdgeWithoutPattern(DigitalTriggerBehavior),
+
+    #[error("A Stop trigger fires when the pattern ends, so it can't require it to be held")]
+    HeldOnStop,
+}
+
 #[derive(Debug)]
 #[must_use]
 pub struct BitTriggerBuilder {
-    bit_states: [BitState; 9],
+    bit_states: [BitState; DIGITAL_CHANNELS],
+    labels: Option<ChannelLabels>,
+    held_for: Option<Duration>,
 }
 
 impl BitTriggerBuilder {
     pub fn new() -> Self {
         Self {
-            bit_states: [BitState::DontCare; 9],
+            bit_states: [BitState::DontCare; DIGITAL_CHANNELS],
+            labels: None,
+            held_for: None,
         }
     }
 
+    /// Channel names for `bit_named`, see `ChannelLabels::start_capturing_when`
+    pub fn with_labels(mut self, labels: ChannelLabels) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    /// Like `set_bit`, but an out-of-range `bit` is an error instead of a panic, for bit
+    /// indices coming from user input
+    pub fn try_set_bit(self, bit: usize, state: BitState) -> Result<Self, DigitalTriggerError> {
+        if bit >= DIGITAL_CHANNELS {
+            return Err(DigitalTriggerError::NoSuchBit(bit));
+        }
+        Ok(self.set_bit(bit, state))
+    }
+
     pub fn set_bit(mut self, bit: usize, state: BitState) -> Self {
         assert!(
             (bit < self.bit_states.len()),
@@ -83,6 +146,21 @@ impl BitTriggerBuilder {
         self
     }
 
+    /// Set the bit of the channel labeled `label`, failing if no labels were given or none
+    /// of them is `label`
+    pub fn bit_named(self, label: &str, state: BitState) -> Result<Self, DigitalTriggerError> {
+        let bit = self
+            .channel(label)
+            .ok_or_else(|| DigitalTriggerError::UnknownLabel(label.to_string()))?;
+        self.try_set_bit(bit, state)
+    }
+
+    fn channel(&self, label: &str) -> Option<usize> {
+        self.labels
+            .as_ref()
+            .and_then(|labels| labels.channel(label))
+    }
+
     pub fn bit0(self, state: BitState) -> Self {
         self.set_bit(0, state)
     }
@@ -119,21 +197,60 @@ impl BitTriggerBuilder {
         self.set_bit(8, state)
     }
 
+    pub fn bit9(self, state: BitState) -> Self {
+        self.set_bit(9, state)
+    }
+
+    /// Only trigger once the pattern has been stable for `duration`, to ignore glitches on
+    /// slow control lines. The trigger is at the end of that window. Not available for
+    /// `stops_matching`.
+    ///
+    /// The firmware has no such condition, so it is applied by `CaptureStream`: the device
+    /// triggers as the pattern starts matching, and captures in which the pattern changes
+    /// within `duration` are dropped and the scope rearmed.
+    /// The capture has to cover `duration` and can't have a delay. Other reads ignore it
+    /// and log a warning.
+    pub fn held_for(mut self, duration: Duration) -> Self {
+        self.held_for = Some(duration);
+        self
+    }
+
     pub fn is_matching(self) -> DigitalTrigger {
-        DigitalTrigger::new(self.bit_states, DigitalTriggerBehavior::While)
+        self.build(DigitalTriggerBehavior::While)
     }
 
-    pub fn starts_matching(self) -> DigitalTrigger {
-        DigitalTrigger::new(self.bit_states, DigitalTriggerBehavior::Start)
+    /// Triggers when the pattern appears. Fails without any High or Low bit, as the
+    /// trigger would never fire.
+    pub fn starts_matching(self) -> Result<DigitalTrigger, DigitalTriggerError> {
+        self.try_build(DigitalTriggerBehavior::Start)
     }
 
-    pub fn stops_matching(self) -> DigitalTrigger {
-        DigitalTrigger::new(self.bit_states, DigitalTriggerBehavior::Stop)
+    /// Triggers when the pattern disappears, see `starts_matching`. Fails with a hold time,
+    /// as the pattern is gone at the trigger.
+    pub fn stops_matching(self) -> Result<DigitalTrigger, DigitalTriggerError> {
+        self.try_build(DigitalTriggerBehavior::Stop)
     }
 
     /// Same as `is_matching`, but will also trigger when the bits did not match within 100ms.
     pub fn auto(self) -> DigitalTrigger {
-        DigitalTrigger::new(self.bit_states, DigitalTriggerBehavior::Auto)
+        self.build(DigitalTriggerBehavior::Auto)
+    }
+
+    /// Finish with `behavior`, rejecting triggers that can never fire
+    pub fn try_build(
+        self,
+        behavior: DigitalTriggerBehavior,
+    ) -> Result<DigitalTrigger, DigitalTriggerError> {
+        let trigger = self.build(behavior);
+        trigger.validate()?;
+        Ok(trigger)
+    }
+
+    fn build(self, behavior: DigitalTriggerBehavior) -> DigitalTrigger {
+        DigitalTrigger {
+            held_for: self.held_for,
+            ..DigitalTrigger::new(self.bit_states, behavior)
+        }
     }
 }
 
@@ -143,23 +260,60 @@ impl Default for BitTriggerBuilder {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DigitalTrigger {
-    pub bit_states: [BitState; 9],
+    pub bit_states: [BitState; DIGITAL_CHANNELS],
     pub behavior: DigitalTriggerBehavior,
+    /// See `BitTriggerBuilder::held_for`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub held_for: Option<Duration>,
 }
 
 impl DigitalTrigger {
-    pub fn new(bit_states: [BitState; 9], behavior: DigitalTriggerBehavior) -> Self {
+    pub fn new(bit_states: [BitState; DIGITAL_CHANNELS], behavior: DigitalTriggerBehavior) -> Self {
         Self {
             bit_states,
             behavior,
+            held_for: None,
         }
     }
 
     pub fn start_capturing_when() -> BitTriggerBuilder {
         BitTriggerBuilder::new()
     }
+
+    /// Check that the trigger can fire: edges need a pattern that can change
+    pub fn validate(&self) -> Result<(), DigitalTriggerError> {
+        let has_pattern = self
+            .bit_states
+            .iter()
+            .any(|state| *state != BitState::DontCare);
+        match self.behavior {
+            DigitalTriggerBehavior::Start | DigitalTriggerBehavior::Stop if !has_pattern => {
+                Err(DigitalTriggerError::EdgeWithoutPattern(self.behavior))
+            }
+            DigitalTriggerBehavior::Stop if self.held_for.is_some() => {
+                Err(DigitalTriggerError::HeldOnStop)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Description for logs, e.g. `bit0=H & bit2=L, start matching`
+    pub fn describe(&self) -> String {
+        let behavior = match self.behavior {
+            DigitalTriggerBehavior::Auto => "auto",
+            DigitalTriggerBehavior::While => "while matching",
+            DigitalTriggerBehavior::Start => "start matching",
+            DigitalTriggerBehavior::Stop => "stop matching",
+        };
+        let description = format!("{}, {behavior}", describe_pattern(&self.bit_states));
+        match self.held_for {
+            Some(duration) => format!("{description}, held for {duration:?}"),
+            None => description,
+        }
+    }
 }
 
 impl TriggerConfig for DigitalTrigger {
@@ -183,6 +337,7 @@ impl TriggerConfig for DigitalTrigger {
             trigger_fields: format!(
                 "{trigger_behavior_flag}0x{active_bits:02x} 0x{relevant_bits:02x}"
             ),
+            emulated: self.held_for.is_some().then(|| self.into()),
         }
     }
 }
@@ -190,8 +345,10 @@ impl TriggerConfig for DigitalTrigger {
 #[derive(Debug, Clone)]
 #[must_use]
 pub struct AnalogTriggerBuilder {
-    pub volts: f64,
+    pub volts: Volts,
     pub behavior: AnalogTriggerBehavior,
+    pub hysteresis: Volts,
+    pub qualifier: Option<[BitState; DIGITAL_CHANNELS]>,
 }
 
 impl AnalogTriggerBuilder {
@@ -216,20 +373,69 @@ impl AnalogTriggerBuilder {
         self
     }
 
+    /// Width of the band beyond the level that a rising or falling edge has to cross
+    /// before the signal may cross the level again, so noise around the level doesn't
+    /// trigger twice. Defaults to none.
+    ///
+    /// The firmware has no hysteresis, so it is applied by `CaptureStream`, which drops
+    /// captures in which the signal re-crosses the level before leaving the band. Other
+    /// reads ignore it and log a warning.
+    pub fn hysteresis(mut self, band: Volts) -> Self {
+        self.hysteresis = band;
+        self
+    }
+
+    /// Only trigger while the digital inputs match `pattern`, e.g. a rising edge on the
+    /// BNC input only while bit 3 is high. Only the bit pattern is used, its behavior is
+    /// ignored.
+    ///
+    /// The firmware triggers on either input but not both, so the pattern is checked at the
+    /// trigger by `CaptureStream`, which drops captures in which it didn't match and rearms.
+    /// Other reads ignore it and log a warning, and it can't be combined with a delay.
+    pub fn qualified_by(mut self, pattern: &DigitalTrigger) -> Self {
+        self.qualifier = Some(pattern.bit_states);
+        self
+    }
+
     pub fn into_trigger(self, flea_probe: &FleaProbe) -> Result<AnalogTrigger, CaptureConfigError> {
+        let (low, high) = flea_probe
+            .measurable_range()
+            .map_err(|_| CaptureConfigError::ProbeNotCalibrated)?;
+        if !(low..=high).contains(&self.volts) {
+            return Err(CaptureConfigError::VoltageOutOfRange);
+        }
+
         let raw_level = (flea_probe.voltage_to_raw(self.volts) / 4.0 + 0.5) as i16;
 
         if !(-1023..=1023).contains(&raw_level) {
             return Err(CaptureConfigError::VoltageOutOfRange);
         }
-        Ok(AnalogTrigger::new(raw_level, self.behavior))
+        // Same units as the level, so scaled by the probe but without its offset
+        let band = (flea_probe.voltage_to_raw(self.hysteresis)
+            - flea_probe.voltage_to_raw(Volts::ZERO))
+        .abs()
+            / 4.0
+            + 0.5;
+        #[allow(clippy::cast_sign_loss)]
+        let band = band as u16;
+        Ok(AnalogTrigger {
+            qualifier: self.qualifier,
+            ..AnalogTrigger::new(raw_level, self.behavior).with_hysteresis(band)
+        })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnalogTrigger {
     pub level: i16,
     pub behavior: AnalogTriggerBehavior,
+    /// In the units of `level`, see `AnalogTriggerBuilder::hysteresis`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hysteresis: u16,
+    /// Digital pattern that must match at the trigger, see `AnalogTriggerBuilder::qualified_by`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub qualifier: Option<[BitState; DIGITAL_CHANNELS]>,
 }
 
 impl AnalogTrigger {
@@ -237,30 +443,75 @@ impl AnalogTrigger {
         Self {
             level: raw_value,
             behavior,
+            hysteresis: 0,
+            qualifier: None,
         }
     }
 
-    pub fn start_capturing_when(volts: f64) -> AnalogTriggerBuilder {
+    /// See `AnalogTriggerBuilder::hysteresis`, `band` is in the units of `level`
+    #[must_use]
+    pub fn with_hysteresis(mut self, band: u16) -> Self {
+        self.hysteresis = band;
+        self
+    }
+
+    /// See `AnalogTriggerBuilder::qualified_by`
+    #[must_use]
+    pub fn qualified_by(mut self, pattern: &DigitalTrigger) -> Self {
+        self.qualifier = Some(pattern.bit_states);
+        self
+    }
+
+    pub fn start_capturing_when(volts: Volts) -> AnalogTriggerBuilder {
         AnalogTriggerBuilder {
             volts,
             behavior: AnalogTriggerBehavior::Auto,
+            hysteresis: Volts::ZERO,
+            qualifier: None,
         }
     }
+
+    /// Description for logs, e.g. `rising through level 500`
+    pub fn describe(&self) -> String {
+        let behavior = match self.behavior {
+            AnalogTriggerBehavior::Auto => "auto at",
+            AnalogTriggerBehavior::Level => "at or above",
+            AnalogTriggerBehavior::Rising => "rising through",
+            AnalogTriggerBehavior::Falling => "falling through",
+        };
+        let hysteresis = if self.hysteresis > 0 {
+            format!(" hysteresis {}", self.hysteresis)
+        } else {
+            String::new()
+        };
+        let qualifier = self
+            .qualifier
+            .map(|pattern| format!(" while {}", describe_pattern(&pattern)))
+            .unwrap_or_default();
+        format!("{behavior} level {}{hysteresis}{qualifier}", self.level)
+    }
 }
 
 impl TriggerConfig for AnalogTrigger {
     fn into_trigger_fields(self) -> StringifiedTriggerConfig {
         let trigger_behavior_flag = self.behavior.as_str();
+        let edge = matches!(
+            self.behavior,
+            AnalogTriggerBehavior::Rising | AnalogTriggerBehavior::Falling
+        );
 
         StringifiedTriggerConfig {
             trigger_fields: format!("{}{} 0", trigger_behavior_flag, self.level),
+            emulated: (edge && self.hysteresis > 0 || self.qualifier.is_some())
+                .then(|| self.into()),
         }
     }
 }
 
 /// A unified trigger type that can represent both analog and digital triggers.
 /// This allows treating all triggers uniformly in the API.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Trigger {
     Analog(AnalogTrigger),
     Digital(DigitalTrigger),
@@ -277,3 +528,559 @@ impl From<DigitalTrigger> for Trigger {
         Self::Digital(trigger)
     }
 }
+
+/// Anything `IdleFleaScope::read_with` can trigger on: the `Trigger` types, and analog
+/// triggers in volts, whose level is resolved through the probe's calibration
+pub trait ProbeTrigger {
+    fn resolve(self, probe: &FleaProbe) -> Result<Trigger, CaptureConfigError>;
+}
+
+impl ProbeTrigger for Trigger {
+    fn resolve(self, _probe: &FleaProbe) -> Result<Trigger, CaptureConfigError> {
+        Ok(self)
+    }
+}
+
+impl ProbeTrigger for DigitalTrigger {
+    fn resolve(self, _probe: &FleaProbe) -> Result<Trigger, CaptureConfigError> {
+        Ok(self.into())
+    }
+}
+
+impl ProbeTrigger for AnalogTrigger {
+    fn resolve(self, _probe: &FleaProbe) -> Result<Trigger, CaptureConfigError> {
+        Ok(self.into())
+    }
+}
+
+impl ProbeTrigger for AnalogTriggerBuilder {
+    fn resolve(self, probe: &FleaProbe) -> Result<Trigger, CaptureConfigError> {
+        Ok(self.into_trigger(probe)?.into())
+    }
+}
+
+impl TriggerConfig for Trigger {
+    fn into_trigger_fields(self) -> StringifiedTriggerConfig {
+        match self {
+            Self::Analog(trigger) => trigger.into_trigger_fields(),
+            Self::Digital(trigger) => trigger.into_trigger_fields(),
+        }
+    }
+}
+
+impl Trigger {
+    /// Description for logs, see `DigitalTrigger::describe` and `AnalogTrigger::describe`
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Analog(trigger) => trigger.describe(),
+            Self::Digital(trigger) => trigger.describe(),
+        }
+    }
+
+    /// Parse trigger fields as sent to the device, e.g. `CaptureMetadata::trigger`
+    pub fn from_fields(fields: &str) -> Result<Self, ParseTriggerError> {
+        let malformed = || ParseTriggerError::Malformed(fields.to_string());
+        let (first, second) = fields.split_once(' ').ok_or_else(malformed)?;
+        // A negative level without a flag reads as a falling edge, as on the device
+        let (flag, first) = first
+            .strip_prefix(['~', '+', '-'])
+            .map_or((None, first), |rest| (first.chars().next(), rest));
+
+        if let (Some(pattern), Some(mask)) = (first.strip_prefix("0x"), second.strip_prefix("0x")) {
+            let parse_hex = |hex| u16::from_str_radix(hex, 16).map_err(|_| malformed());
+            let (pattern, mask) = (parse_hex(pattern)?, parse_hex(mask)?);
+            if mask >> DIGITAL_CHANNELS != 0 {
+                return Err(malformed());
+            }
+            let mut bit_states = [BitState::DontCare; DIGITAL_CHANNELS];
+            for (bit, state) in bit_states.iter_mut().enumerate() {
+                if mask & 1 << bit != 0 {
+                    *state = if pattern & 1 << bit != 0 {
+                        BitState::High
+                    } else {
+                        BitState::Low
+                    };
+                }
+            }
+            let behavior = match flag {
+                Some('~') => DigitalTriggerBehavior::Auto,
+                Some('+') => DigitalTriggerBehavior::Start,
+                Some('-') => DigitalTriggerBehavior::Stop,
+                _ => DigitalTriggerBehavior::While,
+            };
+            return Ok(DigitalTrigger::new(bit_states, behavior).into());
+        }
+
+        if second != "0" {
+            return Err(malformed());
+        }
+        let behavior = match flag {
+            Some('~') => AnalogTriggerBehavior::Auto,
+            Some('+') => AnalogTriggerBehavior::Rising,
+            Some('-') => AnalogTriggerBehavior::Falling,
+            _ => AnalogTriggerBehavior::Level,
+        };
+        Ok(AnalogTrigger::new(parse_level(first)?, behavior).into())
+    }
+}
+
+/// A trigger string that doesn't follow the syntax of `FromStr` or `Trigger::from_fields`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseTriggerError {
+    #[error("Expected \"digital\" or \"analog\", found {0:?}")]
+    UnknownKind(String),
+
+    #[error("Unknown trigger behavior {0:?}")]
+    UnknownBehavior(String),
+
+    #[error("Invalid bit pattern {0:?}, expected up to {DIGITAL_CHANNELS} of 1, 0 or X")]
+    InvalidPattern(String),
+
+    #[error("Invalid trigger level {0:?}, expected -1023 to 1023")]
+    InvalidLevel(String),
+
+    #[error("Invalid hysteresis band {0:?}, expected a whole number of level units")]
+    InvalidHysteresis(String),
+
+    #[error("Invalid hold time {0:?}, expected microseconds like 500us")]
+    InvalidDuration(String),
+
+    #[error("Malformed trigger {0:?}")]
+    Malformed(String),
+}
+
+fn parse_level(level: &str) -> Result<i16, ParseTriggerError> {
+    level
+        .parse()
+        .ok()
+        .filter(|level| (-1023..=1023).contains(level))
+        .ok_or_else(|| ParseTriggerError::InvalidLevel(level.to_string()))
+}
+
+impl DigitalTriggerBehavior {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::While => "while",
+            Self::Start => "start",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+impl AnalogTriggerBehavior {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Level => "level",
+            Self::Rising => "rising",
+            Self::Falling => "falling",
+        }
+    }
+}
+
+/// `digital <behavior> <bits>`, with behavior `auto`, `while`, `start` or `stop` and one
+/// of `1`, `0` or `X` per bit starting from bit 0, e.g. `digital start 1X0XXXXXXX`.
+/// Bits left out when parsing don't care. A hold time follows as `held <micros>us`, e.g.
+/// `digital start 1X0XXXXXXX held 500us`.
+impl std::fmt::Display for DigitalTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "digital {} {}",
+            self.behavior.name(),
+            format_pattern(&self.bit_states)
+        )?;
+        self.held_for.map_or(Ok(()), |duration| {
+            write!(f, " held {}us", duration.as_micros())
+        })
+    }
+}
+
+impl std::str::FromStr for DigitalTrigger {
+    type Err = ParseTriggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, held_for) = match s.split_once(" held ") {
+            Some((trigger, duration)) => {
+                let micros = duration
+                    .trim()
+                    .strip_suffix("us")
+                    .and_then(|micros| micros.parse().ok())
+                    .ok_or_else(|| ParseTriggerError::InvalidDuration(duration.to_string()))?;
+                (trigger, Some(Duration::from_micros(micros)))
+            }
+            None => (s, None),
+        };
+        let [behavior, bits] = fields(s, "digital")?;
+        let behavior = [
+            DigitalTriggerBehavior::Auto,
+            DigitalTriggerBehavior::While,
+            DigitalTriggerBehavior::Start,
+            DigitalTriggerBehavior::Stop,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.name().eq_ignore_ascii_case(behavior))
+        .ok_or_else(|| ParseTriggerError::UnknownBehavior(behavior.to_string()))?;
+        Ok(Self {
+            held_for,
+            ..Self::new(parse_pattern(bits)?, behavior)
+        })
+    }
+}
+
+/// One of `1`, `0` or `X` per bit starting from bit 0
+fn format_pattern(bit_states: &[BitState; DIGITAL_CHANNELS]) -> String {
+    bit_states
+        .iter()
+        .map(|state| match state {
+            BitState::High => '1',
+            BitState::Low => '0',
+            BitState::DontCare => 'X',
+        })
+        .collect()
+}
+
+/// Inverse of `format_pattern`, bits left out don't care
+fn parse_pattern(bits: &str) -> Result<[BitState; DIGITAL_CHANNELS], ParseTriggerError> {
+    let invalid = || ParseTriggerError::InvalidPattern(bits.to_string());
+    if bits.chars().count() > DIGITAL_CHANNELS {
+        return Err(invalid());
+    }
+    let mut bit_states = [BitState::DontCare; DIGITAL_CHANNELS];
+    for (state, bit) in bit_states.iter_mut().zip(bits.chars()) {
+        *state = match bit {
+            '1' => BitState::High,
+            '0' => BitState::Low,
+            'x' | 'X' => BitState::DontCare,
+            _ => return Err(invalid()),
+        };
+    }
+    Ok(bit_states)
+}
+
+/// E.g. `bit0=H & bit2=L`
+fn describe_pattern(bit_states: &[BitState; DIGITAL_CHANNELS]) -> String {
+    let bits: Vec<String> = bit_states
+        .iter()
+        .enumerate()
+        .filter_map(|(bit, state)| match state {
+            BitState::High => Some(format!("bit{bit}=H")),
+            BitState::Low => Some(format!("bit{bit}=L")),
+            BitState::DontCare => None,
+        })
+        .collect();
+    if bits.is_empty() {
+        "any bits".to_string()
+    } else {
+        bits.join(" & ")
+    }
+}
+
+/// `analog <behavior> <level>`, with behavior `auto`, `level`, `rising` or `falling` and the
+/// raw level of `AnalogTrigger::new`, e.g. `analog rising 500`. A hysteresis follows as
+/// `hysteresis <band>`, then a qualifier as `while <bits>` in the syntax of `DigitalTrigger`,
+/// e.g. `analog rising 500 hysteresis 8 while XXX1XXXXXX`.
+impl std::fmt::Display for AnalogTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "analog {} {}", self.behavior.name(), self.level)?;
+        if self.hysteresis > 0 {
+            write!(f, " hysteresis {}", self.hysteresis)?;
+        }
+        self.qualifier.map_or(Ok(()), |pattern| {
+            write!(f, " while {}", format_pattern(&pattern))
+        })
+    }
+}
+
+impl std::str::FromStr for AnalogTrigger {
+    type Err = ParseTriggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, qualifier) = match s.split_once(" while ") {
+            Some((trigger, bits)) => (trigger, Some(parse_pattern(bits.trim())?)),
+            None => (s, None),
+        };
+        let (s, hysteresis) = match s.split_once(" hysteresis ") {
+            Some((trigger, band)) => (
+                trigger,
+                band.trim()
+                    .parse()
+                    .map_err(|_| ParseTriggerError::InvalidHysteresis(band.to_string()))?,
+            ),
+            None => (s, 0),
+        };
+        let [behavior, level] = fields(s, "analog")?;
+        let behavior = [
+            AnalogTriggerBehavior::Auto,
+            AnalogTriggerBehavior::Level,
+            AnalogTriggerBehavior::Rising,
+            AnalogTriggerBehavior::Falling,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.name().eq_ignore_ascii_case(behavior))
+        .ok_or_else(|| ParseTriggerError::UnknownBehavior(behavior.to_string()))?;
+        Ok(Self {
+            qualifier,
+            ..Self::new(parse_level(level)?, behavior).with_hysteresis(hysteresis)
+        })
+    }
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Analog(trigger) => trigger.fmt(f),
+            Self::Digital(trigger) => trigger.fmt(f),
+        }
+    }
+}
+
+/// Either syntax, told apart by the leading `digital` or `analog`
+///
+/// ```rust
+/// use fleascope_rs::Trigger;
+///
+/// let trigger: Trigger = "digital start 1X0".parse()?;
+/// assert_eq!(trigger.to_string(), "digital start 1X0XXXXXXX");
+/// let trigger: Trigger = "analog falling -200".parse()?;
+/// # Ok::<(), fleascope_rs::trigger_config::ParseTriggerError>(())
+/// ```
+impl std::str::FromStr for Trigger {
+    type Err = ParseTriggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().next() {
+            Some(kind) if kind.eq_ignore_ascii_case("digital") => Ok(Self::Digital(s.parse()?)),
+            Some(kind) if kind.eq_ignore_ascii_case("analog") => Ok(Self::Analog(s.parse()?)),
+            kind => Err(ParseTriggerError::UnknownKind(
+                kind.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+}
+
+/// The two fields after the leading `kind`
+fn fields<'a>(s: &'a str, kind: &str) -> Result<[&'a str; 2], ParseTriggerError> {
+    let mut words = s.split_whitespace();
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (Some(found), Some(first), Some(second), None) if found.eq_ignore_ascii_case(kind) => {
+            Ok([first, second])
+        }
+        (Some(found), ..) if !found.eq_ignore_ascii_case(kind) => {
+            Err(ParseTriggerError::UnknownKind(found.to_string()))
+        }
+        _ => Err(ParseTriggerError::Malformed(s.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_held_for() {
+        let pattern = || {
+            DigitalTrigger::start_capturing_when()
+                .bit4(BitState::High)
+                .held_for(Duration::from_micros(500))
+        };
+        let held = pattern().starts_matching().unwrap();
+        assert_eq!(held.held_for, Some(Duration::from_micros(500)));
+        assert_eq!(held.to_string(), "digital start XXXX1XXXXX held 500us");
+        assert_eq!(held.describe(), "bit4=H, start matching, held for 500µs");
+        assert_eq!(held.to_string().parse(), Ok(held.clone()));
+        assert_eq!(
+            "digital start 1 held 5ms".parse::<DigitalTrigger>(),
+            Err(ParseTriggerError::InvalidDuration("5ms".to_string()))
+        );
+
+        // Not part of the fields, but kept for the software check
+        let fields = held.clone().into_trigger_fields();
+        assert_eq!(fields.as_str(), "+0x10 0x10");
+        assert_eq!(fields.to_trigger(), Ok(held.into()));
+
+        assert_eq!(
+            pattern().try_build(DigitalTriggerBehavior::Stop),
+            Err(DigitalTriggerError::HeldOnStop)
+        );
+    }
+
+    #[test]
+    fn test_trigger_description() {
+        let digital = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .bit2(BitState::Low)
+            .starts_matching()
+            .unwrap();
+        assert_eq!(digital.describe(), "bit0=H & bit2=L, start matching");
+        let auto = DigitalTrigger::start_capturing_when().auto();
+        assert_eq!(auto.describe(), "any bits, auto");
+
+        let analog = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).with_hysteresis(8);
+        assert_eq!(
+            Trigger::from(analog).describe(),
+            "rising through level 500 hysteresis 8"
+        );
+    }
+
+    #[test]
+    fn test_fields_round_trip() {
+        let digital = DigitalTrigger::start_capturing_when()
+            .bit9(BitState::High)
+            .bit1(BitState::Low)
+            .stops_matching()
+            .unwrap();
+        let fields = digital.clone().into_trigger_fields();
+        assert_eq!(fields.as_str(), "-0x200 0x202");
+        assert_eq!(fields.to_trigger(), Ok(digital.into()));
+
+        for analog in [
+            AnalogTrigger::new(-200, AnalogTriggerBehavior::Falling),
+            AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).with_hysteresis(8),
+            AnalogTrigger::new(500, AnalogTriggerBehavior::Level).qualified_by(
+                &DigitalTrigger::start_capturing_when()
+                    .bit0(BitState::Low)
+                    .is_matching(),
+            ),
+        ] {
+            let fields = analog.clone().into_trigger_fields();
+            assert_eq!(fields.to_trigger(), Ok(analog.into()));
+        }
+    }
+
+    #[test]
+    fn test_try_set_bit() {
+        let trigger = DigitalTrigger::start_capturing_when()
+            .try_set_bit(9, BitState::High)
+            .unwrap()
+            .try_build(DigitalTriggerBehavior::Start)
+            .unwrap();
+        assert_eq!(trigger.bit_states[9], BitState::High);
+
+        assert_eq!(
+            DigitalTrigger::start_capturing_when()
+                .try_set_bit(DIGITAL_CHANNELS, BitState::Low)
+                .err(),
+            Some(DigitalTriggerError::NoSuchBit(DIGITAL_CHANNELS))
+        );
+        assert_eq!(
+            DigitalTrigger::start_capturing_when()
+                .bit_named("SCL", BitState::Low)
+                .err(),
+            Some(DigitalTriggerError::UnknownLabel("SCL".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_edge_needs_pattern() {
+        for behavior in [DigitalTriggerBehavior::Start, DigitalTriggerBehavior::Stop] {
+            assert_eq!(
+                DigitalTrigger::start_capturing_when()
+                    .try_build(behavior)
+                    .err(),
+                Some(DigitalTriggerError::EdgeWithoutPattern(behavior))
+            );
+        }
+        // Level triggers without a pattern fire right away, the usual untriggered capture
+        assert!(DigitalTrigger::start_capturing_when()
+            .try_build(DigitalTriggerBehavior::While)
+            .is_ok());
+        assert!(DigitalTrigger::start_capturing_when()
+            .is_matching()
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_trigger_syntax() {
+        let digital = DigitalTrigger::start_capturing_when()
+            .bit0(BitState::High)
+            .bit2(BitState::Low)
+            .bit9(BitState::High)
+            .stops_matching()
+            .unwrap();
+        assert_eq!(digital.to_string(), "digital stop 1X0XXXXXX1");
+        assert_eq!("Digital STOP 1x0xxxxxx1".parse(), Ok(digital));
+        assert_eq!(
+            "digital start 1".parse::<Trigger>(),
+            Ok(DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .starts_matching()
+                .unwrap()
+                .into())
+        );
+
+        let analog = AnalogTrigger::new(-200, AnalogTriggerBehavior::Falling);
+        assert_eq!(analog.to_string(), "analog falling -200");
+        let damped = AnalogTrigger::new(500, AnalogTriggerBehavior::Rising).with_hysteresis(8);
+        assert_eq!(damped.to_string(), "analog rising 500 hysteresis 8");
+        assert_eq!(damped.to_string().parse(), Ok(damped.clone()));
+        let qualified = damped.qualified_by(
+            &DigitalTrigger::start_capturing_when()
+                .bit3(BitState::High)
+                .is_matching(),
+        );
+        assert_eq!(
+            qualified.to_string(),
+            "analog rising 500 hysteresis 8 while XXX1XXXXXX"
+        );
+        assert_eq!(
+            qualified.describe(),
+            "rising through level 500 hysteresis 8 while bit3=H"
+        );
+        assert_eq!(
+            "analog rising 500 hysteresis 8 while XXX1".parse(),
+            Ok(qualified)
+        );
+        assert_eq!(analog.to_string().parse::<Trigger>(), Ok(analog.into()));
+
+        assert_eq!(
+            "digital start 1X2".parse::<Trigger>(),
+            Err(ParseTriggerError::InvalidPattern("1X2".to_string()))
+        );
+        assert_eq!(
+            "analog sideways 3".parse::<Trigger>(),
+            Err(ParseTriggerError::UnknownBehavior("sideways".to_string()))
+        );
+        assert_eq!(
+            "analog rising 1024".parse::<Trigger>(),
+            Err(ParseTriggerError::InvalidLevel("1024".to_string()))
+        );
+        assert_eq!(
+            "analog rising 500 hysteresis -8".parse::<Trigger>(),
+            Err(ParseTriggerError::InvalidHysteresis("-8".to_string()))
+        );
+        assert_eq!(
+            "digital start 1 extra".parse::<DigitalTrigger>(),
+            Err(ParseTriggerError::Malformed(
+                "digital start 1 extra".to_string()
+            ))
+        );
+        assert_eq!(
+            "analog rising 500".parse::<DigitalTrigger>(),
+            Err(ParseTriggerError::UnknownKind("analog".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_from_fields() {
+        let triggers: [Trigger; 4] = [
+            DigitalTrigger::start_capturing_when()
+                .bit1(BitState::High)
+                .bit9(BitState::Low)
+                .starts_matching()
+                .unwrap()
+                .into(),
+            DigitalTrigger::start_capturing_when().auto().into(),
+            AnalogTrigger::new(-200, AnalogTriggerBehavior::Falling).into(),
+            AnalogTrigger::new(500, AnalogTriggerBehavior::Level).into(),
+        ];
+        for trigger in triggers {
+            let fields = trigger.clone().into_trigger_fields().into_string();
+            assert_eq!(Trigger::from_fields(&fields), Ok(trigger), "{fields}");
+        }
+        assert!(Trigger::from_fields("+0x01 0x400").is_err());
+        assert!(Trigger::from_fields("500 1").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::flea_scope::CALIBRATED_COLUMN_NAME;
+use polars::prelude::*;
+use std::fmt;
+use std::sync::Arc;
+
+type TransferFn = dyn Fn(Expr) -> Expr + Send + Sync;
+
+/// Transfer function of a sensor front-end, mapping calibrated volts to a physical quantity.
+///
+/// The conversion is applied on top of [`CALIBRATED_COLUMN_NAME`] and adds a new column
+/// named `<quantity>_<unit>`, e.g. `current_A` or `temperature_degC`.
+#[derive(Clone)]
+pub struct UnitConversion {
+    quantity: String,
+    unit: String,
+    transfer: Arc<TransferFn>,
+}
+
+impl UnitConversion {
+    /// Create a conversion from an arbitrary polars expression transform
+    pub fn new<F>(quantity: &str, unit: &str, transfer: F) -> Self
+    where
+        F: Fn(Expr) -> Expr + Send + Sync + 'static,
+    {
+        Self {
+            quantity: quantity.to_string(),
+            unit: unit.to_string(),
+            transfer: Arc::new(transfer),
+        }
+    }
+
+    /// `quantity = volts * scale + offset`, e.g. for sensors with a linear mV/unit output
+    pub fn linear(quantity: &str, unit: &str, scale: f64, offset: f64) -> Self {
+        Self::new(quantity, unit, move |volts| {
+            volts * lit(scale) + lit(offset)
+        })
+    }
+
+    /// Undo a resistive divider in front of the BNC input
+    pub fn voltage_divider(r_top: f64, r_bottom: f64) -> Self {
+        Self::linear("voltage", "V", (r_top + r_bottom) / r_bottom, 0.0)
+    }
+
+    /// Current through a shunt resistor measured across its terminals
+    pub fn shunt(ohms: f64) -> Self {
+        Self::linear("current", "A", 1.0 / ohms, 0.0)
+    }
+
+    /// Name of the column produced by [`Self::apply`]
+    pub fn column_name(&self) -> String {
+        format!("{}_{}", self.quantity, self.unit)
+    }
+
+    pub fn quantity(&self) -> &str {
+        &self.quantity
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Expression converting the given calibrated voltage expression
+    pub fn to_expr(&self, volts: Expr) -> Expr {
+        (self.transfer)(volts).alias(self.column_name())
+    }
+
+    /// Add the physical-unit column to a calibrated frame
+    pub fn apply(&self, df: LazyFrame) -> LazyFrame {
+        profiling::scope!("UnitConversion::apply");
+
+        df.with_column(self.to_expr(col(CALIBRATED_COLUMN_NAME)))
+    }
+}
+
+impl fmt::Debug for UnitConversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnitConversion")
+            .field("quantity", &self.quantity)
+            .field("unit", &self.unit)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(conversion: &UnitConversion, volts: Vec<f64>) -> Vec<f64> {
+        let df = df!(CALIBRATED_COLUMN_NAME => volts).unwrap().lazy();
+        conversion
+            .apply(df)
+            .collect()
+            .unwrap()
+            .column(&conversion.column_name())
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!(UnitConversion::shunt(0.1).column_name(), "current_A");
+        assert_eq!(
+            UnitConversion::linear("temperature", "degC", 100.0, -50.0).column_name(),
+            "temperature_degC"
+        );
+    }
+
+    #[test]
+    fn test_linear_conversions() {
+        let divider = UnitConversion::voltage_divider(9000.0, 1000.0);
+        assert_eq!(convert(&divider, vec![0.0, 1.5]), vec![0.0, 15.0]);
+
+        let tmp36 = UnitConversion::linear("temperature", "degC", 100.0, -50.0);
+        assert_eq!(convert(&tmp36, vec![0.75]), vec![25.0]);
+    }
+}
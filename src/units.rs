@@ -0,0 +1,76 @@
+use crate::flea_scope::CaptureConfigError;
+use std::fmt;
+
+/// A voltage at the probe tip, as opposed to a raw ADC value.
+///
+/// Always finite, so comparisons against a probe's measurable range are meaningful.
+///
+/// ```rust
+/// use fleascope_rs::units::Volts;
+///
+/// let level = Volts::new(1.5)?;
+/// assert_eq!(level.get(), 1.5);
+/// assert_eq!(Volts::from_millivolts(1500), level);
+/// assert!(Volts::new(f64::NAN).is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Volts(f64);
+
+impl Volts {
+    pub const ZERO: Self = Self(0.0);
+    /// The upper calibration point of the probes
+    pub const CALIBRATION_REFERENCE: Self = Self(3.3);
+
+    pub fn new(volts: f64) -> Result<Self, CaptureConfigError> {
+        if volts.is_finite() {
+            Ok(Self(volts))
+        } else {
+            Err(CaptureConfigError::VoltageOutOfRange)
+        }
+    }
+
+    /// Infallible, e.g. for constants
+    pub const fn from_millivolts(millivolts: i32) -> Self {
+        Self(millivolts as f64 / 1000.0)
+    }
+
+    pub const fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<f64> for Volts {
+    type Error = CaptureConfigError;
+
+    fn try_from(volts: f64) -> Result<Self, Self::Error> {
+        Self::new(volts)
+    }
+}
+
+impl From<Volts> for f64 {
+    fn from(volts: Volts) -> Self {
+        volts.0
+    }
+}
+
+impl fmt::Display for Volts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} V", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volts() {
+        assert!(Volts::new(f64::INFINITY).is_err());
+        assert!(Volts::try_from(f64::NAN).is_err());
+        assert_eq!(Volts::from_millivolts(-250), Volts::new(-0.25).unwrap());
+        assert!(Volts::ZERO < Volts::CALIBRATION_REFERENCE);
+        assert_eq!(Volts::from_millivolts(3300), Volts::CALIBRATION_REFERENCE);
+        assert_eq!(Volts::new(1.5).unwrap().to_string(), "1.5 V");
+    }
+}